@@ -406,6 +406,10 @@ TOC = [
                 "module: wezterm.serde",
                 "config/lua/wezterm.serde",
             ),
+            Gen(
+                "module: wezterm.ssh",
+                "config/lua/wezterm.ssh",
+            ),
             Gen(
                 "module: wezterm.time",
                 "config/lua/wezterm.time",
@@ -441,7 +441,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 45;
+pub const CODEC_VERSION: usize = 50;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -502,6 +502,16 @@ pdu! {
     GetPaneDirection: 60,
     GetPaneDirectionResponse: 61,
     AdjustPaneSize: 62,
+    ScreenshotPane: 63,
+    ScreenshotPaneResponse: 64,
+    SshForward: 65,
+    SshForwardResponse: 66,
+    SftpTransfer: 67,
+    SftpTransferResponse: 68,
+    RenderScreenshot: 69,
+    ScreenshotRendered: 70,
+    ConfirmSshAgentRequest: 71,
+    SshAgentRequestConfirmed: 72,
 }
 
 impl Pdu {
@@ -876,6 +886,125 @@ pub struct AdjustPaneSize {
     pub amount: usize,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ScreenshotPane {
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ScreenshotPaneResponse {
+    /// PNG encoded image data
+    pub data: Vec<u8>,
+}
+
+/// Sent by a mux server that cannot render screenshots itself,
+/// asking an attached gui client to render the pane for it.
+/// The client replies with ScreenshotRendered.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct RenderScreenshot {
+    pub id: usize,
+    /// The pane id as known to the server
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ScreenshotRendered {
+    pub id: usize,
+    /// PNG encoded image data, or the reason that rendering failed
+    pub result: Result<Vec<u8>, String>,
+}
+
+/// Sent by a mux server whose built-in ssh agent needs the user
+/// to approve the use of a key, asking the gui client that the
+/// user is interacting with to prompt them.
+/// The client replies with SshAgentRequestConfirmed.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ConfirmSshAgentRequest {
+    pub id: usize,
+    pub message: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshAgentRequestConfirmed {
+    pub id: usize,
+    pub allowed: bool,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub enum SshForwardAction {
+    /// Add a forward described by a spec such as `L 8080:localhost:80`
+    Add(String),
+    Cancel(usize),
+    List,
+}
+
+/// Manages the port forwards of the ssh domain that contains pane_id
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshForward {
+    pub pane_id: PaneId,
+    pub action: SshForwardAction,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshForwardEntry {
+    pub id: usize,
+    pub spec: String,
+    pub bound_port: u16,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshForwardResponse {
+    /// For Add, the new forward; for List, all forwards;
+    /// for Cancel, empty.
+    pub forwards: Vec<SshForwardEntry>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub enum SftpTransferAction {
+    /// Open a remote file for reading.  Relative paths are resolved
+    /// against the working directory of the pane.
+    OpenRead {
+        path: String,
+    },
+    /// Create or truncate a remote file and open it for writing.
+    /// If `path` is an existing directory and `file_name` is
+    /// specified, the file is created with that name inside it.
+    OpenWrite {
+        path: String,
+        file_name: Option<String>,
+    },
+    Read {
+        handle: usize,
+        len: usize,
+    },
+    Write {
+        handle: usize,
+        data: Vec<u8>,
+    },
+    Close {
+        handle: usize,
+    },
+}
+
+/// Transfers a file over sftp using the ssh session of the
+/// domain that contains pane_id
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SftpTransfer {
+    pub pane_id: PaneId,
+    pub action: SftpTransferAction,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SftpTransferResponse {
+    pub handle: usize,
+    /// For OpenRead and OpenWrite, the resolved remote path
+    pub path: String,
+    /// For OpenRead, the size of the file, if known
+    pub size: Option<u64>,
+    /// For Read, the data that was read; empty at the end of the file
+    pub data: Vec<u8>,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct GetPaneDirectionResponse {
     pub pane_id: Option<PaneId>,
@@ -1142,6 +1271,50 @@ pub struct GetImageCellResponse {
     pub data: Option<Arc<ImageData>>,
 }
 
+/// The visible portion of a pane, captured so that it can be rendered
+/// into a screenshot by another process.  A standalone mux server has
+/// no renderer of its own, so it passes this to `wezterm-gui`.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct PaneSnapshot {
+    pub dimensions: RenderableDimensions,
+    pub cursor: StableCursorPosition,
+    pub palette: ColorPalette,
+    /// The stable row index of the first of `lines`
+    pub first_row: StableRowIndex,
+    pub lines: Vec<Line>,
+}
+
+impl PaneSnapshot {
+    pub fn capture(pane: &dyn mux::pane::Pane) -> Self {
+        let dimensions = pane.get_dimensions();
+        let top = dimensions.physical_top;
+        let (first_row, lines) =
+            pane.get_lines(top..top + dimensions.viewport_rows as StableRowIndex);
+        Self {
+            dimensions,
+            cursor: pane.get_cursor_position(),
+            palette: pane.palette(),
+            first_row,
+            lines,
+        }
+    }
+
+    pub fn write_to<W: std::io::Write>(&self, mut w: W) -> anyhow::Result<()> {
+        let (data, is_compressed) = serialize(self)?;
+        w.write_all(&[is_compressed as u8])?;
+        w.write_all(&data)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from<R: std::io::Read>(mut r: R) -> anyhow::Result<Self> {
+        let mut is_compressed = [0u8];
+        r.read_exact(&mut is_compressed)
+            .context("reading pane snapshot")?;
+        deserialize(r, is_compressed[0] != 0).context("decoding pane snapshot")
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1262,4 +1435,40 @@ mod test {
             Pdu::decode(encoded.as_slice()).unwrap()
         );
     }
+
+    #[test]
+    fn pane_snapshot_round_trip() {
+        let snapshot = PaneSnapshot {
+            dimensions: RenderableDimensions {
+                cols: 10,
+                viewport_rows: 2,
+                physical_top: 5,
+                ..Default::default()
+            },
+            cursor: StableCursorPosition {
+                x: 3,
+                y: 6,
+                ..Default::default()
+            },
+            palette: ColorPalette::default(),
+            first_row: 5,
+            // Long enough that it will be compressed
+            lines: vec![Line::from_text(
+                &"hello ".repeat(20),
+                &Default::default(),
+                SequenceNo::default(),
+                None,
+            )],
+        };
+
+        let mut encoded = vec![];
+        snapshot.write_to(&mut encoded).unwrap();
+        assert_eq!(encoded[0], 1, "expected the snapshot to be compressed");
+        assert_eq!(
+            PaneSnapshot::read_from(encoded.as_slice()).unwrap(),
+            snapshot
+        );
+
+        assert!(PaneSnapshot::read_from(&b""[..]).is_err());
+    }
 }
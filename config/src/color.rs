@@ -153,6 +153,18 @@ pub struct Palette {
     /// The color of the "thumb" of the scrollbar; the segment that
     /// represents the current viewable area
     pub scrollbar_thumb: Option<RgbaColor>,
+    /// The color of the scrollbar markers that indicate the positions
+    /// of matches from the search overlay
+    pub scrollbar_search_match: Option<RgbaColor>,
+    /// The color of the scrollbar markers that indicate the start
+    /// of OSC 133 semantic prompts
+    pub scrollbar_prompt: Option<RgbaColor>,
+    /// The color of the scrollbar markers that indicate lines marked
+    /// via the iTerm2 `SetMark` escape sequence
+    pub scrollbar_mark: Option<RgbaColor>,
+    /// The color of the scrollbar markers that indicate lines matching
+    /// one of the `scrollbar_error_patterns`
+    pub scrollbar_error: Option<RgbaColor>,
     /// The color of the split line between panes
     pub split: Option<RgbaColor>,
     /// The color of the visual bell. If unspecified, the foreground
@@ -214,6 +226,10 @@ impl Palette {
                 map
             },
             scrollbar_thumb: overlay!(scrollbar_thumb),
+            scrollbar_search_match: overlay!(scrollbar_search_match),
+            scrollbar_prompt: overlay!(scrollbar_prompt),
+            scrollbar_mark: overlay!(scrollbar_mark),
+            scrollbar_error: overlay!(scrollbar_error),
             split: overlay!(split),
             visual_bell: overlay!(visual_bell),
             compose_cursor: overlay!(compose_cursor),
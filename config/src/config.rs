@@ -3,11 +3,13 @@ use crate::bell::{AudibleBell, EasingFunction, VisualBell};
 use crate::color::{
     ColorSchemeFile, HsbTransform, Palette, SrgbaTuple, TabBarStyle, WindowFrameConfig,
 };
+use crate::customglyph::{validate_custom_glyphs, CustomGlyphPoly};
 use crate::daemon::DaemonOptions;
 use crate::exec_domain::ExecDomain;
 use crate::font::{
     AllowSquareGlyphOverflow, DisplayPixelGeometry, FontLocatorSelection, FontRasterizerSelection,
-    FontShaperSelection, FreeTypeLoadFlags, FreeTypeLoadTarget, StyleRule, TextStyle,
+    FontShaperSelection, FreeTypeLcdFilter, FreeTypeLoadFlags, FreeTypeLoadTarget, StyleRule,
+    TextContrast, TextGamma, TextStyle,
 };
 use crate::frontend::FrontEndSelection;
 use crate::keyassignment::{
@@ -280,6 +282,12 @@ pub struct Config {
     #[dynamic(default)]
     pub font_shaper: FontShaperSelection,
 
+    /// When true, adjacent runs of text that differ only in their
+    /// foreground color are shaped together, so that ligatures can
+    /// form across color changes.
+    #[dynamic(default)]
+    pub shape_across_color_changes: bool,
+
     #[dynamic(default)]
     pub display_pixel_geometry: DisplayPixelGeometry,
     #[dynamic(default)]
@@ -288,6 +296,19 @@ pub struct Config {
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
     #[dynamic(default)]
     pub freetype_load_flags: Option<FreeTypeLoadFlags>,
+    #[dynamic(default)]
+    pub freetype_lcd_filter: FreeTypeLcdFilter,
+
+    /// When true, FreeType will embolden the stems of glyphs
+    /// rendered by its CFF, Type 1 and auto-hinting engines
+    /// at small sizes to improve their legibility.
+    #[dynamic(default)]
+    pub freetype_stem_darkening: bool,
+
+    #[dynamic(default)]
+    pub text_gamma: TextGamma,
+    #[dynamic(default)]
+    pub text_contrast: TextContrast,
 
     /// Selects the freetype interpret version to use.
     /// Likely values are 35, 38 and 40 which have different
@@ -368,6 +389,11 @@ pub struct Config {
     #[dynamic(default)]
     pub ssh_backend: SshBackend,
 
+    /// When true, ssh domains and other ssh connections to the same
+    /// user, host and port share a single authenticated session
+    #[dynamic(default = "default_true")]
+    pub ssh_multiplexing: bool,
+
     /// When running in server mode, defines configuration for
     /// each of the endpoints that we'll listen for connections
     #[dynamic(default)]
@@ -395,6 +421,9 @@ pub struct Config {
     #[dynamic(default = "default_true")]
     pub mux_enable_ssh_agent: bool,
 
+    #[dynamic(default)]
+    pub mux_builtin_ssh_agent: bool,
+
     #[dynamic(default)]
     pub default_ssh_auth_sock: Option<String>,
 
@@ -512,6 +541,17 @@ pub struct Config {
     #[dynamic(try_from = "crate::units::PixelUnit", default = "default_half_cell")]
     pub min_scroll_bar_height: Dimension,
 
+    /// When the scroll bar is enabled, draw markers on its track
+    /// at the positions of search matches, semantic prompts, marked
+    /// lines and lines matching `scroll_bar_error_patterns`.
+    #[dynamic(default = "default_true")]
+    pub show_scroll_bar_markers: bool,
+
+    /// A list of regular expressions; lines in the scrollback that
+    /// match any of these are shown as error markers on the scroll bar.
+    #[dynamic(default)]
+    pub scroll_bar_error_patterns: Vec<String>,
+
     /// If false, do not try to use a Wayland protocol connection
     /// when starting the gui frontend, and instead use X11.
     /// This option is only considered on X11/Wayland systems and
@@ -534,6 +574,12 @@ pub struct Config {
     #[dynamic(default = "default_true")]
     pub anti_alias_custom_block_glyphs: bool,
 
+    /// Additional glyphs to be drawn by wezterm rather than
+    /// by a font, keyed by the character they replace.
+    /// Only used when custom_block_glyphs is enabled.
+    #[dynamic(default, validate = "validate_custom_glyphs")]
+    pub custom_glyphs: HashMap<char, Vec<CustomGlyphPoly>>,
+
     /// Controls the amount of padding to use around the terminal cell area
     #[dynamic(default)]
     pub window_padding: WindowPadding,
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use wezterm_dynamic::{FromDynamic, ToDynamic};
+
+/// A point within the cell, expressed as fractions of the cell
+/// width and height respectively.  `[0, 0]` is the top left
+/// corner and `[1, 1]` is the bottom right corner.
+pub type CustomGlyphPoint = [f32; 2];
+
+/// A path command used to describe a custom glyph.
+/// These correspond to the primitives that wezterm uses to
+/// draw its built-in box drawing and powerline glyphs.
+#[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
+pub enum CustomGlyphCommand {
+    MoveTo(CustomGlyphPoint),
+    LineTo(CustomGlyphPoint),
+    QuadTo {
+        control: CustomGlyphPoint,
+        to: CustomGlyphPoint,
+    },
+    /// An ellipse; the radiuses are fractions of the
+    /// cell width and height respectively
+    Oval {
+        center: CustomGlyphPoint,
+        radiuses: CustomGlyphPoint,
+    },
+    /// A circle; the radius is a fraction of the smaller
+    /// of the cell width and height
+    Circle {
+        center: CustomGlyphPoint,
+        radius: f32,
+    },
+    Close,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub enum CustomGlyphIntensity {
+    /// 100%
+    #[default]
+    Full,
+    /// 75%
+    Dark,
+    /// 50%
+    Medium,
+    /// 25%
+    Light,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub enum CustomGlyphStyle {
+    /// Fill the path
+    #[default]
+    Fill,
+    /// Stroke the path with the thickness of underlines
+    Outline,
+    /// Stroke the path with a thin line
+    OutlineThin,
+    /// Stroke the path with a heavy line
+    OutlineHeavy,
+}
+
+/// One of the polygons that make up a custom glyph
+#[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
+pub struct CustomGlyphPoly {
+    pub path: Vec<CustomGlyphCommand>,
+    #[dynamic(default)]
+    pub intensity: CustomGlyphIntensity,
+    #[dynamic(default)]
+    pub style: CustomGlyphStyle,
+}
+
+pub(crate) fn validate_custom_glyphs(
+    glyphs: &HashMap<char, Vec<CustomGlyphPoly>>,
+) -> Result<(), String> {
+    fn check(c: char, value: f32) -> Result<(), String> {
+        if (0.0..=1.0).contains(&value) {
+            Ok(())
+        } else {
+            Err(format!(
+                "custom_glyphs: coordinate {value} for {c:?} (U+{:04X}) \
+                 must be in the range 0.0 to 1.0",
+                c as u32
+            ))
+        }
+    }
+
+    for (&c, polys) in glyphs {
+        for poly in polys {
+            for command in &poly.path {
+                match command {
+                    CustomGlyphCommand::MoveTo(p) | CustomGlyphCommand::LineTo(p) => {
+                        p.iter().try_for_each(|&v| check(c, v))?;
+                    }
+                    CustomGlyphCommand::QuadTo { control, to } => {
+                        control
+                            .iter()
+                            .chain(to.iter())
+                            .try_for_each(|&v| check(c, v))?;
+                    }
+                    CustomGlyphCommand::Oval { center, radiuses } => {
+                        center
+                            .iter()
+                            .chain(radiuses.iter())
+                            .try_for_each(|&v| check(c, v))?;
+                    }
+                    CustomGlyphCommand::Circle { center, radius } => {
+                        center
+                            .iter()
+                            .chain(std::iter::once(radius))
+                            .try_for_each(|&v| check(c, v))?;
+                    }
+                    CustomGlyphCommand::Close => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn glyph(command: CustomGlyphCommand) -> HashMap<char, Vec<CustomGlyphPoly>> {
+        let mut glyphs = HashMap::new();
+        glyphs.insert(
+            'x',
+            vec![CustomGlyphPoly {
+                path: vec![CustomGlyphCommand::MoveTo([0., 0.]), command],
+                intensity: CustomGlyphIntensity::Full,
+                style: CustomGlyphStyle::Fill,
+            }],
+        );
+        glyphs
+    }
+
+    #[test]
+    fn validate() {
+        for (command, valid) in [
+            (CustomGlyphCommand::LineTo([1., 1.]), true),
+            (CustomGlyphCommand::LineTo([1.5, 0.]), false),
+            (CustomGlyphCommand::LineTo([0., -0.1]), false),
+            (CustomGlyphCommand::LineTo([f32::NAN, 0.]), false),
+            (
+                CustomGlyphCommand::QuadTo {
+                    control: [0.5, 0.5],
+                    to: [1., 0.],
+                },
+                true,
+            ),
+            (
+                CustomGlyphCommand::QuadTo {
+                    control: [0.5, 2.],
+                    to: [1., 0.],
+                },
+                false,
+            ),
+            (
+                CustomGlyphCommand::Oval {
+                    center: [0.5, 0.5],
+                    radiuses: [0.5, 0.25],
+                },
+                true,
+            ),
+            (
+                CustomGlyphCommand::Oval {
+                    center: [0.5, 0.5],
+                    radiuses: [0.5, f32::INFINITY],
+                },
+                false,
+            ),
+            (
+                CustomGlyphCommand::Circle {
+                    center: [0.5, 0.5],
+                    radius: 0.5,
+                },
+                true,
+            ),
+            (
+                CustomGlyphCommand::Circle {
+                    center: [0.5, 0.5],
+                    radius: -1.,
+                },
+                false,
+            ),
+            (CustomGlyphCommand::Close, true),
+        ] {
+            let result = validate_custom_glyphs(&glyph(command.clone()));
+            assert_eq!(result.is_ok(), valid, "{:?}: {:?}", command, result);
+        }
+    }
+
+    #[test]
+    fn validation_error_names_the_glyph() {
+        let err =
+            validate_custom_glyphs(&glyph(CustomGlyphCommand::LineTo([1.5, 0.]))).unwrap_err();
+        assert_eq!(
+            err,
+            "custom_glyphs: coordinate 1.5 for 'x' (U+0078) must be in the range 0.0 to 1.0"
+        );
+    }
+}
@@ -220,9 +220,93 @@ impl FontWeight {
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
 pub enum DisplayPixelGeometry {
+    /// Horizontal subpixels ordered red, green, blue from left to right
     #[default]
     RGB,
+    /// Horizontal subpixels ordered blue, green, red from left to right
     BGR,
+    /// Vertical subpixels ordered red, green, blue from top to bottom
+    VRGB,
+    /// Vertical subpixels ordered blue, green, red from top to bottom
+    VBGR,
+}
+
+impl DisplayPixelGeometry {
+    pub fn is_vertical(self) -> bool {
+        matches!(self, Self::VRGB | Self::VBGR)
+    }
+
+    /// Returns true if the subpixels are ordered blue first
+    pub fn is_bgr(self) -> bool {
+        matches!(self, Self::BGR | Self::VBGR)
+    }
+}
+
+/// Adjusts the gamma applied to the coverage of glyphs.
+/// Values larger than 1.0 make text appear heavier, while smaller
+/// values make it appear thinner.
+#[derive(Debug, Clone, Copy, PartialEq, FromDynamic, ToDynamic)]
+pub struct TextGamma {
+    /// Used for light text, which is assumed to be on a dark background
+    #[dynamic(default = "default_one_point_oh", validate = "validate_text_gamma")]
+    pub light_on_dark: f32,
+    /// Used for dark text, which is assumed to be on a light background
+    #[dynamic(default = "default_one_point_oh", validate = "validate_text_gamma")]
+    pub dark_on_light: f32,
+}
+
+impl Default for TextGamma {
+    fn default() -> Self {
+        Self {
+            light_on_dark: 1.0,
+            dark_on_light: 1.0,
+        }
+    }
+}
+
+fn validate_text_gamma(value: &f32) -> Result<(), String> {
+    if *value > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("text_gamma values must be > 0.0, got {value}"))
+    }
+}
+
+/// Adjusts the contrast of the edges of glyphs.
+/// 0.0 leaves the glyphs unchanged, while larger values make
+/// the edges crisper.
+#[derive(Debug, Default, Clone, Copy, PartialEq, FromDynamic, ToDynamic)]
+pub struct TextContrast {
+    /// Used for light text, which is assumed to be on a dark background
+    #[dynamic(default, validate = "validate_text_contrast")]
+    pub light_on_dark: f32,
+    /// Used for dark text, which is assumed to be on a light background
+    #[dynamic(default, validate = "validate_text_contrast")]
+    pub dark_on_light: f32,
+}
+
+fn validate_text_contrast(value: &f32) -> Result<(), String> {
+    if *value >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!("text_contrast values must be >= 0.0, got {value}"))
+    }
+}
+
+/// Selects the filter that FreeType applies to LCD glyph bitmaps
+/// to reduce color fringes.
+/// See <https://freetype.org/freetype2/docs/reference/ft2-lcd_rendering.html>
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
+pub enum FreeTypeLcdFilter {
+    /// No filtering; this produces strong color fringes
+    None,
+    /// A FIR filter tuned for typical LCD displays
+    #[default]
+    Default,
+    /// A lighter filter that is sharper, but with more fringes
+    Light,
+    /// The filter used by older versions of FreeType
+    Legacy,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
@@ -379,6 +463,10 @@ pub struct FontAttributes {
     pub scale: Option<NotNan<f64>>,
     #[dynamic(default)]
     pub assume_emoji_presentation: Option<bool>,
+    /// Coordinates for the variation axes of a variable font,
+    /// keyed by axis tag, eg: `{wght=450}`
+    #[dynamic(default)]
+    pub variations: Option<BTreeMap<String, NotNan<f64>>>,
 }
 impl_lua_conversion_dynamic!(FontAttributes);
 
@@ -407,6 +495,7 @@ impl FontAttributes {
             freetype_load_flags: None,
             scale: None,
             assume_emoji_presentation: None,
+            variations: None,
         }
     }
 
@@ -424,6 +513,7 @@ impl FontAttributes {
             freetype_load_flags: None,
             scale: None,
             assume_emoji_presentation: None,
+            variations: None,
         }
     }
 }
@@ -443,6 +533,7 @@ impl Default for FontAttributes {
             freetype_load_flags: None,
             scale: None,
             assume_emoji_presentation: None,
+            variations: None,
         }
     }
 }
@@ -645,10 +736,36 @@ pub struct StyleRule {
     /// a value that matches this rule.
     pub invisible: Option<bool>,
 
+    /// If present, this rule applies to the codepoints that fall
+    /// within any of these ranges, rather than to the cell attributes
+    /// above, which are ignored.
+    pub unicode_ranges: Option<Vec<UnicodeRange>>,
+    /// If present, this rule applies to the codepoints that belong
+    /// to any of these scripts, expressed as ISO 15924 codes such
+    /// as "Hani" or "Arab", rather than to the cell attributes
+    /// above, which are ignored.
+    pub scripts: Option<Vec<String>>,
+
     /// When this rule matches, `font` specifies the styling to be used.
     pub font: TextStyle,
 }
 
+impl StyleRule {
+    /// Returns true if this rule selects a font by codepoint
+    /// rather than by cell attributes.  Such rules are resolved
+    /// while shaping rather than when matching the cell style.
+    pub fn is_range_rule(&self) -> bool {
+        self.unicode_ranges.is_some() || self.scripts.is_some()
+    }
+}
+
+/// An inclusive range of unicode codepoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub struct UnicodeRange {
+    pub first: u32,
+    pub last: u32,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
 pub enum AllowSquareGlyphOverflow {
     Never,
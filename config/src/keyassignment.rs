@@ -458,6 +458,24 @@ pub struct QuickSelectArguments {
     /// How many lines before and how many lines after the viewport to
     /// search to produce the quickselect results
     pub scope_lines: Option<usize>,
+    /// Additional patterns, each with its own action to perform
+    /// in place of `action` when a match for that pattern is selected
+    #[dynamic(default)]
+    pub pattern_actions: Vec<QuickSelectPatternAction>,
+    /// Allow selecting multiple matches; typing an uppercase label
+    /// toggles the selection of that match rather than pasting it
+    #[dynamic(default)]
+    pub multi_select: bool,
+    /// The separator used to join the text of multiple matches when
+    /// copying or pasting them.  Defaults to a single space.
+    #[dynamic(default)]
+    pub multi_select_separator: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
+pub struct QuickSelectPatternAction {
+    pub pattern: String,
+    pub action: Box<KeyAssignment>,
 }
 
 #[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
@@ -573,6 +591,7 @@ pub enum KeyAssignment {
     ScrollToBottom,
     ShowTabNavigator,
     ShowDebugOverlay,
+    ShowSftpBrowser,
     HideApplication,
     QuitApplication,
     SpawnCommandInNewTab(SpawnCommand),
@@ -716,10 +735,98 @@ pub enum CopyModeAssignment {
     MoveForwardSemanticZone,
     MoveBackwardZoneOfType(SemanticType),
     MoveForwardZoneOfType(SemanticType),
-    JumpForward { prev_char: bool },
-    JumpBackward { prev_char: bool },
+    JumpForward {
+        prev_char: bool,
+    },
+    JumpBackward {
+        prev_char: bool,
+    },
     JumpAgain,
     JumpReverse,
+    /// Accumulate a digit into the count that is applied to the
+    /// next motion.  A `0` with no count pending moves to the start
+    /// of the line, as it does in vim.
+    AppendCountDigit(u8),
+    /// Select the text object named by the next key press, excluding
+    /// surrounding whitespace and delimiters (vim's `i`)
+    SelectInnerTextObject,
+    /// Select the text object named by the next key press, including
+    /// surrounding whitespace and delimiters (vim's `a`)
+    SelectAroundTextObject,
+    SelectTextObject(CopyModeTextObjectSelection),
+    /// Record the cursor position in the mark named by the next key press
+    SetMark,
+    /// Move to the mark named by the next key press.  When `exact` is
+    /// false, move to the start of the content of the marked line.
+    JumpToMark {
+        exact: bool,
+    },
+    /// Use the register named by the next key press for the next
+    /// CopyTo or PasteFromRegister action
+    SelectRegister,
+    /// Paste the content of the selected register (or the unnamed
+    /// register) into the pane and close copy mode
+    PasteFromRegister,
+    /// Restrict the search to the specified portion of the scrollback
+    SetSearchScope(CopyModeSearchScope),
+    CycleSearchScope,
+    /// Toggle between showing the whole scrollback and showing only
+    /// the lines that contain a match
+    ToggleFilterView,
+}
+
+/// Which portion of the scrollback is considered by the search overlay.
+/// The scopes other than `All` are based on the semantic zones that
+/// are defined by OSC 133 shell integration.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub enum CopyModeSearchScope {
+    #[default]
+    All,
+    /// The output of the most recently completed command
+    LastCommandOutput,
+    /// Prompts and the input typed at them
+    Input,
+    /// The semantic zone that contains the copy mode cursor
+    CurrentZone,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub struct CopyModeTextObjectSelection {
+    pub object: CopyModeTextObject,
+    pub around: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub enum CopyModeTextObject {
+    Word,
+    BigWord,
+    Paragraph,
+    DoubleQuote,
+    SingleQuote,
+    BackTick,
+    Parentheses,
+    Brackets,
+    Braces,
+    AngleBrackets,
+}
+
+impl CopyModeTextObject {
+    /// Map the key used to name a text object in vim to the object
+    pub fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            'w' => Self::Word,
+            'W' => Self::BigWord,
+            'p' => Self::Paragraph,
+            '"' => Self::DoubleQuote,
+            '\'' => Self::SingleQuote,
+            '`' => Self::BackTick,
+            '(' | ')' | 'b' => Self::Parentheses,
+            '[' | ']' => Self::Brackets,
+            '{' | '}' | 'B' => Self::Braces,
+            '<' | '>' => Self::AngleBrackets,
+            _ => return None,
+        })
+    }
 }
 
 pub type KeyTable = HashMap<(KeyCode, Modifiers), KeyTableEntry>;
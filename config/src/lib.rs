@@ -25,6 +25,7 @@ mod bell;
 mod cell;
 mod color;
 mod config;
+mod customglyph;
 mod daemon;
 mod exec_domain;
 mod font;
@@ -49,6 +50,7 @@ pub use background::*;
 pub use bell::*;
 pub use cell::*;
 pub use color::*;
+pub use customglyph::*;
 pub use daemon::*;
 pub use exec_domain::*;
 pub use font::*;
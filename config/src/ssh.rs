@@ -47,6 +47,67 @@ impl Default for Shell {
     }
 }
 
+/// Controls what happens to the panes of an ssh domain when
+/// the connection to the remote host is lost
+#[derive(Debug, Clone, FromDynamic, ToDynamic)]
+pub struct SshReconnect {
+    /// If true, reconnect and start a new shell in the same pane,
+    /// keeping its scrollback.  For domains that use wezterm
+    /// multiplexing, reconnect and re-attach to the remote mux.
+    #[dynamic(default)]
+    pub enabled: bool,
+
+    /// How many consecutive attempts to make before giving up
+    #[dynamic(default = "default_reconnect_max_attempts")]
+    pub max_attempts: usize,
+
+    /// How long to wait before the first attempt.  The delay doubles
+    /// after each failed attempt, up to `max_delay`.
+    #[dynamic(default = "default_reconnect_initial_delay")]
+    pub initial_delay: Duration,
+
+    #[dynamic(default = "default_reconnect_max_delay")]
+    pub max_delay: Duration,
+}
+
+impl Default for SshReconnect {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_attempts: default_reconnect_max_attempts(),
+            initial_delay: default_reconnect_initial_delay(),
+            max_delay: default_reconnect_max_delay(),
+        }
+    }
+}
+
+impl SshReconnect {
+    /// Returns the delay to use before the specified attempt,
+    /// where the first attempt is numbered 1
+    pub fn delay_for_attempt(&self, attempt: usize) -> Duration {
+        let mut delay = self.initial_delay.min(self.max_delay);
+        for _ in 1..attempt {
+            if delay >= self.max_delay {
+                break;
+            }
+            delay = delay.saturating_mul(2).min(self.max_delay);
+        }
+        delay
+    }
+}
+
+fn default_reconnect_max_attempts() -> usize {
+    10
+}
+
+fn default_reconnect_initial_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_reconnect_max_delay() -> Duration {
+    Duration::from_secs(30)
+}
+
 #[derive(Default, Debug, Clone, FromDynamic, ToDynamic)]
 pub struct SshDomain {
     /// The name of this specific domain.  Must be unique amongst
@@ -104,6 +165,10 @@ pub struct SshDomain {
 
     #[dynamic(default)]
     pub assume_shell: Shell,
+
+    /// What to do when the connection to the remote host is lost
+    #[dynamic(default)]
+    pub reconnect: SshReconnect,
 }
 impl_lua_conversion_dynamic!(SshDomain);
 
@@ -183,3 +248,46 @@ impl FromStr for SshParameters {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy(initial: u64, max: u64) -> SshReconnect {
+        SshReconnect {
+            enabled: true,
+            max_attempts: 10,
+            initial_delay: Duration::from_secs(initial),
+            max_delay: Duration::from_secs(max),
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max() {
+        let policy = policy(1, 30);
+        let delays: Vec<u64> = (1..=7)
+            .map(|attempt| policy.delay_for_attempt(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+    }
+
+    #[test]
+    fn delay_is_clamped() {
+        // An initial delay larger than the max is clamped
+        assert_eq!(policy(60, 30).delay_for_attempt(1), Duration::from_secs(30));
+
+        // Attempt 0 is treated like the first attempt
+        assert_eq!(policy(1, 30).delay_for_attempt(0), Duration::from_secs(1));
+
+        // Neither large attempt numbers nor large delays overflow
+        assert_eq!(
+            policy(1, 30).delay_for_attempt(usize::MAX),
+            Duration::from_secs(30)
+        );
+        let unbounded = SshReconnect {
+            max_delay: Duration::MAX,
+            ..policy(1, 0)
+        };
+        assert_eq!(unbounded.delay_for_attempt(200), Duration::MAX);
+    }
+}
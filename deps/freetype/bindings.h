@@ -11,3 +11,4 @@
 #include <freetype/ftsnames.h>
 #include <freetype/otsvg.h>
 #include <freetype/ttnameid.h>
+#include <freetype/ftbdf.h>
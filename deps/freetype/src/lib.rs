@@ -3003,3 +3003,41 @@ pub struct FT_SVG_DocumentRec_ {
 }
 pub type FT_SVG_DocumentRec = FT_SVG_DocumentRec_;
 pub type FT_SVG_Document = *mut FT_SVG_DocumentRec_;
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum BDF_PropertyType_ {
+    BDF_PROPERTY_TYPE_NONE = 0,
+    BDF_PROPERTY_TYPE_ATOM = 1,
+    BDF_PROPERTY_TYPE_INTEGER = 2,
+    BDF_PROPERTY_TYPE_CARDINAL = 3,
+}
+pub use self::BDF_PropertyType_ as BDF_PropertyType;
+pub type BDF_Property = *mut BDF_PropertyRec_;
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct BDF_PropertyRec_ {
+    pub type_: BDF_PropertyType,
+    pub u: BDF_PropertyRec___bindgen_ty_1,
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union BDF_PropertyRec___bindgen_ty_1 {
+    pub atom: *const ::std::os::raw::c_char,
+    pub integer: FT_Int32,
+    pub cardinal: FT_UInt32,
+}
+pub type BDF_PropertyRec = BDF_PropertyRec_;
+extern "C" {
+    pub fn FT_Get_BDF_Charset_ID(
+        face: FT_Face,
+        acharset_encoding: *mut *const ::std::os::raw::c_char,
+        acharset_registry: *mut *const ::std::os::raw::c_char,
+    ) -> FT_Error;
+}
+extern "C" {
+    pub fn FT_Get_BDF_Property(
+        face: FT_Face,
+        prop_name: *const ::std::os::raw::c_char,
+        aproperty: *mut BDF_PropertyRec,
+    ) -> FT_Error;
+}
@@ -1,7 +1,10 @@
 use super::*;
+use luahelper::to_lua;
 use mlua::UserDataRef;
 use mux::domain::{Domain, DomainId, DomainState};
+use mux::ssh::RemoteSshDomain;
 use std::sync::Arc;
+use wezterm_ssh::{ForwardInfo, ForwardSpec};
 
 #[derive(Clone, Copy, Debug)]
 pub struct MuxDomain(pub DomainId);
@@ -13,6 +16,32 @@ impl MuxDomain {
     }
 }
 
+#[derive(Debug, ToDynamic)]
+struct SshForward {
+    id: usize,
+    spec: String,
+    bound_port: u16,
+}
+
+impl From<ForwardInfo> for SshForward {
+    fn from(info: ForwardInfo) -> Self {
+        Self {
+            id: info.id,
+            spec: info.spec.to_string(),
+            bound_port: info.bound_port,
+        }
+    }
+}
+
+fn ssh_domain(domain: &Arc<dyn Domain>) -> mlua::Result<&RemoteSshDomain> {
+    domain.downcast_ref::<RemoteSshDomain>().ok_or_else(|| {
+        mlua::Error::external(format!(
+            "domain {} is not an ssh domain",
+            domain.domain_name()
+        ))
+    })
+}
+
 impl UserData for MuxDomain {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_meta_method(mlua::MetaMethod::ToString, |_, this, _: ()| {
@@ -83,5 +112,41 @@ impl UserData for MuxDomain {
                 .any(|p| p.domain_id() == domain.domain_id());
             Ok(have_panes_in_domain)
         });
+
+        methods.add_async_method("ssh_forward", |lua, this, spec: String| async move {
+            let spec: ForwardSpec = spec.parse().map_err(mlua::Error::external)?;
+            let mux = get_mux()?;
+            let domain = this.resolve(&mux)?;
+            let info = ssh_domain(&domain)?
+                .add_forward(spec)
+                .await
+                .map_err(|err| mlua::Error::external(format!("{err:#}")))?;
+            to_lua(lua, SshForward::from(info))
+        });
+
+        methods.add_async_method("cancel_ssh_forward", |_, this, id: usize| async move {
+            let mux = get_mux()?;
+            let domain = this.resolve(&mux)?;
+            ssh_domain(&domain)?
+                .cancel_forward(id)
+                .await
+                .map_err(|err| mlua::Error::external(format!("{err:#}")))
+        });
+
+        methods.add_async_method("ssh_forwards", |lua, this, _: ()| async move {
+            let mux = get_mux()?;
+            let domain = this.resolve(&mux)?;
+            let forwards = ssh_domain(&domain)?
+                .list_forwards()
+                .await
+                .map_err(|err| mlua::Error::external(format!("{err:#}")))?;
+            to_lua(
+                lua,
+                forwards
+                    .into_iter()
+                    .map(SshForward::from)
+                    .collect::<Vec<_>>(),
+            )
+        });
     }
 }
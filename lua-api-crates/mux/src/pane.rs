@@ -249,6 +249,19 @@ impl UserData for MuxPane {
             Ok(text)
         });
 
+        // Renders the visible portion of the pane and saves it
+        // as a PNG image at the specified path
+        methods.add_async_method("screenshot", |_, this, path: String| async move {
+            let mux = get_mux()?;
+            let pane = this.resolve(&mux)?;
+            let data = mux::screenshot::screenshot_pane(pane)
+                .await
+                .map_err(|e| mlua::Error::external(format!("{:#}", e)))?;
+            std::fs::write(&path, data)
+                .map_err(|e| mlua::Error::external(format!("writing {path}: {e:#}")))?;
+            Ok(())
+        });
+
         methods.add_method(
             "get_logical_lines_as_text",
             |_, this, nlines: Option<usize>| {
@@ -1,6 +1,16 @@
-use config::lua::get_or_create_module;
+use bstr::BString;
 use config::lua::mlua::{self, Lua, Variadic};
+use config::lua::{get_or_create_module, get_or_create_sub_module};
+use config::{SshDomain, SshParameters};
+use filedescriptor::{poll, pollfd, AsRawSocketDescriptor, POLLIN, POLLOUT};
+use luahelper::impl_lua_conversion_dynamic;
+use mux::ssh::{connect_without_ui, ssh_domain_to_ssh_config};
+use smol::io::{AsyncReadExt, AsyncWriteExt};
 use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+use wezterm_dynamic::{FromDynamic, ToDynamic};
+use wezterm_ssh::{ChildKiller, ConfigMap, ExecResult, FileDescriptor, Session, Utf8PathBuf};
 
 pub fn register(lua: &Lua) -> anyhow::Result<()> {
     let wezterm_mod = get_or_create_module(lua, "wezterm")?;
@@ -12,6 +22,11 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         "default_ssh_domains",
         lua.create_function(|_, ()| Ok(config::SshDomain::default_domains()))?,
     )?;
+
+    let ssh_mod = get_or_create_sub_module(lua, "ssh")?;
+    ssh_mod.set("exec", lua.create_async_function(exec)?)?;
+    ssh_mod.set("read_file", lua.create_async_function(read_file)?)?;
+    ssh_mod.set("write_file", lua.create_async_function(write_file)?)?;
     Ok(())
 }
 
@@ -41,3 +56,219 @@ fn enumerate_ssh_hosts<'lua>(
 
     Ok(map)
 }
+
+/// How long `exec` waits for a command to complete if no
+/// `timeout_seconds` is given
+const DEFAULT_EXEC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The most output that `exec` captures from each of stdout and stderr
+const MAX_OUTPUT_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, FromDynamic, ToDynamic)]
+struct ExecParams {
+    host: String,
+    command: String,
+    stdin: Option<String>,
+    set_environment_variables: Option<HashMap<String, String>>,
+    timeout_seconds: Option<f64>,
+}
+impl_lua_conversion_dynamic!(ExecParams);
+
+/// Resolves `host` to an ssh configuration.  `host` is either the name
+/// of an ssh domain, such as `SSH:myhost`, or `[user@]host[:port]`,
+/// which is looked up in the ssh config files.
+fn ssh_config_for_host(host: &str) -> anyhow::Result<ConfigMap> {
+    if let Some(dom) = config::configuration()
+        .ssh_domains()
+        .into_iter()
+        .find(|dom| dom.name == host)
+    {
+        return ssh_domain_to_ssh_config(&dom);
+    }
+    let params: SshParameters = host.parse()?;
+    ssh_domain_to_ssh_config(&SshDomain {
+        name: host.to_string(),
+        remote_address: params.host_and_port,
+        username: params.username,
+        ..SshDomain::default()
+    })
+}
+
+/// Returns an authenticated session to `host`, reusing one that
+/// was established by an ssh domain or `wezterm ssh` if possible
+async fn session_for_host(host: &str) -> mlua::Result<Session> {
+    let ssh_config =
+        ssh_config_for_host(host).map_err(|err| mlua::Error::external(format!("{err:#}")))?;
+    connect_without_ui(&ssh_config)
+        .await
+        .map_err(|err| mlua::Error::external(format!("{err:#}")))
+}
+
+async fn exec<'lua>(lua: &'lua Lua, params: ExecParams) -> mlua::Result<mlua::Table<'lua>> {
+    let timeout = match params.timeout_seconds {
+        Some(seconds) => Duration::try_from_secs_f64(seconds).map_err(|err| {
+            mlua::Error::external(format!("invalid timeout_seconds {seconds}: {err}"))
+        })?,
+        None => DEFAULT_EXEC_TIMEOUT,
+    };
+    let deadline = Instant::now() + timeout;
+
+    // Keeps the session alive until the command has completed
+    let session = session_for_host(&params.host).await?;
+    let ExecResult {
+        stdin,
+        stdout,
+        stderr,
+        mut child,
+    } = session
+        .exec(&params.command, params.set_environment_variables)
+        .await
+        .map_err(|err| mlua::Error::external(format!("{err:#}")))?;
+
+    let input = params.stdin.unwrap_or_default();
+    // Dropping stdin once it has been written sends EOF to the command.
+    // The command may exit without consuming all of its input, so a
+    // failure to write it is not an error, but running out of time is.
+    let write_stdin = smol::unblock(move || {
+        let written = write_before(stdin, input.as_bytes(), deadline);
+        match written {
+            Err(err) if err.kind() == ErrorKind::TimedOut => Err(err),
+            _ => Ok(()),
+        }
+    });
+    let read_stdout = smol::unblock(move || read_before(stdout, deadline));
+    let read_stderr = smol::unblock(move || read_before(stderr, deadline));
+    let outcome = async {
+        let ((written, stdout), stderr) =
+            smol::future::zip(smol::future::zip(write_stdin, read_stdout), read_stderr).await;
+        written?;
+        let (stdout, stderr) = (stdout?, stderr?);
+        let expire = async {
+            smol::Timer::at(deadline).await;
+            Err(ErrorKind::TimedOut.into())
+        };
+        let status = smol::future::or(child.async_wait(), expire).await?;
+        Ok::<_, std::io::Error>((status, stdout, stderr))
+    }
+    .await;
+
+    let (status, (stdout, stdout_truncated), (stderr, stderr_truncated)) = match outcome {
+        Ok(outcome) => outcome,
+        Err(err) if err.kind() == ErrorKind::TimedOut => {
+            // Hang up the channel so that the command doesn't linger
+            // on the remote host
+            if let Err(err) = child.kill() {
+                log::error!("failed to kill `{}`: {err:#}", params.command);
+            }
+            return Err(mlua::Error::external(format!(
+                "`{}` did not complete within {timeout:?}",
+                params.command
+            )));
+        }
+        Err(err) => return Err(mlua::Error::external(err)),
+    };
+
+    let result = lua.create_table()?;
+    result.set("stdout", BString::from(stdout))?;
+    result.set("stderr", BString::from(stderr))?;
+    result.set("exit_code", status.exit_code())?;
+    result.set("success", status.success())?;
+    result.set("truncated", stdout_truncated || stderr_truncated)?;
+    Ok(result)
+}
+
+/// Returns the time left until `deadline`, or a `TimedOut` error
+/// if it has passed
+fn remaining(deadline: Instant) -> std::io::Result<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(remaining) if !remaining.is_zero() => Ok(remaining),
+        _ => Err(ErrorKind::TimedOut.into()),
+    }
+}
+
+/// Waits for `fd` to become ready for `events`, giving up at `deadline`
+fn wait_for(fd: &FileDescriptor, events: i16, deadline: Instant) -> std::io::Result<()> {
+    let mut pfd = [pollfd {
+        fd: fd.as_socket_descriptor(),
+        events,
+        revents: 0,
+    }];
+    poll(&mut pfd, Some(remaining(deadline)?))
+        .map_err(|err| std::io::Error::new(ErrorKind::Other, err))?;
+    Ok(())
+}
+
+/// Writes all of `data` to `fd`, giving up at `deadline`
+fn write_before(mut fd: FileDescriptor, data: &[u8], deadline: Instant) -> std::io::Result<()> {
+    fd.set_non_blocking(true)
+        .map_err(|err| std::io::Error::new(ErrorKind::Other, err))?;
+    let mut data = data;
+    while !data.is_empty() {
+        match fd.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(err) if err.kind() == ErrorKind::WouldBlock => wait_for(&fd, POLLOUT, deadline)?,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Reads `fd` until EOF, giving up at `deadline`.  Only the first
+/// `MAX_OUTPUT_BYTES` are kept; the rest is read and discarded so that
+/// the command can run to completion.  Returns the output, and whether
+/// it was truncated.
+fn read_before(mut fd: FileDescriptor, deadline: Instant) -> std::io::Result<(Vec<u8>, bool)> {
+    fd.set_non_blocking(true)
+        .map_err(|err| std::io::Error::new(ErrorKind::Other, err))?;
+    let mut output = vec![];
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    loop {
+        match fd.read(&mut buf) {
+            Ok(0) => return Ok((output, truncated)),
+            Ok(n) => {
+                let keep = n.min(MAX_OUTPUT_BYTES - output.len());
+                output.extend_from_slice(&buf[..keep]);
+                truncated |= keep < n;
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => wait_for(&fd, POLLIN, deadline)?,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn read_file<'lua>(_: &'lua Lua, (host, path): (String, String)) -> mlua::Result<BString> {
+    let session = session_for_host(&host).await?;
+    let mut file = session
+        .sftp()
+        .open(Utf8PathBuf::from(&path))
+        .await
+        .map_err(|err| mlua::Error::external(format!("opening {path} for read: {err:#}")))?;
+    let mut contents = vec![];
+    file.read_to_end(&mut contents)
+        .await
+        .map_err(|err| mlua::Error::external(format!("reading {path}: {err:#}")))?;
+    Ok(contents.into())
+}
+
+async fn write_file<'lua>(
+    _: &'lua Lua,
+    (host, path, contents): (String, String, BString),
+) -> mlua::Result<()> {
+    let session = session_for_host(&host).await?;
+    let mut file = session
+        .sftp()
+        .create(Utf8PathBuf::from(&path))
+        .await
+        .map_err(|err| mlua::Error::external(format!("opening {path} for write: {err:#}")))?;
+    async {
+        file.write_all(&contents).await?;
+        file.flush().await?;
+        file.close().await
+    }
+    .await
+    .map_err(|err| mlua::Error::external(format!("writing {path}: {err:#}")))
+}
@@ -40,6 +40,8 @@ pub mod domain;
 pub mod localpane;
 pub mod pane;
 pub mod renderable;
+pub mod screenshot;
+pub mod sftp;
 pub mod ssh;
 pub mod ssh_agent;
 pub mod tab;
@@ -95,6 +97,24 @@ pub enum MuxNotification {
         old_workspace: String,
         new_workspace: String,
     },
+    /// The built-in ssh agent wants the user to approve the use
+    /// of a key; the response is passed to
+    /// ssh_agent::resolve_confirmation.
+    /// If `client_id` is set, this process has no gui of its own
+    /// and that gui client is asked to prompt the user instead.
+    SshAgentConfirmation {
+        id: ssh_agent::ConfirmationId,
+        message: String,
+        client_id: Option<Arc<ClientId>>,
+    },
+    /// A screenshot of the pane was requested, but this process
+    /// cannot render it; the gui `client_id` is asked to render it
+    /// and the result is passed to screenshot::resolve_screenshot
+    ScreenshotRequested {
+        id: screenshot::ScreenshotId,
+        pane_id: PaneId,
+        client_id: Arc<ClientId>,
+    },
 }
 
 static SUB_ID: AtomicUsize = AtomicUsize::new(0);
@@ -511,6 +531,18 @@ impl Mux {
         Some((domain, window, tab, pane_id))
     }
 
+    /// Returns the most recently active of the clients that have a
+    /// focused pane.  Only gui clients report focus, so this is the
+    /// gui that the user is interacting with.
+    pub fn most_recently_focused_client(&self) -> Option<Arc<ClientId>> {
+        self.clients
+            .read()
+            .values()
+            .filter(|info| info.focused_pane_id.is_some())
+            .max_by_key(|info| info.last_input)
+            .map(|info| Arc::clone(&info.client_id))
+    }
+
     pub fn record_focus_for_client(&self, client_id: &ClientId, pane_id: PaneId) {
         let mut prior = None;
         if let Some(info) = self.clients.write().get_mut(client_id) {
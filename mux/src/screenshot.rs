@@ -0,0 +1,173 @@
+//! Rendering a pane to an image requires the font and glyph machinery
+//! that lives in the gui.  The gui registers its renderer here so that
+//! the mux server and the lua bindings, which cannot depend upon the
+//! gui directly, are able to produce screenshots.
+//!
+//! A standalone mux server has no renderer of its own; it asks the
+//! gui client that the user is interacting with to render the pane
+//! on its behalf.  When no gui is attached, it falls back to the
+//! windowless renderer that it registered, which runs `wezterm-gui`
+//! to render the pane without a window.
+use crate::pane::{Pane, PaneId};
+use crate::{Mux, MuxNotification};
+use parking_lot::Mutex;
+use smol::channel::{bounded, Receiver, Sender};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Renders the visible portion of the pane and returns the
+/// PNG encoded image data
+pub type ScreenshotRenderer =
+    fn(Arc<dyn Pane>) -> Pin<Box<dyn Future<Output = anyhow::Result<Vec<u8>>>>>;
+
+/// Identifies a screenshot that was delegated to a gui client
+pub type ScreenshotId = usize;
+
+/// How long to wait for a gui client to render a delegated screenshot
+const CLIENT_RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+
+type PendingResult = Result<Vec<u8>, String>;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static::lazy_static! {
+    static ref RENDERER: Mutex<Option<ScreenshotRenderer>> = Mutex::new(None);
+    static ref WINDOWLESS_RENDERER: Mutex<Option<ScreenshotRenderer>> = Mutex::new(None);
+    static ref PENDING: Mutex<HashMap<ScreenshotId, Sender<PendingResult>>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn register_screenshot_renderer(renderer: ScreenshotRenderer) {
+    RENDERER.lock().replace(renderer);
+}
+
+/// Registers the renderer that is used when there is no gui to
+/// render the pane, neither in this process nor attached to it
+pub fn register_windowless_screenshot_renderer(renderer: ScreenshotRenderer) {
+    WINDOWLESS_RENDERER.lock().replace(renderer);
+}
+
+/// Render the visible portion of the pane to PNG encoded image data
+pub async fn screenshot_pane(pane: Arc<dyn Pane>) -> anyhow::Result<Vec<u8>> {
+    let renderer = *RENDERER.lock();
+    if let Some(renderer) = renderer {
+        return renderer(pane).await;
+    }
+
+    let windowless = *WINDOWLESS_RENDERER.lock();
+    let result = if Mux::get().most_recently_focused_client().is_some() {
+        request_screenshot_from_client(pane.pane_id()).await
+    } else {
+        Err(anyhow::anyhow!("no gui is attached to this mux server"))
+    };
+    match (result, windowless) {
+        (Ok(data), _) => Ok(data),
+        (Err(err), Some(windowless)) => {
+            log::debug!("rendering screenshot without a window: {:#}", err);
+            windowless(pane).await
+        }
+        (Err(err), None) => Err(err),
+    }
+}
+
+/// Render the pane using the renderer registered in this process,
+/// without delegating to another client.  This is used to service
+/// MuxNotification::ScreenshotRequested on behalf of a mux server.
+pub async fn render_screenshot_locally(pane: Arc<dyn Pane>) -> anyhow::Result<Vec<u8>> {
+    let renderer = *RENDERER.lock();
+    match renderer {
+        Some(renderer) => renderer(pane).await,
+        None => anyhow::bail!("this client is unable to render screenshots"),
+    }
+}
+
+/// Called when a gui client has finished rendering a screenshot
+/// that we asked it to produce via MuxNotification::ScreenshotRequested
+pub fn resolve_screenshot(id: ScreenshotId, result: PendingResult) {
+    if let Some(tx) = PENDING.lock().remove(&id) {
+        tx.try_send(result).ok();
+    }
+}
+
+fn register_pending() -> (ScreenshotId, Receiver<PendingResult>) {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = bounded(1);
+    PENDING.lock().insert(id, tx);
+    (id, rx)
+}
+
+async fn request_screenshot_from_client(pane_id: PaneId) -> anyhow::Result<Vec<u8>> {
+    let client_id = Mux::get().most_recently_focused_client().ok_or_else(|| {
+        anyhow::anyhow!(
+            "screenshots are rendered by the wezterm gui, \
+             but no gui is attached to this mux server"
+        )
+    })?;
+
+    let (id, rx) = register_pending();
+    Mux::notify_from_any_thread(MuxNotification::ScreenshotRequested {
+        id,
+        pane_id,
+        client_id,
+    });
+
+    let result = smol::future::or(
+        async {
+            match rx.recv().await {
+                Ok(result) => result.map_err(|reason| anyhow::anyhow!("{reason}")),
+                Err(err) => Err(anyhow::anyhow!("{err:#}")),
+            }
+        },
+        async {
+            smol::Timer::after(CLIENT_RENDER_TIMEOUT).await;
+            Err(anyhow::anyhow!(
+                "timed out waiting for the gui to render the screenshot"
+            ))
+        },
+    )
+    .await;
+
+    PENDING.lock().remove(&id);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_delivers_to_pending_request() {
+        let (id, rx) = register_pending();
+        let (other, other_rx) = register_pending();
+        assert_ne!(id, other);
+
+        resolve_screenshot(id, Ok(vec![1, 2, 3]));
+        assert_eq!(rx.try_recv().unwrap(), Ok(vec![1, 2, 3]));
+        assert!(other_rx.try_recv().is_err());
+
+        resolve_screenshot(other, Err("no such pane".to_string()));
+        assert_eq!(
+            other_rx.try_recv().unwrap(),
+            Err("no such pane".to_string())
+        );
+
+        assert!(!PENDING.lock().contains_key(&id));
+        assert!(!PENDING.lock().contains_key(&other));
+    }
+
+    #[test]
+    fn resolve_ignores_unknown_and_repeated_ids() {
+        let (id, rx) = register_pending();
+        resolve_screenshot(id, Ok(vec![1]));
+        // A duplicate answer for the same request is ignored
+        resolve_screenshot(id, Ok(vec![2]));
+        assert_eq!(rx.try_recv().unwrap(), Ok(vec![1]));
+        assert!(rx.try_recv().is_err());
+
+        resolve_screenshot(usize::MAX, Ok(vec![]));
+    }
+}
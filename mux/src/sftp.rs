@@ -0,0 +1,311 @@
+//! Transfers files over the sftp subsystem of the ssh session
+//! that hosts a pane.
+//!
+//! Transfers are performed in chunks so that they can be driven by
+//! the mux protocol on behalf of `wezterm cli sftp` as well as by
+//! the sftp browser overlay in the gui.
+use crate::client::ClientId;
+use crate::pane::{CachePolicy, Pane, PaneId};
+use crate::ssh::RemoteSshDomain;
+use crate::Mux;
+use anyhow::{anyhow, Context};
+use percent_encoding::percent_decode_str;
+use smol::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use url::Url;
+use wezterm_ssh::{File, Metadata, Sftp, Utf8PathBuf};
+
+pub type TransferId = usize;
+
+/// The largest chunk that is read in one go; requests for
+/// more than this are clamped to it
+pub const CHUNK_SIZE: usize = 128 * 1024;
+
+/// Identifies who opened a transfer; only they may use it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferOwner {
+    pub pane_id: PaneId,
+    pub client_id: ClientId,
+}
+
+struct TransferEntry {
+    owner: TransferOwner,
+    file: Arc<smol::lock::Mutex<File>>,
+}
+
+lazy_static::lazy_static! {
+    static ref TRANSFERS: Mutex<HashMap<TransferId, TransferEntry>> = Mutex::new(HashMap::new());
+}
+static NEXT_TRANSFER_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// A remote file that was opened by `open_read` or `open_write`
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub id: TransferId,
+    /// The resolved remote path
+    pub path: Utf8PathBuf,
+    /// The size of the file, if known, when opened for reading
+    pub size: Option<u64>,
+}
+
+/// Returns an sftp handle for the ssh domain that contains the pane
+pub fn sftp_for_pane(pane: &Arc<dyn Pane>) -> anyhow::Result<Sftp> {
+    let mux = Mux::get();
+    let domain = mux
+        .get_domain(pane.domain_id())
+        .ok_or_else(|| anyhow!("no domain for pane {}", pane.pane_id()))?;
+    let domain = domain
+        .downcast_ref::<RemoteSshDomain>()
+        .ok_or_else(|| anyhow!("pane {} is not in an ssh domain", pane.pane_id()))?;
+    domain.sftp()
+}
+
+/// Returns the remote working directory of the pane, as reported
+/// by the shell via OSC 7
+pub fn remote_cwd(pane: &Arc<dyn Pane>) -> Option<Utf8PathBuf> {
+    path_from_url(&pane.get_current_working_dir(CachePolicy::AllowStale)?)
+}
+
+fn path_from_url(url: &Url) -> Option<Utf8PathBuf> {
+    let path = percent_decode_str(url.path()).decode_utf8().ok()?;
+    if path.is_empty() {
+        None
+    } else {
+        Some(Utf8PathBuf::from(path.as_ref()))
+    }
+}
+
+/// Resolves a path relative to the remote working directory of the pane.
+/// If the working directory is not known, the path is returned unchanged
+/// and the sftp server will treat it as relative to the home directory.
+pub fn resolve_remote_path(pane: &Arc<dyn Pane>, path: &str) -> Utf8PathBuf {
+    resolve_path(remote_cwd(pane), path)
+}
+
+fn resolve_path(cwd: Option<Utf8PathBuf>, path: &str) -> Utf8PathBuf {
+    let path = Utf8PathBuf::from(path);
+    if path.is_absolute() {
+        return path;
+    }
+    match cwd {
+        Some(cwd) => cwd.join(path),
+        None => path,
+    }
+}
+
+/// Returns the entries of a remote directory, with directories
+/// listed ahead of files and each group sorted by name
+pub async fn list_dir(
+    sftp: &Sftp,
+    dir: &Utf8PathBuf,
+) -> anyhow::Result<Vec<(Utf8PathBuf, Metadata)>> {
+    let mut entries = sftp
+        .read_dir(dir.clone())
+        .await
+        .with_context(|| format!("reading directory {}", dir))?;
+    sort_entries(&mut entries);
+    Ok(entries)
+}
+
+fn sort_entries(entries: &mut [(Utf8PathBuf, Metadata)]) {
+    entries.sort_by(|(a_path, a_meta), (b_path, b_meta)| {
+        b_meta
+            .is_dir()
+            .cmp(&a_meta.is_dir())
+            .then_with(|| a_path.file_name().cmp(&b_path.file_name()))
+    });
+}
+
+fn register(file: File, owner: TransferOwner, path: Utf8PathBuf, size: Option<u64>) -> Transfer {
+    let id = NEXT_TRANSFER_ID.fetch_add(1, Ordering::Relaxed);
+    TRANSFERS.lock().unwrap().insert(
+        id,
+        TransferEntry {
+            owner,
+            file: Arc::new(smol::lock::Mutex::new(file)),
+        },
+    );
+    Transfer { id, path, size }
+}
+
+fn get_transfer(
+    id: TransferId,
+    owner: &TransferOwner,
+) -> anyhow::Result<Arc<smol::lock::Mutex<File>>> {
+    TRANSFERS
+        .lock()
+        .unwrap()
+        .get(&id)
+        .filter(|entry| entry.owner == *owner)
+        .map(|entry| Arc::clone(&entry.file))
+        .ok_or_else(|| anyhow!("no such sftp transfer {}", id))
+}
+
+/// Opens a remote file for reading
+pub async fn open_read(
+    sftp: &Sftp,
+    owner: TransferOwner,
+    path: Utf8PathBuf,
+) -> anyhow::Result<Transfer> {
+    let file = sftp
+        .open(path.clone())
+        .await
+        .with_context(|| format!("opening {} for read", path))?;
+    let size = file.metadata().await.ok().and_then(|meta| meta.size);
+    Ok(register(file, owner, path, size))
+}
+
+/// Creates or truncates a remote file and opens it for writing.
+/// If `path` is an existing directory and `file_name` is specified,
+/// the file is created with that name inside the directory.
+pub async fn open_write(
+    sftp: &Sftp,
+    owner: TransferOwner,
+    path: Utf8PathBuf,
+    file_name: Option<&str>,
+) -> anyhow::Result<Transfer> {
+    let path = match file_name {
+        Some(name) if is_dir(sftp, &path).await => path.join(name),
+        _ => path,
+    };
+    let file = sftp
+        .create(path.clone())
+        .await
+        .with_context(|| format!("opening {} for write", path))?;
+    Ok(register(file, owner, path, None))
+}
+
+async fn is_dir(sftp: &Sftp, path: &Utf8PathBuf) -> bool {
+    match sftp.metadata(path.clone()).await {
+        Ok(meta) => meta.is_dir(),
+        Err(_) => false,
+    }
+}
+
+/// Reads up to `len` bytes, but no more than `CHUNK_SIZE`, from the
+/// transfer.  An empty result indicates the end of the file.
+pub async fn read_chunk(
+    id: TransferId,
+    owner: &TransferOwner,
+    len: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let file = get_transfer(id, owner)?;
+    let mut file = file.lock().await;
+    read_up_to(&mut *file, len).await
+}
+
+/// `len` is supplied by the client, so it is clamped before it is
+/// used to size the buffer
+async fn read_up_to<R: AsyncRead + Unpin>(reader: &mut R, len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len.min(CHUNK_SIZE)];
+    let n = reader.read(&mut buf).await?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+pub async fn write_chunk(id: TransferId, owner: &TransferOwner, data: &[u8]) -> anyhow::Result<()> {
+    let file = get_transfer(id, owner)?;
+    let mut file = file.lock().await;
+    file.write_all(data).await?;
+    Ok(())
+}
+
+/// Flushes and closes the transfer, releasing the remote file handle
+pub async fn close(id: TransferId, owner: &TransferOwner) -> anyhow::Result<()> {
+    let file = {
+        let mut transfers = TRANSFERS.lock().unwrap();
+        match transfers.get(&id) {
+            Some(entry) if entry.owner == *owner => transfers.remove(&id).unwrap().file,
+            _ => anyhow::bail!("no such sftp transfer {}", id),
+        }
+    };
+    let mut file = file.lock().await;
+    file.flush().await?;
+    file.close().await?;
+    Ok(())
+}
+
+/// Abandons the transfers that were opened by a client that has
+/// since disconnected, releasing their remote file handles
+pub fn remove_client_transfers(client_id: &ClientId) {
+    TRANSFERS
+        .lock()
+        .unwrap()
+        .retain(|_, entry| entry.owner.client_id != *client_id);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wezterm_ssh::FileType;
+
+    #[test]
+    fn resolve_paths() {
+        let cwd = Some(Utf8PathBuf::from("/home/user/src"));
+        assert_eq!(resolve_path(cwd.clone(), "/etc/hosts"), "/etc/hosts");
+        assert_eq!(
+            resolve_path(cwd.clone(), "notes.txt"),
+            "/home/user/src/notes.txt"
+        );
+        assert_eq!(resolve_path(cwd, "../a b"), "/home/user/src/../a b");
+        // Left for the server to resolve against the home directory
+        assert_eq!(resolve_path(None, "notes.txt"), "notes.txt");
+    }
+
+    #[test]
+    fn cwd_from_url() {
+        let url = Url::parse("file://host/home/user/my%20dir").unwrap();
+        assert_eq!(
+            path_from_url(&url),
+            Some(Utf8PathBuf::from("/home/user/my dir"))
+        );
+        let url = Url::parse("file://host").unwrap();
+        assert_eq!(path_from_url(&url), Some(Utf8PathBuf::from("/")));
+    }
+
+    #[test]
+    fn oversized_reads_are_clamped() {
+        let data = vec![1u8; CHUNK_SIZE * 2];
+        let mut reader = smol::io::Cursor::new(data);
+        smol::block_on(async {
+            let chunk = read_up_to(&mut reader, usize::MAX).await.unwrap();
+            assert_eq!(chunk.len(), CHUNK_SIZE);
+            let chunk = read_up_to(&mut reader, 10).await.unwrap();
+            assert_eq!(chunk.len(), 10);
+        });
+    }
+
+    #[test]
+    fn directories_are_listed_first() {
+        fn entry(name: &str, ty: FileType) -> (Utf8PathBuf, Metadata) {
+            (
+                Utf8PathBuf::from(format!("/dir/{name}")),
+                Metadata {
+                    ty,
+                    permissions: None,
+                    size: None,
+                    uid: None,
+                    gid: None,
+                    accessed: None,
+                    modified: None,
+                },
+            )
+        }
+
+        let mut entries = vec![
+            entry("b.txt", FileType::File),
+            entry("zeta", FileType::Dir),
+            entry("a.txt", FileType::File),
+            entry("link", FileType::Symlink),
+            entry("alpha", FileType::Dir),
+        ];
+        sort_entries(&mut entries);
+        let names: Vec<&str> = entries
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap())
+            .collect();
+        assert_eq!(names, vec!["alpha", "zeta", "a.txt", "b.txt", "link"]);
+    }
+}
@@ -5,7 +5,7 @@ use crate::pane::{alloc_pane_id, Pane, PaneId};
 use crate::Mux;
 use anyhow::{anyhow, bail, Context};
 use async_trait::async_trait;
-use config::{Shell, SshBackend, SshDomain};
+use config::{Shell, SshBackend, SshDomain, SshReconnect};
 use filedescriptor::{poll, pollfd, socketpair, AsRawSocketDescriptor, FileDescriptor, POLLIN};
 use portable_pty::cmdbuilder::CommandBuilder;
 use portable_pty::{ChildKiller, ExitStatus, MasterPty, PtySize};
@@ -13,6 +13,7 @@ use smol::channel::{bounded, Receiver as AsyncReceiver};
 use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
 use std::io::{BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
@@ -22,8 +23,11 @@ use termwiz::lineedit::*;
 use termwiz::render::terminfo::TerminfoRenderer;
 use termwiz::surface::{Change, LineAttribute};
 use termwiz::terminal::{ScreenSize, Terminal, TerminalWaker};
+#[cfg(unix)]
+use wezterm_ssh::ControlMaster;
 use wezterm_ssh::{
-    ConfigMap, HostVerificationFailed, Session, SessionEvent, SshChildProcess, SshPty,
+    ConfigMap, ForwardId, ForwardInfo, ForwardSpec, HostVerificationFailed, Session, SessionEvent,
+    Sftp, SshChildProcess, SshPty, WeakSession,
 };
 use wezterm_term::TerminalSize;
 
@@ -57,6 +61,193 @@ impl LineEditorHost for PasswordPromptHost {
     }
 }
 
+/// Identifies the sessions that can be shared with each other
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SharedSessionKey {
+    user: String,
+    hostname: String,
+    port: String,
+}
+
+impl SharedSessionKey {
+    fn new(ssh_config: &ConfigMap) -> Self {
+        let get = |key: &str| ssh_config.get(key).cloned().unwrap_or_default();
+        Self {
+            user: get("user"),
+            hostname: get("hostname"),
+            port: get("port"),
+        }
+    }
+}
+
+/// How long a shared session is kept open after it was last used,
+/// so that short lived users, such as `wezterm.ssh.exec`, don't need
+/// to connect and authenticate each time
+const SHARED_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+struct SharedSession {
+    session: WeakSession,
+    /// Keeps the session open until it has been idle for
+    /// SHARED_SESSION_IDLE_TIMEOUT
+    idle_keepalive: Option<Session>,
+    last_used: Instant,
+    #[cfg(unix)]
+    _control_master: Option<ControlMaster>,
+}
+
+impl SharedSession {
+    fn upgrade(&self) -> Option<Session> {
+        self.session
+            .upgrade()
+            .filter(|session| !session.is_closed())
+    }
+
+    fn touch(&mut self, session: &Session) {
+        self.last_used = Instant::now();
+        if self.idle_keepalive.is_none() {
+            self.idle_keepalive.replace(session.clone());
+        }
+    }
+
+    /// Releases our reference to the session if it has been idle
+    /// for too long; it remains shared for as long as it is used
+    /// elsewhere
+    fn expire(&mut self) {
+        if self.last_used.elapsed() >= SHARED_SESSION_IDLE_TIMEOUT {
+            self.idle_keepalive.take();
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref SHARED_SESSIONS: Mutex<HashMap<SharedSessionKey, SharedSession>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Starts a background thread that closes idle shared sessions
+fn spawn_idle_reaper() {
+    static START: std::sync::Once = std::sync::Once::new();
+    START.call_once(|| {
+        std::thread::spawn(|| loop {
+            std::thread::sleep(Duration::from_secs(10));
+            let mut sessions = SHARED_SESSIONS.lock().unwrap();
+            sessions.values_mut().for_each(SharedSession::expire);
+            sessions.retain(|_, shared| shared.upgrade().is_some());
+        });
+    });
+}
+
+/// Returns a session to the user, host and port described by
+/// `ssh_config` that was established elsewhere, so that it can be
+/// used instead of connecting and authenticating all over again.
+/// That is either a session that this process has already made, or,
+/// like `ssh`, one that is served by another process on the control
+/// socket named by the `ControlPath` ssh option.
+/// Always returns None if `ssh_multiplexing` is disabled.
+pub fn find_shared_session(ssh_config: &ConfigMap) -> Option<Session> {
+    if !config::configuration().ssh_multiplexing {
+        return None;
+    }
+    let key = SharedSessionKey::new(ssh_config);
+    {
+        let mut sessions = SHARED_SESSIONS.lock().unwrap();
+        if let Some(shared) = sessions.get_mut(&key) {
+            match shared.upgrade() {
+                Some(session) => {
+                    shared.touch(&session);
+                    return Some(session);
+                }
+                None => {
+                    sessions.remove(&key);
+                }
+            }
+        }
+    }
+
+    let session = connect_control_socket(ssh_config)?;
+    spawn_idle_reaper();
+    SHARED_SESSIONS.lock().unwrap().insert(
+        key,
+        SharedSession {
+            session: session.downgrade(),
+            idle_keepalive: Some(session.clone()),
+            last_used: Instant::now(),
+            #[cfg(unix)]
+            _control_master: None,
+        },
+    );
+    Some(session)
+}
+
+/// Uses the session of another process that is serving the control
+/// socket named by `ControlPath`.  As with `ssh`, this is skipped when
+/// `ControlMaster` is `yes` or `ask`, which call for being the master.
+#[cfg(unix)]
+fn connect_control_socket(ssh_config: &ConfigMap) -> Option<Session> {
+    let mode = ssh_config
+        .get("controlmaster")
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default();
+    if mode == "yes" || mode == "ask" {
+        return None;
+    }
+    let path = match ssh_config.get("controlpath") {
+        Some(path) if path != "none" => std::path::Path::new(path),
+        _ => return None,
+    };
+    if !path.exists() {
+        return None;
+    }
+    match Session::connect_control_socket(path) {
+        Ok(session) => Some(session),
+        Err(err) => {
+            log::warn!(
+                "Unable to use ssh control socket {}: {:#}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn connect_control_socket(_ssh_config: &ConfigMap) -> Option<Session> {
+    None
+}
+
+/// Makes a newly authenticated session available via `find_shared_session`.
+/// If the `ControlMaster` and `ControlPath` ssh options call for it,
+/// a control socket is served so that `ssh` and `scp` can use it too.
+pub fn share_session(ssh_config: &ConfigMap, session: &Session) {
+    if !config::configuration().ssh_multiplexing {
+        return;
+    }
+
+    #[cfg(unix)]
+    let control_master = match ControlMaster::from_config(session, ssh_config) {
+        Ok(control_master) => control_master,
+        Err(err) => {
+            log::error!("Unable to serve ssh control socket: {:#}", err);
+            None
+        }
+    };
+
+    spawn_idle_reaper();
+    let mut sessions = SHARED_SESSIONS.lock().unwrap();
+    sessions.retain(|_, shared| shared.upgrade().is_some());
+    sessions.insert(
+        SharedSessionKey::new(ssh_config),
+        SharedSession {
+            session: session.downgrade(),
+            idle_keepalive: Some(session.clone()),
+            last_used: Instant::now(),
+            #[cfg(unix)]
+            _control_master: control_master,
+        },
+    );
+}
+
 pub fn ssh_connect_with_ui(
     ssh_config: wezterm_ssh::ConfigMap,
     ui: &mut ConnectionUI,
@@ -66,6 +257,13 @@ pub fn ssh_connect_with_ui(
         let remote_address = ssh_config
             .get("hostname")
             .expect("ssh config to always set hostname");
+        if let Some(session) = find_shared_session(&ssh_config) {
+            ui.output_str(&format!(
+                "Using existing SSH connection to {}\n",
+                remote_address
+            ));
+            return Ok(session);
+        }
         ui.output_str(&format!("Connecting to {} using SSH\n", remote_address));
         let (session, events) = Session::connect(ssh_config.clone())?;
 
@@ -116,21 +314,89 @@ pub fn ssh_connect_with_ui(
                     smol::block_on(auth.answer(answers))?;
                 }
                 SessionEvent::HostVerificationFailed(failed) => {
-                    let message = format_host_verification_for_terminal(failed);
+                    let message = format_host_verification_for_terminal(&failed);
                     ui.output(message);
-                    anyhow::bail!("Host key verification failed");
+                    if !failed.can_replace() {
+                        anyhow::bail!("Host key verification failed");
+                    }
+                    let file = failed.file.as_ref().map(|f| f.display().to_string());
+                    let ok = if let Ok(line) = ui.input(&format!(
+                        "Replace the key in {} and continue connecting? Enter [y/n]> ",
+                        file.unwrap_or_default()
+                    )) {
+                        match line.as_ref() {
+                            "y" | "Y" | "yes" | "YES" => true,
+                            "n" | "N" | "no" | "NO" | _ => false,
+                        }
+                    } else {
+                        false
+                    };
+                    // If we declined, the session reports an Error next
+                    smol::block_on(failed.answer(ok)).context("send replace response")?;
                 }
                 SessionEvent::Error(err) => {
                     anyhow::bail!("Error: {}", err);
                 }
-                SessionEvent::Authenticated => return Ok(session),
+                SessionEvent::Authenticated => {
+                    share_session(&ssh_config, &session);
+                    return Ok(session);
+                }
             }
         }
         bail!("unable to authenticate session");
     })
 }
 
-fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<Change> {
+/// Returns a session to the host described by `ssh_config`, preferring
+/// one that is already shared.  This is intended for callers that have
+/// no way to prompt the user, so connecting fails if the host key is not
+/// yet trusted or if authenticating requires answering a prompt.
+pub async fn connect_without_ui(ssh_config: &ConfigMap) -> anyhow::Result<Session> {
+    if let Some(session) = find_shared_session(ssh_config) {
+        return Ok(session);
+    }
+    let remote_address = ssh_config
+        .get("hostname")
+        .expect("ssh config to always set hostname");
+    let (session, events) = Session::connect(ssh_config.clone())?;
+
+    while let Ok(event) = events.recv().await {
+        match event {
+            SessionEvent::Banner(_) => {}
+            SessionEvent::HostVerify(_) => {
+                // Dropping the event declines to trust the host
+                bail!(
+                    "the host key for {} is not yet trusted; \
+                     connect to it interactively first",
+                    remote_address
+                );
+            }
+            SessionEvent::Authenticate(auth) => {
+                if !auth.prompts.is_empty() {
+                    bail!(
+                        "authenticating to {} requires answering a prompt; \
+                         connect to it interactively first",
+                        remote_address
+                    );
+                }
+                auth.answer(vec![]).await?;
+            }
+            SessionEvent::HostVerificationFailed(failed) => {
+                return Err(failed.into());
+            }
+            SessionEvent::Error(err) => {
+                bail!("Error: {}", err);
+            }
+            SessionEvent::Authenticated => {
+                share_session(ssh_config, &session);
+                return Ok(session);
+            }
+        }
+    }
+    bail!("unable to authenticate session");
+}
+
+fn format_host_verification_for_terminal(failed: &HostVerificationFailed) -> Vec<Change> {
     vec![
         AttributeChange::Intensity(Intensity::Bold).into(),
         LineAttribute::DoubleHeightTopHalfLine.into(),
@@ -151,20 +417,25 @@ fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<
         ),
         AttributeChange::Intensity(Intensity::Normal).into(),
         Change::Text("\r\n".to_string()),
-        match failed.file {
+        Change::Text(format!(
+            "The host is {}, and its fingerprint is\r\n{}\r\n{}\r\n",
+            failed.remote_address,
+            failed.key,
+            failed.randomart.replace('\n', "\r\n"),
+        )),
+        match &failed.file {
+            Some(file) if failed.can_replace() => Change::Text(format!(
+                "If the administrator confirms that the key has changed, you can\r\n\
+                replace the offending entry in {} with the new key.\r\n",
+                file.display(),
+            )),
             Some(file) => Change::Text(format!(
-                "The host is {}, and its fingerprint is\r\n{}\r\n\
-                If the administrator confirms that the key has changed, you can\r\n\
+                "If the administrator confirms that the key has changed, you can\r\n\
                 fix this for yourself by removing the offending entry from\r\n\
                 {} and then try connecting again.\r\n",
-                failed.remote_address,
-                failed.key,
                 file.display(),
             )),
-            None => Change::Text(format!(
-                "The host is {}, and its fingerprint is\r\n{}\r\n",
-                failed.remote_address, failed.key
-            )),
+            None => Change::Text(String::new()),
         },
     ]
 }
@@ -179,6 +450,13 @@ fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<
 /// interactive setup.  The bulk of that is driven by `connect_ssh_session`.
 pub struct RemoteSshDomain {
     session: Mutex<Option<Session>>,
+    /// Held by a pane while it reconnects, so that the panes of the
+    /// domain reconnect one at a time.  Refers to the session that was
+    /// most recently established by reconnecting, which the panes
+    /// that were waiting their turn use rather than connecting and
+    /// authenticating all over again.  It is a weak reference so that
+    /// it doesn't keep an unused session from expiring.
+    reconnected: Arc<Mutex<Option<WeakSession>>>,
     dom: SshDomain,
     id: DomainId,
     name: String,
@@ -236,6 +514,7 @@ impl RemoteSshDomain {
             id,
             name: dom.name.clone(),
             session: Mutex::new(None),
+            reconnected: Arc::new(Mutex::new(None)),
             dom: dom.clone(),
         })
     }
@@ -244,6 +523,45 @@ impl RemoteSshDomain {
         ssh_domain_to_ssh_config(&self.dom)
     }
 
+    /// Returns the session, if the domain has connected
+    fn connected_session(&self) -> anyhow::Result<Session> {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .cloned()
+            .ok_or_else(|| anyhow!("ssh domain {} is not connected", self.name))
+    }
+
+    /// Uses a session to the same host that was established by
+    /// another domain, or by `wezterm ssh`, if there is one
+    fn adopt_shared_session(&self) -> anyhow::Result<Option<Session>> {
+        let session = find_shared_session(&self.ssh_config()?);
+        if let Some(session) = &session {
+            log::debug!("ssh domain {} is using an existing session", self.name);
+            self.session.lock().unwrap().replace(session.clone());
+        }
+        Ok(session)
+    }
+
+    /// Establishes a port forward over the domain's ssh session
+    pub async fn add_forward(&self, spec: ForwardSpec) -> anyhow::Result<ForwardInfo> {
+        self.connected_session()?.forward(spec).await
+    }
+
+    pub async fn cancel_forward(&self, id: ForwardId) -> anyhow::Result<()> {
+        self.connected_session()?.cancel_forward(id).await
+    }
+
+    pub async fn list_forwards(&self) -> anyhow::Result<Vec<ForwardInfo>> {
+        self.connected_session()?.list_forwards().await
+    }
+
+    /// Returns an sftp handle that operates over the domain's ssh session
+    pub fn sftp(&self) -> anyhow::Result<Sftp> {
+        Ok(self.connected_session()?.sftp())
+    }
+
     fn build_command(
         &self,
         pane_id: PaneId,
@@ -326,12 +644,14 @@ impl RemoteSshDomain {
 
     async fn start_new_session(
         &self,
+        pane_id: PaneId,
         command_line: Option<String>,
         env: HashMap<String, String>,
         size: TerminalSize,
     ) -> anyhow::Result<StartNewSessionResult> {
-        let (session, events) = Session::connect(self.ssh_config().context("obtain ssh config")?)
-            .context("connect to ssh server")?;
+        let ssh_config = self.ssh_config().context("obtain ssh config")?;
+        let (session, events) =
+            Session::connect(ssh_config.clone()).context("connect to ssh server")?;
         self.session.lock().unwrap().replace(session.clone());
 
         // We get to establish the session!
@@ -340,48 +660,8 @@ impl RemoteSshDomain {
         // we'll carry out interactive auth, we generate
         // some shim/wrapper versions of the pty, child
         // and reader/writer.
-
-        let (stdout_read, stdout_write) = socketpair()?;
-        let (reader_tx, reader_rx) = channel();
-        let (stdin_read, stdin_write) = socketpair()?;
-        let (writer_tx, writer_rx) = channel();
-
-        let pty_reader = PtyReader {
-            reader: Box::new(stdout_read),
-            rx: reader_rx,
-        };
-
-        let pty_writer = PtyWriter {
-            writer: Box::new(stdin_write),
-            rx: writer_rx,
-        };
-        let writer = Box::new(pty_writer);
-
-        let (child_tx, child_rx) = channel();
-
-        let child = Box::new(WrappedSshChild {
-            status: None,
-            rx: child_rx,
-            exited: None,
-            killer: WrappedSshChildKiller {
-                inner: Arc::new(Mutex::new(KillerInner {
-                    killer: None,
-                    pending_kill: false,
-                })),
-            },
-        });
-
-        let (pty_tx, pty_rx) = channel();
-
-        let size = Arc::new(Mutex::new(size));
-
-        let pty = Box::new(WrappedSshPty {
-            inner: RefCell::new(WrappedSshPtyInner::Connecting {
-                size: Arc::clone(&size),
-                reader: Some(pty_reader),
-                connected: pty_rx,
-            }),
-        });
+        let (connection, result) = PaneConnection::new(self.id, pane_id, size);
+        let (mut stdin_read, stdout_write) = connection.attach_ui()?;
 
         // And with those created, we can now spawn a new thread
         // to perform the blocking (from its perspective) terminal
@@ -389,25 +669,43 @@ impl RemoteSshDomain {
         let mut stdout_write = BufWriter::new(stdout_write);
         std::thread::spawn(move || {
             if let Err(err) = connect_ssh_session(
+                &ssh_config,
                 session,
                 events,
-                stdin_read,
-                writer_tx,
+                &mut stdin_read,
                 &mut stdout_write,
-                reader_tx,
-                child_tx,
-                pty_tx,
-                size,
+                &connection,
                 command_line,
                 env,
             ) {
                 let _ = write!(stdout_write, "{:#}", err);
                 log::error!("Failed to connect ssh: {:#}", err);
+                connection.disconnect();
             }
             let _ = stdout_write.flush();
         });
 
-        Ok(StartNewSessionResult { pty, child, writer })
+        Ok(result)
+    }
+
+    /// Computes how to start a fresh shell, in the same working
+    /// directory, for a pane whose connection was lost
+    fn reconnect_params(&self, pane_id: PaneId) -> anyhow::Result<ReconnectParams> {
+        let cwd = Mux::get()
+            .get_pane(pane_id)
+            .and_then(|pane| crate::sftp::remote_cwd(&pane))
+            .map(|cwd| cwd.into_string());
+        let (command_line, env) = self
+            .build_command(pane_id, None, cwd)
+            .context("build_command")?;
+        Ok(ReconnectParams {
+            name: self.name.clone(),
+            ssh_config: self.ssh_config()?,
+            policy: self.dom.reconnect.clone(),
+            reconnected: Arc::clone(&self.reconnected),
+            command_line,
+            env,
+        })
     }
 }
 
@@ -417,20 +715,325 @@ struct StartNewSessionResult {
     writer: BoxedWriter,
 }
 
-/// Carry out the authentication process and create the initial pty.
-fn connect_ssh_session(
-    session: Session,
-    events: smol::channel::Receiver<SessionEvent>,
-    mut stdin_read: FileDescriptor,
+struct ReconnectParams {
+    name: String,
+    ssh_config: ConfigMap,
+    policy: SshReconnect,
+    reconnected: Arc<Mutex<Option<WeakSession>>>,
+    command_line: Option<String>,
+    env: HashMap<String, String>,
+}
+
+struct PaneSenders {
     stdin_tx: Sender<BoxedWriter>,
-    stdout_write: &mut BufWriter<FileDescriptor>,
     stdout_tx: Sender<BoxedReader>,
     child_tx: Sender<SshChildProcess>,
     pty_tx: Sender<SshPty>,
+}
+
+/// Feeds the wrapped pty, child, reader and writer of a pane.
+/// Sending a new set of these replaces the remote shell, which
+/// is what allows a pane, along with its scrollback, to survive
+/// reconnecting after the connection was lost.
+struct PaneConnection {
+    domain_id: DomainId,
+    pane_id: PaneId,
     size: Arc<Mutex<TerminalSize>>,
+    reconnecting: AtomicBool,
+    /// Taken when the pane will not be connected again, which
+    /// causes it to see its remote shell as having exited
+    senders: Mutex<Option<PaneSenders>>,
+}
+
+impl PaneConnection {
+    fn new(
+        domain_id: DomainId,
+        pane_id: PaneId,
+        size: TerminalSize,
+    ) -> (Arc<Self>, StartNewSessionResult) {
+        let (stdin_tx, stdin_rx) = channel();
+        let (stdout_tx, stdout_rx) = channel();
+        let (child_tx, child_rx) = channel();
+        let (pty_tx, pty_rx) = channel();
+
+        let connection = Arc::new(Self {
+            domain_id,
+            pane_id,
+            size: Arc::new(Mutex::new(size)),
+            reconnecting: AtomicBool::new(false),
+            senders: Mutex::new(Some(PaneSenders {
+                stdin_tx,
+                stdout_tx,
+                child_tx,
+                pty_tx,
+            })),
+        });
+
+        let pty = Box::new(WrappedSshPty {
+            inner: RefCell::new(WrappedSshPtyInner {
+                reader: Some(PtyReader {
+                    reader: Box::new(std::io::empty()),
+                    rx: stdout_rx,
+                }),
+                connected: pty_rx,
+                pty: None,
+                connection: Arc::clone(&connection),
+            }),
+        });
+
+        let child = Box::new(WrappedSshChild {
+            status: None,
+            rx: child_rx,
+            exited: None,
+            killer: WrappedSshChildKiller {
+                inner: Arc::new(Mutex::new(KillerInner {
+                    killer: None,
+                    pending_kill: false,
+                })),
+            },
+            connection: Arc::clone(&connection),
+        });
+
+        let writer = Box::new(PtyWriter {
+            writer: Box::new(std::io::sink()),
+            rx: stdin_rx,
+        });
+
+        (connection, StartNewSessionResult { pty, child, writer })
+    }
+
+    /// Connects the pane to a fresh pair of sockets so that connection
+    /// progress and authentication prompts can be shown in, and
+    /// answered via, the pane.
+    /// Returns the ends of those sockets that drive that UI.
+    fn attach_ui(&self) -> anyhow::Result<(FileDescriptor, FileDescriptor)> {
+        let (stdout_read, stdout_write) = socketpair()?;
+        let (stdin_read, stdin_write) = socketpair()?;
+
+        let senders = self.senders.lock().unwrap();
+        let senders = senders
+            .as_ref()
+            .ok_or_else(|| anyhow!("pane {} is no longer connected", self.pane_id))?;
+        senders
+            .stdout_tx
+            .send(Box::new(stdout_read))
+            .map_err(|e| anyhow!("{:#}", e))?;
+        senders
+            .stdin_tx
+            .send(Box::new(stdin_write))
+            .map_err(|e| anyhow!("{:#}", e))?;
+
+        Ok((stdin_read, stdout_write))
+    }
+
+    /// Connects the pane to a newly established remote shell
+    fn connected(&self, pty: SshPty, child: SshChildProcess) -> anyhow::Result<()> {
+        // Obtain the real stdin/stdout for the pty
+        let reader = pty.try_clone_reader()?;
+        let writer = pty.take_writer()?;
+
+        let senders = self.senders.lock().unwrap();
+        let senders = senders
+            .as_ref()
+            .ok_or_else(|| anyhow!("pane {} is no longer connected", self.pane_id))?;
+
+        // And send them to the wrapped reader/writer
+        senders
+            .stdin_tx
+            .send(Box::new(writer))
+            .map_err(|e| anyhow!("{:#}", e))?;
+        senders
+            .stdout_tx
+            .send(Box::new(reader))
+            .map_err(|e| anyhow!("{:#}", e))?;
+
+        // Likewise, send the real pty and child to
+        // the wrappers.
+        // The pty and child will be picked up when
+        // they are next polled or resized.
+        senders.pty_tx.send(pty)?;
+        senders.child_tx.send(child)?;
+
+        self.reconnecting.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn disconnect(&self) {
+        self.senders.lock().unwrap().take();
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.senders.lock().unwrap().is_none()
+    }
+
+    fn is_reconnecting(&self) -> bool {
+        self.reconnecting.load(Ordering::Relaxed)
+    }
+
+    /// Called on the main thread when the remote shell went away
+    /// because the ssh session ended.
+    /// Returns true if we're going to reconnect, in which case the
+    /// pane should stay alive until the new remote shell arrives.
+    fn connection_lost(self: &Arc<Self>) -> bool {
+        if self.is_disconnected() {
+            return false;
+        }
+        let mux = Mux::get();
+        let domain = match mux.get_domain(self.domain_id) {
+            Some(domain) => domain,
+            None => return false,
+        };
+        let domain = match domain.downcast_ref::<RemoteSshDomain>() {
+            Some(domain) => domain,
+            None => return false,
+        };
+        if !domain.dom.reconnect.enabled {
+            return false;
+        }
+        match domain.reconnect_params(self.pane_id) {
+            Ok(params) => {
+                self.reconnecting.store(true, Ordering::Relaxed);
+                let connection = Arc::clone(self);
+                std::thread::spawn(move || connection.reconnect(params));
+                true
+            }
+            Err(err) => {
+                log::error!("Unable to reconnect pane {}: {:#}", self.pane_id, err);
+                false
+            }
+        }
+    }
+
+    fn reconnect(&self, params: ReconnectParams) {
+        let (mut stdin_read, stdout_write) = match self.attach_ui() {
+            Ok(ui) => ui,
+            Err(err) => {
+                log::error!("Unable to reconnect pane {}: {:#}", self.pane_id, err);
+                self.disconnect();
+                return;
+            }
+        };
+        let mut stdout_write = BufWriter::new(stdout_write);
+
+        // The remote shell may have left the terminal in an alternate
+        // screen or some other mode; DECSTR restores the defaults
+        // without discarding the scrollback
+        let _ = write!(stdout_write, "\x1b[!p\r\n");
+
+        let policy = &params.policy;
+        for attempt in 1..=policy.max_attempts {
+            let delay = policy.delay_for_attempt(attempt);
+            let _ = write!(
+                stdout_write,
+                "Connection to {} was lost; reconnecting in {:?} (attempt {} of {})\r\n",
+                params.name, delay, attempt, policy.max_attempts
+            );
+            let _ = stdout_write.flush();
+            std::thread::sleep(delay);
+
+            if self.is_disconnected() {
+                // The pane was closed in the meantime
+                return;
+            }
+
+            match self.try_reconnect(&params, &mut stdin_read, &mut stdout_write) {
+                Ok(()) => return,
+                Err(err) => {
+                    log::warn!("Failed to reconnect pane {}: {:#}", self.pane_id, err);
+                    let _ = write!(stdout_write, "{:#}\r\n", err);
+                }
+            }
+        }
+
+        let _ = write!(
+            stdout_write,
+            "Giving up after {} attempts to reconnect\r\n",
+            policy.max_attempts
+        );
+        let _ = stdout_write.flush();
+        self.disconnect();
+    }
+
+    fn try_reconnect(
+        &self,
+        params: &ReconnectParams,
+        stdin_read: &mut FileDescriptor,
+        stdout_write: &mut BufWriter<FileDescriptor>,
+    ) -> anyhow::Result<()> {
+        // The connection of every pane of the domain is lost at the
+        // same time; take turns so that only the first of them connects
+        // and authenticates
+        let mut reconnected = params.reconnected.lock().unwrap();
+        if self.is_disconnected() {
+            anyhow::bail!("pane {} was closed", self.pane_id);
+        }
+
+        // Another pane may have already re-established a session
+        let session = reconnected
+            .as_ref()
+            .and_then(WeakSession::upgrade)
+            .filter(|session| !session.is_closed())
+            .or_else(|| find_shared_session(&params.ssh_config));
+        if let Some(session) = session {
+            let (pty, child) = smol::block_on(session.request_pty(
+                &config::configuration().term,
+                crate::terminal_size_to_pty_size(*self.size.lock().unwrap())?,
+                params.command_line.as_deref(),
+                Some(params.env.clone()),
+            ))?;
+            reconnected.replace(session.downgrade());
+            self.set_domain_session(session);
+            return self.connected(pty, child);
+        }
+
+        let (session, events) =
+            Session::connect(params.ssh_config.clone()).context("connect to ssh server")?;
+        self.set_domain_session(session.clone());
+        connect_ssh_session(
+            &params.ssh_config,
+            session.clone(),
+            events,
+            stdin_read,
+            stdout_write,
+            self,
+            params.command_line.clone(),
+            params.env.clone(),
+        )?;
+        reconnected.replace(session.downgrade());
+        Ok(())
+    }
+
+    /// Makes the domain use a session that was established while
+    /// reconnecting, so that new panes, forwards and file transfers
+    /// use it too
+    fn set_domain_session(&self, session: Session) {
+        let domain_id = self.domain_id;
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get();
+            if let Some(domain) = mux.get_domain(domain_id) {
+                if let Some(domain) = domain.downcast_ref::<RemoteSshDomain>() {
+                    domain.session.lock().unwrap().replace(session);
+                }
+            }
+        })
+        .detach();
+    }
+}
+
+/// Carry out the authentication process and create the pty
+/// for the pane.
+fn connect_ssh_session(
+    ssh_config: &ConfigMap,
+    session: Session,
+    events: smol::channel::Receiver<SessionEvent>,
+    stdin_read: &mut FileDescriptor,
+    stdout_write: &mut BufWriter<FileDescriptor>,
+    connection: &PaneConnection,
     command_line: Option<String>,
     env: HashMap<String, String>,
 ) -> anyhow::Result<()> {
+    let size = Arc::clone(&connection.size);
+
     struct StdoutShim<'a> {
         size: Arc<Mutex<TerminalSize>>,
         stdout: &'a mut BufWriter<FileDescriptor>,
@@ -576,7 +1179,7 @@ fn connect_ssh_session(
         },
         size: Arc::clone(&size),
         renderer,
-        stdin: &mut stdin_read,
+        stdin: stdin_read,
         parser: InputParser::new(),
         input_queue: VecDeque::new(),
     };
@@ -643,12 +1246,32 @@ fn connect_ssh_session(
                 shim.output_line(&format!("Error: {}", err))?;
             }
             SessionEvent::HostVerificationFailed(failed) => {
-                let message = format_host_verification_for_terminal(failed);
+                let message = format_host_verification_for_terminal(&failed);
                 shim.render(&message)?;
+                if failed.can_replace() {
+                    let file = failed.file.as_ref().map(|f| f.display().to_string());
+                    let mut editor = LineEditor::new(&mut shim);
+                    let mut host = PasswordPromptHost::default();
+                    host.echo = true;
+                    editor.set_prompt(&format!(
+                        "Replace the key in {} and continue connecting? Enter [y/n]> ",
+                        file.unwrap_or_default()
+                    ));
+                    let ok = if let Some(line) = editor.read_line(&mut host)? {
+                        match line.as_ref() {
+                            "y" | "Y" | "yes" | "YES" => true,
+                            "n" | "N" | "no" | "NO" | _ => false,
+                        }
+                    } else {
+                        false
+                    };
+                    smol::block_on(failed.answer(ok)).context("send replace response")?;
+                }
             }
             SessionEvent::Authenticated => {
                 // Our session has been authenticated: we can now
-                // set up the real pty for the pane
+                // share it and set up the real pty for the pane
+                share_session(ssh_config, &session);
                 match smol::block_on(session.request_pty(
                     &config::configuration().term,
                     crate::terminal_size_to_pty_size(*size.lock().unwrap())?,
@@ -662,31 +1285,12 @@ fn connect_ssh_session(
                     Ok((pty, child)) => {
                         drop(shim);
 
-                        // Obtain the real stdin/stdout for the pty
-                        let reader = pty.try_clone_reader()?;
-                        let writer = pty.take_writer()?;
-
-                        // And send them to the wrapped reader/writer
-                        stdin_tx
-                            .send(Box::new(writer))
-                            .map_err(|e| anyhow!("{:#}", e))?;
-                        stdout_tx
-                            .send(Box::new(reader))
-                            .map_err(|e| anyhow!("{:#}", e))?;
-
-                        // Likewise, send the real pty and child to
-                        // the wrappers
-                        pty_tx.send(pty)?;
-                        child_tx.send(child)?;
+                        connection.connected(pty, child)?;
 
-                        // Now when we return, our stdin_read and
+                        // Now when our caller is done, stdin_read and
                         // stdout_write will close and that will cause
                         // the PtyReader and PtyWriter to recv the
-                        // the new reader/writer above and continue.
-                        //
-                        // The pty and child will be picked up when
-                        // they are next polled or resized.
-
+                        // the new reader/writer and continue.
                         return Ok(());
                     }
                 }
@@ -694,7 +1298,7 @@ fn connect_ssh_session(
         }
     }
 
-    Ok(())
+    anyhow::bail!("the ssh session ended before the remote shell was started");
 }
 
 #[async_trait(?Send)]
@@ -714,6 +1318,9 @@ impl Domain for RemoteSshDomain {
         // This needs to be separate from the if let block below in order
         // for the lock to be released at the appropriate time
         let mut session: Option<Session> = self.session.lock().unwrap().as_ref().cloned();
+        if session.is_none() {
+            session = self.adopt_shared_session()?;
+        }
 
         let StartNewSessionResult { pty, child, writer } = if let Some(session) = session.take() {
             match session
@@ -728,11 +1335,13 @@ impl Domain for RemoteSshDomain {
                 .context("request ssh pty")
             {
                 Ok((concrete_pty, concrete_child)) => {
-                    let pty = Box::new(concrete_pty);
-                    let child = Box::new(concrete_child);
-                    let writer = Box::new(pty.take_writer().context("take writer from pty")?);
-
-                    StartNewSessionResult { pty, child, writer }
+                    // Wrap them up so that the pane can be
+                    // reconnected if the session is lost later
+                    let (connection, result) = PaneConnection::new(self.id, pane_id, size);
+                    connection
+                        .connected(concrete_pty, concrete_child)
+                        .context("connect pane to pty")?;
+                    result
                 }
                 Err(err) => {
                     if err
@@ -742,7 +1351,8 @@ impl Domain for RemoteSshDomain {
                     {
                         // Session died (perhaps they closed the initial tab?)
                         // So we'll try making a new one
-                        self.start_new_session(command_line, env, size).await?
+                        self.start_new_session(pane_id, command_line, env, size)
+                            .await?
                     } else {
                         log::error!("{err:#?}");
                         return Err(err);
@@ -750,7 +1360,8 @@ impl Domain for RemoteSshDomain {
                 }
             }
         } else {
-            self.start_new_session(command_line, env, size).await?
+            self.start_new_session(pane_id, command_line, env, size)
+                .await?
         };
 
         // Wrap up the pty etc. in a LocalPane.  That allows for
@@ -826,10 +1437,21 @@ struct WrappedSshChildKiller {
 
 #[derive(Debug)]
 pub(crate) struct WrappedSshChild {
-    status: Option<AsyncReceiver<ExitStatus>>,
+    /// Yields the exit status of the current remote shell, or None
+    /// if its connection was lost and we are reconnecting, in which
+    /// case the next remote shell will be received via `rx`
+    status: Option<AsyncReceiver<Option<ExitStatus>>>,
     rx: Receiver<SshChildProcess>,
     exited: Option<ExitStatus>,
     killer: WrappedSshChildKiller,
+    connection: Arc<PaneConnection>,
+}
+
+impl Drop for WrappedSshChild {
+    fn drop(&mut self) {
+        // The pane has gone away; don't try to reconnect it
+        self.connection.disconnect();
+    }
 }
 
 impl WrappedSshChild {
@@ -858,9 +1480,15 @@ impl WrappedSshChild {
         }
 
         let (tx, rx) = bounded(1);
+        let connection = Arc::clone(&self.connection);
         promise::spawn::spawn_into_main_thread(async move {
             if let Ok(status) = child.async_wait().await {
-                tx.send(status).await.ok();
+                if child.is_session_closed() && connection.connection_lost() {
+                    tx.send(None).await.ok();
+                    return;
+                }
+                connection.disconnect();
+                tx.send(Some(status)).await.ok();
                 let mux = Mux::get();
                 mux.prune_dead_windows();
             }
@@ -868,6 +1496,22 @@ impl WrappedSshChild {
         .detach();
         self.status.replace(rx);
     }
+
+    /// Records the outcome of the current remote shell.
+    /// If we are reconnecting, arranges to pick up the next
+    /// remote shell and returns None.
+    fn resolve_status(&mut self, status: Option<ExitStatus>) -> Option<ExitStatus> {
+        match status {
+            Some(status) => {
+                self.exited.replace(status.clone());
+                Some(status)
+            }
+            None => {
+                self.status.take();
+                None
+            }
+        }
+    }
 }
 
 impl portable_pty::Child for WrappedSshChild {
@@ -880,10 +1524,7 @@ impl portable_pty::Child for WrappedSshChild {
 
         if let Some(rx) = self.status.as_mut() {
             match rx.try_recv() {
-                Ok(status) => {
-                    self.exited.replace(status.clone());
-                    Ok(Some(status))
-                }
+                Ok(status) => Ok(self.resolve_status(status)),
                 Err(smol::channel::TryRecvError::Empty) => Ok(None),
                 Err(err) => {
                     log::debug!("WrappedSshChild::try_wait err: {:#?}", err);
@@ -902,33 +1543,36 @@ impl portable_pty::Child for WrappedSshChild {
             return Ok(status.clone());
         }
 
-        if self.status.is_none() {
-            match smol::block_on(async { self.rx.recv() }) {
-                Ok(c) => {
-                    self.got_child(c);
+        loop {
+            if self.status.is_none() {
+                match smol::block_on(async { self.rx.recv() }) {
+                    Ok(c) => {
+                        self.got_child(c);
+                    }
+                    Err(err) => {
+                        log::debug!("WrappedSshChild err: {:#?}", err);
+                        let status = ExitStatus::with_exit_code(1);
+                        self.exited.replace(status.clone());
+                        return Ok(status);
+                    }
+                }
+            }
+
+            let rx = self.status.as_mut().unwrap();
+            match smol::block_on(rx.recv()) {
+                Ok(status) => {
+                    if let Some(status) = self.resolve_status(status) {
+                        return Ok(status);
+                    }
                 }
                 Err(err) => {
-                    log::debug!("WrappedSshChild err: {:#?}", err);
+                    log::error!("WrappedSshChild err: {:#?}", err);
                     let status = ExitStatus::with_exit_code(1);
                     self.exited.replace(status.clone());
                     return Ok(status);
                 }
             }
         }
-
-        let rx = self.status.as_mut().unwrap();
-        match smol::block_on(rx.recv()) {
-            Ok(status) => {
-                self.exited.replace(status.clone());
-                Ok(status)
-            }
-            Err(err) => {
-                log::error!("WrappedSshChild err: {:#?}", err);
-                let status = ExitStatus::with_exit_code(1);
-                self.exited.replace(status.clone());
-                Ok(status)
-            }
-        }
     }
 
     fn process_id(&self) -> Option<u32> {
@@ -986,16 +1630,13 @@ impl WrappedSshPty {
     }
 }
 
-enum WrappedSshPtyInner {
-    Connecting {
-        reader: Option<PtyReader>,
-        connected: Receiver<SshPty>,
-        size: Arc<Mutex<TerminalSize>>,
-    },
-    Connected {
-        reader: Option<PtyReader>,
-        pty: SshPty,
-    },
+struct WrappedSshPtyInner {
+    reader: Option<PtyReader>,
+    connected: Receiver<SshPty>,
+    /// The pty of the current remote shell; replaced each time
+    /// the pane is reconnected
+    pty: Option<SshPty>,
+    connection: Arc<PaneConnection>,
 }
 
 struct PtyReader {
@@ -1010,76 +1651,55 @@ struct PtyWriter {
 
 impl WrappedSshPtyInner {
     fn check_connected(&mut self) -> anyhow::Result<()> {
-        match self {
-            Self::Connecting {
-                reader,
-                connected,
-                size,
-                ..
-            } => {
-                if let Ok(pty) = connected.try_recv() {
-                    let res = pty.resize(crate::terminal_size_to_pty_size(*size.lock().unwrap())?);
-                    *self = Self::Connected {
-                        pty,
-                        reader: reader.take(),
-                    };
-                    res
-                } else {
-                    Ok(())
-                }
-            }
-            _ => Ok(()),
+        if let Ok(pty) = self.connected.try_recv() {
+            let res = pty.resize(crate::terminal_size_to_pty_size(
+                *self.connection.size.lock().unwrap(),
+            )?);
+            self.pty.replace(pty);
+            res
+        } else {
+            Ok(())
         }
     }
 
     fn is_connecting(&mut self) -> bool {
         self.check_connected().ok();
-        match self {
-            Self::Connecting { .. } => true,
-            Self::Connected { .. } => false,
-        }
+        self.pty.is_none() || self.connection.is_reconnecting()
     }
 }
 
 impl portable_pty::MasterPty for WrappedSshPty {
     fn resize(&self, new_size: PtySize) -> anyhow::Result<()> {
         let mut inner = self.inner.borrow_mut();
-        match &mut *inner {
-            WrappedSshPtyInner::Connecting { ref mut size, .. } => {
-                {
-                    let mut size = size.lock().unwrap();
-                    size.cols = new_size.cols as usize;
-                    size.rows = new_size.rows as usize;
-                    size.pixel_height = new_size.pixel_height as usize;
-                    size.pixel_width = new_size.pixel_width as usize;
-                }
-                inner.check_connected()
-            }
-            WrappedSshPtyInner::Connected { pty, .. } => pty.resize(new_size),
+        {
+            let mut size = inner.connection.size.lock().unwrap();
+            size.cols = new_size.cols as usize;
+            size.rows = new_size.rows as usize;
+            size.pixel_height = new_size.pixel_height as usize;
+            size.pixel_width = new_size.pixel_width as usize;
+        }
+        inner.check_connected()?;
+        match &inner.pty {
+            Some(pty) => pty.resize(new_size),
+            None => Ok(()),
         }
     }
 
     fn get_size(&self) -> anyhow::Result<PtySize> {
         let mut inner = self.inner.borrow_mut();
-        match &*inner {
-            WrappedSshPtyInner::Connecting { size, .. } => {
-                let size = crate::terminal_size_to_pty_size(*size.lock().unwrap())?;
-                inner.check_connected()?;
-                Ok(size)
-            }
-            WrappedSshPtyInner::Connected { pty, .. } => pty.get_size(),
+        inner.check_connected()?;
+        match &inner.pty {
+            Some(pty) => pty.get_size(),
+            None => crate::terminal_size_to_pty_size(*inner.connection.size.lock().unwrap()),
         }
     }
 
     fn try_clone_reader(&self) -> anyhow::Result<Box<dyn Read + Send + 'static>> {
         let mut inner = self.inner.borrow_mut();
         inner.check_connected()?;
-        match &mut *inner {
-            WrappedSshPtyInner::Connected { ref mut reader, .. }
-            | WrappedSshPtyInner::Connecting { ref mut reader, .. } => match reader.take() {
-                Some(r) => Ok(Box::new(r)),
-                None => anyhow::bail!("reader already taken"),
-            },
+        match inner.reader.take() {
+            Some(r) => Ok(Box::new(r)),
+            None => anyhow::bail!("reader already taken"),
         }
     }
 
@@ -0,0 +1,509 @@
+//! An ssh agent that holds identities in memory, rather than
+//! relaying to an agent provided by the system.
+use super::keys::PrivateKey;
+use super::protocol::{read_message, write_message, Constraints, Request, Response};
+use crate::{Mux, MuxNotification};
+use parking_lot::Mutex;
+use smol::channel::{bounded, Sender};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub type ConfirmationId = usize;
+
+/// How long to wait for the user to respond to a confirmation
+/// prompt before denying the request
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+    static ref CONFIRMATIONS: Mutex<HashMap<ConfirmationId, Sender<bool>>> = Mutex::new(HashMap::new());
+}
+static NEXT_CONFIRMATION_ID: AtomicUsize = AtomicUsize::new(0);
+static LOCAL_PROMPT: AtomicBool = AtomicBool::new(false);
+
+/// Called by the gui to indicate that it prompts the user in
+/// response to MuxNotification::SshAgentConfirmation itself.
+/// Otherwise, as is the case for a standalone mux server, the
+/// prompt is delegated to the gui client that the user is
+/// interacting with.
+pub fn register_local_prompt() {
+    LOCAL_PROMPT.store(true, Ordering::Relaxed);
+}
+
+/// Called with the user's response to a
+/// MuxNotification::SshAgentConfirmation
+pub fn resolve_confirmation(id: ConfirmationId, allowed: bool) {
+    if let Some(sender) = CONFIRMATIONS.lock().remove(&id) {
+        sender.try_send(allowed).ok();
+    }
+}
+
+/// Asks the user to approve the request described by `message` and
+/// waits for them to respond.
+/// The request is denied if there is no gui to show the prompt, or
+/// if the user doesn't respond in time.
+pub async fn request_confirmation(message: String) -> bool {
+    let client_id = if LOCAL_PROMPT.load(Ordering::Relaxed) {
+        None
+    } else {
+        match Mux::try_get().and_then(|mux| mux.most_recently_focused_client()) {
+            Some(client_id) => Some(client_id),
+            None => {
+                log::warn!("ssh agent: no gui is attached to confirm: {message}");
+                return false;
+            }
+        }
+    };
+
+    let id = NEXT_CONFIRMATION_ID.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = bounded(1);
+    CONFIRMATIONS.lock().insert(id, sender);
+
+    Mux::notify_from_any_thread(MuxNotification::SshAgentConfirmation {
+        id,
+        message,
+        client_id,
+    });
+
+    let allowed = smol::future::or(async { receiver.recv().await.unwrap_or(false) }, async {
+        smol::Timer::after(CONFIRMATION_TIMEOUT).await;
+        false
+    })
+    .await;
+    CONFIRMATIONS.lock().remove(&id);
+    allowed
+}
+
+struct Identity {
+    key: PrivateKey,
+    comment: String,
+    expires: Option<Instant>,
+    confirm: bool,
+}
+
+#[derive(Default)]
+struct KeyStore {
+    identities: Vec<Identity>,
+    /// Set while the agent is locked
+    lock_passphrase: Option<Vec<u8>>,
+}
+
+impl KeyStore {
+    fn purge_expired(&mut self) {
+        let now = Instant::now();
+        self.identities.retain(|ident| match ident.expires {
+            Some(expires) if expires <= now => {
+                log::info!(
+                    "ssh agent: lifetime of {} {} has expired",
+                    ident.key.fingerprint(),
+                    ident.comment
+                );
+                false
+            }
+            _ => true,
+        });
+    }
+
+    fn add(&mut self, key: PrivateKey, comment: String, constraints: Constraints) {
+        // Re-adding a key replaces its comment and constraints
+        self.identities
+            .retain(|ident| ident.key.public_blob() != key.public_blob());
+        self.identities.push(Identity {
+            key,
+            comment,
+            expires: constraints
+                .lifetime
+                .map(|lifetime| Instant::now() + lifetime),
+            confirm: constraints.confirm,
+        });
+    }
+
+    fn remove(&mut self, key_blob: &[u8]) -> bool {
+        let before = self.identities.len();
+        self.identities
+            .retain(|ident| ident.key.public_blob() != key_blob);
+        self.identities.len() != before
+    }
+}
+
+/// Carries out a request.  `confirm` is called to ask the user
+/// whether a key that was added with `ssh-add -c` may be used.
+fn handle_request(
+    store: &Mutex<KeyStore>,
+    request: Request,
+    confirm: impl FnOnce(String) -> bool,
+) -> Response {
+    let mut store = store.lock();
+    store.purge_expired();
+    let locked = store.lock_passphrase.is_some();
+
+    match request {
+        Request::RequestIdentities => {
+            if locked {
+                return Response::Identities(vec![]);
+            }
+            Response::Identities(
+                store
+                    .identities
+                    .iter()
+                    .map(|ident| (ident.key.public_blob().to_vec(), ident.comment.clone()))
+                    .collect(),
+            )
+        }
+        Request::SignRequest {
+            key_blob,
+            data,
+            flags,
+        } => {
+            if locked {
+                return Response::Failure;
+            }
+            let (key, prompt) = match store
+                .identities
+                .iter()
+                .find(|ident| ident.key.public_blob() == key_blob)
+            {
+                Some(ident) => (
+                    ident.key.clone(),
+                    if ident.confirm {
+                        Some(format!(
+                            "Allow use of ssh key {} {}?",
+                            ident.key.fingerprint(),
+                            ident.comment
+                        ))
+                    } else {
+                        None
+                    },
+                ),
+                None => return Response::Failure,
+            };
+            // Don't hold the lock while waiting for the user, so that
+            // other clients can continue to use the agent
+            drop(store);
+
+            if let Some(prompt) = prompt {
+                if !confirm(prompt) {
+                    log::info!("ssh agent: use of {} was denied", key.fingerprint());
+                    return Response::Failure;
+                }
+            }
+
+            match key.sign(data, flags) {
+                Ok(signature) => Response::Signature(signature),
+                Err(err) => {
+                    log::error!("ssh agent: signing with {:?} failed: {:#}", key, err);
+                    Response::Failure
+                }
+            }
+        }
+        Request::AddIdentity {
+            key,
+            comment,
+            constraints,
+        } => {
+            if locked {
+                return Response::Failure;
+            }
+            log::info!("ssh agent: adding {} {}", key.fingerprint(), comment);
+            store.add(key, comment, constraints);
+            Response::Success
+        }
+        Request::RemoveIdentity { key_blob } => {
+            if !locked && store.remove(key_blob) {
+                Response::Success
+            } else {
+                Response::Failure
+            }
+        }
+        Request::RemoveAllIdentities => {
+            if locked {
+                return Response::Failure;
+            }
+            store.identities.clear();
+            Response::Success
+        }
+        Request::Lock { passphrase } => {
+            if locked {
+                return Response::Failure;
+            }
+            store.lock_passphrase.replace(passphrase.to_vec());
+            Response::Success
+        }
+        Request::Unlock { passphrase } => {
+            if store.lock_passphrase.as_deref() == Some(passphrase) {
+                store.lock_passphrase.take();
+                Response::Success
+            } else {
+                Response::Failure
+            }
+        }
+        Request::Unsupported(kind) => {
+            log::debug!("ssh agent: unsupported request type {}", kind);
+            Response::Failure
+        }
+    }
+}
+
+fn process_client(
+    store: Arc<Mutex<KeyStore>>,
+    mut stream: impl Read + Write,
+) -> anyhow::Result<()> {
+    while let Some(message) = read_message(&mut stream)? {
+        let response = match Request::parse(&message) {
+            Ok(request) => handle_request(&store, request, |message| {
+                smol::block_on(request_confirmation(message))
+            }),
+            Err(err) => {
+                log::error!("ssh agent: invalid request: {:#}", err);
+                Response::Failure
+            }
+        };
+        write_message(&mut stream, &response.encode())?;
+    }
+    Ok(())
+}
+
+/// Binds the agent socket at `path` such that only we can connect to it
+#[cfg(unix)]
+fn bind(path: &Path) -> anyhow::Result<std::os::unix::net::UnixListener> {
+    wezterm_ssh::bind_private_socket(path)
+}
+
+#[cfg(windows)]
+fn bind(path: &Path) -> anyhow::Result<wezterm_uds::UnixListener> {
+    use anyhow::Context;
+    wezterm_uds::UnixListener::bind(path).with_context(|| format!("binding to {}", path.display()))
+}
+
+/// Binds the agent socket at `path` and services it from
+/// background threads for the remainder of the process
+pub fn serve(path: &Path) -> anyhow::Result<()> {
+    // Remove any stale socket or proxy symlink from a prior run
+    std::fs::remove_file(path).ok();
+    let listener = bind(path)?;
+
+    let store = Arc::new(Mutex::new(KeyStore::default()));
+
+    let reaper = Arc::clone(&store);
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(1));
+        reaper.lock().purge_expired();
+    });
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let store = Arc::clone(&store);
+                    std::thread::spawn(move || {
+                        if let Err(err) = process_client(store, stream) {
+                            log::error!("ssh agent: {:#}", err);
+                        }
+                    });
+                }
+                Err(err) => {
+                    log::error!("ssh agent: accept failed: {:#}", err);
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ssh_agent::protocol::{Reader, Writer};
+    use openssl::pkey::PKey;
+
+    fn generate_key() -> PrivateKey {
+        let pkey = PKey::generate_ed25519().unwrap();
+        let public = pkey.raw_public_key().unwrap();
+        let mut private = pkey.raw_private_key().unwrap();
+        private.extend_from_slice(&public);
+
+        let mut fields = Writer::new();
+        fields.write_string(b"ssh-ed25519");
+        fields.write_string(&public);
+        fields.write_string(&private);
+        let fields = fields.into_inner();
+        PrivateKey::read(&mut Reader::new(&fields)).unwrap()
+    }
+
+    fn never_asked(message: String) -> bool {
+        panic!("unexpected confirmation prompt: {}", message);
+    }
+
+    fn add(store: &Mutex<KeyStore>, key: &PrivateKey, comment: &str, constraints: Constraints) {
+        let response = handle_request(
+            store,
+            Request::AddIdentity {
+                key: key.clone(),
+                comment: comment.to_string(),
+                constraints,
+            },
+            never_asked,
+        );
+        assert_eq!(response, Response::Success);
+    }
+
+    fn comments(store: &Mutex<KeyStore>) -> Vec<String> {
+        match handle_request(store, Request::RequestIdentities, never_asked) {
+            Response::Identities(identities) => identities
+                .into_iter()
+                .map(|(_key_blob, comment)| comment)
+                .collect(),
+            response => panic!("unexpected {:?}", response),
+        }
+    }
+
+    fn sign(
+        store: &Mutex<KeyStore>,
+        key: &PrivateKey,
+        confirm: impl FnOnce(String) -> bool,
+    ) -> bool {
+        let request = Request::SignRequest {
+            key_blob: key.public_blob(),
+            data: b"data",
+            flags: 0,
+        };
+        match handle_request(store, request, confirm) {
+            Response::Signature(_) => true,
+            Response::Failure => false,
+            response => panic!("unexpected {:?}", response),
+        }
+    }
+
+    #[test]
+    fn lock_and_unlock() {
+        let store = Mutex::new(KeyStore::default());
+        let key = generate_key();
+        add(&store, &key, "key", Constraints::default());
+
+        let lock =
+            |passphrase: &[u8]| handle_request(&store, Request::Lock { passphrase }, never_asked);
+        let unlock =
+            |passphrase: &[u8]| handle_request(&store, Request::Unlock { passphrase }, never_asked);
+
+        assert_eq!(lock(b"secret"), Response::Success);
+        // A locked agent has no identities and refuses to change them
+        assert!(comments(&store).is_empty());
+        assert!(!sign(&store, &key, never_asked));
+        assert_eq!(
+            handle_request(
+                &store,
+                Request::RemoveIdentity {
+                    key_blob: key.public_blob()
+                },
+                never_asked
+            ),
+            Response::Failure
+        );
+        assert_eq!(
+            handle_request(&store, Request::RemoveAllIdentities, never_asked),
+            Response::Failure
+        );
+        assert_eq!(lock(b"other"), Response::Failure);
+
+        assert_eq!(unlock(b"wrong"), Response::Failure);
+        assert!(comments(&store).is_empty());
+
+        assert_eq!(unlock(b"secret"), Response::Success);
+        assert_eq!(comments(&store), vec!["key"]);
+        assert!(sign(&store, &key, never_asked));
+        assert_eq!(unlock(b"secret"), Response::Failure);
+    }
+
+    #[test]
+    fn lifetime_expiry() {
+        let store = Mutex::new(KeyStore::default());
+        let expired = generate_key();
+        add(
+            &store,
+            &expired,
+            "expired",
+            Constraints {
+                lifetime: Some(Duration::ZERO),
+                confirm: false,
+            },
+        );
+        add(
+            &store,
+            &generate_key(),
+            "hour",
+            Constraints {
+                lifetime: Some(Duration::from_secs(3600)),
+                confirm: false,
+            },
+        );
+        add(&store, &generate_key(), "forever", Constraints::default());
+
+        assert_eq!(comments(&store), vec!["hour", "forever"]);
+        assert!(!sign(&store, &expired, never_asked));
+    }
+
+    #[test]
+    fn confirm_deny() {
+        let store = Mutex::new(KeyStore::default());
+        let key = generate_key();
+        add(
+            &store,
+            &key,
+            "confirmed",
+            Constraints {
+                lifetime: None,
+                confirm: true,
+            },
+        );
+
+        let fingerprint = key.fingerprint();
+        assert!(!sign(&store, &key, |message| {
+            assert!(message.contains(&fingerprint), "{}", message);
+            assert!(message.contains("confirmed"), "{}", message);
+            false
+        }));
+        assert!(sign(&store, &key, |_| true));
+
+        // Keys added without the constraint don't prompt
+        let other = generate_key();
+        add(&store, &other, "other", Constraints::default());
+        assert!(sign(&store, &other, never_asked));
+    }
+
+    #[test]
+    fn remove() {
+        let store = Mutex::new(KeyStore::default());
+        let first = generate_key();
+        let second = generate_key();
+        add(&store, &first, "first", Constraints::default());
+        add(&store, &second, "second", Constraints::default());
+        // Adding a key again replaces its comment
+        add(&store, &first, "renamed", Constraints::default());
+        assert_eq!(comments(&store), vec!["second", "renamed"]);
+
+        let remove = |key: &PrivateKey| {
+            handle_request(
+                &store,
+                Request::RemoveIdentity {
+                    key_blob: key.public_blob(),
+                },
+                never_asked,
+            )
+        };
+        assert_eq!(remove(&first), Response::Success);
+        assert_eq!(remove(&first), Response::Failure);
+        assert_eq!(comments(&store), vec!["second"]);
+        assert!(!sign(&store, &first, never_asked));
+
+        assert_eq!(
+            handle_request(&store, Request::RemoveAllIdentities, never_asked),
+            Response::Success
+        );
+        assert!(comments(&store).is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+//! The private keys that are held by the built-in agent.
+//! Certificates, security keys and DSA keys are not supported.
+use super::protocol::{Reader, Writer, SSH_AGENT_RSA_SHA2_256, SSH_AGENT_RSA_SHA2_512};
+use anyhow::bail;
+use base64::Engine;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::Signer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Curve {
+    NistP256,
+    NistP384,
+    NistP521,
+}
+
+impl Curve {
+    fn from_key_type(key_type: &str) -> Option<Self> {
+        match key_type {
+            "ecdsa-sha2-nistp256" => Some(Self::NistP256),
+            "ecdsa-sha2-nistp384" => Some(Self::NistP384),
+            "ecdsa-sha2-nistp521" => Some(Self::NistP521),
+            _ => None,
+        }
+    }
+
+    fn key_type(self) -> &'static str {
+        match self {
+            Self::NistP256 => "ecdsa-sha2-nistp256",
+            Self::NistP384 => "ecdsa-sha2-nistp384",
+            Self::NistP521 => "ecdsa-sha2-nistp521",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::NistP256 => "nistp256",
+            Self::NistP384 => "nistp384",
+            Self::NistP521 => "nistp521",
+        }
+    }
+
+    fn nid(self) -> Nid {
+        match self {
+            Self::NistP256 => Nid::X9_62_PRIME256V1,
+            Self::NistP384 => Nid::SECP384R1,
+            Self::NistP521 => Nid::SECP521R1,
+        }
+    }
+
+    fn digest(self) -> MessageDigest {
+        match self {
+            Self::NistP256 => MessageDigest::sha256(),
+            Self::NistP384 => MessageDigest::sha384(),
+            Self::NistP521 => MessageDigest::sha512(),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum KeyKind {
+    Ed25519(PKey<Private>),
+    Rsa(PKey<Private>),
+    Ecdsa(Curve, EcKey<Private>),
+}
+
+#[derive(Clone)]
+pub struct PrivateKey {
+    kind: KeyKind,
+    /// The wire encoding of the public key, which is how
+    /// clients refer to the key
+    public_blob: Vec<u8>,
+}
+
+fn bignum(value: &[u8]) -> anyhow::Result<BigNum> {
+    Ok(BigNum::from_slice(value)?)
+}
+
+impl PrivateKey {
+    /// Reads the key type and key specific fields of an
+    /// add identity request
+    pub fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        let key_type = reader.read_str()?;
+        let mut public_blob = Writer::new();
+        public_blob.write_string(key_type.as_bytes());
+
+        let kind = match key_type {
+            "ssh-ed25519" => {
+                let public = reader.read_string()?;
+                // This is the 32 byte seed followed by the public key
+                let private = reader.read_string()?;
+                if public.len() != 32 || private.len() != 64 {
+                    bail!("invalid ssh-ed25519 key");
+                }
+                public_blob.write_string(public);
+                KeyKind::Ed25519(PKey::private_key_from_raw_bytes(
+                    &private[..32],
+                    Id::ED25519,
+                )?)
+            }
+            "ssh-rsa" => {
+                let n = bignum(reader.read_mpint()?)?;
+                let e = bignum(reader.read_mpint()?)?;
+                let d = bignum(reader.read_mpint()?)?;
+                let iqmp = bignum(reader.read_mpint()?)?;
+                let p = bignum(reader.read_mpint()?)?;
+                let q = bignum(reader.read_mpint()?)?;
+
+                public_blob.write_mpint(&e.to_vec());
+                public_blob.write_mpint(&n.to_vec());
+
+                // The agent protocol doesn't include the CRT exponents
+                // that openssl wants, so derive them
+                let mut ctx = BigNumContext::new()?;
+                let one = BigNum::from_u32(1)?;
+                let mut p1 = BigNum::new()?;
+                p1.checked_sub(&p, &one)?;
+                let mut q1 = BigNum::new()?;
+                q1.checked_sub(&q, &one)?;
+                let mut dmp1 = BigNum::new()?;
+                dmp1.nnmod(&d, &p1, &mut ctx)?;
+                let mut dmq1 = BigNum::new()?;
+                dmq1.nnmod(&d, &q1, &mut ctx)?;
+
+                let rsa = Rsa::from_private_components(n, e, d, p, q, dmp1, dmq1, iqmp)?;
+                if !rsa.check_key()? {
+                    bail!("invalid ssh-rsa key");
+                }
+                KeyKind::Rsa(PKey::from_rsa(rsa)?)
+            }
+            key_type => match Curve::from_key_type(key_type) {
+                Some(curve) => {
+                    let name = reader.read_str()?;
+                    if name != curve.name() {
+                        bail!("curve {} doesn't match key type {}", name, key_type);
+                    }
+                    let public = reader.read_string()?;
+                    let private = bignum(reader.read_mpint()?)?;
+
+                    public_blob.write_string(name.as_bytes());
+                    public_blob.write_string(public);
+
+                    let group = EcGroup::from_curve_name(curve.nid())?;
+                    let mut ctx = BigNumContext::new()?;
+                    let point = EcPoint::from_bytes(&group, public, &mut ctx)?;
+                    let key = EcKey::from_private_components(&group, &private, &point)?;
+                    key.check_key()?;
+                    KeyKind::Ecdsa(curve, key)
+                }
+                None => bail!("unsupported key type {}", key_type),
+            },
+        };
+
+        Ok(Self {
+            kind,
+            public_blob: public_blob.into_inner(),
+        })
+    }
+
+    pub fn public_blob(&self) -> &[u8] {
+        &self.public_blob
+    }
+
+    /// Returns the SHA256 fingerprint in the same form
+    /// that `ssh-keygen -l` and `ssh-add -l` show it
+    pub fn fingerprint(&self) -> String {
+        match hash(MessageDigest::sha256(), &self.public_blob) {
+            Ok(digest) => format!(
+                "SHA256:{}",
+                base64::engine::general_purpose::STANDARD_NO_PAD.encode(&*digest)
+            ),
+            Err(err) => format!("<{:#}>", err),
+        }
+    }
+
+    /// Signs `data`, returning the wire encoding of the signature.
+    /// For RSA keys, `flags` selects the hash algorithm.
+    pub fn sign(&self, data: &[u8], flags: u32) -> anyhow::Result<Vec<u8>> {
+        let mut signature = Writer::new();
+        match &self.kind {
+            KeyKind::Ed25519(key) => {
+                let mut signer = Signer::new_without_digest(key)?;
+                signature.write_string(b"ssh-ed25519");
+                signature.write_string(&signer.sign_oneshot_to_vec(data)?);
+            }
+            KeyKind::Rsa(key) => {
+                let (name, digest) = if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+                    ("rsa-sha2-512", MessageDigest::sha512())
+                } else if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+                    ("rsa-sha2-256", MessageDigest::sha256())
+                } else {
+                    ("ssh-rsa", MessageDigest::sha1())
+                };
+                let mut signer = Signer::new(digest, key)?;
+                signature.write_string(name.as_bytes());
+                signature.write_string(&signer.sign_oneshot_to_vec(data)?);
+            }
+            KeyKind::Ecdsa(curve, key) => {
+                let digest = hash(curve.digest(), data)?;
+                let sig = EcdsaSig::sign(&digest, key)?;
+                let mut blob = Writer::new();
+                blob.write_mpint(&sig.r().to_vec());
+                blob.write_mpint(&sig.s().to_vec());
+                signature.write_string(curve.key_type().as_bytes());
+                signature.write_string(&blob.into_inner());
+            }
+        }
+        Ok(signature.into_inner())
+    }
+}
+
+impl std::fmt::Debug for PrivateKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Don't risk revealing the key material
+        fmt.debug_struct("PrivateKey")
+            .field("fingerprint", &self.fingerprint())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use openssl::sign::Verifier;
+
+    const DATA: &[u8] = b"session identifier and userauth request";
+
+    /// Returns the signature bytes from the wire encoding of a
+    /// signature, after checking that it has the expected algorithm
+    fn signature_bytes(signature: &[u8], algorithm: &str) -> Vec<u8> {
+        let mut reader = Reader::new(signature);
+        assert_eq!(reader.read_str().unwrap(), algorithm);
+        reader.read_string().unwrap().to_vec()
+    }
+
+    #[test]
+    fn ed25519() {
+        let pkey = PKey::generate_ed25519().unwrap();
+        let public = pkey.raw_public_key().unwrap();
+        let mut private = pkey.raw_private_key().unwrap();
+        private.extend_from_slice(&public);
+
+        let mut fields = Writer::new();
+        fields.write_string(b"ssh-ed25519");
+        fields.write_string(&public);
+        fields.write_string(&private);
+        let fields = fields.into_inner();
+        let key = PrivateKey::read(&mut Reader::new(&fields)).unwrap();
+
+        let signature = key.sign(DATA, 0).unwrap();
+        let signature = signature_bytes(&signature, "ssh-ed25519");
+        let public = PKey::public_key_from_raw_bytes(&public, Id::ED25519).unwrap();
+        let mut verifier = Verifier::new_without_digest(&public).unwrap();
+        assert!(verifier.verify_oneshot(&signature, DATA).unwrap());
+    }
+
+    #[test]
+    fn rsa() {
+        let rsa = Rsa::generate(2048).unwrap();
+
+        let mut fields = Writer::new();
+        fields.write_string(b"ssh-rsa");
+        fields.write_mpint(&rsa.n().to_vec());
+        fields.write_mpint(&rsa.e().to_vec());
+        fields.write_mpint(&rsa.d().to_vec());
+        fields.write_mpint(&rsa.iqmp().unwrap().to_vec());
+        fields.write_mpint(&rsa.p().unwrap().to_vec());
+        fields.write_mpint(&rsa.q().unwrap().to_vec());
+        let fields = fields.into_inner();
+        let key = PrivateKey::read(&mut Reader::new(&fields)).unwrap();
+
+        let mut expected = Writer::new();
+        expected.write_string(b"ssh-rsa");
+        expected.write_mpint(&rsa.e().to_vec());
+        expected.write_mpint(&rsa.n().to_vec());
+        assert_eq!(key.public_blob(), expected.into_inner().as_slice());
+
+        let signature = key.sign(DATA, SSH_AGENT_RSA_SHA2_256).unwrap();
+        let signature = signature_bytes(&signature, "rsa-sha2-256");
+        let public = PKey::from_rsa(rsa).unwrap();
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &public).unwrap();
+        assert!(verifier.verify_oneshot(&signature, DATA).unwrap());
+    }
+
+    #[test]
+    fn ecdsa() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let ec = EcKey::generate(&group).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let public = ec
+            .public_key()
+            .to_bytes(
+                &group,
+                openssl::ec::PointConversionForm::UNCOMPRESSED,
+                &mut ctx,
+            )
+            .unwrap();
+
+        let mut fields = Writer::new();
+        fields.write_string(b"ecdsa-sha2-nistp256");
+        fields.write_string(b"nistp256");
+        fields.write_string(&public);
+        fields.write_mpint(&ec.private_key().to_vec());
+        let fields = fields.into_inner();
+        let key = PrivateKey::read(&mut Reader::new(&fields)).unwrap();
+
+        let signature = key.sign(DATA, 0).unwrap();
+        let signature = signature_bytes(&signature, "ecdsa-sha2-nistp256");
+        let mut reader = Reader::new(&signature);
+        let r = bignum(reader.read_mpint().unwrap()).unwrap();
+        let s = bignum(reader.read_mpint().unwrap()).unwrap();
+        let sig = EcdsaSig::from_private_components(r, s).unwrap();
+        let digest = hash(MessageDigest::sha256(), DATA).unwrap();
+        assert!(sig.verify(&digest, &ec).unwrap());
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut fields = Writer::new();
+        fields.write_string(b"sk-ssh-ed25519@openssh.com");
+        let fields = fields.into_inner();
+        assert!(PrivateKey::read(&mut Reader::new(&fields)).is_err());
+    }
+}
@@ -10,6 +10,14 @@ use std::path::{Path, PathBuf};
 use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
 use std::sync::Arc;
 
+mod builtin;
+mod keys;
+mod protocol;
+
+pub use builtin::{
+    register_local_prompt, request_confirmation, resolve_confirmation, ConfirmationId,
+};
+
 /// AgentProxy manages an agent.PID symlink in the wezterm runtime
 /// directory.
 /// The intent is to maintain the symlink and have it point to the
@@ -38,11 +46,17 @@ use std::sync::Arc;
 /// How is this used? The Mux::client_had_input function
 /// will call AgentProxy::update_target to signal when
 /// the active client may have changed.
+///
+/// When `mux_builtin_ssh_agent` is enabled, agent.PID is instead
+/// a socket served by our own agent implementation which holds
+/// the identities that are added to it, and there is no symlink
+/// to maintain.
 
 pub struct AgentProxy {
     sock_path: PathBuf,
     current_target: RwLock<Option<Arc<ClientId>>>,
     sender: SyncSender<()>,
+    builtin: bool,
 }
 
 impl Drop for AgentProxy {
@@ -84,20 +98,28 @@ impl AgentProxy {
         let pid = unsafe { libc::getpid() };
         let sock_path = config::RUNTIME_DIR.join(format!("agent.{pid}"));
 
-        if let Some(inherited) = Self::default_ssh_auth_sock() {
-            if let Err(err) = update_symlink(&inherited, &sock_path) {
-                log::error!("failed to set {sock_path:?} to initial inherited SSH_AUTH_SOCK value of {inherited:?}: {err:#}");
-            }
-        }
-
+        let builtin = config::configuration().mux_builtin_ssh_agent;
         let (sender, receiver) = sync_channel(16);
 
-        std::thread::spawn(move || Self::process_updates(receiver));
+        if builtin {
+            if let Err(err) = builtin::serve(&sock_path) {
+                log::error!("failed to start built-in ssh agent: {err:#}");
+            }
+        } else {
+            if let Some(inherited) = Self::default_ssh_auth_sock() {
+                if let Err(err) = update_symlink(&inherited, &sock_path) {
+                    log::error!("failed to set {sock_path:?} to initial inherited SSH_AUTH_SOCK value of {inherited:?}: {err:#}");
+                }
+            }
+
+            std::thread::spawn(move || Self::process_updates(receiver));
+        }
 
         Self {
             sock_path,
             current_target: RwLock::new(None),
             sender,
+            builtin,
         }
     }
 
@@ -113,6 +135,10 @@ impl AgentProxy {
     }
 
     pub fn update_target(&self) {
+        if self.builtin {
+            // Our own agent doesn't depend upon which client is active
+            return;
+        }
         // If the send fails, the channel is most likely
         // full, which means that the updater thread is
         // going to observe the now-current state when
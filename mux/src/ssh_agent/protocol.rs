@@ -0,0 +1,413 @@
+//! Encoding and decoding of the messages that make up the ssh-agent
+//! protocol, as described by draft-miller-ssh-agent.
+use super::keys::PrivateKey;
+use anyhow::{anyhow, bail};
+use std::io::{Read, Write};
+use std::time::Duration;
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENT_SUCCESS: u8 = 6;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+const SSH_AGENTC_ADD_IDENTITY: u8 = 17;
+const SSH_AGENTC_REMOVE_IDENTITY: u8 = 18;
+const SSH_AGENTC_REMOVE_ALL_IDENTITIES: u8 = 19;
+const SSH_AGENTC_LOCK: u8 = 22;
+const SSH_AGENTC_UNLOCK: u8 = 23;
+const SSH_AGENTC_ADD_ID_CONSTRAINED: u8 = 25;
+
+const SSH_AGENT_CONSTRAIN_LIFETIME: u8 = 1;
+const SSH_AGENT_CONSTRAIN_CONFIRM: u8 = 2;
+
+pub const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+pub const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// The largest message that we're prepared to accept,
+/// which matches the limit used by OpenSSH
+const MAX_MESSAGE_SIZE: usize = 256 * 1024;
+
+/// A cursor over the fields of a message
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if len > self.data.len() {
+            bail!(
+                "truncated message: wanted {} bytes but only {} remain",
+                len,
+                self.data.len()
+            );
+        }
+        let (field, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(field)
+    }
+
+    pub fn read_u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_string(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn read_str(&mut self) -> anyhow::Result<&'a str> {
+        Ok(std::str::from_utf8(self.read_string()?)?)
+    }
+
+    /// Returns the big-endian magnitude of a non-negative mpint,
+    /// without any leading zeroes
+    pub fn read_mpint(&mut self) -> anyhow::Result<&'a [u8]> {
+        let value = self.read_string()?;
+        if value.first().map(|b| b & 0x80 != 0).unwrap_or(false) {
+            bail!("negative mpint");
+        }
+        let leading_zeroes = value.iter().take_while(|&&b| b == 0).count();
+        Ok(&value[leading_zeroes..])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Accumulates the fields of a message
+#[derive(Default)]
+pub struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_string(&mut self, value: &[u8]) {
+        self.write_u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+
+    /// Writes a non-negative mpint given its big-endian magnitude
+    pub fn write_mpint(&mut self, magnitude: &[u8]) {
+        let leading_zeroes = magnitude.iter().take_while(|&&b| b == 0).count();
+        let magnitude = &magnitude[leading_zeroes..];
+        // A leading zero byte is needed to keep the value positive
+        // when the high bit is set
+        let pad = magnitude.first().map(|b| b & 0x80 != 0).unwrap_or(false);
+        self.write_u32(magnitude.len() as u32 + pad as u32);
+        if pad {
+            self.data.push(0);
+        }
+        self.data.extend_from_slice(magnitude);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Restrictions on the use of an identity
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Constraints {
+    /// How long the identity remains usable after it is added
+    pub lifetime: Option<Duration>,
+    /// Whether the user has to confirm each use of the identity
+    pub confirm: bool,
+}
+
+impl Constraints {
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        let mut constraints = Self::default();
+        while !reader.is_empty() {
+            match reader.read_u8()? {
+                SSH_AGENT_CONSTRAIN_LIFETIME => {
+                    constraints.lifetime = Some(Duration::from_secs(reader.read_u32()? as u64));
+                }
+                SSH_AGENT_CONSTRAIN_CONFIRM => {
+                    constraints.confirm = true;
+                }
+                // We don't support any constraint extensions, and an
+                // agent must refuse identities with unknown constraints
+                other => bail!("unsupported constraint {}", other),
+            }
+        }
+        Ok(constraints)
+    }
+}
+
+pub enum Request<'a> {
+    RequestIdentities,
+    SignRequest {
+        key_blob: &'a [u8],
+        data: &'a [u8],
+        flags: u32,
+    },
+    AddIdentity {
+        key: PrivateKey,
+        comment: String,
+        constraints: Constraints,
+    },
+    RemoveIdentity {
+        key_blob: &'a [u8],
+    },
+    RemoveAllIdentities,
+    Lock {
+        passphrase: &'a [u8],
+    },
+    Unlock {
+        passphrase: &'a [u8],
+    },
+    /// Includes extensions, smartcard requests and the
+    /// obsolete protocol 1 requests
+    Unsupported(u8),
+}
+
+impl<'a> Request<'a> {
+    pub fn parse(message: &'a [u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(message);
+        let request = match reader.read_u8()? {
+            SSH_AGENTC_REQUEST_IDENTITIES => Self::RequestIdentities,
+            SSH_AGENTC_SIGN_REQUEST => Self::SignRequest {
+                key_blob: reader.read_string()?,
+                data: reader.read_string()?,
+                flags: reader.read_u32()?,
+            },
+            kind @ (SSH_AGENTC_ADD_IDENTITY | SSH_AGENTC_ADD_ID_CONSTRAINED) => {
+                let key = PrivateKey::read(&mut reader)?;
+                let comment = reader.read_str()?.to_string();
+                let constraints = if kind == SSH_AGENTC_ADD_ID_CONSTRAINED {
+                    Constraints::read(&mut reader)?
+                } else {
+                    Constraints::default()
+                };
+                Self::AddIdentity {
+                    key,
+                    comment,
+                    constraints,
+                }
+            }
+            SSH_AGENTC_REMOVE_IDENTITY => Self::RemoveIdentity {
+                key_blob: reader.read_string()?,
+            },
+            SSH_AGENTC_REMOVE_ALL_IDENTITIES => Self::RemoveAllIdentities,
+            SSH_AGENTC_LOCK => Self::Lock {
+                passphrase: reader.read_string()?,
+            },
+            SSH_AGENTC_UNLOCK => Self::Unlock {
+                passphrase: reader.read_string()?,
+            },
+            other => return Ok(Self::Unsupported(other)),
+        };
+        if !reader.is_empty() {
+            return Err(anyhow!("trailing data in request"));
+        }
+        Ok(request)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response {
+    Success,
+    Failure,
+    /// The public key blob and comment of each identity
+    Identities(Vec<(Vec<u8>, String)>),
+    Signature(Vec<u8>),
+}
+
+impl Response {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        match self {
+            Self::Success => writer.write_u8(SSH_AGENT_SUCCESS),
+            Self::Failure => writer.write_u8(SSH_AGENT_FAILURE),
+            Self::Identities(identities) => {
+                writer.write_u8(SSH_AGENT_IDENTITIES_ANSWER);
+                writer.write_u32(identities.len() as u32);
+                for (key_blob, comment) in identities {
+                    writer.write_string(key_blob);
+                    writer.write_string(comment.as_bytes());
+                }
+            }
+            Self::Signature(signature) => {
+                writer.write_u8(SSH_AGENT_SIGN_RESPONSE);
+                writer.write_string(signature);
+            }
+        }
+        writer.into_inner()
+    }
+}
+
+/// Reads a length prefixed message, returning None if the
+/// peer closed the connection
+pub fn read_message(stream: &mut impl Read) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        bail!("message of {} bytes is too large", len);
+    }
+    let mut message = vec![0u8; len];
+    stream.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+pub fn write_message(stream: &mut impl Write, message: &[u8]) -> anyhow::Result<()> {
+    stream.write_all(&(message.len() as u32).to_be_bytes())?;
+    stream.write_all(message)?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use openssl::pkey::PKey;
+
+    #[test]
+    fn mpint() {
+        let mut writer = Writer::new();
+        writer.write_mpint(&[]);
+        writer.write_mpint(&[0x00, 0x7f]);
+        writer.write_mpint(&[0x80, 0x01]);
+        let data = writer.into_inner();
+        assert_eq!(
+            data,
+            vec![0, 0, 0, 0, 0, 0, 0, 1, 0x7f, 0, 0, 0, 3, 0, 0x80, 0x01]
+        );
+
+        let mut reader = Reader::new(&data);
+        assert_eq!(reader.read_mpint().unwrap(), &[] as &[u8]);
+        assert_eq!(reader.read_mpint().unwrap(), &[0x7f]);
+        assert_eq!(reader.read_mpint().unwrap(), &[0x80, 0x01]);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut reader = Reader::new(&[0, 0, 0, 5, b'a']);
+        assert!(reader.read_string().is_err());
+    }
+
+    #[test]
+    fn add_constrained_identity() {
+        let pkey = PKey::generate_ed25519().unwrap();
+        let public = pkey.raw_public_key().unwrap();
+        let mut private = pkey.raw_private_key().unwrap();
+        private.extend_from_slice(&public);
+
+        let mut writer = Writer::new();
+        writer.write_u8(SSH_AGENTC_ADD_ID_CONSTRAINED);
+        writer.write_string(b"ssh-ed25519");
+        writer.write_string(&public);
+        writer.write_string(&private);
+        writer.write_string(b"me@host");
+        writer.write_u8(SSH_AGENT_CONSTRAIN_LIFETIME);
+        writer.write_u32(300);
+        writer.write_u8(SSH_AGENT_CONSTRAIN_CONFIRM);
+        let message = writer.into_inner();
+
+        match Request::parse(&message).unwrap() {
+            Request::AddIdentity {
+                key,
+                comment,
+                constraints,
+            } => {
+                let mut expected = Writer::new();
+                expected.write_string(b"ssh-ed25519");
+                expected.write_string(&public);
+                assert_eq!(key.public_blob(), expected.into_inner().as_slice());
+                assert_eq!(comment, "me@host");
+                assert_eq!(
+                    constraints,
+                    Constraints {
+                        lifetime: Some(Duration::from_secs(300)),
+                        confirm: true,
+                    }
+                );
+            }
+            _ => panic!("expected AddIdentity"),
+        }
+    }
+
+    #[test]
+    fn unknown_constraint() {
+        let pkey = PKey::generate_ed25519().unwrap();
+        let public = pkey.raw_public_key().unwrap();
+        let mut private = pkey.raw_private_key().unwrap();
+        private.extend_from_slice(&public);
+
+        let mut writer = Writer::new();
+        writer.write_u8(SSH_AGENTC_ADD_ID_CONSTRAINED);
+        writer.write_string(b"ssh-ed25519");
+        writer.write_string(&public);
+        writer.write_string(&private);
+        writer.write_string(b"");
+        writer.write_u8(255);
+        writer.write_string(b"restrict-destination-v00@openssh.com");
+        assert!(Request::parse(&writer.into_inner()).is_err());
+    }
+
+    #[test]
+    fn identities_answer() {
+        let response = Response::Identities(vec![(vec![1, 2], "a".to_string())]);
+        assert_eq!(
+            response.encode(),
+            vec![
+                SSH_AGENT_IDENTITIES_ANSWER,
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                0,
+                2,
+                1,
+                2,
+                0,
+                0,
+                0,
+                1,
+                b'a'
+            ]
+        );
+    }
+
+    #[test]
+    fn framing() {
+        let mut buf = vec![];
+        write_message(&mut buf, &Response::Success.encode()).unwrap();
+        let mut stream = buf.as_slice();
+        assert_eq!(
+            read_message(&mut stream).unwrap(),
+            Some(vec![SSH_AGENT_SUCCESS])
+        );
+        assert_eq!(read_message(&mut stream).unwrap(), None);
+    }
+}
@@ -2683,6 +2683,17 @@ impl TerminalState {
             }
             Sgr::StrikeThrough(strike) => {
                 self.pen.set_strikethrough(strike);
+                self.pen.set_double_strikethrough(false);
+            }
+            Sgr::DoubleStrikeThrough => {
+                self.pen.set_strikethrough(true);
+                self.pen.set_double_strikethrough(true);
+            }
+            Sgr::StrikeThroughColor(col) => {
+                self.pen.set_strikethrough_color(col);
+            }
+            Sgr::OverlineColor(col) => {
+                self.pen.set_overline_color(col);
             }
             Sgr::Foreground(col) => {
                 self.pen.set_foreground(col);
@@ -826,6 +826,11 @@ impl<'a> Performer<'a> {
                     self.writer.flush().ok();
                 }
                 ITermProprietary::File(image) => self.set_image(*image),
+                ITermProprietary::SetMark => {
+                    let idx = self.screen.phys_row(self.cursor.y);
+                    let seqno = self.seqno;
+                    self.screen.line_mut(idx).set_marked(true, seqno);
+                }
                 ITermProprietary::SetUserVar { name, value } => {
                     self.user_vars.insert(name.clone(), value.clone());
                     if let Some(handler) = self.alert_handler.as_mut() {
@@ -755,6 +755,19 @@ fn test_dec_double_width() {
     assert!(lines[3].is_single_width());
 }
 
+/// Test the iTerm2 SetMark sequence
+#[test]
+fn test_iterm_set_mark() {
+    let mut term = TestTerm::new(3, 10, 0);
+
+    term.print("one\r\n\u{1b}]1337;SetMark\u{7}two\r\nthree");
+
+    let lines = term.screen().visible_lines();
+    assert!(!lines[0].is_marked());
+    assert!(lines[1].is_marked());
+    assert!(!lines[2].is_marked());
+}
+
 /// This test skips over an edge case with cursor positioning,
 /// while sizing down, but tries to trip over the same edge
 /// case while sizing back up again
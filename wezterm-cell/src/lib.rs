@@ -78,6 +78,7 @@ impl core::fmt::Debug for CellAttributes {
             .field("invisible", &self.invisible())
             .field("wrapped", &self.wrapped())
             .field("overline", &self.overline())
+            .field("double_strikethrough", &self.double_strikethrough())
             .field("semantic_type", &self.semantic_type())
             .field("foreground", &self.foreground)
             .field("background", &self.background)
@@ -97,6 +98,12 @@ struct FatAttributes {
     /// The color of the underline.  If None, then
     /// the foreground color is to be used
     underline_color: ColorAttribute,
+    /// The color of the overline.  If None, then
+    /// the foreground color is to be used
+    overline_color: ColorAttribute,
+    /// The color of the strikethrough.  If None, then
+    /// the foreground color is to be used
+    strikethrough_color: ColorAttribute,
     foreground: ColorAttribute,
     background: ColorAttribute,
 }
@@ -111,6 +118,8 @@ impl FatAttributes {
             cell.compute_shape_hash(hasher);
         }
         self.underline_color.hash(hasher);
+        self.overline_color.hash(hasher);
+        self.strikethrough_color.hash(hasher);
         self.foreground.hash(hasher);
         self.background.hash(hasher);
     }
@@ -210,6 +219,9 @@ impl CellAttributes {
     bitfield!(overline, set_overline, 12);
     bitfield!(semantic_type, set_semantic_type, SemanticType, 0b11, 13);
     bitfield!(vertical_align, set_vertical_align, VerticalAlign, 0b11, 15);
+    // When set along with strikethrough, the strikethrough is
+    // rendered using a double line
+    bitfield!(double_strikethrough, set_double_strikethrough, 17);
 
     pub const fn blank() -> Self {
         Self {
@@ -321,6 +333,8 @@ impl CellAttributes {
                 #[cfg(feature = "use_image")]
                 image: vec![],
                 underline_color: ColorAttribute::Default,
+                overline_color: ColorAttribute::Default,
+                strikethrough_color: ColorAttribute::Default,
                 foreground: ColorAttribute::Default,
                 background: ColorAttribute::Default,
             }));
@@ -340,6 +354,8 @@ impl CellAttributes {
                 }
                 fat.hyperlink.is_none()
                     && fat.underline_color == ColorAttribute::Default
+                    && fat.overline_color == ColorAttribute::Default
+                    && fat.strikethrough_color == ColorAttribute::Default
                     && fat.foreground == ColorAttribute::Default
                     && fat.background == ColorAttribute::Default
             })
@@ -419,6 +435,33 @@ impl CellAttributes {
         }
     }
 
+    pub fn set_overline_color<C: Into<ColorAttribute>>(&mut self, overline_color: C) -> &mut Self {
+        let overline_color = overline_color.into();
+        if overline_color == ColorAttribute::Default && self.fat.is_none() {
+            self
+        } else {
+            self.allocate_fat_attributes();
+            self.fat.as_mut().unwrap().overline_color = overline_color;
+            self.deallocate_fat_attributes_if_none();
+            self
+        }
+    }
+
+    pub fn set_strikethrough_color<C: Into<ColorAttribute>>(
+        &mut self,
+        strikethrough_color: C,
+    ) -> &mut Self {
+        let strikethrough_color = strikethrough_color.into();
+        if strikethrough_color == ColorAttribute::Default && self.fat.is_none() {
+            self
+        } else {
+            self.allocate_fat_attributes();
+            self.fat.as_mut().unwrap().strikethrough_color = strikethrough_color;
+            self.deallocate_fat_attributes_if_none();
+            self
+        }
+    }
+
     /// Clone the attributes, but exclude fancy extras such
     /// as hyperlinks or future sprite things
     pub fn clone_sgr_only(&self) -> Self {
@@ -444,6 +487,8 @@ impl CellAttributes {
         // easier time in get_semantic_zones.
         res.set_semantic_type(SemanticType::default());
         res.set_underline_color(self.underline_color());
+        res.set_overline_color(self.overline_color());
+        res.set_strikethrough_color(self.strikethrough_color());
 
         // Turn off underline because it can have surprising results
         // if underline is on, then we get CRLF and then SGR reset:
@@ -458,6 +503,7 @@ impl CellAttributes {
         res.set_underline(Underline::None);
         res.set_overline(false);
         res.set_strikethrough(false);
+        res.set_double_strikethrough(false);
         res
     }
 
@@ -484,6 +530,20 @@ impl CellAttributes {
             .unwrap_or(ColorAttribute::Default)
     }
 
+    pub fn overline_color(&self) -> ColorAttribute {
+        self.fat
+            .as_ref()
+            .map(|fat| fat.overline_color)
+            .unwrap_or(ColorAttribute::Default)
+    }
+
+    pub fn strikethrough_color(&self) -> ColorAttribute {
+        self.fat
+            .as_ref()
+            .map(|fat| fat.strikethrough_color)
+            .unwrap_or(ColorAttribute::Default)
+    }
+
     pub fn apply_change(&mut self, change: &AttributeChange) {
         use AttributeChange::*;
         match change {
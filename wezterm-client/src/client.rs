@@ -4,7 +4,7 @@ use anyhow::{anyhow, bail, Context};
 use async_ossl::AsyncSslStream;
 use async_trait::async_trait;
 use codec::*;
-use config::{configuration, SshDomain, TlsDomainClient, UnixDomain, UnixTarget};
+use config::{configuration, SshDomain, SshReconnect, TlsDomainClient, UnixDomain, UnixTarget};
 use filedescriptor::FileDescriptor;
 use futures::FutureExt;
 use mux::client::ClientId;
@@ -33,6 +33,7 @@ use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
 use thiserror::Error;
+use wezterm_ssh::Session;
 use wezterm_uds::UnixStream;
 
 #[derive(Error, Debug)]
@@ -191,6 +192,51 @@ async fn process_unilateral_inner_async(
     client_pane.process_unilateral(decoded.pdu).await
 }
 
+/// Renders the local counterpart of a remote pane, on behalf of
+/// the server that asked for it via RenderScreenshot
+async fn render_screenshot(local_domain_id: DomainId, pane_id: PaneId) -> anyhow::Result<Vec<u8>> {
+    let mux = Mux::try_get().ok_or_else(|| anyhow!("no more mux"))?;
+    let local_pane_id = ClientDomain::get_client_inner_for_domain(local_domain_id)?
+        .remote_to_local_pane_id(pane_id)
+        .ok_or_else(|| anyhow!("remote pane id {} does not have a local pane id", pane_id))?;
+    let pane = mux
+        .get_pane(local_pane_id)
+        .ok_or_else(|| anyhow!("local pane {local_pane_id} not found"))?;
+    mux::screenshot::render_screenshot_locally(pane).await
+}
+
+async fn reply_with_screenshot(
+    local_domain_id: DomainId,
+    id: usize,
+    pane_id: PaneId,
+) -> anyhow::Result<()> {
+    let result = render_screenshot(local_domain_id, pane_id)
+        .await
+        .map_err(|err| format!("{err:#}"));
+    let inner = ClientDomain::get_client_inner_for_domain(local_domain_id)?;
+    inner
+        .client
+        .screenshot_rendered(ScreenshotRendered { id, result })
+        .await?;
+    Ok(())
+}
+
+/// Prompts the user on behalf of the built-in ssh agent of the
+/// server that asked for it via ConfirmSshAgentRequest
+async fn reply_with_confirmation(
+    local_domain_id: DomainId,
+    id: usize,
+    message: String,
+) -> anyhow::Result<()> {
+    let allowed = mux::ssh_agent::request_confirmation(message).await;
+    let inner = ClientDomain::get_client_inner_for_domain(local_domain_id)?;
+    inner
+        .client
+        .ssh_agent_request_confirmed(SshAgentRequestConfirmed { id, allowed })
+        .await?;
+    Ok(())
+}
+
 fn process_unilateral(
     local_domain_id: Option<DomainId>,
     decoded: DecodedPdu,
@@ -297,6 +343,32 @@ fn process_unilateral(
             .detach();
             return Ok(());
         }
+        Pdu::RenderScreenshot(RenderScreenshot { id, pane_id }) => {
+            let id = *id;
+            let pane_id = *pane_id;
+            promise::spawn::spawn_into_main_thread(async move {
+                // Rendering is not Send, so it is spawned from the main thread
+                promise::spawn::spawn(async move {
+                    if let Err(err) = reply_with_screenshot(local_domain_id, id, pane_id).await {
+                        log::error!("replying with screenshot {id}: {err:#}");
+                    }
+                })
+                .detach();
+            })
+            .detach();
+            return Ok(());
+        }
+        Pdu::ConfirmSshAgentRequest(ConfirmSshAgentRequest { id, message }) => {
+            let id = *id;
+            let message = message.clone();
+            promise::spawn::spawn_into_main_thread(async move {
+                if let Err(err) = reply_with_confirmation(local_domain_id, id, message).await {
+                    log::error!("replying to ssh agent confirmation {id}: {err:#}");
+                }
+            })
+            .detach();
+            return Ok(());
+        }
         Pdu::TabResized(_) | Pdu::TabAddedToWindow(_) => {
             log::trace!("resync due to {:?}", decoded.pdu);
             promise::spawn::spawn_into_main_thread(async move {
@@ -540,6 +612,7 @@ struct Reconnectable {
     config: ClientDomainConfig,
     stream: Option<Box<dyn AsyncReadAndWrite>>,
     tls_creds: Option<GetTlsCredsResponse>,
+    ssh_session: Option<Session>,
 }
 
 struct SshStream {
@@ -604,6 +677,7 @@ impl Reconnectable {
             config,
             stream,
             tls_creds: None,
+            ssh_session: None,
         }
     }
 
@@ -636,12 +710,29 @@ impl Reconnectable {
             // the set of tabs and we'd have confusing and inconsistent state
             ClientDomainConfig::Unix(_) => false,
             ClientDomainConfig::Tls(_) => true,
-            // It *does* make sense to reconnect with an ssh session, but we
-            // need to grow some smarts about whether the disconnect was because
-            // we sent CTRL-D to close the last session, or whether it was a network
-            // level disconnect, because we will otherwise throw up authentication
-            // dialogs that would be annoying
-            ClientDomainConfig::Ssh(_) => false,
+            // It *does* make sense to reconnect with an ssh session, but
+            // that may throw up authentication dialogs, so it is opt-in.
+            // `connection_lost` distinguishes a network level disconnect
+            // from the proxy exiting because we closed the last session.
+            ClientDomainConfig::Ssh(ssh) => ssh.reconnect.enabled,
+        }
+    }
+
+    /// Returns true if the stream ended because the underlying
+    /// ssh session was lost, rather than because the remote
+    /// proxy exited
+    fn connection_lost(&self) -> bool {
+        self.ssh_session
+            .as_ref()
+            .map(|session| session.is_closed())
+            .unwrap_or(false)
+    }
+
+    /// Returns the user specified reconnection policy, if any
+    fn reconnect_policy(&self) -> Option<SshReconnect> {
+        match &self.config {
+            ClientDomainConfig::Ssh(ssh) => Some(ssh.reconnect.clone()),
+            _ => None,
         }
     }
 
@@ -696,6 +787,7 @@ impl Reconnectable {
         log::debug!("going to run {}", cmd);
 
         let exec = smol::block_on(sess.exec(&cmd, None))?;
+        self.ssh_session.replace(sess);
 
         let mut stderr = exec.stderr;
         std::thread::spawn(move || {
@@ -1050,7 +1142,7 @@ impl Client {
             const MAX_INTERVAL: Duration = Duration::from_secs(10);
 
             let mut backoff = BASE_INTERVAL;
-            loop {
+            'reconnect: loop {
                 if let Err(e) = client_thread(&mut reconnectable, local_domain_id, &mut receiver) {
                     if !reconnectable.reconnectable() || local_domain_id.is_none() {
                         log::debug!("client thread ended: {}", e);
@@ -1061,9 +1153,12 @@ impl Client {
 
                     if let Some(ioerr) = e.root_cause().downcast_ref::<std::io::Error>() {
                         if let std::io::ErrorKind::UnexpectedEof = ioerr.kind() {
-                            // Don't reconnect for a simple EOF
-                            log::error!("server closed connection ({})", e);
-                            break;
+                            // Don't reconnect for a simple EOF, unless
+                            // it was caused by losing the ssh session
+                            if !reconnectable.connection_lost() {
+                                log::error!("server closed connection ({})", e);
+                                break;
+                            }
                         }
                     }
 
@@ -1075,7 +1170,22 @@ impl Client {
                     let mut ui = ConnectionUI::new();
                     ui.title("wezterm: Reconnecting...");
 
+                    let policy = reconnectable.reconnect_policy();
+                    if let Some(policy) = &policy {
+                        backoff = policy.delay_for_attempt(1);
+                    }
+                    let mut attempt = 0;
                     loop {
+                        attempt += 1;
+                        if let Some(policy) = &policy {
+                            if attempt > policy.max_attempts {
+                                ui.output_str(&format!(
+                                    "Giving up after {} attempts to reconnect\n",
+                                    policy.max_attempts
+                                ));
+                                break 'reconnect;
+                            }
+                        }
                         ui.sleep_with_reason(
                             &format!("client disconnected {}; will reconnect", e),
                             backoff,
@@ -1094,7 +1204,10 @@ impl Client {
                                 break;
                             }
                             Err(err) => {
-                                backoff = (backoff + backoff).min(MAX_INTERVAL);
+                                backoff = match &policy {
+                                    Some(policy) => policy.delay_for_attempt(attempt + 1),
+                                    None => (backoff + backoff).min(MAX_INTERVAL),
+                                };
                                 ui.output_str(&format!(
                                     "problem reconnecting: {}; will reconnect in {:?}\n",
                                     err, backoff
@@ -1389,4 +1502,13 @@ impl Client {
         GetPaneDirectionResponse
     );
     rpc!(adjust_pane_size, AdjustPaneSize, UnitResponse);
+    rpc!(screenshot_pane, ScreenshotPane, ScreenshotPaneResponse);
+    rpc!(screenshot_rendered, ScreenshotRendered, UnitResponse);
+    rpc!(
+        ssh_agent_request_confirmed,
+        SshAgentRequestConfirmed,
+        UnitResponse
+    );
+    rpc!(ssh_forward, SshForward, SshForwardResponse);
+    rpc!(sftp_transfer, SftpTransfer, SftpTransferResponse);
 }
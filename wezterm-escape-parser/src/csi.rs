@@ -1478,10 +1478,19 @@ pub enum Sgr {
     Inverse(bool),
     Invisible(bool),
     StrikeThrough(bool),
+    /// Enables strikethrough using a double line.
+    /// This is a wezterm extension, expressed as `CSI 9:2 m`
+    DoubleStrikeThrough,
+    /// Sets the color of the strikethrough line.
+    /// This is a wezterm extension.
+    StrikeThroughColor(ColorSpec),
     Font(Font),
     Foreground(ColorSpec),
     Background(ColorSpec),
     Overline(bool),
+    /// Sets the color of the overline.
+    /// This is a wezterm extension.
+    OverlineColor(ColorSpec),
     VerticalAlign(VerticalAlign),
 }
 
@@ -1537,6 +1546,7 @@ impl Display for Sgr {
             Sgr::Invisible(false) => code!(InvisibleOff),
             Sgr::StrikeThrough(true) => code!(StrikeThroughOn),
             Sgr::StrikeThrough(false) => code!(StrikeThroughOff),
+            Sgr::DoubleStrikeThrough => write!(f, "9:2m")?,
             Sgr::Overline(true) => code!(OverlineOn),
             Sgr::Overline(false) => code!(OverlineOff),
             Sgr::VerticalAlign(VerticalAlign::BaseLine) => code!(VerticalAlignBaseLine),
@@ -1674,11 +1684,49 @@ impl Display for Sgr {
             Sgr::UnderlineColor(ColorSpec::PaletteIndex(idx)) => {
                 write!(f, "{}:5:{}m", SgrCode::UnderlineColor as i64, *idx)?
             }
+            Sgr::OverlineColor(spec) => write_decoration_color(
+                f,
+                SgrCode::OverlineColor,
+                SgrCode::ResetOverlineColor,
+                spec,
+            )?,
+            Sgr::StrikeThroughColor(spec) => write_decoration_color(
+                f,
+                SgrCode::StrikeThroughColor,
+                SgrCode::ResetStrikeThroughColor,
+                spec,
+            )?,
         }
         Ok(())
     }
 }
 
+/// Writes a decoration color sequence, which uses the same
+/// parameters as the underline color sequence
+fn write_decoration_color(
+    f: &mut Formatter,
+    code: SgrCode,
+    reset: SgrCode,
+    spec: &ColorSpec,
+) -> Result<(), FmtError> {
+    match spec {
+        ColorSpec::Default => write!(f, "{}m", reset as i64),
+        ColorSpec::TrueColor(c) => {
+            let (red, green, blue, alpha) = c.to_srgb_u8();
+            if alpha == 255 {
+                write!(f, "{}:2::{}:{}:{}m", code as i64, red, green, blue)
+            } else {
+                write!(
+                    f,
+                    "{}:6::{}:{}:{}:{}m",
+                    code as i64, red, green, blue, alpha
+                )
+            }
+        }
+        ColorSpec::PaletteIndex(idx) => write!(f, "{}:5:{}m", code as i64, *idx),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Font {
     Default,
@@ -2672,6 +2720,17 @@ impl<'a> CSIParser<'a> {
         Ok(self.advance_by(n, params, sgr))
     }
 
+    fn strikethrough(&mut self, params: &'a [CsiParam]) -> Result<Sgr, ()> {
+        let (sgr, n) = match params {
+            [_, CsiParam::P(b':'), CsiParam::Integer(0), ..] => (Sgr::StrikeThrough(false), 3),
+            [_, CsiParam::P(b':'), CsiParam::Integer(1), ..] => (Sgr::StrikeThrough(true), 3),
+            [_, CsiParam::P(b':'), CsiParam::Integer(2), ..] => (Sgr::DoubleStrikeThrough, 3),
+            _ => (Sgr::StrikeThrough(true), 1),
+        };
+
+        Ok(self.advance_by(n, params, sgr))
+    }
+
     fn sgr(&mut self, params: &'a [CsiParam]) -> Result<Sgr, ()> {
         if params.is_empty() {
             // With no parameters, treat as equivalent to Reset.
@@ -2851,10 +2910,22 @@ impl<'a> CSIParser<'a> {
                         SgrCode::InverseOff => one!(Sgr::Inverse(false)),
                         SgrCode::InvisibleOn => one!(Sgr::Invisible(true)),
                         SgrCode::InvisibleOff => one!(Sgr::Invisible(false)),
-                        SgrCode::StrikeThroughOn => one!(Sgr::StrikeThrough(true)),
+                        SgrCode::StrikeThroughOn => self.strikethrough(params),
                         SgrCode::StrikeThroughOff => one!(Sgr::StrikeThrough(false)),
                         SgrCode::OverlineOn => one!(Sgr::Overline(true)),
                         SgrCode::OverlineOff => one!(Sgr::Overline(false)),
+                        SgrCode::OverlineColor => {
+                            self.parse_sgr_color(params).map(Sgr::OverlineColor)
+                        }
+                        SgrCode::ResetOverlineColor => {
+                            one!(Sgr::OverlineColor(ColorSpec::default()))
+                        }
+                        SgrCode::StrikeThroughColor => {
+                            self.parse_sgr_color(params).map(Sgr::StrikeThroughColor)
+                        }
+                        SgrCode::ResetStrikeThroughColor => {
+                            one!(Sgr::StrikeThroughColor(ColorSpec::default()))
+                        }
                         SgrCode::DefaultFont => one!(Sgr::Font(Font::Default)),
                         SgrCode::AltFont1 => one!(Sgr::Font(Font::Alternate(1))),
                         SgrCode::AltFont2 => one!(Sgr::Font(Font::Alternate(2))),
@@ -2925,10 +2996,17 @@ pub enum SgrCode {
     BackgroundDefault = 49,
     OverlineOn = 53,
     OverlineOff = 55,
+    /// wezterm extension; uses the same parameters as UnderlineColor
+    OverlineColor = 56,
+    ResetOverlineColor = 57,
 
     UnderlineColor = 58,
     ResetUnderlineColor = 59,
 
+    /// wezterm extension; uses the same parameters as UnderlineColor
+    StrikeThroughColor = 68,
+    ResetStrikeThroughColor = 69,
+
     VerticalAlignSuperScript = 73,
     VerticalAlignSubScript = 74,
     VerticalAlignBaseLine = 75,
@@ -3132,6 +3210,49 @@ mod test {
         );
     }
 
+    #[test]
+    fn decoration_colors() {
+        assert_eq!(
+            parse('m', &[56, 5, 220], "\x1b[56:5:220m"),
+            vec![CSI::Sgr(Sgr::OverlineColor(ColorSpec::PaletteIndex(220)))]
+        );
+        assert_eq!(
+            parse('m', &[57], "\x1b[57m"),
+            vec![CSI::Sgr(Sgr::OverlineColor(ColorSpec::Default))]
+        );
+        assert_eq!(
+            parse('m', &[68, 2, 255, 0, 0], "\x1b[68:2::255:0:0m"),
+            vec![CSI::Sgr(Sgr::StrikeThroughColor(ColorSpec::TrueColor(
+                (255, 0, 0).into(),
+            )))]
+        );
+        assert_eq!(
+            parse('m', &[69], "\x1b[69m"),
+            vec![CSI::Sgr(Sgr::StrikeThroughColor(ColorSpec::Default))]
+        );
+    }
+
+    #[test]
+    fn strikethrough() {
+        assert_eq!(
+            parse('m', &[9], "\x1b[9m"),
+            vec![CSI::Sgr(Sgr::StrikeThrough(true))]
+        );
+        assert_eq!(
+            parse('m', &[29], "\x1b[29m"),
+            vec![CSI::Sgr(Sgr::StrikeThrough(false))]
+        );
+
+        let params = [
+            CsiParam::Integer(9),
+            CsiParam::P(b':'),
+            CsiParam::Integer(2),
+        ];
+        let res: Vec<CSI> = CSI::parse(&params, false, 'm').collect();
+        assert_eq!(res, vec![CSI::Sgr(Sgr::DoubleStrikeThrough)]);
+        assert_eq!(encode(&res), "\x1b[9:2m");
+    }
+
     #[test]
     fn color() {
         assert_eq!(
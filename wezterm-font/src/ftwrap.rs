@@ -4,11 +4,11 @@ use crate::locator::{FontDataHandle, FontDataSource};
 use crate::parser::ParsedFont;
 use crate::rasterizer::colr::DrawOp;
 use anyhow::{anyhow, Context};
-use config::{configuration, FreeTypeLoadFlags, FreeTypeLoadTarget};
+use config::{configuration, FreeTypeLcdFilter, FreeTypeLoadFlags, FreeTypeLoadTarget};
 pub use freetype::*;
 use memmap2::{Mmap, MmapOptions};
 use rangeset::RangeSet;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use std::ffi::{c_int, c_void, CStr};
 use std::fs::File;
@@ -81,14 +81,25 @@ pub fn compute_load_flags_from_config(
         }
     }
 
-    let load_target = target_to_render(freetype_load_target.unwrap_or(config.freetype_load_target));
-    let render = target_to_render(
+    // A display with vertical subpixels needs vertically
+    // decimated LCD bitmaps
+    let orient = |t: FreeTypeLoadTarget| match t {
+        FreeTypeLoadTarget::HorizontalLcd if config.display_pixel_geometry.is_vertical() => {
+            FreeTypeLoadTarget::VerticalLcd
+        }
+        t => t,
+    };
+
+    let load_target = target_to_render(orient(
+        freetype_load_target.unwrap_or(config.freetype_load_target),
+    ));
+    let render = target_to_render(orient(
         freetype_render_target.unwrap_or(
             config
                 .freetype_render_target
                 .unwrap_or(config.freetype_load_target),
         ),
-    );
+    ));
 
     let load_flags = load_flags | render_mode_to_load_target(load_target);
 
@@ -127,6 +138,16 @@ struct ComputedCellMetrics {
     pub height: f64,
 }
 
+/// Describes a variation axis of a variable font
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationAxis {
+    pub tag: String,
+    pub name: String,
+    pub minimum: f64,
+    pub default: f64,
+    pub maximum: f64,
+}
+
 #[derive(Debug)]
 pub struct SelectedFontSize {
     pub width: f64,
@@ -208,6 +229,83 @@ impl Face {
         }
     }
 
+    /// Returns the variation axes of a variable font.
+    /// The list is empty if the font is not variable.
+    pub fn variation_axes(&self) -> Vec<VariationAxis> {
+        let mut mm = std::ptr::null_mut();
+
+        unsafe {
+            if ft_result(FT_Get_MM_Var(self.face, &mut mm), ()).is_err() {
+                return vec![];
+            }
+
+            let axes = from_raw_parts((*mm).axis, (*mm).num_axis as usize)
+                .iter()
+                .map(|axis| VariationAxis {
+                    tag: tag_to_string(axis.tag),
+                    name: if axis.name.is_null() {
+                        String::new()
+                    } else {
+                        CStr::from_ptr(axis.name).to_string_lossy().to_string()
+                    },
+                    minimum: axis.minimum.to_num::<f64>(),
+                    default: axis.def.to_num::<f64>(),
+                    maximum: axis.maximum.to_num::<f64>(),
+                })
+                .collect();
+
+            FT_Done_MM_Var(self.lib, mm);
+
+            axes
+        }
+    }
+
+    /// Set the design coordinates of the axes named by `variations`.
+    /// Axes that are not mentioned retain the coordinates of the named
+    /// instance that was selected when the face was loaded.
+    pub fn set_variations(&mut self, variations: &BTreeMap<String, f64>) -> anyhow::Result<()> {
+        let axes = self.variation_axes();
+        if axes.is_empty() {
+            log::warn!(
+                "{} is not a variable font; ignoring variations {:?}",
+                self.postscript_name(),
+                variations
+            );
+            return Ok(());
+        }
+
+        let mut coords = vec![FT_Fixed::from_num(0); axes.len()];
+        unsafe {
+            ft_result(
+                FT_Get_Var_Design_Coordinates(self.face, coords.len() as _, coords.as_mut_ptr()),
+                (),
+            )
+            .context("FT_Get_Var_Design_Coordinates")?;
+        }
+
+        for (tag, value) in variations {
+            match axes.iter().position(|axis| axis.tag == *tag) {
+                Some(idx) => {
+                    let axis = &axes[idx];
+                    coords[idx] = FT_Fixed::from_num(value.clamp(axis.minimum, axis.maximum));
+                }
+                None => log::warn!(
+                    "{} has no variation axis named {}",
+                    self.postscript_name(),
+                    tag
+                ),
+            }
+        }
+
+        unsafe {
+            ft_result(
+                FT_Set_Var_Design_Coordinates(self.face, coords.len() as _, coords.as_mut_ptr()),
+                (),
+            )
+            .context("FT_Set_Var_Design_Coordinates")
+        }
+    }
+
     pub fn get_glyph_name(&self, glyph_index: u32) -> Option<String> {
         let mut buf = [0u8; 128];
         let res = unsafe {
@@ -327,10 +425,64 @@ impl Face {
         }
     }
 
+    /// Returns the value of a string property from a BDF or PCF font
+    pub fn bdf_atom(&self, name: &str) -> Option<String> {
+        let name = std::ffi::CString::new(name).ok()?;
+        unsafe {
+            let mut prop = MaybeUninit::<BDF_PropertyRec>::zeroed();
+            if !succeeded(FT_Get_BDF_Property(
+                self.face,
+                name.as_ptr(),
+                prop.as_mut_ptr(),
+            )) {
+                return None;
+            }
+            let prop = prop.assume_init();
+            if prop.type_ != BDF_PropertyType::BDF_PROPERTY_TYPE_ATOM || prop.u.atom.is_null() {
+                return None;
+            }
+            Some(CStr::from_ptr(prop.u.atom).to_string_lossy().to_string())
+        }
+    }
+
+    /// Bitmap fonts in the BDF and PCF formats have no OS/2 table;
+    /// their weight and width are described by the XLFD properties
+    /// of the font instead.
+    fn bdf_weight_and_width(&self) -> Option<(f64, f64)> {
+        let weight = self
+            .bdf_atom("WEIGHT_NAME")
+            .and_then(|name| xlfd_weight(&name))
+            .unwrap_or_else(|| {
+                let bold =
+                    unsafe { ((*self.face).style_flags & FT_STYLE_FLAG_BOLD as FT_Long) != 0 };
+                if bold {
+                    700
+                } else {
+                    400
+                }
+            });
+        let width = self
+            .bdf_atom("SETWIDTH_NAME")
+            .and_then(|name| xlfd_width(&name))
+            .unwrap_or(5);
+        Some((weight as f64, width as f64))
+    }
+
+    pub fn is_scalable(&self) -> bool {
+        unsafe { ((*self.face).face_flags & FT_FACE_FLAG_SCALABLE as FT_Long) != 0 }
+    }
+
     pub fn weight_and_width(&self) -> (u16, u16) {
         let (mut weight, mut width) = self
             .get_os2_table()
             .map(|os2| (os2.usWeightClass as f64, os2.usWidthClass as f64))
+            .or_else(|| {
+                if self.is_scalable() {
+                    None
+                } else {
+                    self.bdf_weight_and_width()
+                }
+            })
             .unwrap_or((400., 5.));
 
         unsafe {
@@ -436,8 +588,9 @@ impl Face {
         sizes
             .iter()
             .filter_map(|info| {
-                if info.height > 0 {
-                    Some(info.height as u16)
+                let size = strike_pixel_size(info);
+                if size > 0 {
+                    Some(size as u16)
                 } else {
                     None
                 }
@@ -472,7 +625,17 @@ impl Face {
         // the fallback code for set_pixel_sizes below.
         let size = FT_F26Dot6::from_num(point_size);
 
-        let selected_size = match self.set_char_size(size, size, dpi, dpi) {
+        // A face with only bitmap strikes may accept a size request
+        // that exactly matches the height of one of its strikes, but
+        // we want consistent strike selection for those, so we skip
+        // straight to inspecting the strikes.
+        let char_size = if self.is_scalable() {
+            self.set_char_size(size, size, dpi, dpi)
+        } else {
+            Err(anyhow!("face has only bitmap strikes"))
+        };
+
+        let selected_size = match char_size {
             Ok(_) => {
                 // Compute metrics for the nominal monospace cell
                 let ComputedCellMetrics { width, height } = self.cell_metrics();
@@ -494,8 +657,10 @@ impl Face {
                 if sizes.is_empty() {
                     return Err(err);
                 }
-                // Find the best matching size; we look for the strike whose height
-                // is closest to the desired size.
+                // Find the best matching size; we look for the strike whose
+                // nominal pixel size is closest to the desired size, preferring
+                // the smaller strike when two are equally close so that the
+                // glyphs don't overflow the cell.
                 struct Best {
                     idx: usize,
                     distance: usize,
@@ -506,7 +671,8 @@ impl Face {
 
                 for (idx, info) in sizes.iter().enumerate() {
                     log::debug!("idx={} info={:?}", idx, info);
-                    let distance = (info.height - (pixel_height as i16)).abs() as usize;
+                    let distance =
+                        (strike_pixel_size(info) - pixel_height.round() as i16).abs() as usize;
                     let candidate = Best {
                         idx,
                         distance,
@@ -516,11 +682,16 @@ impl Face {
 
                     match best.take() {
                         Some(existing) => {
-                            best.replace(if candidate.distance < existing.distance {
-                                candidate
-                            } else {
-                                existing
-                            });
+                            best.replace(
+                                if candidate.distance < existing.distance
+                                    || (candidate.distance == existing.distance
+                                        && candidate.height < existing.height)
+                                {
+                                    candidate
+                                } else {
+                                    existing
+                                },
+                            );
                         }
                         None => {
                             best.replace(candidate);
@@ -916,6 +1087,14 @@ impl Face {
         synthesize_bold: bool,
     ) -> anyhow::Result<&FT_GlyphSlotRec_> {
         unsafe {
+            // A bitmap-only face has nothing to render if we ask
+            // FreeType to skip its bitmaps, and there are no outlines
+            // for the hinter to work on
+            let load_flags = if self.is_scalable() {
+                load_flags
+            } else {
+                load_flags & !(FT_LOAD_NO_BITMAP as i32) & !(FT_LOAD_FORCE_AUTOHINT as i32)
+            };
             ft_result(
                 FT_Load_Glyph(self.face, glyph_index, load_flags | FT_LOAD_NO_SVG as i32),
                 (),
@@ -1185,12 +1364,39 @@ impl Library {
             }
         }
 
-        // Due to patent concerns, the freetype library disables the LCD
-        // filtering feature by default, and since we always build our
-        // own copy of freetype, it is likewise disabled by default for
-        // us too.  As a result, this call will generally fail.
-        // Freetype is still able to render a decent result without it!
-        lib.set_lcd_filter(FT_LcdFilter::FT_LCD_FILTER_DEFAULT).ok();
+        {
+            // Stem darkening is implemented by these drivers only;
+            // it has no effect on hinted TrueType glyphs.
+            let no_stem_darkening: FT_Bool = if config.freetype_stem_darkening { 0 } else { 1 };
+            for driver in [
+                &b"cff\0"[..],
+                &b"type1\0"[..],
+                &b"t1cid\0"[..],
+                &b"autofitter\0"[..],
+            ] {
+                unsafe {
+                    FT_Property_Set(
+                        lib.lib,
+                        driver.as_ptr() as *const FT_String,
+                        b"no-stem-darkening\0" as *const u8 as *const FT_String,
+                        &no_stem_darkening as *const FT_Bool as *const _,
+                    );
+                }
+            }
+        }
+
+        // Our build of freetype enables FT_CONFIG_OPTION_SUBPIXEL_RENDERING,
+        // so the filter is honored.  If it were to fail, freetype is
+        // still able to render a decent result without it!
+        let filter = match config.freetype_lcd_filter {
+            FreeTypeLcdFilter::None => FT_LcdFilter::FT_LCD_FILTER_NONE,
+            FreeTypeLcdFilter::Default => FT_LcdFilter::FT_LCD_FILTER_DEFAULT,
+            FreeTypeLcdFilter::Light => FT_LcdFilter::FT_LCD_FILTER_LIGHT,
+            FreeTypeLcdFilter::Legacy => FT_LcdFilter::FT_LCD_FILTER_LEGACY,
+        };
+        if let Err(err) = lib.set_lcd_filter(filter) {
+            log::debug!("{:#}", err);
+        }
 
         Ok(lib)
     }
@@ -1530,6 +1736,149 @@ pub fn composite_mode_to_operator(mode: FT_Composite_Mode) -> cairo::Operator {
     }
 }
 
+/// Returns the nominal pixel size of a bitmap strike.
+/// For BDF and PCF fonts the height of the strike is the
+/// line height, which is usually larger than the pixel size
+/// that was requested, so prefer y_ppem when it is available.
+fn strike_pixel_size(info: &FT_Bitmap_Size) -> i16 {
+    if info.y_ppem > 0 {
+        (info.y_ppem as f64 / 64.0).round() as i16
+    } else {
+        info.height
+    }
+}
+
+/// Maps an XLFD WEIGHT_NAME to an OpenType weight class
+fn xlfd_weight(name: &str) -> Option<u16> {
+    let name = name.to_ascii_lowercase().replace(&['-', ' '][..], "");
+    Some(match name.as_str() {
+        "thin" | "hairline" => 100,
+        "extralight" | "ultralight" => 200,
+        "light" => 300,
+        "book" | "normal" | "regular" => 400,
+        "medium" => 500,
+        "demibold" | "semibold" | "demi" => 600,
+        "bold" => 700,
+        "extrabold" | "ultrabold" => 800,
+        "black" | "heavy" => 900,
+        _ => return None,
+    })
+}
+
+/// Maps an XLFD SETWIDTH_NAME to an OpenType width class
+fn xlfd_width(name: &str) -> Option<u16> {
+    let name = name.to_ascii_lowercase().replace(&['-', ' '][..], "");
+    Some(match name.as_str() {
+        "ultracondensed" => 1,
+        "extracondensed" => 2,
+        "condensed" | "narrow" => 3,
+        "semicondensed" => 4,
+        "normal" | "medium" | "regular" => 5,
+        "semiexpanded" => 6,
+        "expanded" | "wide" => 7,
+        "extraexpanded" => 8,
+        "ultraexpanded" => 9,
+        _ => return None,
+    })
+}
+
+/// Converts an OpenType tag to its string form, without
+/// any trailing space padding
+fn tag_to_string(tag: FT_ULong) -> String {
+    let bytes = (tag as u32).to_be_bytes();
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
+}
+
 fn ft_make_tag(a: u8, b: u8, c: u8, d: u8) -> FT_ULong {
     (a as FT_ULong) << 24 | (b as FT_ULong) << 16 | (c as FT_ULong) << 8 | (d as FT_ULong)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn xlfd_weight_names() {
+        for (name, expected) in [
+            ("Thin", Some(100)),
+            ("ExtraLight", Some(200)),
+            ("ultra-light", Some(200)),
+            ("Light", Some(300)),
+            ("book", Some(400)),
+            ("Regular", Some(400)),
+            ("Medium", Some(500)),
+            ("demi bold", Some(600)),
+            ("Demi", Some(600)),
+            ("Bold", Some(700)),
+            ("Extra-Bold", Some(800)),
+            ("BLACK", Some(900)),
+            ("heavy", Some(900)),
+            ("", None),
+            ("roman", None),
+        ] {
+            assert_eq!(xlfd_weight(name), expected, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn xlfd_width_names() {
+        for (name, expected) in [
+            ("UltraCondensed", Some(1)),
+            ("extra-condensed", Some(2)),
+            ("Condensed", Some(3)),
+            ("narrow", Some(3)),
+            ("Semi Condensed", Some(4)),
+            ("Normal", Some(5)),
+            ("medium", Some(5)),
+            ("SemiExpanded", Some(6)),
+            ("wide", Some(7)),
+            ("Extra-Expanded", Some(8)),
+            ("ULTRAEXPANDED", Some(9)),
+            ("", None),
+            ("double wide", None),
+        ] {
+            assert_eq!(xlfd_width(name), expected, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn strike_pixel_sizes() {
+        for (height, y_ppem, expected) in [
+            // The line height of a 13 pixel BDF font with descenders
+            (16, 13 * 64, 13),
+            // y_ppem is 26.6 fixed point and is rounded
+            (16, 13 * 64 + 31, 13),
+            (16, 13 * 64 + 32, 14),
+            // Without y_ppem, the height is all we have to go on
+            (16, 0, 16),
+        ] {
+            let info = FT_Bitmap_Size {
+                height,
+                width: 8,
+                size: y_ppem,
+                x_ppem: y_ppem,
+                y_ppem,
+            };
+            assert_eq!(
+                strike_pixel_size(&info),
+                expected,
+                "height={} y_ppem={}",
+                height,
+                y_ppem
+            );
+        }
+    }
+
+    #[test]
+    fn tag_strings() {
+        for (tag, expected) in [
+            (ft_make_tag(b'w', b'g', b'h', b't'), "wght"),
+            (ft_make_tag(b'C', b'O', b'L', b'R'), "COLR"),
+            // Trailing space padding is removed
+            (ft_make_tag(b'c', b'v', b't', b' '), "cvt"),
+            (ft_make_tag(b'a', b' ', b' ', b' '), "a"),
+        ] {
+            assert_eq!(tag_to_string(tag), expected);
+        }
+    }
+}
@@ -8,6 +8,7 @@ use crate::rasterizer::colr::{ColorLine, ColorStop, DrawOp};
 use anyhow::{ensure, Context, Error};
 use cairo::Extend;
 use memmap2::{Mmap, MmapOptions};
+use std::collections::BTreeMap;
 use std::ffi::CStr;
 use std::io::Read;
 use std::mem;
@@ -33,6 +34,34 @@ pub fn language_from_string(s: &str) -> Result<hb_language_t, Error> {
     }
 }
 
+/// Parses an ISO 15924 script code such as "Hani" or "Arab"
+/// into the tag form used by `script_tag_for_char`.
+pub fn script_tag_from_string(s: &str) -> Result<hb_tag_t, Error> {
+    ensure!(
+        s.len() == 4 && s.bytes().all(|b| b.is_ascii_alphabetic()),
+        "{} is not an ISO 15924 script code",
+        s
+    );
+    // Script tags are title case
+    let b = s.as_bytes();
+    Ok(hb_tag(
+        b[0].to_ascii_uppercase(),
+        b[1].to_ascii_lowercase(),
+        b[2].to_ascii_lowercase(),
+        b[3].to_ascii_lowercase(),
+    ))
+}
+
+/// Returns the ISO 15924 tag for the script of the specified codepoint
+pub fn script_tag_for_char(c: char) -> hb_tag_t {
+    unsafe {
+        hb_script_to_iso15924_tag(hb_unicode_script(
+            hb_unicode_funcs_get_default(),
+            c as hb_codepoint_t,
+        ))
+    }
+}
+
 pub fn feature_from_string(s: &str) -> Result<hb_feature_t, Error> {
     unsafe {
         let mut feature = mem::zeroed();
@@ -249,10 +278,29 @@ impl Face {
         Ok(Self { face })
     }
 
-    #[allow(dead_code)]
     pub fn get_upem(&self) -> c_uint {
         unsafe { hb_face_get_upem(self.face) }
     }
+
+    /// Returns true if the face has an OpenType `SVG ` table
+    pub fn has_svg(&self) -> bool {
+        unsafe { hb_ot_color_has_svg(self.face) != 0 }
+    }
+
+    /// Returns the SVG document that contains the specified glyph,
+    /// or None if the glyph has no SVG representation.
+    /// The document may be gzip compressed.
+    pub fn get_svg_document(&self, glyph: hb_codepoint_t) -> Option<Blob> {
+        // The returned blob already holds a reference for us
+        let blob = Blob {
+            blob: unsafe { hb_ot_color_glyph_reference_svg(self.face, glyph) },
+        };
+        if blob.as_slice().is_empty() {
+            None
+        } else {
+            Some(blob)
+        }
+    }
 }
 
 pub struct Font {
@@ -288,7 +336,6 @@ impl Font {
         Ok(Self { font })
     }
 
-    #[allow(dead_code)]
     pub fn get_face(&self) -> Face {
         let face = unsafe { hb_font_get_face(self.font) };
         unsafe {
@@ -310,6 +357,27 @@ impl Font {
         }
     }
 
+    /// Set the coordinates of the variation axes, keyed by axis tag
+    pub fn set_variations(&mut self, variations: &BTreeMap<String, f64>) {
+        let variations: Vec<hb_variation_t> = variations
+            .iter()
+            .map(|(tag, value)| {
+                // Tags shorter than 4 characters are padded with spaces
+                let mut t = [b' '; 4];
+                for (dest, src) in t.iter_mut().zip(tag.bytes()) {
+                    *dest = src;
+                }
+                hb_variation_t {
+                    tag: hb_tag(t[0], t[1], t[2], t[3]),
+                    value: *value as f32,
+                }
+            })
+            .collect();
+        unsafe {
+            hb_font_set_variations(self.font, variations.as_ptr(), variations.len() as c_uint);
+        }
+    }
+
     pub fn set_synthetic_slant(&mut self, slant: f32) {
         unsafe {
             hb_font_set_synthetic_slant(self.font, slant);
@@ -8,6 +8,7 @@ use config::{
     configuration, BoldBrightening, ConfigHandle, DisplayPixelGeometry, FontAttributes,
     FontRasterizerSelection, FontStretch, FontStyle, FontWeight, TextStyle,
 };
+use finl_unicode::grapheme_clusters::Graphemes;
 use rangeset::RangeSet;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
@@ -52,6 +53,39 @@ lazy_static::lazy_static! {
     static ref LAST_WARNING: Mutex<Option<(Instant, usize)>> = Mutex::new(None);
 }
 
+/// A font_rules entry that selects a font by codepoint rather
+/// than by cell attributes, resolved to the index of its font
+/// in the fallback list of a LoadedFont
+#[derive(Debug)]
+struct RangeRule {
+    ranges: RangeSet<u32>,
+    scripts: Vec<hbwrap::hb_tag_t>,
+    /// The fonts of the rule, which are searched ahead of
+    /// the regular fallback list
+    fonts: Vec<FallbackIdx>,
+}
+
+impl RangeRule {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.contains(c as u32)
+            || (!self.scripts.is_empty() && self.scripts.contains(&hbwrap::script_tag_for_char(c)))
+    }
+}
+
+/// Returns the indices of the fonts to search for glyphs, in order:
+/// the fonts in `first`, followed by the regular fallback list, which
+/// consists of all `num_handles` fonts other than the `rule_fonts`
+/// that were loaded only for range rules.
+fn fallback_chain(
+    first: &[FallbackIdx],
+    rule_fonts: &Range<FallbackIdx>,
+    num_handles: usize,
+) -> Vec<FallbackIdx> {
+    let mut chain = first.to_vec();
+    chain.extend((0..num_handles).filter(|idx| !rule_fonts.contains(idx) && !first.contains(idx)));
+    chain
+}
+
 pub struct LoadedFont {
     rasterizers: RefCell<HashMap<FallbackIdx, Box<dyn FontRasterizer>>>,
     handles: RefCell<Vec<ParsedFont>>,
@@ -67,6 +101,12 @@ pub struct LoadedFont {
     /// Glyphs for which no font was found and for which we should
     /// stop searching
     tried_glyphs: RefCell<HashSet<char>>,
+    /// font_rules that select a font by codepoint; text matching
+    /// these is shaped starting with the fonts of the rule
+    range_rules: Vec<RangeRule>,
+    /// The fonts that were loaded only because they are used by
+    /// range_rules, which are excluded from the regular fallback list
+    rule_fonts: Range<FallbackIdx>,
 }
 
 impl std::fmt::Debug for LoadedFont {
@@ -203,16 +243,27 @@ impl LoadedFont {
             }
         }
 
-        let result = self.shaper.borrow().shape(
-            text,
-            self.font_size,
-            self.dpi,
-            &mut no_glyphs,
-            presentation,
-            direction,
-            range,
-            presentation_width,
-        );
+        let result = if self.range_rules.is_empty() {
+            self.shaper.borrow().shape(
+                text,
+                self.font_size,
+                self.dpi,
+                &mut no_glyphs,
+                presentation,
+                direction,
+                range,
+                presentation_width,
+            )
+        } else {
+            self.shape_range_runs(
+                text,
+                &mut no_glyphs,
+                presentation,
+                direction,
+                range,
+                presentation_width,
+            )
+        };
 
         no_glyphs.retain(|&c| c != '\u{FE0F}' && c != '\u{FE0E}');
         filter_out_synthetic(&mut no_glyphs);
@@ -242,6 +293,69 @@ impl LoadedFont {
         result.map(|r| (async_resolve, r))
     }
 
+    /// Splits the text into runs of graphemes that are matched by the
+    /// same range rule, and shapes each of those runs starting with
+    /// the fonts selected by that rule, followed by the regular
+    /// fallback list.  Text that is not matched by any rule is shaped
+    /// using the regular fallback list alone.
+    fn shape_range_runs(
+        &self,
+        text: &str,
+        no_glyphs: &mut Vec<char>,
+        presentation: Option<Presentation>,
+        direction: Direction,
+        range: Option<Range<usize>>,
+        presentation_width: Option<&PresentationWidth>,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        let range = range.unwrap_or_else(|| 0..text.len());
+
+        let mut runs: Vec<(Option<usize>, Range<usize>)> = vec![];
+        let mut start = range.start;
+        for grapheme in Graphemes::new(&text[range.clone()]) {
+            let end = start + grapheme.len();
+            let rule_idx = grapheme
+                .chars()
+                .next()
+                .and_then(|c| self.range_rules.iter().position(|rule| rule.matches(c)));
+            match runs.last_mut() {
+                Some((idx, run)) if *idx == rule_idx => run.end = end,
+                _ => runs.push((rule_idx, start..end)),
+            }
+            start = end;
+        }
+
+        // The shaper produces glyphs in visual order, so the runs
+        // need to be arranged in visual order too
+        if direction == Direction::RightToLeft {
+            runs.reverse();
+        }
+
+        // Fallback fonts may have been added since the rules were
+        // resolved, so compute the chains from the current handles
+        let num_handles = self.handles.borrow().len();
+        let shaper = self.shaper.borrow();
+        let mut glyphs = vec![];
+        for (rule_idx, run) in runs {
+            let first = match rule_idx {
+                Some(idx) => self.range_rules[idx].fonts.as_slice(),
+                None => &[],
+            };
+            let fallback = fallback_chain(first, &self.rule_fonts, num_handles);
+            glyphs.append(&mut shaper.shape_with_fallback(
+                &fallback,
+                text,
+                self.font_size,
+                self.dpi,
+                no_glyphs,
+                presentation,
+                direction,
+                Some(run),
+                presentation_width,
+            )?);
+        }
+        Ok(glyphs)
+    }
+
     pub fn metrics_for_idx(&self, font_idx: usize) -> anyhow::Result<FontMetrics> {
         self.shaper
             .borrow()
@@ -647,6 +761,8 @@ impl FontConfigInner {
             text_style: text_style.clone(),
             id: alloc_font_id(),
             tried_glyphs: RefCell::new(HashSet::new()),
+            range_rules: vec![],
+            rule_fonts: 0..0,
             pixel_geometry: config.display_pixel_geometry,
         });
 
@@ -797,15 +913,109 @@ impl FontConfigInner {
         Ok((handles, loaded))
     }
 
+    /// Resolves the fonts for the font_rules entries that select a font
+    /// by codepoint, appending those that are not already present
+    /// to `handles`.
+    /// The fonts in those rules inherit the weight and style of the
+    /// primary font of `style`, so that eg: bold text in the range
+    /// uses the bold variant of the font from the rule.
+    fn resolve_range_rules(
+        &self,
+        style: &TextStyle,
+        config: &ConfigHandle,
+        pixel_size: u16,
+        handles: &mut Vec<ParsedFont>,
+    ) -> anyhow::Result<Vec<RangeRule>> {
+        let primary = style.font.first().cloned().unwrap_or_default();
+        let mut range_rules = vec![];
+
+        for rule in config.font_rules.iter().filter(|rule| rule.is_range_rule()) {
+            let mut ranges = RangeSet::new();
+            for range in rule.unicode_ranges.iter().flatten() {
+                ranges.add_range(range.first..range.last.saturating_add(1));
+            }
+
+            let mut scripts = vec![];
+            for script in rule.scripts.iter().flatten() {
+                match hbwrap::script_tag_from_string(script) {
+                    Ok(tag) => scripts.push(tag),
+                    Err(err) => log::error!("font_rules: {:#}", err),
+                }
+            }
+
+            let attributes = rule
+                .font
+                .font
+                .iter()
+                .map(|attr| {
+                    let mut attr = attr.clone();
+                    if attr.weight == FontWeight::default() {
+                        attr.weight = primary.weight;
+                    }
+                    if attr.style == FontStyle::default() {
+                        attr.style = primary.style;
+                    }
+                    attr
+                })
+                .collect::<Vec<_>>();
+
+            let (rule_handles, _loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
+            if rule_handles.is_empty() {
+                config::show_error(&format!(
+                    "Unable to load a font matching one of your font_rules: {}. \
+                    The text that it applies to will use the fonts \
+                    from your font configuration instead",
+                    attributes
+                        .first()
+                        .map(|attr| attr.to_string())
+                        .unwrap_or_default()
+                ));
+                continue;
+            }
+
+            let mut fonts = vec![];
+            for handle in rule_handles {
+                // ParsedFont equality disregards the harfbuzz features,
+                // but a rule may deliberately use the same font with
+                // different features, so we need to consider them here
+                let idx = match handles.iter().position(|existing| {
+                    *existing == handle && existing.harfbuzz_features == handle.harfbuzz_features
+                }) {
+                    Some(idx) => idx,
+                    None => {
+                        handles.push(handle);
+                        handles.len() - 1
+                    }
+                };
+                if !fonts.contains(&idx) {
+                    fonts.push(idx);
+                }
+            }
+
+            range_rules.push(RangeRule {
+                ranges,
+                scripts,
+                fonts,
+            });
+        }
+
+        Ok(range_rules)
+    }
+
     fn resolve_font_helper(
         &self,
         style: &TextStyle,
         config: &ConfigHandle,
         pixel_size: u16,
-    ) -> anyhow::Result<(Box<dyn FontShaper>, Vec<ParsedFont>)> {
+    ) -> anyhow::Result<(
+        Box<dyn FontShaper>,
+        Vec<ParsedFont>,
+        Vec<RangeRule>,
+        Range<FallbackIdx>,
+    )> {
         let attributes = style.font_with_fallback();
 
-        let (handles, loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
+        let (mut handles, loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
 
         for attr in &attributes {
             if !attr.is_synthetic && !attr.is_fallback && !loaded.contains(attr) {
@@ -855,7 +1065,16 @@ impl FontConfigInner {
             }
         }
 
-        Ok((new_shaper(&*config, &handles)?, handles))
+        let num_handles = handles.len();
+        let range_rules = self.resolve_range_rules(style, config, pixel_size, &mut handles)?;
+        let rule_fonts = num_handles..handles.len();
+
+        Ok((
+            new_shaper(&*config, &handles)?,
+            handles,
+            range_rules,
+            rule_fonts,
+        ))
     }
 
     /// Given a text style, load (with caching) the font that best
@@ -879,7 +1098,8 @@ impl FontConfigInner {
         let dpi = *self.dpi.borrow() as u32;
         let pixel_size = (font_size * dpi as f64 / 72.0) as u16;
 
-        let (mut shaper, mut handles) = self.resolve_font_helper(style, &config, pixel_size)?;
+        let (mut shaper, mut handles, mut range_rules, mut rule_fonts) =
+            self.resolve_font_helper(style, &config, pixel_size)?;
 
         let mut metrics = shaper.metrics(font_size, dpi).with_context(|| {
             format!(
@@ -907,10 +1127,12 @@ impl FontConfigInner {
                             scaled_font_size,
                             metrics,
                         );
-                        let (alt_shaper, alt_handles) =
+                        let (alt_shaper, alt_handles, alt_range_rules, alt_rule_fonts) =
                             self.resolve_font_helper(style, &config, scaled_pixel_size)?;
                         shaper = alt_shaper;
                         handles = alt_handles;
+                        range_rules = alt_range_rules;
+                        rule_fonts = alt_rule_fonts;
 
                         metrics = shaper.metrics(scaled_font_size, dpi).with_context(|| {
                             format!(
@@ -938,6 +1160,8 @@ impl FontConfigInner {
             text_style: style.clone(),
             id: alloc_font_id(),
             tried_glyphs: RefCell::new(HashSet::new()),
+            range_rules,
+            rule_fonts,
             pixel_geometry: config.display_pixel_geometry,
         });
 
@@ -1020,6 +1244,10 @@ impl FontConfigInner {
         };
 
         for rule in &config.font_rules {
+            if rule.is_range_rule() {
+                // These are resolved by codepoint while shaping
+                continue;
+            }
             if let Some(intensity) = rule.intensity {
                 let effective_intensity = match config.bold_brightens_ansi_colors {
                     BoldBrightening::BrightOnly if would_bright => Intensity::Normal,
@@ -1130,3 +1358,26 @@ impl FontConfiguration {
         self.inner.match_style(config, attrs)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fallback_chain_for_text_without_a_rule() {
+        // Fonts 2 and 3 were loaded for range rules, and font 4
+        // was added as a fallback afterwards
+        assert_eq!(fallback_chain(&[], &(2..4), 5), vec![0, 1, 4]);
+        assert_eq!(fallback_chain(&[], &(0..0), 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn fallback_chain_for_text_matching_a_rule() {
+        // The fonts of the rule come first, followed by the
+        // regular fallback list
+        assert_eq!(fallback_chain(&[3, 2], &(2..4), 5), vec![3, 2, 0, 1, 4]);
+        // A rule may use a font from the regular list, which
+        // isn't searched twice
+        assert_eq!(fallback_chain(&[1], &(2..4), 5), vec![1, 0, 4]);
+    }
+}
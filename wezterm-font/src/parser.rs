@@ -1,9 +1,11 @@
+use crate::ftwrap::VariationAxis;
 use crate::locator::{FontDataHandle, FontDataSource, FontOrigin};
 use crate::shaper::GlyphInfo;
 use config::{FontAttributes, FontStyle, FreeTypeLoadFlags, FreeTypeLoadTarget};
 pub use config::{FontStretch, FontWeight};
 use rangeset::RangeSet;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::sync::Mutex;
 
 #[derive(Debug)]
@@ -36,12 +38,14 @@ pub struct ParsedFont {
     pub pixel_sizes: Vec<u16>,
     pub is_built_in_fallback: bool,
     pub palettes: Vec<FontPaletteInfo>,
+    pub axes: Vec<VariationAxis>,
 
     pub harfbuzz_features: Option<Vec<String>>,
     pub freetype_load_target: Option<FreeTypeLoadTarget>,
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
     pub freetype_load_flags: Option<FreeTypeLoadFlags>,
     pub scale: Option<f64>,
+    pub variations: Option<BTreeMap<String, f64>>,
 }
 
 impl std::fmt::Debug for ParsedFont {
@@ -63,6 +67,7 @@ impl std::fmt::Debug for ParsedFont {
             .field("freetype_render_target", &self.freetype_render_target)
             .field("freetype_load_flags", &self.freetype_load_flags)
             .field("scale", &self.scale)
+            .field("variations", &self.variations)
             .finish()
     }
 }
@@ -89,6 +94,8 @@ impl Clone for ParsedFont {
             is_built_in_fallback: self.is_built_in_fallback,
             scale: self.scale,
             palettes: self.palettes.clone(),
+            axes: self.axes.clone(),
+            variations: self.variations.clone(),
         }
     }
 }
@@ -325,6 +332,12 @@ impl ParsedFont {
                     code.push_str(&info);
                 }
             }
+            for axis in &p.axes {
+                code.push_str(&format!(
+                    "  -- Axis: {} \"{}\" {}..{}, default {}\n",
+                    axis.tag, axis.name, axis.minimum, axis.maximum, axis.default
+                ));
+            }
             for aka in &p.names.aliases {
                 code.push_str(&format!("  -- AKA: \"{}\"\n", aka));
             }
@@ -337,6 +350,7 @@ impl ParsedFont {
                 && p.freetype_load_flags.is_none()
                 && p.harfbuzz_features.is_none()
                 && p.scale.is_none()
+                && p.variations.is_none()
             {
                 code.push_str(&format!("  \"{}\",\n", p.names.family));
             } else {
@@ -353,6 +367,16 @@ impl ParsedFont {
                 if let Some(scale) = p.scale {
                     code.push_str(&format!(", scale={}", scale));
                 }
+                if let Some(variations) = &p.variations {
+                    code.push_str(", variations={");
+                    for (idx, (tag, value)) in variations.iter().enumerate() {
+                        if idx > 0 {
+                            code.push_str(", ");
+                        }
+                        code.push_str(&format!("{}={}", tag, value));
+                    }
+                    code.push('}');
+                }
                 if let Some(item) = p.freetype_load_flags {
                     code.push_str(&format!(", freetype_load_flags=\"{}\"", item.to_string()));
                 }
@@ -393,6 +417,7 @@ impl ParsedFont {
         let stretch = FontStretch::from_opentype_stretch(width);
         let cap_height = face.cap_height();
         let pixel_sizes = face.pixel_sizes();
+        let axes = face.variation_axes();
 
         let palettes = match face.get_palette_data() {
             Ok(info) => info
@@ -530,6 +555,8 @@ impl ParsedFont {
             freetype_load_flags: None,
             scale: None,
             palettes,
+            axes,
+            variations: None,
         })
     }
 
@@ -781,6 +808,12 @@ impl ParsedFont {
         self.freetype_load_target = attr.freetype_load_target;
         self.freetype_load_flags = attr.freetype_load_flags;
         self.scale = attr.scale.map(|f| *f);
+        self.variations = attr.variations.as_ref().map(|variations| {
+            variations
+                .iter()
+                .map(|(tag, value)| (tag.clone(), **value))
+                .collect()
+        });
 
         self.synthesize_italic = self.style == FontStyle::Normal && attr.style != FontStyle::Normal;
         self.synthesize_bold = attr.weight >= FontWeight::DEMIBOLD
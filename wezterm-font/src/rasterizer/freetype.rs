@@ -44,6 +44,15 @@ impl FontRasterizer for FreeTypeRasterizer {
         size: f64,
         dpi: u32,
     ) -> anyhow::Result<RasterizedGlyph> {
+        // FreeType is asked not to load SVG glyphs, so we need to
+        // check for them before we go any further
+        if let Some(glyph) = self
+            .hb_raster
+            .rasterize_svg_glyph(glyph_pos, size * self.scale, dpi)
+        {
+            return Ok(glyph);
+        }
+
         let SelectedFontSize { is_scaled, .. } = self
             .face
             .borrow_mut()
@@ -238,9 +247,10 @@ impl FreeTypeRasterizer {
                 let green = linear_u8_to_srgb8(green);
                 let blue = linear_u8_to_srgb8(blue);
 
-                let (red, blue) = match self.display_pixel_geometry {
-                    DisplayPixelGeometry::RGB => (red, blue),
-                    DisplayPixelGeometry::BGR => (blue, red),
+                let (red, blue) = if self.display_pixel_geometry.is_bgr() {
+                    (blue, red)
+                } else {
+                    (red, blue)
                 };
 
                 rgba[dest_offset + (x * 4)] = red;
@@ -291,9 +301,10 @@ impl FreeTypeRasterizer {
                 let green = linear_u8_to_srgb8(green);
                 let blue = linear_u8_to_srgb8(blue);
 
-                let (red, blue) = match self.display_pixel_geometry {
-                    DisplayPixelGeometry::RGB => (red, blue),
-                    DisplayPixelGeometry::BGR => (blue, red),
+                let (red, blue) = if self.display_pixel_geometry.is_bgr() {
+                    (blue, red)
+                } else {
+                    (red, blue)
                 };
 
                 rgba[dest_offset + (x * 4)] = red;
@@ -384,6 +395,9 @@ impl FreeTypeRasterizer {
         log::trace!("Rasterizier wants {:?}", parsed);
         let lib = ftwrap::Library::new()?;
         let mut face = lib.face_from_locator(&parsed.handle)?;
+        if let Some(variations) = &parsed.variations {
+            face.set_variations(variations)?;
+        }
         let has_color = unsafe {
             (((*face.face).face_flags as u32) & (ftwrap::FT_FACE_FLAG_COLOR as u32)) != 0
         };
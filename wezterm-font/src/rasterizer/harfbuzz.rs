@@ -5,6 +5,7 @@ use crate::hbwrap::{
 use crate::rasterizer::colr::{
     apply_draw_ops_to_context, paint_linear_gradient, paint_radial_gradient, paint_sweep_gradient,
 };
+use crate::rasterizer::svg::SvgRasterizer;
 use crate::rasterizer::FAKE_ITALIC_SKEW;
 use crate::units::PixelLength;
 use crate::{FontRasterizer, ParsedFont, RasterizedGlyph};
@@ -14,6 +15,7 @@ use image::GenericImageView;
 
 pub struct HarfbuzzRasterizer {
     font: Font,
+    svg: Option<SvgRasterizer>,
 }
 
 impl HarfbuzzRasterizer {
@@ -27,8 +29,32 @@ impl HarfbuzzRasterizer {
         if parsed.synthesize_bold {
             font.set_synthetic_bold(0.02, 0.02, false);
         }
+        if let Some(variations) = &parsed.variations {
+            font.set_variations(variations);
+        }
+
+        let svg = SvgRasterizer::new(font.get_face());
+
+        Ok(Self { font, svg })
+    }
 
-        Ok(Self { font })
+    /// If the font has an SVG representation of the glyph, rasterize it.
+    /// Returns None if the glyph should instead be rasterized from
+    /// its outlines or color paint graph.
+    pub fn rasterize_svg_glyph(
+        &self,
+        glyph_pos: u32,
+        size: f64,
+        dpi: u32,
+    ) -> Option<RasterizedGlyph> {
+        let svg = self.svg.as_ref()?;
+        match svg.rasterize_glyph(glyph_pos, size, dpi) {
+            Ok(glyph) => glyph,
+            Err(err) => {
+                log::warn!("Failed to rasterize SVG glyph {glyph_pos}, will use outlines: {err:#}");
+                None
+            }
+        }
     }
 }
 
@@ -39,6 +65,10 @@ impl FontRasterizer for HarfbuzzRasterizer {
         size: f64,
         dpi: u32,
     ) -> anyhow::Result<RasterizedGlyph> {
+        if let Some(glyph) = self.rasterize_svg_glyph(glyph_pos, size, dpi) {
+            return Ok(glyph);
+        }
+
         let pixel_size = (size * dpi as f64 / 72.) as u32;
 
         let scale = pixel_size as i32 * 64;
@@ -0,0 +1,206 @@
+//! Rasterization of glyphs from the OpenType `SVG ` table.
+//! <https://learn.microsoft.com/en-us/typography/opentype/spec/svg>
+use crate::hbwrap::Face;
+use crate::units::PixelLength;
+use crate::RasterizedGlyph;
+use anyhow::{anyhow, Context as _};
+use resvg::{tiny_skia, usvg};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Identifies a document within the SVG table of the face by the
+/// location and length of its data.  A single document may hold
+/// the descriptions of a range of glyphs.
+type DocumentKey = (usize, usize);
+
+pub struct SvgRasterizer {
+    face: Face,
+    upem: f64,
+    /// Parsed documents, so that each document is parsed only once
+    /// no matter how many of its glyphs are rasterized, or at how
+    /// many sizes
+    trees: RefCell<HashMap<DocumentKey, Rc<usvg::Tree>>>,
+}
+
+impl SvgRasterizer {
+    /// Returns a rasterizer for the SVG glyphs of the face,
+    /// or None if the face has no SVG glyphs
+    pub fn new(face: Face) -> Option<Self> {
+        if !face.has_svg() {
+            return None;
+        }
+        let upem = face.get_upem() as f64;
+        Some(Self {
+            face,
+            upem,
+            trees: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the parsed document that describes the glyph, or None
+    /// if the font has no SVG document for it
+    fn tree_for_glyph(&self, glyph_pos: u32) -> anyhow::Result<Option<Rc<usvg::Tree>>> {
+        let document = match self.face.get_svg_document(glyph_pos) {
+            Some(doc) => doc,
+            None => return Ok(None),
+        };
+        let data = document.as_slice();
+        // The blob references the data of the face, which we keep
+        // alive, so its address is stable
+        let key = (data.as_ptr() as usize, data.len());
+
+        if let Some(tree) = self.trees.borrow().get(&key) {
+            return Ok(Some(Rc::clone(tree)));
+        }
+
+        // Note that usvg transparently decompresses gzipped documents
+        let tree = Rc::new(
+            usvg::Tree::from_data(data, &usvg::Options::default())
+                .with_context(|| format!("parsing SVG document for glyph {glyph_pos}"))?,
+        );
+        self.trees.borrow_mut().insert(key, Rc::clone(&tree));
+        Ok(Some(tree))
+    }
+
+    /// Rasterize the SVG representation of the glyph.
+    /// Returns Ok(None) if the font has no SVG document for it,
+    /// in which case the glyph should be rasterized from its outlines.
+    pub fn rasterize_glyph(
+        &self,
+        glyph_pos: u32,
+        size: f64,
+        dpi: u32,
+    ) -> anyhow::Result<Option<RasterizedGlyph>> {
+        let tree = match self.tree_for_glyph(glyph_pos)? {
+            Some(tree) => tree,
+            None => return Ok(None),
+        };
+
+        // The glyph is described in font units, with the origin
+        // on the baseline and the y axis pointing downwards
+        let scale = (size * dpi as f64 / 72. / self.upem) as f32;
+
+        render_glyph(&tree, glyph_pos, scale).map(Some)
+    }
+}
+
+/// Render the glyph from a parsed document at the specified scale
+fn render_glyph(tree: &usvg::Tree, glyph_pos: u32, scale: f32) -> anyhow::Result<RasterizedGlyph> {
+    // A document may hold several glyphs; the spec requires that
+    // each of them is identified by its glyph id
+    let id = format!("glyph{glyph_pos}");
+    let node = tree
+        .node_by_id(&id)
+        .ok_or_else(|| anyhow!("SVG document has no element with id {id}"))?;
+
+    // usvg reports a nominal layer bounding box for an element
+    // that has no content, so check that there is something to draw
+    let bounds = node.abs_bounding_box();
+    if bounds.width() == 0. && bounds.height() == 0. {
+        return Ok(empty_glyph());
+    }
+
+    let bbox = match node.abs_layer_bounding_box() {
+        Some(bbox) => bbox,
+        None => return Ok(empty_glyph()),
+    };
+    let width = (bbox.width() * scale).ceil() as u32;
+    let height = (bbox.height() * scale).ceil() as u32;
+    if width == 0 || height == 0 {
+        return Ok(empty_glyph());
+    }
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| anyhow!("failed to allocate {width}x{height} pixmap"))?;
+
+    // render_node places the top left of the layer bounding box
+    // of the node at the origin of the pixmap
+    resvg::render_node(
+        node,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    )
+    .ok_or_else(|| anyhow!("failed to render SVG glyph {glyph_pos}"))?;
+
+    Ok(RasterizedGlyph {
+        // tiny_skia produces premultiplied RGBA, which is
+        // what we need here
+        data: pixmap.take(),
+        height: height as usize,
+        width: width as usize,
+        bearing_x: PixelLength::new((bbox.x() * scale) as f64),
+        bearing_y: PixelLength::new((bbox.y() * scale * -1.) as f64),
+        has_color: true,
+        is_scaled: true,
+    })
+}
+
+fn empty_glyph() -> RasterizedGlyph {
+    RasterizedGlyph {
+        data: vec![],
+        height: 0,
+        width: 0,
+        bearing_x: PixelLength::new(0.),
+        bearing_y: PixelLength::new(0.),
+        has_color: true,
+        is_scaled: true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A document describing two glyphs in a 1000 unit em.
+    /// Glyph 1 is a red box that sits on the baseline and glyph 2
+    /// has nothing to draw.
+    const DOCUMENT: &str = r#"<svg xmlns="http://www.w3.org/2000/svg">
+        <g id="glyph1">
+            <rect x="100" y="-500" width="200" height="500" fill="red"/>
+        </g>
+        <g id="glyph2"/>
+    </svg>"#;
+
+    fn tree() -> usvg::Tree {
+        usvg::Tree::from_data(DOCUMENT.as_bytes(), &usvg::Options::default()).unwrap()
+    }
+
+    fn pixel(glyph: &RasterizedGlyph, x: usize, y: usize) -> &[u8] {
+        let idx = (y * glyph.width + x) * 4;
+        &glyph.data[idx..idx + 4]
+    }
+
+    #[test]
+    fn renders_glyph_by_id() {
+        // 10 pixels per 1000 units
+        let glyph = render_glyph(&tree(), 1, 0.01).unwrap();
+        assert_eq!((glyph.width, glyph.height), (2, 5));
+        assert_eq!(glyph.data.len(), 2 * 5 * 4);
+        assert_eq!(glyph.bearing_x, PixelLength::new(1.));
+        // The box extends above the baseline
+        assert_eq!(glyph.bearing_y, PixelLength::new(5.));
+        assert!(glyph.has_color);
+        assert_eq!(pixel(&glyph, 0, 0), &[255, 0, 0, 255]);
+        assert_eq!(pixel(&glyph, 1, 4), &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn scales_glyph() {
+        let glyph = render_glyph(&tree(), 1, 0.02).unwrap();
+        assert_eq!((glyph.width, glyph.height), (4, 10));
+        assert_eq!(glyph.bearing_x, PixelLength::new(2.));
+        assert_eq!(glyph.bearing_y, PixelLength::new(10.));
+    }
+
+    #[test]
+    fn empty_and_missing_glyphs() {
+        let tree = tree();
+        let glyph = render_glyph(&tree, 2, 0.01).unwrap();
+        assert_eq!((glyph.width, glyph.height), (0, 0));
+        assert!(glyph.data.is_empty());
+
+        let err = render_glyph(&tree, 3, 0.01).unwrap_err();
+        assert!(err.to_string().contains("glyph3"), "{err:#}");
+    }
+}
@@ -157,11 +157,20 @@ impl HarfbuzzShaper {
                 if opt_pair.is_none() {
                     let handle = &self.handles[font_idx];
                     log::trace!("shaper wants {} {:?}", font_idx, handle);
-                    let face = self.lib.face_from_locator(&handle.handle)?;
+                    let mut face = self.lib.face_from_locator(&handle.handle)?;
+                    if let Some(variations) = &handle.variations {
+                        face.set_variations(variations)?;
+                    }
 
                     let font = if USE_OT_FACE {
-                        harfbuzz::Font::from_locator(&handle.handle)?
+                        let mut font = harfbuzz::Font::from_locator(&handle.handle)?;
+                        if let Some(variations) = &handle.variations {
+                            font.set_variations(variations);
+                        }
+                        font
                     } else {
+                        // The harfbuzz font picks up the variations
+                        // from the freetype face
                         let (load_flags, _) = ftwrap::compute_load_flags_from_config(
                             handle.freetype_load_flags,
                             handle.freetype_load_target,
@@ -202,9 +211,12 @@ impl HarfbuzzShaper {
         }
     }
 
+    /// Shape the text using the font at `fallback[pos]`, moving on to the
+    /// subsequent entries in `fallback` for clusters that it cannot shape
     fn do_shape(
         &self,
-        mut font_idx: FallbackIdx,
+        fallback: &[FallbackIdx],
+        mut pos: usize,
         s: &str,
         font_size: f64,
         dpi: u32,
@@ -240,7 +252,9 @@ impl HarfbuzzShaper {
         // will use the notdef glyph from the base font.
         let mut no_more_fallbacks = false;
 
+        let mut font_idx;
         loop {
+            font_idx = fallback.get(pos).copied().unwrap_or(FallbackIdx::MAX);
             match self.load_fallback(font_idx, dpi).context("load_fallback")? {
                 Some(mut pair) => {
                     if let Some(p) = presentation {
@@ -251,7 +265,7 @@ impl HarfbuzzShaper {
                                      font_idx={font_idx}",
                                 pair.presentation
                             );
-                            font_idx += 1;
+                            pos += 1;
                             continue;
                         }
                     }
@@ -319,6 +333,7 @@ impl HarfbuzzShaper {
                         // that glyph in a fallback font and swap it out a little
                         // later after a flash of showing the emoji one.
                         return self.do_shape(
+                            fallback,
                             0,
                             s,
                             font_size,
@@ -334,7 +349,7 @@ impl HarfbuzzShaper {
                     // One more go around to pick up the base font and
                     // accept using the notdef glyph from that.
                     no_more_fallbacks = true;
-                    font_idx = 0;
+                    pos = 0;
                     continue;
                 }
             }
@@ -480,7 +495,8 @@ impl HarfbuzzShaper {
                 let first_info = &infos[0];
 
                 let mut shape = match self.do_shape(
-                    font_idx + 1,
+                    fallback,
+                    pos + 1,
                     s,
                     font_size,
                     dpi,
@@ -495,6 +511,7 @@ impl HarfbuzzShaper {
                     Err(e) => {
                         error!("{:?} for {:?}", e, substr);
                         self.do_shape(
+                            fallback,
                             0,
                             &make_question_string(substr),
                             font_size,
@@ -574,15 +591,42 @@ impl FontShaper for HarfbuzzShaper {
         direction: Direction,
         range: Option<Range<usize>>,
         presentation_width: Option<&PresentationWidth>,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        let fallback: Vec<FallbackIdx> = (0..self.handles.len()).collect();
+        self.shape_with_fallback(
+            &fallback,
+            text,
+            size,
+            dpi,
+            no_glyphs,
+            presentation,
+            direction,
+            range,
+            presentation_width,
+        )
+    }
+
+    fn shape_with_fallback(
+        &self,
+        fallback: &[FallbackIdx],
+        text: &str,
+        size: f64,
+        dpi: u32,
+        no_glyphs: &mut Vec<char>,
+        presentation: Option<Presentation>,
+        direction: Direction,
+        range: Option<Range<usize>>,
+        presentation_width: Option<&PresentationWidth>,
     ) -> anyhow::Result<Vec<GlyphInfo>> {
         let range = range.unwrap_or_else(|| 0..text.len());
 
         log::trace!(
-            "shape {range:?} `{}` with presentation={presentation:?}",
+            "shape {range:?} `{}` with fallback={fallback:?} presentation={presentation:?}",
             text.escape_debug()
         );
         let start = std::time::Instant::now();
         let result = self.do_shape(
+            fallback,
             0,
             text,
             size,
@@ -625,6 +669,15 @@ impl FontShaper for HarfbuzzShaper {
 
         let selected_size = pair.face.set_font_size(size * scale, dpi)?;
         let y_scale = unsafe { (*(*pair.face.face).size).metrics.y_scale.to_num::<f64>() };
+        let (strikethrough_thickness, strikethrough_position) = match pair.face.get_os2_table() {
+            Some(os2) if selected_size.is_scaled && os2.yStrikeoutSize > 0 => (
+                Some(PixelLength::new(os2.yStrikeoutSize as f64 * y_scale / 64.)),
+                Some(PixelLength::new(
+                    os2.yStrikeoutPosition as f64 * y_scale / 64.,
+                )),
+            ),
+            _ => (None, None),
+        };
         let mut metrics = FontMetrics {
             cell_height: PixelLength::new(selected_size.height),
             cell_width: PixelLength::new(selected_size.width),
@@ -639,6 +692,8 @@ impl FontShaper for HarfbuzzShaper {
             underline_position: PixelLength::new(
                 unsafe { (*pair.face.face).underline_position as f64 } * y_scale / 64.,
             ),
+            strikethrough_thickness,
+            strikethrough_position,
             cap_height_ratio: selected_size.cap_height_to_height_ratio,
             cap_height: selected_size.cap_height.map(PixelLength::new),
             is_scaled: selected_size.is_scaled,
@@ -862,6 +917,7 @@ mod test {
                     harfbuzz_features: None,
                     scale: None,
                     assume_emoji_presentation: None,
+                    variations: None,
                 },
                 14,
             )
@@ -1239,4 +1295,81 @@ mod test {
             );
         }
     }
+
+    fn built_in_handle(db: &FontDatabase, family: &str) -> ParsedFont {
+        db.resolve(
+            &FontAttributes {
+                family: family.into(),
+                stretch: Default::default(),
+                weight: Default::default(),
+                is_fallback: false,
+                is_synthetic: false,
+                style: Default::default(),
+                freetype_load_flags: None,
+                freetype_load_target: None,
+                freetype_render_target: None,
+                harfbuzz_features: None,
+                scale: None,
+                assume_emoji_presentation: None,
+                variations: None,
+            },
+            14,
+        )
+        .unwrap()
+        .clone()
+    }
+
+    /// Shape `text` with the specified fallback order, returning
+    /// the font_idx and glyph_pos of each glyph along with the
+    /// characters that had no glyph
+    fn shape_with(
+        shaper: &HarfbuzzShaper,
+        fallback: &[FallbackIdx],
+        text: &str,
+    ) -> (Vec<(String, FallbackIdx, u32)>, Vec<char>) {
+        let mut no_glyphs = vec![];
+        let info = shaper
+            .shape_with_fallback(
+                fallback,
+                text,
+                10.,
+                72,
+                &mut no_glyphs,
+                None,
+                Direction::LeftToRight,
+                None,
+                None,
+            )
+            .unwrap();
+        (
+            info.into_iter()
+                .map(|g| (g.text, g.font_idx, g.glyph_pos))
+                .collect(),
+            no_glyphs,
+        )
+    }
+
+    #[test]
+    fn fallback_order() {
+        let db = FontDatabase::with_built_in().unwrap();
+        let jetbrains = built_in_handle(&db, "JetBrains Mono");
+        let roboto = built_in_handle(&db, "Roboto");
+        let config = config::configuration();
+        let shaper = HarfbuzzShaper::new(&config, &[jetbrains, roboto]).unwrap();
+
+        // Roboto has no box drawing glyphs, so the search continues
+        // with the next font in the fallback order
+        let (glyphs, no_glyphs) = shape_with(&shaper, &[1, 0], "a\u{2500}");
+        assert!(no_glyphs.is_empty(), "{:?}", no_glyphs);
+        assert_eq!(glyphs[0].0, "a");
+        assert_eq!(glyphs[0].1, 1);
+        assert_eq!(glyphs[1].0, "\u{2500}");
+        assert_eq!(glyphs[1].1, 0);
+
+        // Fonts that are not in the fallback order are not searched,
+        // so we get the notdef glyph of the first font in the order
+        let (glyphs, no_glyphs) = shape_with(&shaper, &[1], "\u{2500}");
+        assert_eq!(no_glyphs, vec!['\u{2500}']);
+        assert_eq!(glyphs, vec![("\u{2500}".to_string(), 1, 0)]);
+    }
 }
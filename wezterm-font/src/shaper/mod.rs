@@ -56,13 +56,22 @@ pub struct FontMetrics {
     /// descender is typically negative.
     pub descender: PixelLength,
 
-    /// Vertical size of underline/strikethrough in pixels
+    /// Vertical size of underline in pixels
     pub underline_thickness: PixelLength,
 
     /// Position of underline relative to descender. Negative
     /// values are below the descender.
     pub underline_position: PixelLength,
 
+    /// Vertical size of strikethrough in pixels, if the font
+    /// provides it via its OS/2 table
+    pub strikethrough_thickness: Option<PixelLength>,
+
+    /// Position of the top of the strikethrough relative to the
+    /// baseline, if the font provides it via its OS/2 table.
+    /// Positive values are above the baseline.
+    pub strikethrough_position: Option<PixelLength>,
+
     /// Fraction of the EM square occupied by the cap height
     pub cap_height_ratio: Option<f64>,
     pub cap_height: Option<PixelLength>,
@@ -127,6 +136,22 @@ pub trait FontShaper {
         presentation_width: Option<&PresentationWidth>,
     ) -> anyhow::Result<Vec<GlyphInfo>>;
 
+    /// Shape text and return a vector of GlyphInfo, searching for
+    /// glyphs in the fonts at the indices in `fallback`, in that order,
+    /// rather than in all of the fonts in their natural order
+    fn shape_with_fallback(
+        &self,
+        fallback: &[FallbackIdx],
+        text: &str,
+        size: f64,
+        dpi: u32,
+        no_glyphs: &mut Vec<char>,
+        presentation: Option<termwiz::cell::Presentation>,
+        direction: Direction,
+        range: Option<Range<usize>>,
+        presentation_width: Option<&PresentationWidth>,
+    ) -> anyhow::Result<Vec<GlyphInfo>>;
+
     /// Compute the font metrics for the preferred font
     /// at the specified size.
     fn metrics(&self, size: f64, dpi: u32) -> anyhow::Result<FontMetrics>;
@@ -260,6 +260,13 @@ pub struct LsFontsCommand {
     /// Show rasterized glyphs for the text in --text or --codepoints using ascii blocks.
     #[arg(long, requires = "text")]
     pub rasterize_ascii: bool,
+
+    /// Scan the screen and scrollback of the specified pane in the
+    /// running wezterm instance, and explain which fonts are used
+    /// to render its text, listing any codepoints for which no font
+    /// has a glyph.
+    #[arg(long, conflicts_with_all = &["list_system", "text", "codepoints"])]
+    pub pane_id: Option<usize>,
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -790,6 +790,16 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
             menubar: &[],
             icon: Some("oct_search"),
         },
+        ShowSftpBrowser => CommandDef {
+            brief: "Browse files over SFTP".into(),
+            doc: "Browses the remote working directory of the current ssh pane, \
+                  downloading selected files and uploading dropped files"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Shell"],
+            icon: Some("md_folder_network"),
+        },
         ShowDebugOverlay => CommandDef {
             brief: "Show debug overlay".into(),
             doc: "Activates the debug overlay and Lua REPL".into(),
@@ -2038,6 +2048,7 @@ fn compute_default_actions() -> Vec<KeyAssignment> {
         }),
         CloseCurrentTab { confirm: true },
         CloseCurrentPane { confirm: true },
+        ShowSftpBrowser,
         DetachDomain(SpawnTabDomain::CurrentPaneDomain),
         ResetTerminal,
         // ----------------- Edit
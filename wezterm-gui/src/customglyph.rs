@@ -2,8 +2,15 @@ use crate::glyphcache::{GlyphCache, SizedBlockKey};
 use crate::utilsprites::RenderMetrics;
 use ::window::bitmaps::atlas::Sprite;
 use ::window::color::SrgbaPixel;
-use config::DimensionContext;
+use config::{
+    CustomGlyphCommand, CustomGlyphIntensity, CustomGlyphPoint, CustomGlyphPoly, CustomGlyphStyle,
+    DimensionContext,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use termwiz::surface::CursorShape;
 use tiny_skia::{BlendMode, FillRule, Paint, Path, PathBuilder, PixmapMut, Stroke, Transform};
 use wezterm_font::units::{IntPixelLength, PixelLength};
@@ -578,6 +585,8 @@ pub enum PolyCommand {
         control: BlockPoint,
         to: BlockPoint,
     },
+    /// An ellipse centered on `center`, whose horizontal and vertical
+    /// radiuses are relative to the width and height of the cell
     Oval {
         center: BlockPoint,
         radiuses: BlockPoint,
@@ -613,12 +622,12 @@ impl PolyCommand {
                 center: (x, y),
                 radiuses: (w, h),
             } => {
-                let x = x.to_pixel(width, underline_height, width.min(height)) - width as f32;
-                let y = y.to_pixel(height, underline_height, width.min(height)) - height as f32;
-                let w = w.to_pixel(width, underline_height, width.min(height)) * 2.0;
-                let h = h.to_pixel(height, underline_height, width.min(height)) * 2.0;
+                let x = x.to_pixel(width, underline_height, width.min(height));
+                let y = y.to_pixel(height, underline_height, width.min(height));
+                let w = w.to_pixel(width, underline_height, width.min(height));
+                let h = h.to_pixel(height, underline_height, width.min(height));
 
-                if let Some(oval) = tiny_skia::Rect::from_xywh(x, y, w, h) {
+                if let Some(oval) = tiny_skia::Rect::from_xywh(x - w, y - h, w * 2.0, h * 2.0) {
                     pb.push_oval(oval);
                 } else {
                     log::error!("Can't push oval, values: {:?}", self);
@@ -677,6 +686,116 @@ impl PolyStyle {
     }
 }
 
+/// The glyphs defined by the custom_glyphs configuration,
+/// converted to their BlockKey representation
+type CustomGlyphMap = HashMap<char, &'static [Poly]>;
+
+/// Incremented each time that the configuration is reloaded, so that
+/// the per-thread CUSTOM_GLYPHS maps know to rebuild themselves
+static CONFIG_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static::lazy_static! {
+    static ref CONFIG_SUBSCRIPTION: config::ConfigSubscription =
+        config::subscribe_to_config_reload(|| {
+            CONFIG_EPOCH.fetch_add(1, Ordering::Relaxed);
+            true
+        });
+    /// Every distinct glyph definition that has been converted.
+    /// BlockKey refers to its polygons by static reference, so the
+    /// converted polygons are leaked; keeping them here means that
+    /// this happens at most once for each distinct definition, no
+    /// matter how many times the configuration is reloaded.
+    static ref INTERNED_POLYS: Mutex<Vec<(Vec<CustomGlyphPoly>, &'static [Poly])>> =
+        Mutex::new(vec![]);
+}
+
+thread_local! {
+    /// The custom glyphs, along with the CONFIG_EPOCH
+    /// at which they were built
+    static CUSTOM_GLYPHS: RefCell<Option<(usize, CustomGlyphMap)>> = RefCell::new(None);
+}
+
+fn intern_polys(definition: &[CustomGlyphPoly]) -> &'static [Poly] {
+    let mut interned = INTERNED_POLYS.lock().unwrap();
+    if let Some((_, polys)) = interned.iter().find(|(def, _)| def == definition) {
+        return *polys;
+    }
+    let polys: &'static [Poly] = Box::leak(
+        definition
+            .iter()
+            .map(poly_from_config)
+            .collect::<Vec<_>>()
+            .into_boxed_slice(),
+    );
+    interned.push((definition.to_vec(), polys));
+    polys
+}
+
+/// Converts the custom_glyphs from the current configuration
+fn build_custom_glyphs() -> CustomGlyphMap {
+    config::configuration()
+        .custom_glyphs
+        .iter()
+        .map(|(&c, definition)| (c, intern_polys(definition)))
+        .collect()
+}
+
+/// Converts a fraction of the cell from the custom_glyphs configuration
+/// to the nearest 120th of the cell.  The configuration is validated
+/// when it is loaded, but values outside of the cell are clamped to its
+/// edges, and NaN is treated as zero, rather than overflowing here.
+fn coord(value: f32) -> BlockCoord {
+    match (value * 120.).round() as i8 {
+        n if n <= 0 => BlockCoord::Zero,
+        n if n >= 120 => BlockCoord::One,
+        n => BlockCoord::Frac(n, 120),
+    }
+}
+
+fn point([x, y]: CustomGlyphPoint) -> BlockPoint {
+    (coord(x), coord(y))
+}
+
+fn poly_from_config(poly: &CustomGlyphPoly) -> Poly {
+    let path: Vec<PolyCommand> = poly
+        .path
+        .iter()
+        .map(|command| match *command {
+            CustomGlyphCommand::MoveTo([x, y]) => PolyCommand::MoveTo(coord(x), coord(y)),
+            CustomGlyphCommand::LineTo([x, y]) => PolyCommand::LineTo(coord(x), coord(y)),
+            CustomGlyphCommand::QuadTo { control, to } => PolyCommand::QuadTo {
+                control: point(control),
+                to: point(to),
+            },
+            CustomGlyphCommand::Oval { center, radiuses } => PolyCommand::Oval {
+                center: point(center),
+                radiuses: point(radiuses),
+            },
+            CustomGlyphCommand::Circle { center, radius } => PolyCommand::Circle {
+                center: point(center),
+                radius: coord(radius),
+            },
+            CustomGlyphCommand::Close => PolyCommand::Close,
+        })
+        .collect();
+
+    Poly {
+        path: Box::leak(path.into_boxed_slice()),
+        intensity: match poly.intensity {
+            CustomGlyphIntensity::Full => BlockAlpha::Full,
+            CustomGlyphIntensity::Dark => BlockAlpha::Dark,
+            CustomGlyphIntensity::Medium => BlockAlpha::Medium,
+            CustomGlyphIntensity::Light => BlockAlpha::Light,
+        },
+        style: match poly.style {
+            CustomGlyphStyle::Fill => PolyStyle::Fill,
+            CustomGlyphStyle::Outline => PolyStyle::Outline,
+            CustomGlyphStyle::OutlineThin => PolyStyle::OutlineThin,
+            CustomGlyphStyle::OutlineHeavy => PolyStyle::OutlineHeavy,
+        },
+    }
+}
+
 impl BlockKey {
     pub fn filter_out_synthetic(glyphs: &mut Vec<char>) {
         let config = config::configuration();
@@ -4983,7 +5102,28 @@ impl BlockKey {
             0xF60D => Self::Branches(
                 Branch::CIRCLE_OUTLINE | Branch::LEFT | Branch::RIGHT | Branch::UP | Branch::DOWN,
             ),
-            _ => return None,
+            _ => return Self::from_custom_glyphs(c),
+        })
+    }
+
+    /// Returns the glyph that was defined for the codepoint via
+    /// the custom_glyphs configuration, if any.
+    /// This is called for every character that isn't one of the
+    /// built-in glyphs, so the definitions are converted only when
+    /// the configuration changes, and looking them up is lock-free.
+    fn from_custom_glyphs(c: u32) -> Option<Self> {
+        let c = char::from_u32(c)?;
+        lazy_static::initialize(&CONFIG_SUBSCRIPTION);
+        let epoch = CONFIG_EPOCH.load(Ordering::Relaxed);
+
+        CUSTOM_GLYPHS.with(|glyphs| {
+            let mut glyphs = glyphs.borrow_mut();
+            let stale = !matches!(&*glyphs, Some((built, _)) if *built == epoch);
+            if stale {
+                glyphs.replace((epoch, build_custom_glyphs()));
+            }
+            let (_, glyphs) = glyphs.as_ref()?;
+            glyphs.get(&c).map(|polys| Self::Poly(*polys))
         })
     }
 
@@ -5043,7 +5183,15 @@ impl GlyphCache {
             for item in path.iter() {
                 item.to_skia(width, height, metrics.underline_height as f32, &mut pb);
             }
-            let path = pb.finish().expect("poly path to be valid");
+            // Paths from the custom_glyphs configuration may not
+            // describe anything that can be drawn
+            let path = match pb.finish() {
+                Some(path) => path,
+                None => {
+                    log::error!("Can't draw empty or invalid poly path: {:?}", path);
+                    continue;
+                }
+            };
             style.apply(metrics.underline_height as f32, &paint, &path, &mut pixmap);
         }
     }
@@ -5154,6 +5302,7 @@ impl GlyphCache {
                 descender_plus_two: 0,
                 underline_height: *underline_height,
                 strike_row: 0,
+                strike_height: *underline_height,
                 cell_size: cell_size.clone(),
             },
             _ => render_metrics.clone(),
@@ -6034,3 +6183,150 @@ fn fill_rect(buffer: &mut Image, x: Range<f32>, y: Range<f32>, intensity: BlockA
         None,
     );
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::CustomGlyphCommand::*;
+
+    #[test]
+    fn coords_from_config() {
+        for (value, expected) in [
+            (0.0, BlockCoord::Zero),
+            (1.0, BlockCoord::One),
+            (0.5, BlockCoord::Frac(60, 120)),
+            (0.25, BlockCoord::Frac(30, 120)),
+            (1. / 3., BlockCoord::Frac(40, 120)),
+            // Rounded to the nearest 120th
+            (0.001, BlockCoord::Zero),
+            (0.999, BlockCoord::One),
+            // Out of range values are clamped to the edges of the cell
+            (-0.5, BlockCoord::Zero),
+            (2.0, BlockCoord::One),
+            (1000.0, BlockCoord::One),
+            (f32::INFINITY, BlockCoord::One),
+            (f32::NEG_INFINITY, BlockCoord::Zero),
+            (f32::NAN, BlockCoord::Zero),
+        ] {
+            assert_eq!(coord(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn polys_from_config() {
+        for (poly, expected) in [
+            (
+                CustomGlyphPoly {
+                    path: vec![
+                        MoveTo([0., 0.]),
+                        LineTo([1., 0.5]),
+                        QuadTo {
+                            control: [0.5, 1.],
+                            to: [0., 1.],
+                        },
+                        Close,
+                    ],
+                    intensity: CustomGlyphIntensity::Full,
+                    style: CustomGlyphStyle::Fill,
+                },
+                Poly {
+                    path: &[
+                        PolyCommand::MoveTo(BlockCoord::Zero, BlockCoord::Zero),
+                        PolyCommand::LineTo(BlockCoord::One, BlockCoord::Frac(60, 120)),
+                        PolyCommand::QuadTo {
+                            control: (BlockCoord::Frac(60, 120), BlockCoord::One),
+                            to: (BlockCoord::Zero, BlockCoord::One),
+                        },
+                        PolyCommand::Close,
+                    ],
+                    intensity: BlockAlpha::Full,
+                    style: PolyStyle::Fill,
+                },
+            ),
+            (
+                CustomGlyphPoly {
+                    path: vec![
+                        Oval {
+                            center: [0.5, 0.5],
+                            radiuses: [0.25, 0.5],
+                        },
+                        Circle {
+                            center: [0.5, 0.25],
+                            radius: 0.25,
+                        },
+                    ],
+                    intensity: CustomGlyphIntensity::Medium,
+                    style: CustomGlyphStyle::OutlineHeavy,
+                },
+                Poly {
+                    path: &[
+                        PolyCommand::Oval {
+                            center: (BlockCoord::Frac(60, 120), BlockCoord::Frac(60, 120)),
+                            radiuses: (BlockCoord::Frac(30, 120), BlockCoord::Frac(60, 120)),
+                        },
+                        PolyCommand::Circle {
+                            center: (BlockCoord::Frac(60, 120), BlockCoord::Frac(30, 120)),
+                            radius: BlockCoord::Frac(30, 120),
+                        },
+                    ],
+                    intensity: BlockAlpha::Medium,
+                    style: PolyStyle::OutlineHeavy,
+                },
+            ),
+            // Coordinates outside of the cell are clamped to its edges
+            (
+                CustomGlyphPoly {
+                    path: vec![MoveTo([-1., 2.]), LineTo([f32::NAN, 0.5])],
+                    intensity: CustomGlyphIntensity::Light,
+                    style: CustomGlyphStyle::OutlineThin,
+                },
+                Poly {
+                    path: &[
+                        PolyCommand::MoveTo(BlockCoord::Zero, BlockCoord::One),
+                        PolyCommand::LineTo(BlockCoord::Zero, BlockCoord::Frac(60, 120)),
+                    ],
+                    intensity: BlockAlpha::Light,
+                    style: PolyStyle::OutlineThin,
+                },
+            ),
+            // An empty path converts to an empty poly, which is skipped
+            // when it is drawn
+            (
+                CustomGlyphPoly {
+                    path: vec![],
+                    intensity: CustomGlyphIntensity::Dark,
+                    style: CustomGlyphStyle::Outline,
+                },
+                Poly {
+                    path: &[],
+                    intensity: BlockAlpha::Dark,
+                    style: PolyStyle::Outline,
+                },
+            ),
+        ] {
+            assert_eq!(poly_from_config(&poly), expected, "{:?}", poly);
+        }
+    }
+
+    #[test]
+    fn oval_is_centered_on_its_center() {
+        // A 20x40 cell with an oval that spans the middle half of its
+        // width and all of its height
+        let mut pb = PathBuilder::new();
+        PolyCommand::Oval {
+            center: (BlockCoord::Frac(60, 120), BlockCoord::Frac(60, 120)),
+            radiuses: (BlockCoord::Frac(30, 120), BlockCoord::Frac(60, 120)),
+        }
+        .to_skia(20, 40, 1., &mut pb);
+        let bounds = pb.finish().unwrap().bounds();
+        assert_eq!(
+            (
+                bounds.left().round(),
+                bounds.top().round(),
+                bounds.right().round(),
+                bounds.bottom().round()
+            ),
+            (5., 0., 14., 39.)
+        );
+    }
+}
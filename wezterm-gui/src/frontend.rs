@@ -90,10 +90,15 @@ impl GuiFrontEnd {
                 MuxNotification::WindowTitleChanged { .. } => {}
                 MuxNotification::TabResized(_) => {}
                 MuxNotification::TabAddedToWindow { .. } => {}
-                MuxNotification::PaneRemoved(_) => {}
+                MuxNotification::PaneRemoved(pane_id) => {
+                    crate::overlay::copy::forget_pane(pane_id);
+                }
                 MuxNotification::WindowInvalidated(_) => {}
                 MuxNotification::PaneOutput(_) => {}
                 MuxNotification::PaneAdded(_) => {}
+                // We register a screenshot renderer, so the mux never
+                // needs to delegate rendering to another client
+                MuxNotification::ScreenshotRequested { .. } => {}
                 MuxNotification::Alert {
                     pane_id,
                     alert:
@@ -200,6 +205,45 @@ impl GuiFrontEnd {
                     })
                     .detach();
                 }
+                MuxNotification::SshAgentConfirmation {
+                    client_id: Some(_), ..
+                } => {
+                    // Delegated to a mux client
+                }
+                MuxNotification::SshAgentConfirmation {
+                    id,
+                    message,
+                    client_id: None,
+                } => {
+                    promise::spawn::spawn_into_main_thread(async move {
+                        let fe = crate::frontend::front_end();
+                        let mux = Mux::get();
+                        // Prefer to prompt in the window that the user is
+                        // interacting with
+                        let window = mux
+                            .resolve_focused_pane(&fe.client_id)
+                            .and_then(|(_domain, window_id, _tab, _pane)| {
+                                fe.gui_window_for_mux_window(window_id)
+                            })
+                            .or_else(|| fe.gui_windows().into_iter().next());
+                        match window {
+                            Some(gui_win) => {
+                                gui_win.window.notify(TermWindowNotif::Apply(Box::new(
+                                    move |term_window| {
+                                        term_window.show_ssh_agent_confirmation(id, message);
+                                    },
+                                )));
+                            }
+                            None => {
+                                log::error!(
+                                    "Cannot confirm ssh agent request as there are no windows"
+                                );
+                                mux::ssh_agent::resolve_confirmation(id, false);
+                            }
+                        }
+                    })
+                    .detach();
+                }
             }
             true
         });
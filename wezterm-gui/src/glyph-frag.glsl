@@ -21,6 +21,8 @@ uniform sampler2D atlas_nearest_sampler;
 uniform sampler2D atlas_linear_sampler;
 uniform bool subpixel_aa;
 uniform uint milliseconds;
+uniform vec2 text_gamma;
+uniform vec2 text_contrast;
 
 struct ColorEase {
   vec4 in_function;
@@ -110,18 +112,51 @@ vec4 to_srgb(vec4 linearRGB)
   return vec4(mix(higher, lower, cutoff), linearRGB.a);
 }
 
+// Blending happens in sRGB space rather than in linear space,
+// which makes light text on a dark background appear too thin
+// and dark text on a light background appear too heavy.
+// Adjust the per-channel coverage to compensate, based on the
+// luminance of the text color; the adjustment is exact for
+// black text on white and vice versa.
+vec3 lcd_coverage(vec3 coverage, vec3 text_color) {
+  float luminance = dot(text_color, vec3(0.2126, 0.7152, 0.0722));
+  vec3 lighten = pow(coverage, vec3(1.0 / 2.2));
+  vec3 darken = vec3(1.0) - pow(vec3(1.0) - coverage, vec3(1.0 / 2.2));
+  return mix(darken, lighten, luminance);
+}
+
+// Apply the text_contrast and text_gamma configuration to the
+// coverage of a glyph.  The x component of each holds the value
+// for light text, and the y component the value for dark text.
+vec3 adjust_coverage(vec3 coverage, vec3 text_color) {
+  float luminance = dot(text_color, vec3(0.2126, 0.7152, 0.0722));
+  bool light = luminance > 0.5;
+  float contrast = light ? text_contrast.x : text_contrast.y;
+  float gamma = light ? text_gamma.x : text_gamma.y;
+  coverage = clamp((coverage - vec3(0.5)) * (1.0 + contrast) + vec3(0.5), 0.0, 1.0);
+  return pow(coverage, vec3(1.0 / gamma));
+}
+
 void main() {
   vec4 fg_color = mix(o_fg_color, o_fg_color_alt, o_fg_color_mix);
   if (o_has_color == 3.0) {
     // Solid color block
     color = fg_color;
-    colorMask = vec4(1.0);
+    if (subpixel_aa) {
+      // The dual source blend multiplies by colorMask rather
+      // than by the alpha, so carry the alpha there
+      colorMask = fg_color.aaaa;
+      color.a = 1.0;
+    } else {
+      colorMask = vec4(1.0);
+    }
   } else if (o_has_color == 2.0) {
     // The window background attachment
     color = texture(atlas_linear_sampler, o_tex);
     // Apply window_background_image_opacity to the background image
     if (subpixel_aa) {
-      colorMask = fg_color.aaaa;
+      colorMask = vec4(color.a * fg_color.a);
+      color.a = 1.0;
     } else {
       color.a *= fg_color.a;
     }
@@ -130,6 +165,9 @@ void main() {
     color = texture(atlas_nearest_sampler, o_tex);
     // this is the alpha
     colorMask = color.aaaa;
+    if (subpixel_aa) {
+      color.a = 1.0;
+    }
   } else if (o_has_color == 4.0) {
     // Grayscale poly quad for non-aa text render layers
     colorMask = texture(atlas_nearest_sampler, o_tex);
@@ -142,15 +180,28 @@ void main() {
     // for the background.
     color.a = mix(o_fg_color.a, o_fg_color_alt.a, clamp(o_fg_color_mix, 0.0, 1.0));
     color.a *= colorMask.a;
+    if (subpixel_aa) {
+      colorMask = color.aaaa;
+      color.a = 1.0;
+    }
   } else if (o_has_color == 0.0) {
     // the texture is the alpha channel/color mask
     colorMask = texture(atlas_nearest_sampler, o_tex);
     // and we need to tint with the fg_color
     color = fg_color;
-    if (!subpixel_aa) {
-      color.a = colorMask.a;
-    }
     color = apply_hsv(color, foreground_text_hsb);
+    if (subpixel_aa) {
+      // Each of the RGB channels holds the coverage of the
+      // corresponding subpixel.  Scale them by the alpha of
+      // the text color so that translucent text and windows
+      // with background opacity blend correctly.
+      vec3 text_color = apply_hsv(color, o_hsv).rgb;
+      colorMask.rgb = adjust_coverage(lcd_coverage(colorMask.rgb, text_color), text_color);
+      colorMask *= color.a;
+      color.a = 1.0;
+    } else {
+      color.a = adjust_coverage(colorMask.aaa, apply_hsv(color, o_hsv).rgb).x;
+    }
   }
 
   color = apply_hsv(color, o_hsv);
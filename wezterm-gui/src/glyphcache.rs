@@ -185,12 +185,44 @@ impl std::fmt::Debug for CachedGlyph {
     }
 }
 
+/// The style of strikethrough to draw in a line sprite
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StrikeThrough {
+    None,
+    Single,
+    Double,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 struct LineKey {
-    strike_through: bool,
+    strike_through: StrikeThrough,
     underline: Underline,
     overline: bool,
     size: CellMetricKey,
+    geometry: LineGeometryKey,
+}
+
+/// The decoration geometry of the face that a line sprite is drawn
+/// for; faces with different metrics need their own sprites
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct LineGeometryKey {
+    descender_row: IntPixelLength,
+    descender_plus_two: IntPixelLength,
+    underline_height: IntPixelLength,
+    strike_row: IntPixelLength,
+    strike_height: IntPixelLength,
+}
+
+impl From<&RenderMetrics> for LineGeometryKey {
+    fn from(metrics: &RenderMetrics) -> LineGeometryKey {
+        LineGeometryKey {
+            descender_row: metrics.descender_row,
+            descender_plus_two: metrics.descender_plus_two,
+            underline_height: metrics.underline_height,
+            strike_row: metrics.strike_row,
+            strike_height: metrics.strike_height,
+        }
+    }
 }
 
 /// A helper struct to implement BitmapImage for ImageDataType while
@@ -1176,23 +1208,22 @@ impl GlyphCache {
         };
 
         let draw_dotted = |buffer: &mut Image| {
+            let width = metrics.cell_size.width as usize;
+            let dots = underline_dots(width, metrics.underline_height);
+
             for row in 0..metrics.underline_height {
-                let y = (cell_rect.origin.y + metrics.descender_row + row) as usize;
-                if y >= metrics.cell_size.height as usize {
+                let y = cell_rect.origin.y + metrics.descender_row + row;
+                if y < 0 {
+                    continue;
+                }
+                if y >= metrics.cell_size.height {
                     break;
                 }
 
-                let mut color = white;
-                let segment_length = (metrics.cell_size.width / 4) as usize;
-                let mut count = segment_length;
-                let range =
-                    buffer.horizontal_pixel_range_mut(0, metrics.cell_size.width as usize, y);
-                for c in range.iter_mut() {
-                    *c = color.as_srgba32();
-                    count -= 1;
-                    if count == 0 {
-                        color = if color == white { black } else { white };
-                        count = segment_length;
+                let range = buffer.horizontal_pixel_range_mut(0, width, y as usize);
+                for dot in &dots {
+                    for c in &mut range[dot.clone()] {
+                        *c = white.as_srgba32();
                     }
                 }
             }
@@ -1293,16 +1324,13 @@ impl GlyphCache {
             }
         };
 
-        let draw_strike = |buffer: &mut Image| {
-            for row in 0..metrics.underline_height {
+        let draw_strike = |buffer: &mut Image, top_row: IntPixelLength| {
+            for row in 0..metrics.strike_height {
                 buffer.draw_line(
-                    Point::new(
-                        cell_rect.origin.x,
-                        cell_rect.origin.y + metrics.strike_row + row,
-                    ),
+                    Point::new(cell_rect.origin.x, cell_rect.origin.y + top_row + row),
                     Point::new(
                         cell_rect.origin.x + metrics.cell_size.width,
-                        cell_rect.origin.y + metrics.strike_row + row,
+                        cell_rect.origin.y + top_row + row,
                     ),
                     white,
                 );
@@ -1334,8 +1362,14 @@ impl GlyphCache {
             Underline::Dotted => draw_dotted(&mut buffer),
             Underline::Double => draw_double(&mut buffer),
         }
-        if key.strike_through {
-            draw_strike(&mut buffer);
+        match key.strike_through {
+            StrikeThrough::None => {}
+            StrikeThrough::Single => draw_strike(&mut buffer, metrics.strike_row),
+            StrikeThrough::Double => {
+                let (upper, lower) = metrics.double_strike_rows();
+                draw_strike(&mut buffer, upper);
+                draw_strike(&mut buffer, lower);
+            }
         }
         let sprite = self.atlas.allocate(&buffer)?;
         self.line_glyphs.insert(key, sprite.clone());
@@ -1348,7 +1382,7 @@ impl GlyphCache {
     pub fn cached_line_sprite(
         &mut self,
         is_highlited_hyperlink: bool,
-        is_strike_through: bool,
+        strike_through: StrikeThrough,
         underline: Underline,
         overline: bool,
         metrics: &RenderMetrics,
@@ -1361,10 +1395,11 @@ impl GlyphCache {
         };
 
         let key = LineKey {
-            strike_through: is_strike_through,
+            strike_through,
             overline,
             underline: effective_underline,
             size: metrics.into(),
+            geometry: metrics.into(),
         };
 
         if let Some(s) = self.line_glyphs.get(&key) {
@@ -1374,3 +1409,46 @@ impl GlyphCache {
         self.line_sprite(key, metrics)
     }
 }
+
+/// Returns the columns of the dots of a dotted underline: square dots
+/// the size of the underline thickness, spaced evenly across a cell
+/// that is `width` pixels wide with a gap of at least one dot
+fn underline_dots(width: usize, thickness: IntPixelLength) -> Vec<std::ops::Range<usize>> {
+    let dot_size = (thickness.max(1) as usize).min(width).max(1);
+    let num_dots = (width / (2 * dot_size)).max(1);
+    (0..num_dots)
+        .map(|dot| {
+            let start = dot * width / num_dots;
+            start..(start + dot_size).min(width)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn underline_dots_fit_small_and_large_cells() {
+        for ((width, thickness), expected) in [
+            ((8, 1), vec![0..1, 2..3, 4..5, 6..7]),
+            ((9, 2), vec![0..2, 4..6]),
+            // A single pixel wide cell still gets a dot
+            ((1, 1), vec![0..1]),
+            // Dots are no wider than the cell
+            ((2, 4), vec![0..2]),
+            // A large cell with a thick underline
+            ((40, 5), vec![0..5, 10..15, 20..25, 30..35]),
+            // A zero thickness is treated as a single pixel
+            ((4, 0), vec![0..1, 2..3]),
+        ] {
+            assert_eq!(
+                underline_dots(width, thickness),
+                expected,
+                "width={} thickness={}",
+                width,
+                thickness
+            );
+        }
+    }
+}
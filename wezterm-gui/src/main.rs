@@ -45,6 +45,7 @@ mod overlay;
 mod quad;
 mod renderstate;
 mod resize_increment_calculator;
+mod screenshot;
 mod scripting;
 mod scrollbar;
 mod selection;
@@ -131,6 +132,11 @@ enum SubCommand {
 
     #[command(name = "show-keys", about = "Show key assignments")]
     ShowKeys(ShowKeysCommand),
+
+    /// Render a pane snapshot read from stdin as PNG data on stdout.
+    /// This is used by wezterm-mux-server when no gui is attached.
+    #[command(name = "render-screenshot", hide = true)]
+    RenderScreenshot,
 }
 
 async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
@@ -832,6 +838,8 @@ fn main() {
 
     config::designate_this_as_the_main_thread();
     config::assign_error_callback(mux::connui::show_configuration_error_message);
+    mux::screenshot::register_screenshot_renderer(screenshot::screenshot_pane);
+    mux::ssh_agent::register_local_prompt();
     notify_on_panic();
     if let Err(e) = run() {
         terminate_with_error(e);
@@ -858,6 +866,199 @@ fn run_show_keys(config: config::ConfigHandle, cmd: &ShowKeysCommand) -> anyhow:
     Ok(())
 }
 
+/// Fetches the lines of the screen and scrollback of a pane from
+/// the running wezterm instance.
+/// Returns the lines along with the stable row index of the top
+/// of the screen, so that rows can be reported relative to it.
+fn fetch_pane_lines(pane_id: usize) -> anyhow::Result<(Vec<(isize, Line)>, isize)> {
+    let executor = promise::spawn::ScopedExecutor::new();
+    block_on(executor.run(async move {
+        let mut ui = mux::connui::ConnectionUI::new_headless();
+        let client = wezterm_client::client::Client::new_default_unix_domain(
+            true,
+            &mut ui,
+            true,
+            false,
+            DEFAULT_WINDOW_CLASS,
+        )?;
+
+        let info = client
+            .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+            .await?;
+        let dims = info.dimensions;
+        let end = dims.physical_top + dims.viewport_rows as isize;
+
+        // Request the lines in chunks so that a large scrollback
+        // doesn't produce an unreasonably large response
+        const CHUNK: isize = 1024;
+        let mut lines = vec![];
+        let mut start = dims.scrollback_top;
+        while start < end {
+            let chunk_end = (start + CHUNK).min(end);
+            let response = client
+                .get_lines(codec::GetLines {
+                    pane_id,
+                    lines: vec![start..chunk_end],
+                })
+                .await?;
+            lines.append(&mut response.lines.extract_data().0);
+            start = chunk_end;
+        }
+
+        Ok((lines, dims.physical_top))
+    }))
+}
+
+/// Where a codepoint in a pane was shaped from
+struct CodepointUsage {
+    /// The font that shaped it
+    font: String,
+    diagnostic: String,
+    /// True if the shaper produced the notdef glyph
+    notdef: bool,
+    /// True if the glyph came from the last resort font
+    last_resort: bool,
+    /// Rows on which it appears, relative to the top of the screen
+    rows: Vec<isize>,
+}
+
+/// Implements `wezterm ls-fonts --pane-id N`, which shapes the text in a
+/// pane and reports the font used for each codepoint, highlighting
+/// those that couldn't be resolved.
+fn ls_fonts_for_pane(
+    config: &ConfigHandle,
+    font_config: &FontConfiguration,
+    pane_id: usize,
+    bidi_hint: Option<wezterm_bidi::ParagraphDirectionHint>,
+) -> anyhow::Result<()> {
+    use std::collections::BTreeMap;
+
+    let (lines, physical_top) = fetch_pane_lines(pane_id)?;
+
+    let mut usage: BTreeMap<char, CodepointUsage> = BTreeMap::new();
+
+    for (stable_row, line) in &lines {
+        let row = stable_row - physical_top;
+        for cluster in line.cluster(bidi_hint) {
+            let style = font_config.match_style(config, &cluster.attrs);
+            let font = font_config.resolve_font(style)?;
+            let presentation_width = PresentationWidth::with_cluster(&cluster);
+            let infos = font.blocking_shape(
+                &cluster.text,
+                Some(cluster.presentation),
+                cluster.direction,
+                None,
+                Some(&presentation_width),
+            )?;
+
+            // We must grab the handles after shaping, so that we get the
+            // revised list that includes system fallbacks!
+            let handles = font.clone_handles();
+
+            // Map the start of each shaped cluster to the font that it
+            // used; if any glyph in a cluster is notdef, then the
+            // whole cluster is considered to be unresolved
+            let mut glyph_clusters: BTreeMap<usize, (usize, bool)> = BTreeMap::new();
+            for info in &infos {
+                let entry = glyph_clusters
+                    .entry(info.cluster as usize)
+                    .or_insert((info.font_idx, false));
+                entry.1 |= info.glyph_pos == 0;
+            }
+
+            for (byte_idx, c) in cluster.text.char_indices() {
+                if c.is_whitespace() {
+                    continue;
+                }
+                let (font_idx, notdef) = match glyph_clusters.range(..=byte_idx).next_back() {
+                    Some((_, &pair)) => pair,
+                    None => continue,
+                };
+                let parsed = &handles[font_idx];
+                let last_resort = parsed.names().family == "Last Resort High-Efficiency";
+                let entry = usage.entry(c).or_insert_with(|| CodepointUsage {
+                    font: parsed.lua_name(),
+                    diagnostic: parsed.handle.diagnostic_string(),
+                    notdef,
+                    last_resort,
+                    rows: vec![],
+                });
+                if (notdef || last_resort) && !(entry.notdef || entry.last_resort) {
+                    // It resolved in one style but not in this one;
+                    // report the failure
+                    entry.font = parsed.lua_name();
+                    entry.diagnostic = parsed.handle.diagnostic_string();
+                    entry.notdef = notdef;
+                    entry.last_resort = last_resort;
+                }
+                if entry.rows.last() != Some(&row) {
+                    entry.rows.push(row);
+                }
+            }
+        }
+    }
+
+    let is_custom_block = |c: char| config.custom_block_glyphs && BlockKey::from_char(c).is_some();
+
+    let mut by_font: BTreeMap<(&str, &str), String> = BTreeMap::new();
+    for (c, entry) in &usage {
+        if entry.notdef || entry.last_resort || is_custom_block(*c) {
+            continue;
+        }
+        by_font
+            .entry((&entry.font, &entry.diagnostic))
+            .or_default()
+            .push(*c);
+    }
+
+    println!("Fonts used to render pane {pane_id}:");
+    for ((font, diagnostic), text) in &by_font {
+        println!();
+        println!("{font}");
+        println!("  -- {diagnostic}");
+        println!("  {text}");
+    }
+    println!();
+
+    let unresolved: Vec<_> = usage
+        .iter()
+        .filter(|(c, entry)| (entry.notdef || entry.last_resort) && !is_custom_block(**c))
+        .collect();
+
+    if unresolved.is_empty() {
+        println!("All codepoints in pane {pane_id} were resolved to a font.");
+        return Ok(());
+    }
+
+    println!(
+        "{} codepoints in pane {pane_id} could not be resolved:",
+        unresolved.len()
+    );
+    for (c, entry) in unresolved {
+        let reason = if entry.notdef {
+            "notdef glyph"
+        } else {
+            "last resort font"
+        };
+        let rows = entry
+            .rows
+            .iter()
+            .take(8)
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let more = if entry.rows.len() > 8 { ",..." } else { "" };
+        println!(
+            "U+{:04X} {:4} {reason} from {} rows={rows}{more}",
+            *c as u32,
+            c.to_string(),
+            entry.font,
+        );
+    }
+
+    Ok(())
+}
+
 pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyhow::Result<()> {
     use wezterm_font::parser::ParsedFont;
 
@@ -885,6 +1086,10 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
 
     let unicode_version = config.unicode_version();
 
+    if let Some(pane_id) = cmd.pane_id {
+        return ls_fonts_for_pane(&config, &font_config, pane_id, bidi_hint);
+    }
+
     let text = match (&cmd.text, &cmd.codepoints) {
         (Some(text), _) => Some(text.to_string()),
         (_, Some(codepoints)) => {
@@ -1102,6 +1307,16 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
         if let Some(invisible) = &rule.invisible {
             condition.push_str(&format!(" Invisible={:?}", invisible));
         }
+        if let Some(ranges) = &rule.unicode_ranges {
+            let ranges = ranges
+                .iter()
+                .map(|r| format!("U+{:04X}-U+{:04X}", r.first, r.last))
+                .collect::<Vec<_>>();
+            condition.push_str(&format!(" UnicodeRanges={}", ranges.join(",")));
+        }
+        if let Some(scripts) = &rule.scripts {
+            condition.push_str(&format!(" Scripts={}", scripts.join(",")));
+        }
 
         println!("{}:", condition);
         let font = font_config.resolve_font(&rule.font)?;
@@ -1274,5 +1489,6 @@ fn run() -> anyhow::Result<()> {
         ),
         SubCommand::LsFonts(cmd) => run_ls_fonts(config, &cmd),
         SubCommand::ShowKeys(cmd) => run_show_keys(config, &cmd),
+        SubCommand::RenderScreenshot => screenshot::run_render_screenshot(),
     }
 }
@@ -2,8 +2,8 @@ use crate::selection::{SelectionCoordinate, SelectionRange, SelectionX};
 use crate::termwindow::keyevent::KeyTableArgs;
 use crate::termwindow::{TermWindow, TermWindowNotif};
 use config::keyassignment::{
-    ClipboardCopyDestination, CopyModeAssignment, KeyAssignment, KeyTable, KeyTableEntry,
-    ScrollbackEraseMode, SelectionMode,
+    ClipboardCopyDestination, ClipboardPasteSource, CopyModeAssignment, CopyModeSearchScope,
+    CopyModeTextObject, KeyAssignment, KeyTable, KeyTableEntry, ScrollbackEraseMode, SelectionMode,
 };
 use mux::domain::DomainId;
 use mux::pane::{
@@ -28,15 +28,38 @@ use url::Url;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{
     unicode_column_width, Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, SemanticType,
-    StableRowIndex, TerminalSize,
+    SemanticZone, StableRowIndex, TerminalSize,
 };
 use window::{KeyCode as WKeyCode, Modifiers, WindowOps};
 
 lazy_static::lazy_static! {
     static ref SAVED_PATTERN: Mutex<HashMap<TabId, Pattern>> = Mutex::new(HashMap::new());
+    /// Marks set with `m`, which persist across copy mode sessions in a pane
+    static ref SAVED_MARKS: Mutex<HashMap<PaneId, HashMap<char, (usize, StableRowIndex)>>> =
+        Mutex::new(HashMap::new());
+    /// Named registers, which are shared by all panes
+    static ref REGISTERS: Mutex<HashMap<char, String>> = Mutex::new(HashMap::new());
 }
 
+/// Discards the marks that were set in a pane.
+/// Called when the pane is removed from the mux.
+/// The registers are shared by all panes, so they are left alone.
+pub fn forget_pane(pane_id: PaneId) {
+    SAVED_MARKS.lock().remove(&pane_id);
+}
+
+/// The register that receives all yanks and that is used by
+/// PasteFromRegister when no register was selected
+const UNNAMED_REGISTER: char = '"';
+/// The register that holds the most recent yank that didn't
+/// name a register
+const YANK_REGISTER: char = '0';
+/// The mark that records the position prior to the most recent mark jump
+const PREVIOUS_MARK: char = '\'';
+
 const SEARCH_CHUNK_SIZE: StableRowIndex = 1000;
+/// Upper bound on the count that can be typed prior to a motion
+const MAX_COUNT: usize = 100_000;
 
 pub struct CopyOverlay {
     delegate: Arc<dyn Pane>,
@@ -48,6 +71,16 @@ pub struct CopyOverlay {
 struct PendingJump {
     forward: bool,
     prev_char: bool,
+    count: usize,
+}
+
+/// An action that is waiting for the next key press to name its target
+#[derive(Copy, Clone, Debug)]
+enum PendingKey {
+    TextObject { around: bool },
+    SetMark,
+    JumpToMark { exact: bool },
+    Register,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -85,6 +118,20 @@ struct CopyRenderable {
     searching: Option<Searching>,
     pending_jump: Option<PendingJump>,
     last_jump: Option<Jump>,
+    pending_key: Option<PendingKey>,
+    /// The count accumulated from digits typed prior to a motion
+    pending_count: Option<usize>,
+    /// The register selected for the next yank or paste
+    register: Option<char>,
+    search_scope: CopyModeSearchScope,
+    /// The zones that bound the search when search_scope is not All.
+    /// Computed when the search is started.
+    scope_zones: Option<Vec<SemanticZone>>,
+    /// When true, only the lines containing matches are displayed
+    filter_view: bool,
+    /// The sorted rows that contain matches; these are the rows that
+    /// are shown when filter_view is enabled
+    filter_rows: Vec<StableRowIndex>,
 }
 
 struct Searching {
@@ -162,6 +209,13 @@ impl CopyOverlay {
             searching: None,
             pending_jump: None,
             last_jump: None,
+            pending_key: None,
+            pending_count: None,
+            register: None,
+            search_scope: CopyModeSearchScope::default(),
+            scope_zones: None,
+            filter_view: false,
+            filter_rows: vec![],
         };
 
         let search_row = render.compute_search_row();
@@ -202,6 +256,12 @@ impl CopyOverlay {
         render.dirty_results.add(search_row);
     }
 
+    /// Returns the starting row of each of the current search matches
+    pub fn search_result_rows(&self) -> Vec<StableRowIndex> {
+        let render = self.render.lock();
+        render.results.iter().map(|res| res.start_y).collect()
+    }
+
     pub fn viewport_changed(&self, viewport: Option<StableRowIndex>) {
         let mut render = self.render.lock();
         if render.viewport != viewport {
@@ -239,6 +299,13 @@ impl CopyRenderable {
     }
 
     fn incrementally_recompute_results(&mut self, mut results: Vec<SearchResult>) {
+        if let Some(zones) = &self.scope_zones {
+            results.retain(|res| {
+                zones
+                    .iter()
+                    .any(|zone| zone_contains(zone, res.start_y, res.start_x))
+            });
+        }
         results.sort();
         results.reverse();
         for (result_index, res) in results.iter().enumerate() {
@@ -270,6 +337,10 @@ impl CopyRenderable {
             }
         }
         self.results.append(&mut results);
+
+        let mut rows: Vec<StableRowIndex> = self.by_line.keys().copied().collect();
+        rows.sort();
+        self.filter_rows = rows;
     }
 
     fn schedule_update_search(&mut self) {
@@ -307,7 +378,9 @@ impl CopyRenderable {
 
         self.results.clear();
         self.by_line.clear();
+        self.filter_rows.clear();
         self.result_pos.take();
+        self.scope_zones = self.compute_scope_zones();
 
         SAVED_PATTERN.lock().insert(self.tab_id, self.get_pattern());
 
@@ -319,15 +392,22 @@ impl CopyRenderable {
         if !pattern.is_empty() {
             let pane: Arc<dyn Pane> = self.delegate.clone();
             let window = self.window.clone();
-            let dims = pane.get_dimensions();
+            let bounds = self.search_bounds();
+            if bounds.is_empty() {
+                // The scope doesn't contain any zones, so there
+                // can be no matches
+                self.searching.take();
+                self.set_viewport(None);
+                self.clear_selection();
+                self.window.invalidate();
+                return;
+            }
 
-            let end = dims.scrollback_top + dims.scrollback_rows as StableRowIndex;
-            let range = end
-                .saturating_sub(SEARCH_CHUNK_SIZE)
-                .max(dims.scrollback_top)..end;
+            let end = bounds.end;
+            let range = end.saturating_sub(SEARCH_CHUNK_SIZE).max(bounds.start)..end;
 
             self.searching.replace(Searching {
-                remain: range.start - dims.scrollback_top,
+                remain: range.start - bounds.start,
             });
 
             promise::spawn::spawn(async move {
@@ -379,8 +459,8 @@ impl CopyRenderable {
             }
         }
 
-        let dims = self.delegate.get_dimensions();
-        if range.start == dims.scrollback_top {
+        let bounds = self.search_bounds();
+        if range.start <= bounds.start {
             self.searching.take();
             return;
         }
@@ -389,12 +469,10 @@ impl CopyRenderable {
         let pane: Arc<dyn Pane> = self.delegate.clone();
         let window = self.window.clone();
         let end = range.start;
-        let range = end
-            .saturating_sub(SEARCH_CHUNK_SIZE)
-            .max(dims.scrollback_top)..end;
+        let range = end.saturating_sub(SEARCH_CHUNK_SIZE).max(bounds.start)..end;
 
         self.searching.replace(Searching {
-            remain: range.start - dims.scrollback_top,
+            remain: range.start - bounds.start,
         });
 
         promise::spawn::spawn(async move {
@@ -509,6 +587,11 @@ impl CopyRenderable {
     }
 
     fn adjust_selection(&self, start: SelectionCoordinate, range: SelectionRange) {
+        if self.filtering() {
+            // The selection will be applied when the filter view is closed
+            self.adjust_viewport_for_cursor_position();
+            return;
+        }
         let pane_id = self.delegate.pane_id();
         let window = self.window.clone();
         let mode = self.selection_mode;
@@ -541,17 +624,25 @@ impl CopyRenderable {
 
     fn adjust_viewport_for_cursor_position(&self) {
         let dims = self.dimensions();
+        let cursor_y = if self.filtering() {
+            match self.filter_display_row(self.cursor.y) {
+                Some(y) => y,
+                None => return,
+            }
+        } else {
+            self.cursor.y
+        };
 
-        if dims.top > self.cursor.y {
+        if dims.top > cursor_y {
             // Cursor is off the top of the viewport; adjust
-            self.set_viewport(Some(self.cursor.y.saturating_sub(dims.vertical_gap)));
+            self.set_viewport(Some(cursor_y.saturating_sub(dims.vertical_gap)));
             return;
         }
 
-        let top_gap = self.cursor.y - dims.top;
+        let top_gap = cursor_y - dims.top;
         if top_gap < dims.vertical_gap {
             // Increase the gap so we can "look ahead"
-            self.set_viewport(Some(self.cursor.y.saturating_sub(dims.vertical_gap)));
+            self.set_viewport(Some(cursor_y.saturating_sub(dims.vertical_gap)));
             return;
         }
 
@@ -701,6 +792,146 @@ impl CopyRenderable {
         self.schedule_update_search();
     }
 
+    fn set_search_scope(&mut self, scope: CopyModeSearchScope) {
+        self.search_scope = scope;
+        self.update_search();
+    }
+
+    fn cycle_search_scope(&mut self) {
+        let scope = match self.search_scope {
+            CopyModeSearchScope::All => CopyModeSearchScope::LastCommandOutput,
+            CopyModeSearchScope::LastCommandOutput => CopyModeSearchScope::Input,
+            CopyModeSearchScope::Input => CopyModeSearchScope::CurrentZone,
+            CopyModeSearchScope::CurrentZone => CopyModeSearchScope::All,
+        };
+        self.set_search_scope(scope);
+    }
+
+    /// Resolve the search scope into the set of zones that
+    /// contain the matches that we want to show.
+    /// Returns None if the scope is unrestricted.
+    fn compute_scope_zones(&self) -> Option<Vec<SemanticZone>> {
+        if self.search_scope == CopyModeSearchScope::All {
+            return None;
+        }
+        let zones = self
+            .delegate
+            .get_semantic_zones()
+            .unwrap_or_else(|_| vec![]);
+        Some(match self.search_scope {
+            CopyModeSearchScope::All => unreachable!(),
+            CopyModeSearchScope::LastCommandOutput => zones
+                .into_iter()
+                .rev()
+                .find(|zone| zone.semantic_type == SemanticType::Output)
+                .into_iter()
+                .collect(),
+            CopyModeSearchScope::Input => zones
+                .into_iter()
+                .filter(|zone| zone.semantic_type != SemanticType::Output)
+                .collect(),
+            CopyModeSearchScope::CurrentZone => zones
+                .into_iter()
+                .find(|zone| zone_contains(zone, self.cursor.y, self.cursor.x))
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    /// Returns the range of rows that need to be searched to satisfy
+    /// the current search scope
+    fn search_bounds(&self) -> Range<StableRowIndex> {
+        let dims = self.delegate.get_dimensions();
+        let top = dims.scrollback_top;
+        let end = dims.scrollback_top + dims.scrollback_rows as StableRowIndex;
+        match &self.scope_zones {
+            None => top..end,
+            Some(zones) => {
+                let start = zones.iter().map(|z| z.start_y).min();
+                let last = zones.iter().map(|z| z.end_y).max();
+                match (start, last) {
+                    (Some(start), Some(last)) => start.max(top)..(last + 1).min(end),
+                    _ => top..top,
+                }
+            }
+        }
+    }
+
+    fn search_scope_label(&self) -> &'static str {
+        match self.search_scope {
+            CopyModeSearchScope::All => "",
+            CopyModeSearchScope::LastCommandOutput => " in last output",
+            CopyModeSearchScope::Input => " in input",
+            CopyModeSearchScope::CurrentZone => " in current zone",
+        }
+    }
+
+    /// Returns true if the filter view is active and is hiding lines
+    fn filtering(&self) -> bool {
+        self.filter_view && !self.search_line.get_line().is_empty()
+    }
+
+    fn toggle_filter_view(&mut self) {
+        self.filter_view = !self.filter_view;
+        for idx in self.by_line.keys() {
+            self.dirty_results.add(*idx);
+        }
+        if self.filtering() {
+            // The selection is expressed in terms of the unfiltered
+            // rows, so it cannot be meaningfully displayed
+            self.clear_selection();
+            self.set_viewport(None);
+        } else if let Some(n) = self.result_pos {
+            self.activate_match_number(n);
+        } else {
+            self.select_to_cursor_pos();
+        }
+        self.window.invalidate();
+    }
+
+    /// In the filter view, the matching rows are stacked up so that the
+    /// last of them is displayed just above the search bar when the
+    /// viewport is scrolled to the bottom.  This returns the row that
+    /// displays the final matching row.
+    fn filter_anchor_row(&self) -> StableRowIndex {
+        let dims = self.delegate.get_dimensions();
+        dims.physical_top + dims.viewport_rows as StableRowIndex - 2
+    }
+
+    /// Map a displayed row to the row of the pane whose content
+    /// is shown there in the filter view
+    fn filter_source_row(&self, row: StableRowIndex) -> Option<StableRowIndex> {
+        filter_source_row(&self.filter_rows, self.filter_anchor_row(), row)
+    }
+
+    /// Map a row of the pane to the row at which it is displayed
+    /// in the filter view
+    fn filter_display_row(&self, row: StableRowIndex) -> Option<StableRowIndex> {
+        filter_display_row(&self.filter_rows, self.filter_anchor_row(), row)
+    }
+
+    /// Produce the lines to display for the filter view
+    fn filter_view_lines(&self, lines: Range<StableRowIndex>) -> Vec<Line> {
+        lines
+            .map(|row| {
+                self.filter_source_row(row)
+                    .and_then(|src| self.delegate.get_lines(src..src + 1).1.pop())
+                    .unwrap_or_else(|| Line::with_width(self.width, SEQ_ZERO))
+            })
+            .collect()
+    }
+
+    /// Returns the matches to highlight on a displayed row, taking
+    /// the filter view into account
+    fn matches_for_displayed_row(&self, row: StableRowIndex) -> Option<&Vec<MatchResult>> {
+        let row = if self.filtering() {
+            self.filter_source_row(row)?
+        } else {
+            row
+        };
+        self.by_line.get(&row)
+    }
+
     fn move_to_viewport_middle(&mut self) {
         let dims = self.dimensions();
         self.cursor.y = dims.top + (dims.dims.viewport_rows as isize) / 2;
@@ -719,36 +950,47 @@ impl CopyRenderable {
         self.select_to_cursor_pos();
     }
 
-    fn move_left_single_cell(&mut self) {
-        self.cursor.x = self.cursor.x.saturating_sub(1);
+    fn move_left(&mut self, count: usize) {
+        self.cursor.x = self.cursor.x.saturating_sub(count);
         self.select_to_cursor_pos();
     }
 
-    fn move_right_single_cell(&mut self) {
-        self.cursor.x += 1;
+    fn move_right(&mut self, count: usize) {
+        self.cursor.x = self.cursor.x.saturating_add(count);
         self.select_to_cursor_pos();
     }
 
-    fn move_up_single_row(&mut self) {
-        self.cursor.y = self.cursor.y.saturating_sub(1);
+    fn move_up(&mut self, count: usize) {
+        self.cursor.y = self.cursor.y.saturating_sub(count as isize);
         self.select_to_cursor_pos();
     }
 
-    fn move_down_single_row(&mut self) {
-        self.cursor.y += 1;
+    fn move_down(&mut self, count: usize) {
+        self.cursor.y = self.cursor.y.saturating_add(count as isize);
         self.select_to_cursor_pos();
     }
+
     fn move_to_start_of_line(&mut self) {
         self.cursor.x = 0;
         self.select_to_cursor_pos();
     }
 
-    fn move_to_start_of_next_line(&mut self) {
+    fn move_to_start_of_next_line(&mut self, count: usize) {
         self.cursor.x = 0;
-        self.cursor.y += 1;
+        self.cursor.y = self.cursor.y.saturating_add(count as isize);
         self.select_to_cursor_pos();
     }
 
+    /// Move to the specified 1-based line number, counting from the
+    /// top of the scrollback, as `G` does in vim when given a count
+    fn move_to_line_number(&mut self, line_number: usize) {
+        let dims = self.delegate.get_dimensions();
+        self.cursor.y = dims
+            .scrollback_top
+            .saturating_add(line_number.saturating_sub(1) as isize);
+        self.move_to_start_of_line_content();
+    }
+
     fn move_to_top(&mut self) {
         // This will get fixed up by clamp_cursor_to_scrollback
         self.cursor.y = 0;
@@ -1005,7 +1247,7 @@ impl CopyRenderable {
         self.select_to_cursor_pos();
     }
 
-    fn perform_jump(&mut self, jump: Jump, repeat: bool) {
+    fn perform_jump(&mut self, jump: Jump, repeat: bool, count: usize) {
         let y = self.cursor.y;
         let (_top, lines) = self.delegate.get_lines(y..y + 1);
         let target_str = jump.target.to_string();
@@ -1037,13 +1279,14 @@ impl CopyRenderable {
             // Find the target that matches the jump
             let target = candidates
                 .iter()
-                .find(|&&idx| {
+                .filter(|&&idx| {
                     if jump.forward {
                         idx > cursor_x
                     } else {
                         idx < cursor_x
                     }
                 })
+                .nth(count.saturating_sub(1))
                 .copied();
 
             if let Some(target) = target {
@@ -1061,18 +1304,286 @@ impl CopyRenderable {
         }
     }
 
-    fn jump(&mut self, forward: bool, prev_char: bool) {
-        self.pending_jump
-            .replace(PendingJump { forward, prev_char });
+    fn jump(&mut self, forward: bool, prev_char: bool, count: usize) {
+        self.pending_jump.replace(PendingJump {
+            forward,
+            prev_char,
+            count,
+        });
     }
 
-    fn jump_again(&mut self, reverse: bool) {
+    fn jump_again(&mut self, reverse: bool, count: usize) {
         if let Some(mut jump) = self.last_jump {
             if reverse {
                 jump.forward = !jump.forward;
             }
-            self.perform_jump(jump, true);
+            self.perform_jump(jump, true, count);
+        }
+    }
+
+    fn ring_bell(&self) {
+        self.delegate
+            .perform_actions(vec![termwiz::escape::Action::Control(
+                termwiz::escape::ControlCode::Bell,
+            )]);
+    }
+
+    fn append_count_digit(&mut self, digit: u8) {
+        match self.pending_count {
+            None if digit == 0 => self.move_to_start_of_line(),
+            None => {
+                self.pending_count.replace(digit as usize);
+            }
+            Some(count) => {
+                self.pending_count.replace(
+                    count
+                        .saturating_mul(10)
+                        .saturating_add(digit as usize)
+                        .min(MAX_COUNT),
+                );
+            }
+        }
+    }
+
+    /// Called by key_down with the key that completes a pending action
+    fn resolve_pending_key(&mut self, pending: PendingKey, c: char) {
+        match pending {
+            PendingKey::TextObject { around } => match CopyModeTextObject::from_char(c) {
+                Some(object) => self.select_text_object(object, around),
+                None => self.ring_bell(),
+            },
+            PendingKey::SetMark => self.set_mark(c),
+            PendingKey::JumpToMark { exact } => self.jump_to_mark(c, exact),
+            PendingKey::Register => {
+                if is_valid_register(c) {
+                    self.register.replace(c);
+                } else {
+                    self.ring_bell();
+                }
+            }
+        }
+    }
+
+    fn set_mark(&mut self, name: char) {
+        if !name.is_ascii_alphanumeric() {
+            self.ring_bell();
+            return;
+        }
+        SAVED_MARKS
+            .lock()
+            .entry(self.delegate.pane_id())
+            .or_default()
+            .insert(name, (self.cursor.x, self.cursor.y));
+    }
+
+    fn jump_to_mark(&mut self, name: char, exact: bool) {
+        // vim uses both '' and `` to refer to the prior position
+        let name = if name == '`' { PREVIOUS_MARK } else { name };
+        let target = {
+            let mut marks = SAVED_MARKS.lock();
+            let marks = marks.entry(self.delegate.pane_id()).or_default();
+            let target = marks.get(&name).copied();
+            if target.is_some() {
+                marks.insert(PREVIOUS_MARK, (self.cursor.x, self.cursor.y));
+            }
+            target
+        };
+
+        match target {
+            Some((x, y)) => {
+                self.cursor.y = y;
+                if exact {
+                    self.cursor.x = x;
+                    self.select_to_cursor_pos();
+                } else {
+                    self.move_to_start_of_line_content();
+                }
+            }
+            None => self.ring_bell(),
+        }
+    }
+
+    /// Copy the selected text to `dest`, or to the register that was
+    /// selected prior to the yank, and record it in the unnamed and
+    /// yank registers.
+    fn yank_to_register(&mut self, dest: ClipboardCopyDestination) {
+        let register = self.register.take();
+        let pane_id = self.delegate.pane_id();
+        self.window
+            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                let pane = match mux::Mux::get().get_pane(pane_id) {
+                    Some(pane) => pane,
+                    None => return,
+                };
+                let text = term_window.selection_text(&pane);
+                match register {
+                    Some('_') => return,
+                    Some('+') => term_window
+                        .copy_to_clipboard(ClipboardCopyDestination::Clipboard, text.clone()),
+                    Some('*') => term_window.copy_to_clipboard(
+                        ClipboardCopyDestination::PrimarySelection,
+                        text.clone(),
+                    ),
+                    None => term_window.copy_to_clipboard(dest, text.clone()),
+                    Some(_) => {}
+                }
+
+                let mut registers = REGISTERS.lock();
+                match register {
+                    None => {
+                        registers.insert(YANK_REGISTER, text.clone());
+                    }
+                    Some(name) if name.is_ascii_uppercase() => {
+                        // An uppercase register name appends to the register
+                        registers
+                            .entry(name.to_ascii_lowercase())
+                            .or_default()
+                            .push_str(&text);
+                    }
+                    Some(name) if name.is_ascii_alphanumeric() => {
+                        registers.insert(name, text.clone());
+                    }
+                    Some(_) => {}
+                }
+                registers.insert(UNNAMED_REGISTER, text);
+            })));
+    }
+
+    fn paste_from_register(&mut self) {
+        let register = self.register.take().unwrap_or(UNNAMED_REGISTER);
+        let pane_id = self.delegate.pane_id();
+        let source = match register {
+            '+' => Some(ClipboardPasteSource::Clipboard),
+            '*' => Some(ClipboardPasteSource::PrimarySelection),
+            _ => None,
+        };
+
+        match source {
+            Some(source) => {
+                self.window
+                    .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                        if let Some(pane) = mux::Mux::get().get_pane(pane_id) {
+                            term_window.paste_from_clipboard(&pane, source);
+                        }
+                    })));
+            }
+            None => {
+                let text = REGISTERS
+                    .lock()
+                    .get(&register.to_ascii_lowercase())
+                    .cloned();
+                match text {
+                    Some(text) => {
+                        if let Err(err) = self.delegate.send_paste(&text) {
+                            log::error!("Failed to paste register {register}: {err:#}");
+                        }
+                    }
+                    None => {
+                        self.ring_bell();
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.set_viewport(None);
+        self.close();
+    }
+
+    fn select_text_object(&mut self, object: CopyModeTextObject, around: bool) {
+        if object == CopyModeTextObject::Paragraph {
+            return self.select_paragraph(around);
+        }
+
+        let y = self.cursor.y;
+        let (top, lines) = self.delegate.get_lines(y..y + 1);
+        let cells: Vec<ObjectCell> = match lines.get(0) {
+            Some(line) => line
+                .visible_cells()
+                .map(|cell| ObjectCell {
+                    x: cell.cell_index(),
+                    width: cell.width(),
+                    text: cell.str().to_string(),
+                })
+                .collect(),
+            None => return,
+        };
+        let cursor_idx = match cells.iter().rposition(|cell| cell.x <= self.cursor.x) {
+            Some(idx) => idx,
+            None => return self.ring_bell(),
+        };
+
+        let range = match object {
+            CopyModeTextObject::Word => word_object(&cells, cursor_idx, false, around),
+            CopyModeTextObject::BigWord => word_object(&cells, cursor_idx, true, around),
+            CopyModeTextObject::DoubleQuote => quote_object(&cells, cursor_idx, "\"", around),
+            CopyModeTextObject::SingleQuote => quote_object(&cells, cursor_idx, "'", around),
+            CopyModeTextObject::BackTick => quote_object(&cells, cursor_idx, "`", around),
+            CopyModeTextObject::Parentheses => bracket_object(&cells, cursor_idx, "(", ")", around),
+            CopyModeTextObject::Brackets => bracket_object(&cells, cursor_idx, "[", "]", around),
+            CopyModeTextObject::Braces => bracket_object(&cells, cursor_idx, "{", "}", around),
+            CopyModeTextObject::AngleBrackets => {
+                bracket_object(&cells, cursor_idx, "<", ">", around)
+            }
+            CopyModeTextObject::Paragraph => unreachable!(),
+        };
+
+        match range {
+            Some(range) => {
+                let first = &cells[range.start];
+                let last = &cells[range.end - 1];
+                self.selection_mode = SelectionMode::Cell;
+                self.start.replace(SelectionCoordinate::x_y(first.x, top));
+                self.cursor.y = top;
+                self.cursor.x = last.x + last.width.saturating_sub(1);
+                self.select_to_cursor_pos();
+            }
+            None => self.ring_bell(),
+        }
+    }
+
+    fn select_paragraph(&mut self, around: bool) {
+        let dims = self.delegate.get_dimensions();
+        let first_row = dims.scrollback_top;
+        let last_row = dims.scrollback_top + dims.scrollback_rows as StableRowIndex - 1;
+        let is_blank = |y: StableRowIndex| -> bool {
+            let (_top, lines) = self.delegate.get_lines(y..y + 1);
+            lines
+                .get(0)
+                .map(|line| line.is_whitespace())
+                .unwrap_or(true)
+        };
+
+        let y = self.cursor.y.max(first_row).min(last_row);
+        let blank = is_blank(y);
+        let mut top = y;
+        while top > first_row && is_blank(top - 1) == blank {
+            top -= 1;
+        }
+        let mut bottom = y;
+        while bottom < last_row && is_blank(bottom + 1) == blank {
+            bottom += 1;
+        }
+
+        if around {
+            // Include the following run of lines of the other kind,
+            // or the preceding run if there is nothing following
+            if bottom < last_row {
+                while bottom < last_row && is_blank(bottom + 1) != blank {
+                    bottom += 1;
+                }
+            } else {
+                while top > first_row && is_blank(top - 1) != blank {
+                    top -= 1;
+                }
+            }
         }
+
+        self.selection_mode = SelectionMode::Line;
+        self.start.replace(SelectionCoordinate::x_y(0, top));
+        self.cursor.x = 0;
+        self.cursor.y = bottom;
+        self.select_to_cursor_pos();
     }
 
     fn set_selection_mode(&mut self, mode: &Option<SelectionMode>) {
@@ -1143,20 +1654,27 @@ impl Pane for CopyOverlay {
             match (key, mods) {
                 (KeyCode::Char(c), KeyModifiers::NONE)
                 | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                    let count = jump.count;
                     let jump = Jump {
                         forward: jump.forward,
                         prev_char: jump.prev_char,
                         target: c,
                     };
                     render.last_jump.replace(jump);
-                    render.perform_jump(jump, false);
+                    render.perform_jump(jump, false, count);
                 }
-                _ => {
-                    self.delegate
-                        .perform_actions(vec![termwiz::escape::Action::Control(
-                            termwiz::escape::ControlCode::Bell,
-                        )]);
+                _ => render.ring_bell(),
+            }
+            return Ok(());
+        }
+
+        if let Some(pending) = render.pending_key.take() {
+            match (key, mods) {
+                (KeyCode::Char(c), KeyModifiers::NONE)
+                | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                    render.resolve_pending_key(pending, c);
                 }
+                _ => render.ring_bell(),
             }
             return Ok(());
         }
@@ -1241,38 +1759,72 @@ impl Pane for CopyOverlay {
     fn perform_assignment(&self, assignment: &KeyAssignment) -> PerformAssignmentResult {
         use CopyModeAssignment::*;
         let mut render = self.render.lock();
-        if render.pending_jump.is_some() {
+        if render.pending_jump.is_some() || render.pending_key.is_some() {
             // Block key assignments until key_down is called
             // and resolves the next state
             return PerformAssignmentResult::BlockAssignmentAndRouteToKeyDown;
         }
         match assignment {
+            KeyAssignment::CopyTo(dest) if !render.editing_search => {
+                render.pending_count.take();
+                render.yank_to_register(*dest);
+                PerformAssignmentResult::Handled
+            }
+            KeyAssignment::CopyMode(AppendCountDigit(digit)) => {
+                render.append_count_digit(*digit);
+                PerformAssignmentResult::Handled
+            }
             KeyAssignment::CopyMode(assignment) => {
+                let count = render.pending_count.take();
+                let repeat = count.unwrap_or(1);
                 match assignment {
                     MoveToViewportBottom => render.move_to_viewport_bottom(),
                     MoveToViewportTop => render.move_to_viewport_top(),
                     MoveToViewportMiddle => render.move_to_viewport_middle(),
+                    MoveToScrollbackTop | MoveToScrollbackBottom if count.is_some() => {
+                        render.move_to_line_number(repeat)
+                    }
                     MoveToScrollbackTop => render.move_to_top(),
                     MoveToScrollbackBottom => render.move_to_bottom(),
                     MoveToStartOfLineContent => render.move_to_start_of_line_content(),
                     MoveToEndOfLineContent => render.move_to_end_of_line_content(),
                     MoveToStartOfLine => render.move_to_start_of_line(),
-                    MoveToStartOfNextLine => render.move_to_start_of_next_line(),
+                    MoveToStartOfNextLine => render.move_to_start_of_next_line(repeat),
                     MoveToSelectionOtherEnd => render.move_to_selection_other_end(),
                     MoveToSelectionOtherEndHoriz => render.move_to_selection_other_end_horiz(),
-                    MoveBackwardWord => render.move_backward_one_word(),
-                    MoveForwardWord => render.move_forward_one_word(),
-                    MoveForwardWordEnd => render.move_to_end_of_word(),
-                    MoveRight => render.move_right_single_cell(),
-                    MoveLeft => render.move_left_single_cell(),
-                    MoveUp => render.move_up_single_row(),
-                    MoveDown => render.move_down_single_row(),
-                    MoveByPage(n) => render.move_by_page(**n),
-                    PageUp => render.move_by_page(-1.0),
-                    PageDown => render.move_by_page(1.0),
+                    MoveBackwardWord => {
+                        for _ in 0..repeat {
+                            render.move_backward_one_word();
+                        }
+                    }
+                    MoveForwardWord => {
+                        for _ in 0..repeat {
+                            render.move_forward_one_word();
+                        }
+                    }
+                    MoveForwardWordEnd => {
+                        for _ in 0..repeat {
+                            render.move_to_end_of_word();
+                        }
+                    }
+                    MoveRight => render.move_right(repeat),
+                    MoveLeft => render.move_left(repeat),
+                    MoveUp => render.move_up(repeat),
+                    MoveDown => render.move_down(repeat),
+                    MoveByPage(n) => render.move_by_page(**n * repeat as f64),
+                    PageUp => render.move_by_page(-(repeat as f64)),
+                    PageDown => render.move_by_page(repeat as f64),
                     Close => render.close(),
-                    PriorMatch => render.prior_match(),
-                    NextMatch => render.next_match(),
+                    PriorMatch => {
+                        for _ in 0..repeat {
+                            render.prior_match();
+                        }
+                    }
+                    NextMatch => {
+                        for _ in 0..repeat {
+                            render.next_match();
+                        }
+                    }
                     PriorMatchPage => render.prior_match_page(),
                     NextMatchPage => render.next_match_page(),
                     CycleMatchType => render.cycle_match_type(),
@@ -1281,14 +1833,47 @@ impl Pane for CopyOverlay {
                     AcceptPattern => render.accept_pattern(),
                     SetSelectionMode(mode) => render.set_selection_mode(mode),
                     ClearSelectionMode => render.clear_selection_mode(),
-                    MoveBackwardSemanticZone => render.move_by_zone(-1, None),
-                    MoveForwardSemanticZone => render.move_by_zone(1, None),
-                    MoveBackwardZoneOfType(zone_type) => render.move_by_zone(-1, Some(*zone_type)),
-                    MoveForwardZoneOfType(zone_type) => render.move_by_zone(1, Some(*zone_type)),
-                    JumpForward { prev_char } => render.jump(true, *prev_char),
-                    JumpBackward { prev_char } => render.jump(false, *prev_char),
-                    JumpAgain => render.jump_again(false),
-                    JumpReverse => render.jump_again(true),
+                    MoveBackwardSemanticZone => render.move_by_zone(-(repeat as isize), None),
+                    MoveForwardSemanticZone => render.move_by_zone(repeat as isize, None),
+                    MoveBackwardZoneOfType(zone_type) => {
+                        render.move_by_zone(-(repeat as isize), Some(*zone_type))
+                    }
+                    MoveForwardZoneOfType(zone_type) => {
+                        render.move_by_zone(repeat as isize, Some(*zone_type))
+                    }
+                    JumpForward { prev_char } => render.jump(true, *prev_char, repeat),
+                    JumpBackward { prev_char } => render.jump(false, *prev_char, repeat),
+                    JumpAgain => render.jump_again(false, repeat),
+                    JumpReverse => render.jump_again(true, repeat),
+                    AppendCountDigit(_) => unreachable!(),
+                    SelectInnerTextObject => {
+                        render
+                            .pending_key
+                            .replace(PendingKey::TextObject { around: false });
+                    }
+                    SelectAroundTextObject => {
+                        render
+                            .pending_key
+                            .replace(PendingKey::TextObject { around: true });
+                    }
+                    SelectTextObject(selection) => {
+                        render.select_text_object(selection.object, selection.around)
+                    }
+                    SetMark => {
+                        render.pending_key.replace(PendingKey::SetMark);
+                    }
+                    JumpToMark { exact } => {
+                        render
+                            .pending_key
+                            .replace(PendingKey::JumpToMark { exact: *exact });
+                    }
+                    SelectRegister => {
+                        render.pending_key.replace(PendingKey::Register);
+                    }
+                    PasteFromRegister => render.paste_from_register(),
+                    SetSearchScope(scope) => render.set_search_scope(*scope),
+                    CycleSearchScope => render.cycle_search_scope(),
+                    ToggleFilterView => render.toggle_filter_view(),
                 }
                 PerformAssignmentResult::Handled
             }
@@ -1353,6 +1938,17 @@ impl Pane for CopyOverlay {
                 shape: termwiz::surface::CursorShape::SteadyBlock,
                 visibility: termwiz::surface::CursorVisibility::Visible,
             }
+        } else if renderer.filtering() {
+            match renderer.filter_display_row(renderer.cursor.y) {
+                Some(y) => StableCursorPosition {
+                    y,
+                    ..renderer.cursor
+                },
+                None => StableCursorPosition {
+                    visibility: termwiz::surface::CursorVisibility::Hidden,
+                    ..renderer.cursor
+                },
+            }
         } else {
             renderer.cursor
         }
@@ -1402,6 +1998,23 @@ impl Pane for CopyOverlay {
             renderer: &'a mut CopyRenderable,
         }
 
+        if renderer.filtering() {
+            // The filtered lines don't correspond to the rows of the
+            // delegate, so we synthesize them rather than asking the
+            // delegate to supply them
+            let first_row = lines.start;
+            let mut filtered = renderer.filter_view_lines(lines);
+            let mut filtered_refs: Vec<&mut Line> = filtered.iter_mut().collect();
+            OverlayLines {
+                with_lines,
+                dims,
+                search_row,
+                renderer: &mut *renderer,
+            }
+            .with_lines_mut(first_row, &mut filtered_refs);
+            return;
+        }
+
         self.delegate.with_lines_mut(
             lines,
             &mut OverlayLines {
@@ -1446,18 +2059,26 @@ impl Pane for CopyOverlay {
                         line.overlay_text_with_attribute(
                             0,
                             &format!(
-                                "Search: {} ({}/{} matches. {}{remain})",
+                                "Search: {} ({}/{} matches{}. {}{}{remain})",
                                 *pattern,
                                 self.renderer.result_pos.map(|x| x + 1).unwrap_or(0),
                                 self.renderer.results.len(),
-                                mode
+                                self.renderer.search_scope_label(),
+                                mode,
+                                if self.renderer.filtering() {
+                                    ", filtered"
+                                } else {
+                                    ""
+                                },
                             ),
                             rev,
                             SEQ_ZERO,
                         );
                         self.renderer.last_bar_pos = Some(self.search_row);
                         line.clear_appdata();
-                    } else if let Some(matches) = self.renderer.by_line.get(&stable_idx) {
+                    } else if let Some(matches) =
+                        self.renderer.matches_for_displayed_row(stable_idx)
+                    {
                         for m in matches {
                             // highlight
                             for cell_idx in m.range.clone() {
@@ -1514,7 +2135,11 @@ impl Pane for CopyOverlay {
         renderer.check_for_resize();
         let dims = self.get_dimensions();
 
-        let (top, mut lines) = self.delegate.get_lines(lines);
+        let (top, mut lines) = if renderer.filtering() {
+            (lines.start, renderer.filter_view_lines(lines))
+        } else {
+            self.delegate.get_lines(lines)
+        };
 
         let config = config::configuration();
         let colors = &config.resolved_palette;
@@ -1539,17 +2164,23 @@ impl Pane for CopyOverlay {
                 line.overlay_text_with_attribute(
                     0,
                     &format!(
-                        "Search: {} ({}/{} matches. {})",
+                        "Search: {} ({}/{} matches{}. {}{})",
                         *pattern,
                         renderer.result_pos.map(|x| x + 1).unwrap_or(0),
                         renderer.results.len(),
-                        mode
+                        renderer.search_scope_label(),
+                        mode,
+                        if renderer.filtering() {
+                            ", filtered"
+                        } else {
+                            ""
+                        },
                     ),
                     rev,
                     SEQ_ZERO,
                 );
                 renderer.last_bar_pos = Some(search_row);
-            } else if let Some(matches) = renderer.by_line.get(&stable_idx) {
+            } else if let Some(matches) = renderer.matches_for_displayed_row(stable_idx) {
                 for m in matches {
                     // highlight
                     for cell_idx in m.range.clone() {
@@ -1616,6 +2247,37 @@ impl std::io::Write for SearchOverlayPatternWriter {
     }
 }
 
+/// Map a displayed row to the row of the pane whose content is shown
+/// there in the filter view, where the final entry of the sorted
+/// `filter_rows` is displayed at `anchor` and the others are stacked
+/// up above it
+fn filter_source_row(
+    filter_rows: &[StableRowIndex],
+    anchor: StableRowIndex,
+    row: StableRowIndex,
+) -> Option<StableRowIndex> {
+    let back = anchor - row;
+    if back < 0 || back as usize >= filter_rows.len() {
+        return None;
+    }
+    Some(filter_rows[filter_rows.len() - 1 - back as usize])
+}
+
+/// The inverse of filter_source_row
+fn filter_display_row(
+    filter_rows: &[StableRowIndex],
+    anchor: StableRowIndex,
+    row: StableRowIndex,
+) -> Option<StableRowIndex> {
+    let idx = filter_rows.binary_search(&row).ok()?;
+    Some(anchor - (filter_rows.len() - 1 - idx) as StableRowIndex)
+}
+
+/// Returns true if the cell at the specified position lies within the zone
+fn zone_contains(zone: &SemanticZone, y: StableRowIndex, x: usize) -> bool {
+    (y, x) >= (zone.start_y, zone.start_x) && (y, x) <= (zone.end_y, zone.end_x)
+}
+
 fn is_whitespace_word(word: &str) -> bool {
     if let Some(c) = word.chars().next() {
         c.is_whitespace()
@@ -1624,6 +2286,160 @@ fn is_whitespace_word(word: &str) -> bool {
     }
 }
 
+fn is_valid_register(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '"' | '+' | '*' | '_')
+}
+
+/// A cell on the line that is being searched for a text object
+struct ObjectCell {
+    x: usize,
+    width: usize,
+    text: String,
+}
+
+impl ObjectCell {
+    fn is_whitespace(&self) -> bool {
+        is_whitespace_word(&self.text)
+    }
+
+    /// Classify the cell for the purposes of word motion:
+    /// 0 for whitespace, 1 for keyword characters and 2 for punctuation.
+    /// For a `big` word, all non-whitespace is considered to be alike.
+    fn word_class(&self, big: bool) -> u8 {
+        if self.is_whitespace() {
+            0
+        } else if big || self.text.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+/// Computes the range of cell indices in `cells` for a vim `iw`/`aw`
+/// (or `iW`/`aW` when `big` is true) text object
+fn word_object(cells: &[ObjectCell], idx: usize, big: bool, around: bool) -> Option<Range<usize>> {
+    let class = cells[idx].word_class(big);
+    let mut start = idx;
+    while start > 0 && cells[start - 1].word_class(big) == class {
+        start -= 1;
+    }
+    let mut end = idx + 1;
+    while end < cells.len() && cells[end].word_class(big) == class {
+        end += 1;
+    }
+
+    if around {
+        if class == 0 {
+            // Whitespace is extended to include the following word
+            if let Some(next) = cells.get(end).map(|c| c.word_class(big)) {
+                while end < cells.len() && cells[end].word_class(big) == next {
+                    end += 1;
+                }
+            }
+        } else if end < cells.len() && cells[end].is_whitespace() {
+            while end < cells.len() && cells[end].is_whitespace() {
+                end += 1;
+            }
+        } else {
+            while start > 0 && cells[start - 1].is_whitespace() {
+                start -= 1;
+            }
+        }
+    }
+
+    Some(start..end)
+}
+
+/// Computes the range of cell indices in `cells` for a vim `i"`/`a"`
+/// style text object.  Quotes are paired up from the start of the line;
+/// if the cursor isn't inside a quoted region, the next quoted region
+/// on the line is used instead.
+fn quote_object(
+    cells: &[ObjectCell],
+    idx: usize,
+    quote: &str,
+    around: bool,
+) -> Option<Range<usize>> {
+    let quotes: Vec<usize> = cells
+        .iter()
+        .enumerate()
+        .filter(|(i, cell)| cell.text == quote && (*i == 0 || cells[*i - 1].text != "\\"))
+        .map(|(i, _)| i)
+        .collect();
+
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_open, close)| close >= idx)?;
+
+    if around {
+        let mut end = close + 1;
+        while end < cells.len() && cells[end].is_whitespace() {
+            end += 1;
+        }
+        Some(open..end)
+    } else if close > open + 1 {
+        Some(open + 1..close)
+    } else {
+        None
+    }
+}
+
+/// Computes the range of cell indices in `cells` for a vim `i(`/`a(`
+/// style text object, taking nesting into account
+fn bracket_object(
+    cells: &[ObjectCell],
+    idx: usize,
+    open_text: &str,
+    close_text: &str,
+    around: bool,
+) -> Option<Range<usize>> {
+    let open = if cells[idx].text == open_text {
+        idx
+    } else {
+        let mut depth = 0;
+        let mut found = None;
+        // If we're on the closing bracket, it belongs to the pair that
+        // we are looking for, so start scanning from the cell prior
+        for (i, cell) in cells[..idx].iter().enumerate().rev() {
+            if cell.text == close_text {
+                depth += 1;
+            } else if cell.text == open_text {
+                if depth == 0 {
+                    found = Some(i);
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+        found?
+    };
+
+    let mut depth = 0;
+    let mut close = None;
+    for (i, cell) in cells.iter().enumerate().skip(open + 1) {
+        if cell.text == open_text {
+            depth += 1;
+        } else if cell.text == close_text {
+            if depth == 0 {
+                close = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let close = close?;
+
+    if around {
+        Some(open..close + 1)
+    } else if close > open + 1 {
+        Some(open + 1..close)
+    } else {
+        None
+    }
+}
+
 pub fn search_key_table() -> KeyTable {
     let mut table = KeyTable::default();
     for (key, mods, action) in [
@@ -1677,6 +2493,16 @@ pub fn search_key_table() -> KeyTable {
             Modifiers::CTRL,
             KeyAssignment::CopyMode(CopyModeAssignment::ClearPattern),
         ),
+        (
+            WKeyCode::Char('s'),
+            Modifiers::CTRL,
+            KeyAssignment::CopyMode(CopyModeAssignment::CycleSearchScope),
+        ),
+        (
+            WKeyCode::Char('l'),
+            Modifiers::CTRL,
+            KeyAssignment::CopyMode(CopyModeAssignment::ToggleFilterView),
+        ),
     ] {
         table.insert((key, mods), KeyTableEntry { action });
     }
@@ -1801,7 +2627,7 @@ pub fn copy_key_table() -> KeyTable {
         (
             WKeyCode::Char('0'),
             Modifiers::NONE,
-            KeyAssignment::CopyMode(CopyModeAssignment::MoveToStartOfLine),
+            KeyAssignment::CopyMode(CopyModeAssignment::AppendCountDigit(0)),
         ),
         (
             WKeyCode::Char('\r'),
@@ -1813,6 +2639,16 @@ pub fn copy_key_table() -> KeyTable {
             Modifiers::SHIFT,
             KeyAssignment::CopyMode(CopyModeAssignment::MoveToEndOfLineContent),
         ),
+        (
+            WKeyCode::Char('&'),
+            Modifiers::SHIFT,
+            KeyAssignment::CopyMode(CopyModeAssignment::ToggleFilterView),
+        ),
+        (
+            WKeyCode::Char('&'),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::ToggleFilterView),
+        ),
         (
             WKeyCode::Char('$'),
             Modifiers::NONE,
@@ -2016,8 +2852,205 @@ pub fn copy_key_table() -> KeyTable {
             Modifiers::NONE,
             KeyAssignment::CopyMode(CopyModeAssignment::MoveToEndOfLineContent),
         ),
+        (
+            WKeyCode::Char('i'),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::SelectInnerTextObject),
+        ),
+        (
+            WKeyCode::Char('a'),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::SelectAroundTextObject),
+        ),
+        (
+            WKeyCode::Char('m'),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::SetMark),
+        ),
+        (
+            WKeyCode::Char('\''),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::JumpToMark { exact: false }),
+        ),
+        (
+            WKeyCode::Char('`'),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::JumpToMark { exact: true }),
+        ),
+        (
+            WKeyCode::Char('"'),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::SelectRegister),
+        ),
+        (
+            WKeyCode::Char('"'),
+            Modifiers::SHIFT,
+            KeyAssignment::CopyMode(CopyModeAssignment::SelectRegister),
+        ),
+        (
+            WKeyCode::Char('p'),
+            Modifiers::NONE,
+            KeyAssignment::CopyMode(CopyModeAssignment::PasteFromRegister),
+        ),
     ] {
         table.insert((key, mods), KeyTableEntry { action });
     }
+    for digit in 1..=9u8 {
+        table.insert(
+            (WKeyCode::Char((b'0' + digit) as char), Modifiers::NONE),
+            KeyTableEntry {
+                action: KeyAssignment::CopyMode(CopyModeAssignment::AppendCountDigit(digit)),
+            },
+        );
+    }
     table
 }
+
+#[cfg(test)]
+mod text_object_test {
+    use super::*;
+
+    fn cells(s: &str) -> Vec<ObjectCell> {
+        s.chars()
+            .enumerate()
+            .map(|(x, c)| ObjectCell {
+                x,
+                width: 1,
+                text: c.to_string(),
+            })
+            .collect()
+    }
+
+    fn text(s: &str, range: Option<Range<usize>>) -> Option<&str> {
+        range.map(|r| &s[r])
+    }
+
+    #[test]
+    fn words() {
+        let s = "echo foo.bar  baz";
+        let c = cells(s);
+        assert_eq!(text(s, word_object(&c, 6, false, false)), Some("foo"));
+        assert_eq!(text(s, word_object(&c, 6, true, false)), Some("foo.bar"));
+        assert_eq!(text(s, word_object(&c, 6, true, true)), Some("foo.bar  "));
+        // No trailing whitespace, so the leading whitespace is used
+        assert_eq!(text(s, word_object(&c, 15, false, true)), Some("  baz"));
+    }
+
+    #[test]
+    fn quotes() {
+        let s = r#"say "hello \"there\"" 'x'"#;
+        let c = cells(s);
+        assert_eq!(
+            text(s, quote_object(&c, 8, "\"", false)),
+            Some(r#"hello \"there\""#)
+        );
+        // Not inside quotes, so the next pair on the line is used
+        assert_eq!(text(s, quote_object(&c, 0, "'", true)), Some("'x'"));
+        assert_eq!(text(s, quote_object(&c, 0, "`", true)), None);
+    }
+
+    #[test]
+    fn brackets() {
+        let s = "f(a, (b, c), d)";
+        let c = cells(s);
+        assert_eq!(
+            text(s, bracket_object(&c, 3, "(", ")", false)),
+            Some("a, (b, c), d")
+        );
+        assert_eq!(
+            text(s, bracket_object(&c, 6, "(", ")", true)),
+            Some("(b, c)")
+        );
+        // On the closing bracket
+        assert_eq!(
+            text(s, bracket_object(&c, 10, "(", ")", false)),
+            Some("b, c")
+        );
+        assert_eq!(text(s, bracket_object(&c, 3, "[", "]", false)), None);
+    }
+}
+
+#[cfg(test)]
+mod filter_test {
+    use super::*;
+
+    #[test]
+    fn zone_contains_is_inclusive_and_spans_lines() {
+        let zone = SemanticZone {
+            start_y: 2,
+            start_x: 4,
+            end_y: 4,
+            end_x: 1,
+            semantic_type: SemanticType::Output,
+        };
+        assert!(zone_contains(&zone, 2, 4));
+        assert!(zone_contains(&zone, 4, 1));
+        // Lines in the middle of the zone are covered in full
+        assert!(zone_contains(&zone, 3, 0));
+        assert!(zone_contains(&zone, 3, 1000));
+        assert!(zone_contains(&zone, 2, 80));
+
+        assert!(!zone_contains(&zone, 2, 3));
+        assert!(!zone_contains(&zone, 4, 2));
+        assert!(!zone_contains(&zone, 1, 10));
+        assert!(!zone_contains(&zone, 5, 0));
+    }
+
+    #[test]
+    fn filter_rows_are_stacked_above_the_anchor() {
+        let rows = [3, 10, 42];
+        let anchor = 100;
+
+        assert_eq!(filter_source_row(&rows, anchor, 100), Some(42));
+        assert_eq!(filter_source_row(&rows, anchor, 99), Some(10));
+        assert_eq!(filter_source_row(&rows, anchor, 98), Some(3));
+        // Above the stack, and below the anchor
+        assert_eq!(filter_source_row(&rows, anchor, 97), None);
+        assert_eq!(filter_source_row(&rows, anchor, 101), None);
+        assert_eq!(filter_source_row(&[], anchor, 100), None);
+    }
+
+    #[test]
+    fn filter_display_row_is_inverse_of_source_row() {
+        let rows = [3, 10, 42];
+        let anchor = 100;
+        for display in 98..=100 {
+            let source = filter_source_row(&rows, anchor, display).unwrap();
+            assert_eq!(filter_display_row(&rows, anchor, source), Some(display));
+        }
+        // Rows that didn't match are not displayed
+        assert_eq!(filter_display_row(&rows, anchor, 4), None);
+    }
+}
+
+#[cfg(test)]
+mod register_test {
+    use super::*;
+
+    #[test]
+    fn forget_pane_discards_marks_but_keeps_registers() {
+        // Use pane ids and a register that no other test will use,
+        // as the marks and registers are global
+        let (closed, open) = (1_000_001, 1_000_002);
+        SAVED_MARKS
+            .lock()
+            .entry(closed)
+            .or_default()
+            .insert('a', (0, 0));
+        SAVED_MARKS
+            .lock()
+            .entry(open)
+            .or_default()
+            .insert('a', (1, 1));
+        REGISTERS.lock().insert('8', "yanked".to_string());
+
+        forget_pane(closed);
+
+        assert!(!SAVED_MARKS.lock().contains_key(&closed));
+        assert!(SAVED_MARKS.lock().contains_key(&open));
+        assert_eq!(
+            REGISTERS.lock().get(&'8').map(|text| text.as_str()),
+            Some("yanked")
+        );
+    }
+}
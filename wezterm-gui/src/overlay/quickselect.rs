@@ -1,6 +1,9 @@
+use crate::scripting::guiwin::GuiWin;
 use crate::selection::{SelectionCoordinate, SelectionRange};
 use crate::termwindow::{TermWindow, TermWindowNotif};
-use config::keyassignment::{ClipboardCopyDestination, QuickSelectArguments, ScrollbackEraseMode};
+use config::keyassignment::{
+    ClipboardCopyDestination, KeyAssignment, QuickSelectArguments, ScrollbackEraseMode,
+};
 use config::ConfigHandle;
 use mux::domain::DomainId;
 use mux::pane::{
@@ -8,10 +11,12 @@ use mux::pane::{
     WithPaneLines,
 };
 use mux::renderable::*;
+use mux_lua::MuxPane;
 use parking_lot::{MappedMutexGuard, Mutex};
 use rangeset::RangeSet;
 use std::collections::HashMap;
 use std::ops::Range;
+use std::rc::Rc;
 use std::sync::Arc;
 use termwiz::cell::{Cell, CellAttributes};
 use termwiz::color::AnsiColor;
@@ -204,6 +209,14 @@ struct MatchResult {
     label: String,
 }
 
+/// Identifies the location of a search result, so that results from
+/// the per-pattern searches can be correlated with the overall results
+type ResultKey = (StableRowIndex, usize, StableRowIndex, usize);
+
+fn result_key(res: &SearchResult) -> ResultKey {
+    (res.start_y, res.start_x, res.end_y, res.end_x)
+}
+
 struct QuickSelectRenderable {
     delegate: Arc<dyn Pane>,
     /// The text that the user entered
@@ -212,6 +225,11 @@ struct QuickSelectRenderable {
     results: Vec<SearchResult>,
     by_line: HashMap<StableRowIndex, Vec<MatchResult>>,
     by_label: HashMap<String, usize>,
+    /// For each entry in results, the index of the matching
+    /// entry in args.pattern_actions, if any
+    result_actions: Vec<Option<usize>>,
+    /// Labels that have been toggled on in multi_select mode
+    selected: Vec<String>,
     selection: String,
 
     viewport: Option<StableRowIndex>,
@@ -242,6 +260,15 @@ impl QuickSelectOverlay {
 
         let mut pattern = "(?m)(".to_string();
         let mut have_patterns = false;
+        // Patterns with an associated action come first so that they
+        // take precedence over the more general patterns
+        for p in &args.pattern_actions {
+            if have_patterns {
+                pattern.push('|');
+            }
+            pattern.push_str(&p.pattern);
+            have_patterns = true;
+        }
         if !args.patterns.is_empty() {
             for p in &args.patterns {
                 if have_patterns {
@@ -281,6 +308,8 @@ impl QuickSelectOverlay {
             results: vec![],
             by_line: HashMap::new(),
             by_label: HashMap::new(),
+            result_actions: vec![],
+            selected: vec![],
             dirty_results: RangeSet::default(),
             viewport,
             last_bar_pos: None,
@@ -348,6 +377,16 @@ impl Pane for QuickSelectOverlay {
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
         let mods = mods.remove_positional_mods();
+        if key == KeyCode::Enter && mods == KeyModifiers::NONE {
+            // Accept the matches that were toggled on in multi_select mode
+            let mut r = self.renderer.lock();
+            if !r.selected.is_empty() {
+                let labels = r.selected.clone();
+                r.select_and_copy_labels(&labels, false);
+                r.close();
+                return Ok(());
+            }
+        }
         match (key, mods) {
             (KeyCode::Escape, KeyModifiers::NONE) => self.renderer.lock().close(),
             (KeyCode::UpArrow, KeyModifiers::NONE)
@@ -416,10 +455,19 @@ impl Pane for QuickSelectOverlay {
                 let mut r = self.renderer.lock();
                 r.selection.push(c);
                 let lowered = r.selection.to_lowercase();
-                let paste = lowered != r.selection;
-                if let Some(result_index) = r.by_label.get(&lowered).cloned() {
-                    r.select_and_copy_match_number(result_index, paste);
-                    r.close();
+                let uppercase = lowered != r.selection;
+                if r.by_label.contains_key(&lowered) {
+                    if r.args.multi_select && uppercase {
+                        r.toggle_label(lowered);
+                    } else {
+                        let mut labels = r.selected.clone();
+                        if !labels.contains(&lowered) {
+                            labels.push(lowered);
+                        }
+                        let paste = uppercase && !r.args.multi_select;
+                        r.select_and_copy_labels(&labels, paste);
+                        r.close();
+                    }
                 }
             }
             (KeyCode::Backspace, KeyModifiers::NONE) => {
@@ -569,15 +617,7 @@ impl Pane for QuickSelectOverlay {
                         line.fill_range(0..self.dims.cols, &Cell::new(' ', rev.clone()), SEQ_ZERO);
                         line.overlay_text_with_attribute(
                             0,
-                            &format!(
-                                "Select: {}  (type highlighted prefix to {}, uppercase pastes, ESC to cancel)",
-                                self.renderer.selection,
-                                if self.renderer.args.label.is_empty() {
-                                    "copy"
-                                } else {
-                                    &self.renderer.args.label
-                                },
-                            ),
+                            &self.renderer.status_text(),
                             rev,
                             SEQ_ZERO,
                         );
@@ -585,7 +625,9 @@ impl Pane for QuickSelectOverlay {
                         line.clear_appdata();
                     } else if let Some(matches) = self.renderer.by_line.get(&stable_idx) {
                         for m in matches {
-                            // highlight
+                            // highlight; matches that were toggled on in
+                            // multi_select mode are shown in reverse video
+                            let toggled = self.renderer.selected.contains(&m.label);
                             for cell_idx in m.range.clone() {
                                 if let Some(cell) =
                                     line.cells_mut_for_attr_changes_only().get_mut(cell_idx)
@@ -601,7 +643,7 @@ impl Pane for QuickSelectOverlay {
                                                 .quick_select_match_fg
                                                 .unwrap_or(AnsiColor::Green.into()),
                                         )
-                                        .set_reverse(false)
+                                        .set_reverse(toggled)
                                         .set_intensity(Intensity::Bold);
                                 }
                             }
@@ -661,24 +703,13 @@ impl Pane for QuickSelectOverlay {
                 // Replace with search UI
                 let rev = CellAttributes::default().set_reverse(true).clone();
                 line.fill_range(0..dims.cols, &Cell::new(' ', rev.clone()), SEQ_ZERO);
-                line.overlay_text_with_attribute(
-                    0,
-                    &format!(
-                        "Select: {}  (type highlighted prefix to {}, uppercase pastes, ESC to cancel)",
-                        renderer.selection,
-                        if renderer.args.label.is_empty() {
-                            "copy"
-                        } else {
-                            &renderer.args.label
-                        },
-                    ),
-                    rev,
-                    SEQ_ZERO,
-                );
+                line.overlay_text_with_attribute(0, &renderer.status_text(), rev, SEQ_ZERO);
                 renderer.last_bar_pos = Some(search_row);
             } else if let Some(matches) = renderer.by_line.get(&stable_idx) {
                 for m in matches {
-                    // highlight
+                    // highlight; matches that were toggled on in
+                    // multi_select mode are shown in reverse video
+                    let toggled = renderer.selected.contains(&m.label);
                     for cell_idx in m.range.clone() {
                         if let Some(cell) = line.cells_mut_for_attr_changes_only().get_mut(cell_idx)
                         {
@@ -693,7 +724,7 @@ impl Pane for QuickSelectOverlay {
                                         .quick_select_match_fg
                                         .unwrap_or(AnsiColor::Green.into()),
                                 )
-                                .set_reverse(false)
+                                .set_reverse(toggled)
                                 .set_intensity(Intensity::Bold);
                         }
                     }
@@ -850,6 +881,7 @@ impl QuickSelectRenderable {
         }
 
         self.results.clear();
+        self.result_actions.clear();
         self.by_line.clear();
         self.result_pos.take();
 
@@ -861,6 +893,7 @@ impl QuickSelectRenderable {
             let window = self.window.clone();
             let pattern = self.pattern.clone();
             let scope = self.args.scope_lines;
+            let pattern_actions = self.args.pattern_actions.clone();
             let viewport = self.viewport;
             promise::spawn::spawn(async move {
                 let dims = pane.get_dimensions();
@@ -869,11 +902,25 @@ impl QuickSelectRenderable {
                 let range = top.saturating_sub(scope as StableRowIndex)
                     ..top + (dims.viewport_rows + scope) as StableRowIndex;
                 let limit = None;
-                let mut results = pane.search(pattern, range, limit).await?;
+                let mut results = pane.search(pattern, range.clone(), limit).await?;
                 results.sort();
 
+                // Search again for each pattern that has its own action,
+                // so that we can tell which results it produced
+                let mut action_by_key = HashMap::new();
+                for (idx, p) in pattern_actions.iter().enumerate() {
+                    let pattern = Pattern::Regex(format!("(?m){}", p.pattern));
+                    for res in pane.search(pattern, range.clone(), limit).await? {
+                        action_by_key.entry(result_key(&res)).or_insert(idx);
+                    }
+                }
+                let result_actions: Vec<Option<usize>> = results
+                    .iter()
+                    .map(|res| action_by_key.get(&result_key(res)).copied())
+                    .collect();
+
                 let pane_id = pane.pane_id();
-                let mut results = Some(results);
+                let mut results = Some((results, result_actions));
                 window.notify(TermWindowNotif::Apply(Box::new(move |term_window| {
                     let state = term_window.pane_state(pane_id);
                     if let Some(overlay) = state.overlay.as_ref() {
@@ -881,7 +928,9 @@ impl QuickSelectRenderable {
                             overlay.pane.downcast_ref::<QuickSelectOverlay>()
                         {
                             let mut r = search_overlay.renderer.lock();
-                            r.results = results.take().unwrap();
+                            let (results, result_actions) = results.take().unwrap();
+                            r.results = results;
+                            r.result_actions = result_actions;
                             r.recompute_results();
                             let num_results = r.results.len();
 
@@ -927,52 +976,99 @@ impl QuickSelectRenderable {
             })));
     }
 
-    fn select_and_copy_match_number(&mut self, n: usize, paste: bool) {
-        let result = self.results[n].clone();
+    fn status_text(&self) -> String {
+        let label = if self.args.label.is_empty() {
+            "copy"
+        } else {
+            &self.args.label
+        };
+        if self.args.multi_select {
+            format!(
+                "Select: {}  ({} selected; type highlighted prefix to {}, \
+                 uppercase toggles, Enter accepts, ESC to cancel)",
+                self.selection,
+                self.selected.len(),
+                label
+            )
+        } else {
+            format!(
+                "Select: {}  (type highlighted prefix to {}, uppercase pastes, ESC to cancel)",
+                self.selection, label
+            )
+        }
+    }
+
+    /// Toggle the selection of the match with the specified label
+    /// in multi_select mode
+    fn toggle_label(&mut self, label: String) {
+        match self.selected.iter().position(|l| *l == label) {
+            Some(idx) => {
+                self.selected.remove(idx);
+            }
+            None => self.selected.push(label),
+        }
+        self.selection.clear();
+
+        for row in self.by_line.keys() {
+            self.dirty_results.add(*row);
+        }
+        if let Some(row) = self.last_bar_pos {
+            self.dirty_results.add(row);
+        }
+        self.window.invalidate();
+    }
+
+    /// Paste and/or copy the text of the matches with the specified
+    /// labels, then perform the action associated with each of them.
+    /// Matches that have no associated action are joined together
+    /// and copied to the clipboard.
+    fn select_and_copy_labels(&mut self, labels: &[String], paste: bool) {
+        let plan = plan_selection(
+            labels,
+            &self.by_label,
+            &self.result_actions,
+            &self.args,
+            paste,
+        );
+        let results_for = |indices: &[usize]| -> Vec<SearchResult> {
+            indices.iter().map(|&n| self.results[n].clone()).collect()
+        };
+        let paste_results = results_for(&plan.paste);
+        let copy_results = results_for(&plan.copy);
+        let actions: Vec<(SearchResult, KeyAssignment)> = plan
+            .actions
+            .into_iter()
+            .map(|(n, action)| (self.results[n].clone(), action))
+            .collect();
 
         let pane_id = self.delegate.pane_id();
-        let action = self.args.action.clone();
-        let skip_action_on_paste = self.args.skip_action_on_paste;
+        let separator = self
+            .args
+            .multi_select_separator
+            .clone()
+            .unwrap_or_else(|| " ".to_string());
         self.window
             .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
                 let mux = mux::Mux::get();
                 if let Some(pane) = mux.get_pane(pane_id) {
-                    {
-                        let mut selection = term_window.selection(pane_id);
-                        let start = SelectionCoordinate::x_y(result.start_x, result.start_y);
-                        selection.origin = Some(start);
-                        selection.range = Some(SelectionRange {
-                            start,
-                            // inclusive range for selection, but the result
-                            // range is exclusive
-                            end: SelectionCoordinate::x_y(
-                                result.end_x.saturating_sub(1),
-                                result.end_y,
-                            ),
-                        });
-                        // Ensure that selection doesn't get invalidated when
-                        // the overlay is closed
-                        selection.seqno = pane.get_current_seqno();
+                    let texts = result_texts(term_window, &pane, &paste_results);
+                    if !texts.is_empty() {
+                        let _ = pane.send_paste(&texts.join(&separator));
                     }
 
-                    let text = term_window.selection_text(&pane);
-                    if !text.is_empty() {
-                        if paste {
-                            let _ = pane.send_paste(&text);
-                        }
-                        if let Some(action) = action {
-                            if !paste || !skip_action_on_paste {
-                                let _ = term_window.perform_key_assignment(&pane, &action);
-                            }
-                        } else {
-                            term_window.copy_to_clipboard(
-                                ClipboardCopyDestination::ClipboardAndPrimarySelection,
-                                text,
-                            );
-                        }
+                    let texts = result_texts(term_window, &pane, &copy_results);
+                    if !texts.is_empty() {
+                        term_window.copy_to_clipboard(
+                            ClipboardCopyDestination::ClipboardAndPrimarySelection,
+                            texts.join(&separator),
+                        );
                     }
                 }
             })));
+
+        if !actions.is_empty() {
+            promise::spawn::spawn(perform_actions(self.window.clone(), pane_id, actions)).detach();
+        }
     }
 
     fn activate_match_number(&mut self, n: usize) {
@@ -981,3 +1077,261 @@ impl QuickSelectRenderable {
         self.set_viewport(Some(result.start_y));
     }
 }
+
+/// What to do with each of the matches that were accepted.
+/// The entries are indices into the results.
+#[derive(Debug, Default, PartialEq)]
+struct SelectionPlan {
+    /// Matches whose text is pasted into the pane
+    paste: Vec<usize>,
+    /// Matches that have no action, whose text is copied
+    copy: Vec<usize>,
+    /// Matches that have an action, in the order that they
+    /// were selected
+    actions: Vec<(usize, KeyAssignment)>,
+}
+
+fn plan_selection(
+    labels: &[String],
+    by_label: &HashMap<String, usize>,
+    result_actions: &[Option<usize>],
+    args: &QuickSelectArguments,
+    paste: bool,
+) -> SelectionPlan {
+    let mut plan = SelectionPlan::default();
+    for label in labels {
+        let n = match by_label.get(label) {
+            Some(n) => *n,
+            None => continue,
+        };
+        if paste {
+            plan.paste.push(n);
+        }
+        let action = match result_actions.get(n).copied().flatten() {
+            Some(idx) => Some(&args.pattern_actions[idx].action),
+            None => args.action.as_ref(),
+        };
+        match action {
+            Some(action) => {
+                if !paste || !args.skip_action_on_paste {
+                    plan.actions.push((n, (**action).clone()));
+                }
+            }
+            None => plan.copy.push(n),
+        }
+    }
+    plan
+}
+
+/// Returns the non-empty text of each of the results
+fn result_texts(
+    term_window: &TermWindow,
+    pane: &Arc<dyn Pane>,
+    results: &[SearchResult],
+) -> Vec<String> {
+    results
+        .iter()
+        .filter_map(|result| {
+            select_result(term_window, pane, result);
+            let text = term_window.selection_text(pane);
+            if text.is_empty() {
+                None
+            } else {
+                Some(text)
+            }
+        })
+        .collect()
+}
+
+/// Performs the actions for the accepted matches one at a time, with
+/// the selection set to the corresponding match.  Events, including
+/// those produced by wezterm.action_callback, are dispatched to lua
+/// directly and awaited before moving on to the next match, so that
+/// each handler observes the selection for its own match.
+async fn perform_actions(
+    window: ::window::Window,
+    pane_id: PaneId,
+    actions: Vec<(SearchResult, KeyAssignment)>,
+) {
+    for (result, action) in actions {
+        let (tx, rx) = smol::channel::bounded(1);
+        window.notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+            let pane = match mux::Mux::get().get_pane(pane_id) {
+                Some(pane) => pane,
+                None => return,
+            };
+            select_result(term_window, &pane, &result);
+            match action {
+                KeyAssignment::EmitEvent(name) => {
+                    tx.try_send(Some((name, GuiWin::new(term_window)))).ok();
+                }
+                action => {
+                    if let Err(err) = term_window.perform_key_assignment(&pane, &action) {
+                        log::error!("quick select action {action:?} failed: {err:#}");
+                    }
+                    tx.try_send(None).ok();
+                }
+            }
+        })));
+
+        match rx.recv().await {
+            Ok(Some((name, gui_win))) => {
+                if let Err(err) = config::with_lua_config_on_main_thread(move |lua| {
+                    emit_action_event(lua, name, gui_win, pane_id)
+                })
+                .await
+                {
+                    log::error!("quick select action failed: {err:#}");
+                }
+            }
+            Ok(None) => {}
+            // The pane or the window has gone away
+            Err(_) => break,
+        }
+    }
+}
+
+async fn emit_action_event(
+    lua: Option<Rc<mlua::Lua>>,
+    name: String,
+    window: GuiWin,
+    pane_id: PaneId,
+) -> anyhow::Result<()> {
+    if let Some(lua) = lua {
+        let args = lua.pack_multi((window, MuxPane(pane_id)))?;
+        if let Err(err) = config::lua::emit_event(&lua, (name.clone(), args)).await {
+            log::error!("while processing {} event: {:#}", name, err);
+        }
+    }
+    Ok(())
+}
+
+/// Set the selection in pane to the range of the search result
+fn select_result(term_window: &TermWindow, pane: &Arc<dyn Pane>, result: &SearchResult) {
+    let mut selection = term_window.selection(pane.pane_id());
+    let start = SelectionCoordinate::x_y(result.start_x, result.start_y);
+    selection.origin = Some(start);
+    selection.range = Some(SelectionRange {
+        start,
+        // inclusive range for selection, but the result
+        // range is exclusive
+        end: SelectionCoordinate::x_y(result.end_x.saturating_sub(1), result.end_y),
+    });
+    // Ensure that selection doesn't get invalidated when
+    // the overlay is closed
+    selection.seqno = pane.get_current_seqno();
+}
+
+#[cfg(test)]
+mod selection_test {
+    use super::*;
+    use config::keyassignment::QuickSelectPatternAction;
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|l| l.to_string()).collect()
+    }
+
+    /// Four matches labelled a-d, where b and d were produced by
+    /// the pattern that has its own action
+    fn setup() -> (
+        HashMap<String, usize>,
+        Vec<Option<usize>>,
+        QuickSelectArguments,
+    ) {
+        let by_label = ["a", "b", "c", "d"]
+            .iter()
+            .enumerate()
+            .map(|(n, l)| (l.to_string(), n))
+            .collect();
+        let result_actions = vec![None, Some(0), None, Some(0)];
+        let args = QuickSelectArguments {
+            pattern_actions: vec![QuickSelectPatternAction {
+                pattern: "x".to_string(),
+                action: Box::new(KeyAssignment::EmitEvent("pattern".to_string())),
+            }],
+            multi_select: true,
+            ..Default::default()
+        };
+        (by_label, result_actions, args)
+    }
+
+    fn emit(name: &str) -> KeyAssignment {
+        KeyAssignment::EmitEvent(name.to_string())
+    }
+
+    #[test]
+    fn each_selected_match_gets_its_own_action() {
+        let (by_label, result_actions, args) = setup();
+        let plan = plan_selection(
+            &labels(&["d", "a", "b", "c"]),
+            &by_label,
+            &result_actions,
+            &args,
+            false,
+        );
+        assert_eq!(
+            plan,
+            SelectionPlan {
+                paste: vec![],
+                copy: vec![0, 2],
+                actions: vec![(3, emit("pattern")), (1, emit("pattern"))],
+            }
+        );
+    }
+
+    #[test]
+    fn default_action_applies_to_matches_without_their_own() {
+        let (by_label, result_actions, mut args) = setup();
+        args.action = Some(Box::new(emit("default")));
+        let plan = plan_selection(
+            &labels(&["a", "b", "c"]),
+            &by_label,
+            &result_actions,
+            &args,
+            false,
+        );
+        assert_eq!(
+            plan,
+            SelectionPlan {
+                paste: vec![],
+                copy: vec![],
+                actions: vec![
+                    (0, emit("default")),
+                    (1, emit("pattern")),
+                    (2, emit("default"))
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn paste_can_skip_actions() {
+        let (by_label, result_actions, mut args) = setup();
+        let all = labels(&["a", "b"]);
+
+        let plan = plan_selection(&all, &by_label, &result_actions, &args, true);
+        assert_eq!(plan.paste, vec![0, 1]);
+        assert_eq!(plan.copy, vec![0]);
+        assert_eq!(plan.actions, vec![(1, emit("pattern"))]);
+
+        args.skip_action_on_paste = true;
+        let plan = plan_selection(&all, &by_label, &result_actions, &args, true);
+        assert_eq!(plan.paste, vec![0, 1]);
+        assert_eq!(plan.copy, vec![0]);
+        assert!(plan.actions.is_empty());
+    }
+
+    #[test]
+    fn unknown_labels_are_ignored() {
+        let (by_label, result_actions, args) = setup();
+        let plan = plan_selection(
+            &labels(&["zz", "c"]),
+            &by_label,
+            &result_actions,
+            &args,
+            false,
+        );
+        assert_eq!(plan.copy, vec![2]);
+        assert!(plan.actions.is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+use mux::pane::PaneId;
+use mux::sftp::CHUNK_SIZE;
+use mux::termwiztermtab::TermWizTerminal;
+use smol::io::{AsyncReadExt, AsyncWriteExt};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use termwiz::cell::{AttributeChange, CellAttributes};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use termwiz_funcs::truncate_right;
+use wezterm_ssh::{Metadata, Sftp, Utf8PathBuf};
+
+/// The header, the blank line below it and the status line
+const ROW_OVERHEAD: usize = 3;
+
+lazy_static::lazy_static! {
+    /// Maps the pane that is being browsed to the channel that
+    /// delivers files dropped onto its browser overlay
+    static ref DROP_TARGETS: Mutex<HashMap<PaneId, Sender<Vec<PathBuf>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// If an sftp browser is active for pane_id, hands the paths
+/// over to it for upload and returns true.
+pub fn accept_dropped_files(pane_id: PaneId, paths: &[PathBuf]) -> bool {
+    match DROP_TARGETS.lock().unwrap().get(&pane_id) {
+        Some(tx) => tx.send(paths.to_vec()).is_ok(),
+        None => false,
+    }
+}
+
+struct DropTarget {
+    pane_id: PaneId,
+    rx: Receiver<Vec<PathBuf>>,
+}
+
+impl DropTarget {
+    fn register(pane_id: PaneId) -> Self {
+        let (tx, rx) = channel();
+        DROP_TARGETS.lock().unwrap().insert(pane_id, tx);
+        Self { pane_id, rx }
+    }
+}
+
+impl Drop for DropTarget {
+    fn drop(&mut self) {
+        DROP_TARGETS.lock().unwrap().remove(&self.pane_id);
+    }
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit + 1 < UNITS.len() {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_progress(name: &str, done: u64, size: Option<u64>) -> String {
+    match size {
+        Some(size) if size > 0 => format!(
+            "{}: {}% ({} of {})",
+            name,
+            done * 100 / size,
+            format_size(done),
+            format_size(size)
+        ),
+        _ => format!("{}: {}", name, format_size(done)),
+    }
+}
+
+/// Returns `name` with ` (n)` inserted before its extension, as used
+/// to avoid overwriting an existing file; `n == 0` returns `name`
+fn numbered_name(name: &str, n: usize) -> String {
+    if n == 0 {
+        return name.to_string();
+    }
+    match name.rfind('.') {
+        // A leading dot introduces a hidden file, not an extension
+        Some(dot) if dot > 0 => format!("{} ({}){}", &name[..dot], n, &name[dot..]),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+/// Creates a file named `name` in `dir`, or if that already exists,
+/// the first of `name (1)`, `name (2)`... that doesn't
+fn create_unique_local(dir: &Path, name: &str) -> std::io::Result<(std::fs::File, PathBuf)> {
+    for n in 0.. {
+        let path = dir.join(numbered_name(name, n));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((file, path)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+struct BrowserState {
+    sftp: Sftp,
+    dir: Utf8PathBuf,
+    entries: Vec<(Utf8PathBuf, Metadata)>,
+    active_idx: usize,
+    top_row: usize,
+    max_items: usize,
+    status: String,
+}
+
+impl BrowserState {
+    fn change_dir(&mut self, dir: Utf8PathBuf) {
+        match smol::block_on(mux::sftp::list_dir(&self.sftp, &dir)) {
+            Ok(entries) => {
+                self.dir = dir;
+                self.entries = entries;
+                self.active_idx = 0;
+                self.top_row = 0;
+                self.status.clear();
+            }
+            Err(err) => {
+                self.status = format!("{:#}", err);
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        let active_idx = self.active_idx;
+        self.change_dir(self.dir.clone());
+        self.active_idx = active_idx.min(self.entries.len().saturating_sub(1));
+    }
+
+    fn move_up(&mut self) {
+        self.active_idx = self.active_idx.saturating_sub(1);
+        if self.active_idx < self.top_row {
+            self.top_row = self.active_idx;
+        }
+    }
+
+    fn move_down(&mut self) {
+        self.active_idx = (self.active_idx + 1).min(self.entries.len().saturating_sub(1));
+        if self.active_idx >= self.top_row + self.max_items {
+            self.top_row = self.active_idx + 1 - self.max_items;
+        }
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let max_width = size.cols.saturating_sub(1);
+        self.max_items = size.rows.saturating_sub(ROW_OVERHEAD).max(1);
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            AttributeChange::Intensity(termwiz::cell::Intensity::Bold).into(),
+            Change::Text(format!(
+                "{}\r\n\r\n",
+                truncate_right(&format!("SFTP: {}", self.dir), max_width)
+            )),
+            Change::AllAttributes(CellAttributes::default()),
+        ];
+
+        for (entry_idx, (path, meta)) in self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(self.top_row)
+            .take(self.max_items)
+        {
+            let name = path.file_name().unwrap_or(path.as_str());
+            let size = match meta.size {
+                Some(size) if meta.is_file() => format_size(size),
+                _ => String::new(),
+            };
+            let name = if meta.is_dir() {
+                format!("{}/", name)
+            } else {
+                name.to_string()
+            };
+            let name_width = max_width.saturating_sub(12);
+            let line = format!(
+                " {:<name_width$} {:>10}",
+                truncate_right(&name, name_width),
+                size,
+                name_width = name_width
+            );
+
+            if entry_idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            changes.push(Change::Text(truncate_right(&line, max_width)));
+            if entry_idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(false).into());
+            }
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        let status = if self.status.is_empty() {
+            "Enter: open/download  Backspace: parent  r: refresh  Esc: close  \
+             Drop files to upload"
+        } else {
+            self.status.as_str()
+        };
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(size.rows.saturating_sub(1)),
+        });
+        changes.push(Change::Text(truncate_right(status, max_width)));
+
+        term.render(&changes)
+    }
+
+    fn set_status(&mut self, term: &mut TermWizTerminal, status: String) -> termwiz::Result<()> {
+        self.status = status;
+        self.render(term)
+    }
+
+    fn download(
+        &mut self,
+        term: &mut TermWizTerminal,
+        path: &Utf8PathBuf,
+        size: Option<u64>,
+    ) -> anyhow::Result<PathBuf> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("{} has no file name", path))?;
+        let dir = dirs_next::download_dir()
+            .or_else(dirs_next::home_dir)
+            .ok_or_else(|| anyhow::anyhow!("cannot determine the download directory"))?;
+
+        let mut remote = smol::block_on(self.sftp.open(path.clone()))?;
+        // Never overwrite an existing download
+        let (mut file, local) = create_unique_local(&dir, name)?;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut done = 0u64;
+        loop {
+            self.set_status(
+                term,
+                format!("Downloading {}", format_progress(name, done, size)),
+            )?;
+            let n = smol::block_on(remote.read(&mut buf))?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])?;
+            done += n as u64;
+        }
+        Ok(local)
+    }
+
+    fn upload(&mut self, term: &mut TermWizTerminal, local: &Path) -> anyhow::Result<Utf8PathBuf> {
+        let name = local
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("{} has no file name", local.display()))?;
+        let mut file = std::fs::File::open(local)?;
+        let size = file.metadata().ok().map(|meta| meta.len());
+
+        // Never overwrite an existing remote file
+        let path = (0..)
+            .map(|n| self.dir.join(numbered_name(name, n)))
+            .find(|path| smol::block_on(self.sftp.symlink_metadata(path.clone())).is_err())
+            .expect("an unused name");
+        let mut remote = smol::block_on(self.sftp.create(path.clone()))?;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut done = 0u64;
+        loop {
+            self.set_status(
+                term,
+                format!("Uploading {}", format_progress(name, done, size)),
+            )?;
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            smol::block_on(remote.write_all(&buf[..n]))?;
+            done += n as u64;
+        }
+        smol::block_on(async {
+            remote.flush().await?;
+            remote.close().await
+        })?;
+        Ok(path)
+    }
+
+    fn activate(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let (path, mut meta) = match self.entries.get(self.active_idx) {
+            Some((path, meta)) => (path.clone(), *meta),
+            None => return Ok(()),
+        };
+        if meta.is_symlink() {
+            // Follow the link so that we can tell whether to browse or download
+            if let Ok(target) = smol::block_on(self.sftp.metadata(path.clone())) {
+                meta = target;
+            }
+        }
+        if meta.is_dir() {
+            self.change_dir(path);
+            return Ok(());
+        }
+        let status = match self.download(term, &path, meta.size) {
+            Ok(local) => format!("Downloaded {} to {}", path, local.display()),
+            Err(err) => format!("Failed to download {}: {:#}", path, err),
+        };
+        self.status = status;
+        Ok(())
+    }
+
+    fn upload_all(
+        &mut self,
+        term: &mut TermWizTerminal,
+        paths: Vec<PathBuf>,
+    ) -> termwiz::Result<()> {
+        let mut uploaded = 0;
+        let mut renamed = vec![];
+        let mut failed = vec![];
+        for local in paths {
+            if !local.is_file() {
+                failed.push(format!("{} is not a file", local.display()));
+                continue;
+            }
+            match self.upload(term, &local) {
+                Ok(path) => {
+                    uploaded += 1;
+                    if path.file_name() != local.file_name().and_then(|name| name.to_str()) {
+                        renamed.push(path.to_string());
+                    }
+                }
+                Err(err) => failed.push(format!("{}: {:#}", local.display(), err)),
+            }
+        }
+        self.refresh();
+        self.status = if !failed.is_empty() {
+            format!("Upload failed: {}", failed.join("; "))
+        } else if !renamed.is_empty() {
+            format!(
+                "Uploaded {} file(s) to {}; saved as {} to avoid overwriting",
+                uploaded,
+                self.dir,
+                renamed.join(", ")
+            )
+        } else {
+            format!("Uploaded {} file(s) to {}", uploaded, self.dir)
+        };
+        Ok(())
+    }
+
+    fn run_loop(&mut self, term: &mut TermWizTerminal, drops: &DropTarget) -> anyhow::Result<()> {
+        self.render(term)?;
+        loop {
+            if let Ok(paths) = drops.rx.try_recv() {
+                self.upload_all(term, paths)?;
+                self.render(term)?;
+            }
+
+            let event = match term.poll_input(Some(Duration::from_millis(100)))? {
+                Some(event) => event,
+                None => continue,
+            };
+
+            match event {
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Escape,
+                    ..
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('q'),
+                    ..
+                }) => break,
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::UpArrow,
+                    ..
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('k'),
+                    ..
+                }) => self.move_up(),
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::DownArrow,
+                    ..
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('j'),
+                    ..
+                }) => self.move_down(),
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Backspace,
+                    ..
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::LeftArrow,
+                    ..
+                }) => {
+                    if let Some(parent) = self.dir.parent() {
+                        self.change_dir(parent.to_path_buf());
+                    }
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('r'),
+                    ..
+                }) => self.refresh(),
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Enter,
+                    ..
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::RightArrow,
+                    ..
+                }) => self.activate(term)?,
+                _ => continue,
+            }
+            self.render(term)?;
+        }
+        Ok(())
+    }
+}
+
+/// Browses the remote filesystem of the ssh domain that hosts pane_id,
+/// starting in its working directory.
+/// Files can be downloaded to the local download directory, and
+/// files that are dropped onto the overlay are uploaded into the
+/// directory that is being browsed.
+pub fn sftp_browser(
+    pane_id: PaneId,
+    mut term: TermWizTerminal,
+    sftp: anyhow::Result<Sftp>,
+    cwd: Option<Utf8PathBuf>,
+) -> anyhow::Result<()> {
+    term.no_grab_mouse_in_raw_mode();
+
+    let sftp = match sftp {
+        Ok(sftp) => sftp,
+        Err(err) => {
+            term.render(&[Change::Text(format!(
+                "Cannot browse files for this pane: {:#}\r\n\r\nPress any key to close\r\n",
+                err
+            ))])?;
+            while let Ok(Some(event)) = term.poll_input(None) {
+                if let InputEvent::Key(_) = event {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    let dir = match cwd {
+        Some(cwd) => cwd,
+        None => smol::block_on(sftp.canonicalize("."))?,
+    };
+
+    let drops = DropTarget::register(pane_id);
+    let mut state = BrowserState {
+        sftp,
+        dir: dir.clone(),
+        entries: vec![],
+        active_idx: 0,
+        top_row: 0,
+        max_items: 0,
+        status: String::new(),
+    };
+    state.change_dir(dir);
+    state.run_loop(&mut term, &drops)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbered_names() {
+        assert_eq!(numbered_name("notes.txt", 0), "notes.txt");
+        assert_eq!(numbered_name("notes.txt", 1), "notes (1).txt");
+        assert_eq!(numbered_name("archive.tar.gz", 2), "archive.tar (2).gz");
+        assert_eq!(numbered_name("Makefile", 3), "Makefile (3)");
+        assert_eq!(numbered_name(".bashrc", 1), ".bashrc (1)");
+    }
+
+    #[test]
+    fn existing_local_files_are_not_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "keep me").unwrap();
+        std::fs::write(dir.path().join("notes (1).txt"), "keep me too").unwrap();
+
+        let (_file, path) = create_unique_local(dir.path(), "notes.txt").unwrap();
+        assert_eq!(path, dir.path().join("notes (2).txt"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("notes.txt")).unwrap(),
+            "keep me"
+        );
+
+        let (_file, path) = create_unique_local(dir.path(), "other.txt").unwrap();
+        assert_eq!(path, dir.path().join("other.txt"));
+    }
+}
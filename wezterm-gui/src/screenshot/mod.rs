@@ -0,0 +1,224 @@
+//! Renders the visible portion of a pane into an image.  The rendering
+//! is performed by a TermWindow, through the same quad pipeline that
+//! paints the window, into an offscreen target whose pixels are read
+//! back and cropped to the cells of the pane.
+//!
+//! When there is no window to do that, such as when running headless
+//! or on a machine without a GPU, the pane is rendered on the CPU by
+//! the `windowless` module instead.
+use crate::termwindow::TermWindowNotif;
+use anyhow::Context;
+use codec::PaneSnapshot;
+use mux::pane::Pane;
+use mux::Mux;
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::Arc;
+
+mod windowless;
+
+/// Render the visible portion of `pane` and return PNG encoded data.
+/// This is registered with `mux::screenshot` so that it can be used
+/// by the mux server and from lua.
+pub fn screenshot_pane(
+    pane: Arc<dyn Pane>,
+) -> Pin<Box<dyn Future<Output = anyhow::Result<Vec<u8>>>>> {
+    Box::pin(async move {
+        let mux = Mux::get();
+
+        // Prefer the window that contains the pane, so that it is
+        // rendered with that window's fonts and layout
+        let window = crate::frontend::try_front_end().and_then(|fe| {
+            mux.resolve_pane_id(pane.pane_id())
+                .and_then(|(_domain, window_id, _tab)| fe.gui_window_for_mux_window(window_id))
+                .or_else(|| fe.gui_windows().into_iter().next())
+        });
+        let window = match window {
+            Some(window) => window,
+            None => {
+                log::debug!("no gui window is available, rendering screenshot without one");
+                return windowless::render(&PaneSnapshot::capture(&*pane)).await;
+            }
+        };
+
+        let (tx, rx) = smol::channel::bounded(1);
+        window
+            .window
+            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                tx.try_send(term_window.screenshot_pane(&pane)).ok();
+            })));
+        rx.recv()
+            .await
+            .context("waiting for the window to render the screenshot")?
+    })
+}
+
+/// Implements the hidden `render-screenshot` subcommand, which is used by
+/// `wezterm-mux-server` to render screenshots when no gui is attached.
+/// Reads a `PaneSnapshot` from stdin and writes PNG encoded data to stdout.
+pub fn run_render_screenshot() -> anyhow::Result<()> {
+    // Disable the normal config error UI window, as we don't have
+    // a fully baked GUI environment running
+    config::assign_error_callback(|err| eprintln!("{}", err));
+
+    let snapshot = PaneSnapshot::read_from(&mut std::io::stdin().lock())?;
+    let png = smol::block_on(windowless::render(&snapshot))?;
+    let mut stdout = std::io::stdout().lock();
+    stdout
+        .write_all(&png)
+        .context("writing screenshot to stdout")?;
+    stdout.flush().context("flushing stdout")?;
+    Ok(())
+}
+
+/// RGBA pixels, with the top row first
+#[derive(Debug, PartialEq)]
+pub struct ScreenshotImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl ScreenshotImage {
+    /// Builds the image from rows of pixels read back from an OpenGL
+    /// texture, whose first row is the bottom of the image
+    pub fn from_bottom_up_rows(rows: Vec<Vec<(u8, u8, u8, u8)>>) -> Self {
+        let height = rows.len();
+        let width = rows.first().map(|row| row.len()).unwrap_or(0);
+        let mut data = Vec::with_capacity(width * height * 4);
+        for row in rows.iter().rev() {
+            for &(r, g, b, a) in row {
+                data.extend_from_slice(&[r, g, b, a]);
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Builds the image from a buffer that was copied out of a webgpu
+    /// texture, whose rows are padded out to `bytes_per_row`
+    pub fn from_padded_rows(
+        buffer: &[u8],
+        width: usize,
+        height: usize,
+        bytes_per_row: usize,
+        bgra: bool,
+    ) -> Self {
+        let mut data = Vec::with_capacity(width * height * 4);
+        for row in buffer.chunks(bytes_per_row).take(height) {
+            for pixel in row[..width * 4].chunks_exact(4) {
+                if bgra {
+                    data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                } else {
+                    data.extend_from_slice(pixel);
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Returns the portion of the image in the specified rectangle,
+    /// clipped to the bounds of the image
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let mut data = Vec::with_capacity(width * height * 4);
+        for row in y..y + height {
+            let start = (row * self.width + x) * 4;
+            data.extend_from_slice(&self.data[start..start + width * 4]);
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn to_png(self) -> anyhow::Result<Vec<u8>> {
+        let image = image::RgbaImage::from_raw(self.width as u32, self.height as u32, self.data)
+            .ok_or_else(|| anyhow::anyhow!("screenshot buffer has unexpected size"))?;
+        let mut png = vec![];
+        image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .context("encoding screenshot as png")?;
+        Ok(png)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pixel(n: u8) -> (u8, u8, u8, u8) {
+        (n, n + 1, n + 2, 0xff)
+    }
+
+    #[test]
+    fn bottom_up_rows_are_flipped() {
+        let image = ScreenshotImage::from_bottom_up_rows(vec![
+            vec![pixel(10), pixel(20)],
+            vec![pixel(30), pixel(40)],
+        ]);
+        assert_eq!(image.width, 2);
+        assert_eq!(image.height, 2);
+        assert_eq!(
+            image.data,
+            vec![30, 31, 32, 255, 40, 41, 42, 255, 10, 11, 12, 255, 20, 21, 22, 255]
+        );
+    }
+
+    #[test]
+    fn padded_rows_are_trimmed_and_swizzled() {
+        // 1 pixel wide with 4 bytes of padding per row
+        let buffer = [1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0];
+
+        let rgba = ScreenshotImage::from_padded_rows(&buffer, 1, 2, 8, false);
+        assert_eq!(rgba.data, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let bgra = ScreenshotImage::from_padded_rows(&buffer, 1, 2, 8, true);
+        assert_eq!(bgra.data, vec![3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn crop_selects_and_clips() {
+        let image = ScreenshotImage::from_bottom_up_rows(vec![
+            vec![pixel(70), pixel(80), pixel(90)],
+            vec![pixel(40), pixel(50), pixel(60)],
+            vec![pixel(10), pixel(20), pixel(30)],
+        ]);
+
+        let cropped = image.crop(1, 1, 2, 1);
+        assert_eq!((cropped.width, cropped.height), (2, 1));
+        assert_eq!(cropped.data, vec![50, 51, 52, 255, 60, 61, 62, 255]);
+
+        let clipped = image.crop(2, 2, 10, 10);
+        assert_eq!((clipped.width, clipped.height), (1, 1));
+        assert_eq!(clipped.data, vec![90, 91, 92, 255]);
+
+        let empty = image.crop(5, 5, 1, 1);
+        assert_eq!((empty.width, empty.height), (0, 0));
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let image = ScreenshotImage::from_bottom_up_rows(vec![
+            vec![pixel(10), pixel(20)],
+            vec![pixel(30), pixel(40)],
+        ]);
+        let expected = image.data.clone();
+        let png = image.to_png().unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (2, 2));
+        assert_eq!(decoded.into_raw(), expected);
+    }
+}
@@ -0,0 +1,519 @@
+//! Renders a snapshot of a pane into an image without a window or a GPU,
+//! which is what makes screenshots possible on headless, CPU-only machines.
+//! Glyphs, custom block glyphs, line decorations, cursor sprites and images
+//! are produced by an in-memory `GlyphCache` that we own, exactly as they are
+//! for the on-screen renderer, and are then composited on the CPU.
+use super::ScreenshotImage;
+use crate::customglyph::BlockKey;
+use crate::glyphcache::{GlyphCache, LoadState, StrikeThrough};
+use crate::termwindow::render::paint::AllowImage;
+use crate::termwindow::render::resolve_fg_color_attr;
+use crate::utilsprites::RenderMetrics;
+use ::window::bitmaps::atlas::{OutOfTextureSpace, Sprite};
+use ::window::bitmaps::{BitmapImage, ImageTexture};
+use ::window::color::LinearRgba;
+use anyhow::Context;
+use codec::PaneSnapshot;
+use config::{ConfigHandle, TextStyle};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use termwiz::cell::{CellAttributes, Underline};
+use termwiz::color::ColorAttribute;
+use termwiz::surface::{CursorShape, CursorVisibility};
+use wezterm_font::shaper::PresentationWidth;
+use wezterm_font::FontConfiguration;
+use wezterm_term::StableRowIndex;
+
+const INITIAL_ATLAS_SIZE: usize = 1024;
+
+/// How long to wait for an image to finish decoding before
+/// giving up and rendering without it
+const IMAGE_LOAD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Renders `snapshot` and returns PNG encoded data
+pub async fn render(snapshot: &PaneSnapshot) -> anyhow::Result<Vec<u8>> {
+    let config = config::configuration();
+    let dpi = if snapshot.dimensions.dpi == 0 {
+        config.dpi.unwrap_or_else(|| ::window::default_dpi()) as usize
+    } else {
+        snapshot.dimensions.dpi as usize
+    };
+    let fonts = Rc::new(FontConfiguration::new(Some(config.clone()), dpi)?);
+    let render_metrics = RenderMetrics::new(&fonts)?;
+
+    let mut atlas_size = INITIAL_ATLAS_SIZE;
+    loop {
+        let mut compositor = Compositor {
+            config: config.clone(),
+            fonts: Rc::clone(&fonts),
+            glyph_cache: GlyphCache::new_in_memory(&fonts, atlas_size)?,
+            render_metrics: render_metrics.clone(),
+            canvas: Canvas::new(
+                snapshot.dimensions.cols * render_metrics.cell_size.width as usize,
+                snapshot.dimensions.viewport_rows * render_metrics.cell_size.height as usize,
+            ),
+        };
+        match compositor.render(snapshot).await {
+            Ok(()) => return compositor.canvas.into_image().to_png(),
+            Err(err) => {
+                if let Some(&OutOfTextureSpace {
+                    size: Some(size), ..
+                }) = err.root_cause().downcast_ref::<OutOfTextureSpace>()
+                {
+                    log::trace!("screenshot: growing atlas to {size}");
+                    atlas_size = size;
+                    continue;
+                }
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// An RGBA image accumulated in linear color space
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<LinearRgba>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![LinearRgba::TRANSPARENT; width * height],
+        }
+    }
+
+    fn pixel_mut(&mut self, x: isize, y: isize) -> Option<&mut LinearRgba> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some(&mut self.pixels[(y as usize * self.width) + x as usize])
+    }
+
+    fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: LinearRgba) {
+        for py in y..y + height as isize {
+            for px in x..x + width as isize {
+                if let Some(pixel) = self.pixel_mut(px, py) {
+                    *pixel = blend(*pixel, color, color.3);
+                }
+            }
+        }
+    }
+
+    /// Composites the whole of `sprite` at its natural size.
+    /// When `tint` is Some, the sprite is treated as a coverage mask
+    /// for that color, otherwise it is treated as color data.
+    fn draw_sprite(&mut self, sprite: &Sprite, x: isize, y: isize, tint: Option<LinearRgba>) {
+        let width = sprite.coords.size.width as usize;
+        let height = sprite.coords.size.height as usize;
+        self.draw_scaled_sprite(sprite, ((0., 0.), (1., 1.)), (x, y, width, height), tint);
+    }
+
+    /// Composites a sub-rectangle of `sprite`, scaling it to fill the
+    /// destination rectangle.  `src` is expressed in the 0.0-1.0 range
+    /// relative to the sprite.
+    fn draw_scaled_sprite(
+        &mut self,
+        sprite: &Sprite,
+        src: ((f32, f32), (f32, f32)),
+        dest: (isize, isize, usize, usize),
+        tint: Option<LinearRgba>,
+    ) {
+        let texture = match sprite.texture.downcast_ref::<ImageTexture>() {
+            Some(t) => t,
+            None => return,
+        };
+        let image = texture.image.borrow();
+        let ((left, top), (right, bottom)) = src;
+        let (dx, dy, width, height) = dest;
+        let sprite_width = sprite.coords.size.width.max(0) as usize;
+        let sprite_height = sprite.coords.size.height.max(0) as usize;
+        if sprite_width == 0 || sprite_height == 0 {
+            return;
+        }
+
+        for py in 0..height {
+            let v = top + (bottom - top) * (py as f32 + 0.5) / height as f32;
+            let ty = sprite.coords.min_y() as usize
+                + ((v * sprite_height as f32) as usize).min(sprite_height - 1);
+            for px in 0..width {
+                let u = left + (right - left) * (px as f32 + 0.5) / width as f32;
+                let tx = sprite.coords.min_x() as usize
+                    + ((u * sprite_width as f32) as usize).min(sprite_width - 1);
+                let (r, g, b, a) = unpack_texel(*image.pixel(tx, ty));
+                if a == 0 {
+                    continue;
+                }
+                if let Some(pixel) = self.pixel_mut(dx + px as isize, dy + py as isize) {
+                    let coverage = a as f32 / 255.;
+                    *pixel = match tint {
+                        Some(color) => blend(*pixel, color, coverage * color.3),
+                        None => blend(*pixel, LinearRgba::with_srgba(r, g, b, 0xff), coverage),
+                    };
+                }
+            }
+        }
+    }
+
+    fn into_image(self) -> ScreenshotImage {
+        let mut data = Vec::with_capacity(self.width * self.height * 4);
+        for pixel in &self.pixels {
+            let (r, g, b, a) = pixel.to_srgb().as_rgba_u8();
+            data.extend_from_slice(&[r, g, b, a]);
+        }
+        ScreenshotImage {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+}
+
+/// Atlas texels are big endian RGBA
+fn unpack_texel(texel: u32) -> (u8, u8, u8, u8) {
+    let texel = u32::from_be(texel);
+    (
+        (texel >> 24) as u8,
+        (texel >> 16) as u8,
+        (texel >> 8) as u8,
+        (texel & 0xff) as u8,
+    )
+}
+
+/// Source-over blend of `src` with the specified alpha onto `dest`
+fn blend(dest: LinearRgba, src: LinearRgba, alpha: f32) -> LinearRgba {
+    let alpha = alpha.clamp(0., 1.);
+    let inv = 1.0 - alpha;
+    LinearRgba::with_components(
+        src.0 * alpha + dest.0 * inv,
+        src.1 * alpha + dest.1 * inv,
+        src.2 * alpha + dest.2 * inv,
+        alpha + dest.3 * inv,
+    )
+}
+
+struct Compositor {
+    config: ConfigHandle,
+    fonts: Rc<FontConfiguration>,
+    glyph_cache: GlyphCache,
+    render_metrics: RenderMetrics,
+    canvas: Canvas,
+}
+
+/// The colors that a cell is drawn with
+struct CellColors {
+    fg: LinearRgba,
+    bg: LinearRgba,
+    underline: LinearRgba,
+    overline: LinearRgba,
+    strikethrough: LinearRgba,
+}
+
+impl Compositor {
+    async fn render(&mut self, snapshot: &PaneSnapshot) -> anyhow::Result<()> {
+        let palette = &snapshot.palette;
+        let cursor = &snapshot.cursor;
+        let cell_width = self.render_metrics.cell_size.width as isize;
+        let cell_height = self.render_metrics.cell_size.height as isize;
+
+        let background = palette
+            .background
+            .to_linear()
+            .mul_alpha(self.config.window_background_opacity);
+        self.canvas.pixels.fill(background);
+
+        let cursor_shape = self
+            .config
+            .default_cursor_style
+            .effective_shape(cursor.shape);
+        let cursor_row = if cursor.visibility == CursorVisibility::Visible {
+            Some(cursor.y - snapshot.first_row)
+        } else {
+            None
+        };
+        let block_cursor = matches!(
+            cursor_shape,
+            CursorShape::Default | CursorShape::BlinkingBlock | CursorShape::SteadyBlock
+        );
+
+        let bidi_hint = if self.config.bidi_enabled {
+            Some(self.config.bidi_direction)
+        } else {
+            None
+        };
+
+        for (line_idx, line) in snapshot.lines.iter().enumerate() {
+            let y = line_idx as isize * cell_height;
+            let cursor_x = if cursor_row == Some(line_idx as StableRowIndex) {
+                Some(cursor.x)
+            } else {
+                None
+            };
+
+            // Backgrounds first, so that glyphs can overlap adjacent cells
+            for cell in line.visible_cells() {
+                let attrs = cell.attrs();
+                let style = self.fonts.match_style(&self.config, attrs);
+                let colors = self.resolve_colors(snapshot, attrs, style);
+                let x = cell.cell_index() as isize * cell_width;
+                let width = cell.width() * cell_width as usize;
+                let bg = if block_cursor && cursor_x == Some(cell.cell_index()) {
+                    palette.cursor_bg.to_linear()
+                } else {
+                    colors.bg
+                };
+                if bg != background {
+                    self.canvas.fill_rect(x, y, width, cell_height as usize, bg);
+                }
+
+                for image in attrs.images().unwrap_or_else(Vec::new) {
+                    self.draw_image_cell(&image, x, y).await?;
+                }
+            }
+
+            for cluster in line.cluster(bidi_hint) {
+                let style = self.fonts.match_style(&self.config, &cluster.attrs);
+                let font = self.fonts.resolve_font(style)?;
+                let presentation_width = PresentationWidth::with_cluster(&cluster);
+                let infos = font.blocking_shape(
+                    &cluster.text,
+                    Some(cluster.presentation),
+                    cluster.direction,
+                    None,
+                    Some(&presentation_width),
+                )?;
+                let colors = self.resolve_colors(snapshot, &cluster.attrs, style);
+
+                for info in &infos {
+                    let cell_idx = cluster.byte_to_cell_idx(info.cluster as usize);
+                    let x = cell_idx as isize * cell_width;
+                    let fg = if block_cursor && cursor_x == Some(cell_idx) {
+                        palette.cursor_fg.to_linear()
+                    } else {
+                        colors.fg
+                    };
+
+                    if self.config.custom_block_glyphs {
+                        if let Some(block) = info.only_char.and_then(BlockKey::from_char) {
+                            let sprite =
+                                self.glyph_cache.cached_block(block, &self.render_metrics)?;
+                            self.canvas.draw_sprite(&sprite, x, y, Some(fg));
+                            continue;
+                        }
+                    }
+
+                    let followed_by_space = match line.get_cell(cell_idx + 1) {
+                        Some(cell) => cell.str() == " ",
+                        None => false,
+                    };
+                    let glyph = self.glyph_cache.cached_glyph(
+                        info,
+                        style,
+                        followed_by_space,
+                        &font,
+                        &self.render_metrics,
+                        info.num_cells,
+                    )?;
+                    if let Some(sprite) = &glyph.texture {
+                        let gx = x + (glyph.x_offset + glyph.bearing_x).get() as isize;
+                        let gy = y
+                            + cell_height
+                            + (self.render_metrics.descender.get()
+                                - (glyph.y_offset + glyph.bearing_y).get())
+                                as isize;
+                        let width = (sprite.coords.size.width as f64 * glyph.scale) as usize;
+                        let height = (sprite.coords.size.height as f64 * glyph.scale) as usize;
+                        self.canvas.draw_scaled_sprite(
+                            sprite,
+                            ((0., 0.), (1., 1.)),
+                            (gx, gy, width, height),
+                            if glyph.has_color { None } else { Some(fg) },
+                        );
+                    }
+                }
+
+                // Decorations are drawn with the metrics of the face
+                // that was used to render the cluster
+                let metrics = match infos.first() {
+                    Some(info) => self
+                        .render_metrics
+                        .with_face_decorations(&self.fonts, &font.metrics_for_idx(info.font_idx)?),
+                    None => self.render_metrics.clone(),
+                };
+                let attrs = &cluster.attrs;
+                let strike_through = match (attrs.strikethrough(), attrs.double_strikethrough()) {
+                    (false, _) => StrikeThrough::None,
+                    (true, false) => StrikeThrough::Single,
+                    (true, true) => StrikeThrough::Double,
+                };
+                let mut decorations = vec![];
+                if attrs.underline() != Underline::None {
+                    decorations.push((
+                        StrikeThrough::None,
+                        attrs.underline(),
+                        false,
+                        colors.underline,
+                    ));
+                }
+                if attrs.overline() {
+                    decorations.push((StrikeThrough::None, Underline::None, true, colors.overline));
+                }
+                if strike_through != StrikeThrough::None {
+                    decorations.push((
+                        strike_through,
+                        Underline::None,
+                        false,
+                        colors.strikethrough,
+                    ));
+                }
+                for (strike_through, underline, overline, color) in decorations {
+                    let sprite = self.glyph_cache.cached_line_sprite(
+                        false,
+                        strike_through,
+                        underline,
+                        overline,
+                        &metrics,
+                    )?;
+                    for cell_idx in cluster.first_cell_idx..cluster.first_cell_idx + cluster.width {
+                        self.canvas.draw_sprite(
+                            &sprite,
+                            cell_idx as isize * cell_width,
+                            y,
+                            Some(color),
+                        );
+                    }
+                }
+            }
+
+            if let (Some(cursor_x), false) = (cursor_x, block_cursor) {
+                let sprite =
+                    self.glyph_cache
+                        .cursor_sprite(Some(cursor_shape), &self.render_metrics, 1)?;
+                self.canvas.draw_sprite(
+                    &sprite,
+                    cursor_x as isize * cell_width,
+                    y,
+                    Some(palette.cursor_border.to_linear()),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_colors(
+        &self,
+        snapshot: &PaneSnapshot,
+        attrs: &CellAttributes,
+        style: &TextStyle,
+    ) -> CellColors {
+        let palette = &snapshot.palette;
+        let fg = resolve_fg_color_attr(attrs, attrs.foreground(), palette, &self.config, style);
+        let bg = palette.resolve_bg(attrs.background()).to_linear();
+        let (fg, bg) = if attrs.reverse() { (bg, fg) } else { (fg, bg) };
+        let fg = if attrs.invisible() { bg } else { fg };
+        let decoration_color = |color: ColorAttribute| match color {
+            ColorAttribute::Default => fg,
+            c => resolve_fg_color_attr(attrs, c, palette, &self.config, style),
+        };
+        CellColors {
+            fg,
+            bg,
+            underline: decoration_color(attrs.underline_color()),
+            overline: decoration_color(attrs.overline_color()),
+            strikethrough: decoration_color(attrs.strikethrough_color()),
+        }
+    }
+
+    async fn draw_image_cell(
+        &mut self,
+        image: &termwiz::image::ImageCell,
+        x: isize,
+        y: isize,
+    ) -> anyhow::Result<()> {
+        // Images are decoded on another thread; since we only get a
+        // single shot at this, give the decoder a little while to
+        // finish, without blocking the thread that we're running on
+        let deadline = Instant::now() + IMAGE_LOAD_TIMEOUT;
+        let sprite = loop {
+            let (sprite, _next_due, load_state) = self
+                .glyph_cache
+                .cached_image(image.image_data(), None, AllowImage::Yes)
+                .context("cached_image")?;
+            if load_state == LoadState::Loaded || Instant::now() >= deadline {
+                break sprite;
+            }
+            smol::Timer::after(Duration::from_millis(10)).await;
+        };
+
+        let top_left = image.top_left();
+        let bottom_right = image.bottom_right();
+        let (padding_left, padding_top, padding_right, padding_bottom) = image.padding();
+        let cell_width = self.render_metrics.cell_size.width as usize;
+        let cell_height = self.render_metrics.cell_size.height as usize;
+
+        self.canvas.draw_scaled_sprite(
+            &sprite,
+            (
+                (*top_left.x, *top_left.y),
+                (*bottom_right.x, *bottom_right.y),
+            ),
+            (
+                x + padding_left as isize,
+                y + padding_top as isize,
+                (cell_width + padding_left as usize).saturating_sub(padding_right as usize),
+                (cell_height + padding_top as usize).saturating_sub(padding_bottom as usize),
+            ),
+            None,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn texels_are_big_endian_rgba() {
+        let texel = u32::from_ne_bytes([1, 2, 3, 4]);
+        assert_eq!(unpack_texel(texel), (1, 2, 3, 4));
+    }
+
+    #[test]
+    fn blend_is_source_over() {
+        let dest = LinearRgba::with_components(0., 0., 1., 1.);
+        let src = LinearRgba::with_components(1., 0., 0., 1.);
+        assert_eq!(blend(dest, src, 1.), src);
+        assert_eq!(blend(dest, src, 0.), dest);
+        assert_eq!(
+            blend(dest, src, 0.5),
+            LinearRgba::with_components(0.5, 0., 0.5, 1.)
+        );
+        // Out of range alpha is clamped
+        assert_eq!(blend(dest, src, 2.), src);
+    }
+
+    #[test]
+    fn fill_rect_is_clipped_to_the_canvas() {
+        let red = LinearRgba::with_components(1., 0., 0., 1.);
+        let mut canvas = Canvas::new(2, 2);
+        canvas.fill_rect(-1, 1, 5, 5, red);
+        assert_eq!(
+            canvas.pixels,
+            vec![LinearRgba::TRANSPARENT, LinearRgba::TRANSPARENT, red, red]
+        );
+    }
+
+    #[test]
+    fn into_image_converts_to_srgb() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.fill_rect(0, 0, 1, 1, LinearRgba::with_srgba(0xff, 0, 0xff, 0xff));
+        let image = canvas.into_image();
+        assert_eq!((image.width, image.height), (1, 1));
+        assert_eq!(image.data, vec![0xff, 0, 0xff, 0xff]);
+    }
+}
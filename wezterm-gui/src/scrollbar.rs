@@ -1,4 +1,7 @@
 use mux::pane::Pane;
+use regex::Regex;
+use std::collections::BTreeSet;
+use termwiz::surface::{SequenceNo, SEQ_ZERO};
 use wezterm_term::StableRowIndex;
 
 pub struct ScrollHit {
@@ -47,6 +50,23 @@ impl ScrollHit {
         }
     }
 
+    /// Compute the y-coordinate, relative to the top of the scrollbar
+    /// track, at which the marker for `row` should be drawn.
+    /// Markers are positioned proportionally to the whole scrollback,
+    /// rather than relative to the thumb.
+    pub fn marker_top(
+        pane: &dyn Pane,
+        row: StableRowIndex,
+        max_thumb_height: usize,
+        marker_height: usize,
+    ) -> usize {
+        let render_dims = pane.get_dimensions();
+        let scroll_size = render_dims.scrollback_rows.max(1) as f32;
+        let offset = row.saturating_sub(render_dims.scrollback_top).max(0) as f32;
+        let percent = (offset / scroll_size).min(1.0);
+        (percent * max_thumb_height.saturating_sub(marker_height) as f32) as usize
+    }
+
     /// Given a new thumb top coordinate (produced by dragging the thumb),
     /// compute the equivalent viewport offset.
     pub fn thumb_top_to_scroll_top(
@@ -68,3 +88,101 @@ impl ScrollHit {
         )
     }
 }
+
+/// The kinds of marker that can be drawn on the scrollbar track.
+/// The ordering is significant: when several markers land on the
+/// same pixel row, the greatest kind is the one that is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScrollbarMarkerKind {
+    Prompt,
+    Mark,
+    Error,
+    SearchMatch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScrollbarMarker {
+    pub row: StableRowIndex,
+    pub kind: ScrollbarMarkerKind,
+}
+
+/// Tracks the marked lines and the lines matching the configured
+/// error patterns for a pane.  Only lines that have changed since
+/// the last update are rescanned.
+#[derive(Default)]
+pub struct ScrollbarMarkerCache {
+    seqno: SequenceNo,
+    patterns: Vec<String>,
+    regexes: Vec<Regex>,
+    marks: BTreeSet<StableRowIndex>,
+    errors: BTreeSet<StableRowIndex>,
+}
+
+impl ScrollbarMarkerCache {
+    pub fn update(&mut self, pane: &dyn Pane, patterns: &[String]) {
+        if self.patterns != patterns {
+            self.regexes = patterns
+                .iter()
+                .filter_map(|p| match Regex::new(p) {
+                    Ok(re) => Some(re),
+                    Err(err) => {
+                        log::error!("invalid scroll_bar_error_patterns entry {p:?}: {err:#}");
+                        None
+                    }
+                })
+                .collect();
+            self.patterns = patterns.to_vec();
+            self.marks.clear();
+            self.errors.clear();
+            self.seqno = SEQ_ZERO;
+        }
+
+        let seqno = pane.get_current_seqno();
+        if seqno == self.seqno {
+            return;
+        }
+
+        let dims = pane.get_dimensions();
+        let range =
+            dims.scrollback_top..dims.scrollback_top + dims.scrollback_rows as StableRowIndex;
+
+        // Forget about rows that are no longer part of the scrollback
+        for set in [&mut self.marks, &mut self.errors] {
+            *set = set.split_off(&range.start);
+            set.split_off(&range.end);
+        }
+
+        let changed = pane.get_changed_since(range, self.seqno);
+        for lines in changed.iter() {
+            let (first_row, lines) = pane.get_lines(lines.clone());
+            for (idx, line) in lines.iter().enumerate() {
+                let row = first_row + idx as StableRowIndex;
+
+                if line.is_marked() {
+                    self.marks.insert(row);
+                } else {
+                    self.marks.remove(&row);
+                }
+
+                if !self.regexes.is_empty() {
+                    let text = line.as_str();
+                    if self.regexes.iter().any(|re| re.is_match(&text)) {
+                        self.errors.insert(row);
+                    } else {
+                        self.errors.remove(&row);
+                    }
+                }
+            }
+        }
+
+        self.seqno = seqno;
+    }
+
+    pub fn marks(&self) -> impl Iterator<Item = StableRowIndex> + '_ {
+        self.marks.iter().copied()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = StableRowIndex> + '_ {
+        self.errors.iter().copied()
+    }
+}
@@ -39,6 +39,9 @@ struct ShaderUniform {
   foreground_text_hsb: vec3<f32>,
   milliseconds: u32,
   projection: mat4x4<f32>,
+  // x is used for light text and y for dark text
+  text_gamma: vec2<f32>,
+  text_contrast: vec2<f32>,
 };
 @group(0) @binding(0) var<uniform> uniforms: ShaderUniform;
 
@@ -72,6 +75,18 @@ fn apply_hsv(c: vec4<f32>, transform: vec3<f32>) -> vec4<f32>
   return vec4<f32>(hsv2rgb(hsv).rgb, c.a);
 }
 
+// Apply the text_contrast and text_gamma configuration to the
+// coverage of a glyph, selecting the values based on whether
+// the text is light or dark
+fn adjust_coverage(coverage: f32, text_color: vec3<f32>) -> f32
+{
+  let light = dot(text_color, vec3<f32>(0.2126, 0.7152, 0.0722)) > 0.5;
+  let contrast = select(uniforms.text_contrast.y, uniforms.text_contrast.x, light);
+  let gamma = select(uniforms.text_gamma.y, uniforms.text_gamma.x, light);
+  let c = clamp((coverage - 0.5) * (1.0 + contrast) + 0.5, 0.0, 1.0);
+  return pow(c, 1.0 / gamma);
+}
+
 @vertex
 fn vs_main(
     model: VertexInput,
@@ -114,8 +129,8 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     // the texture is the alpha channel/color mask
     // and we need to tint with the fg_color
     color = in.fg_color;
-    color.a = nearest_tex.a;
     hsv *= uniforms.foreground_text_hsb;
+    color.a = adjust_coverage(nearest_tex.a, apply_hsv(color, hsv).rgb);
   }
 
   color = apply_hsv(color, hsv);
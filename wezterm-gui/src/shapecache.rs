@@ -2,7 +2,7 @@ use crate::customglyph::BlockKey;
 use crate::glyphcache::CachedGlyph;
 use config::TextStyle;
 use std::rc::Rc;
-use wezterm_font::shaper::GlyphInfo;
+use wezterm_font::shaper::{FallbackIdx, GlyphInfo};
 use wezterm_font::units::*;
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
@@ -25,6 +25,8 @@ pub struct ShapedInfo {
     pub glyph: Rc<CachedGlyph>,
     pub pos: GlyphPosition,
     pub block_key: Option<BlockKey>,
+    /// The face that the glyph was shaped with
+    pub font_idx: FallbackIdx,
 }
 
 impl ShapedInfo {
@@ -47,6 +49,7 @@ impl ShapedInfo {
                 },
                 glyph: Rc::clone(glyph),
                 block_key: info.only_char.and_then(BlockKey::from_char),
+                font_idx: info.font_idx,
             });
         }
         pos
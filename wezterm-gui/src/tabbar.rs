@@ -691,6 +691,17 @@ pub fn parse_status_text(text: &str, default_cell: CellAttributes) -> Line {
                         }
                         Sgr::StrikeThrough(strike) => {
                             pen.set_strikethrough(strike);
+                            pen.set_double_strikethrough(false);
+                        }
+                        Sgr::DoubleStrikeThrough => {
+                            pen.set_strikethrough(true);
+                            pen.set_double_strikethrough(true);
+                        }
+                        Sgr::StrikeThroughColor(col) => {
+                            pen.set_strikethrough_color(col);
+                        }
+                        Sgr::OverlineColor(col) => {
+                            pen.set_overline_color(col);
                         }
                         Sgr::Foreground(col) => {
                             if let ColorSpec::Default = col {
@@ -158,6 +158,7 @@ pub enum UIItemType {
     AboveScrollThumb,
     ScrollThumb,
     BelowScrollThumb,
+    ScrollbarMarker(StableRowIndex),
     Split(PositionedSplit),
 }
 
@@ -406,6 +407,7 @@ pub struct TermWindow {
     tab_state: RefCell<HashMap<TabId, TabState>>,
     pane_state: RefCell<HashMap<PaneId, PaneState>>,
     semantic_zones: HashMap<PaneId, SemanticZoneCache>,
+    scrollbar_markers: HashMap<PaneId, ScrollbarMarkerCache>,
 
     window_background: Vec<LoadedBackgroundLayer>,
 
@@ -781,6 +783,7 @@ impl TermWindow {
             scheduled_animation: RefCell::new(None),
             allow_images: AllowImage::Yes,
             semantic_zones: HashMap::new(),
+            scrollbar_markers: HashMap::new(),
             ui_items: vec![],
             dragging: None,
             last_ui_item: None,
@@ -1038,6 +1041,11 @@ impl TermWindow {
                 Ok(true)
             }
             WindowEvent::DroppedFile(paths) => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    if crate::overlay::sftp::accept_dropped_files(pane.pane_id(), &paths) {
+                        return Ok(true);
+                    }
+                }
                 let pane = match self.get_active_pane_or_overlay() {
                     Some(pane) => pane,
                     None => return Ok(true),
@@ -1297,6 +1305,9 @@ impl TermWindow {
                 MuxNotification::SaveToDownloads { .. } => {
                     // Handled by frontend
                 }
+                MuxNotification::SshAgentConfirmation { .. } => {
+                    // Handled by frontend
+                }
                 MuxNotification::PaneFocused(_) => {
                     // Also handled by clientpane
                     self.update_title_post_status();
@@ -1308,9 +1319,12 @@ impl TermWindow {
                 MuxNotification::TabTitleChanged { .. } => {
                     self.update_title_post_status();
                 }
+                MuxNotification::PaneRemoved(pane_id) => {
+                    self.scrollbar_markers.remove(&pane_id);
+                }
                 MuxNotification::PaneAdded(_)
+                | MuxNotification::ScreenshotRequested { .. }
                 | MuxNotification::WorkspaceRenamed { .. }
-                | MuxNotification::PaneRemoved(_)
                 | MuxNotification::WindowWorkspaceChanged(_)
                 | MuxNotification::ActiveWorkspaceChanged(_)
                 | MuxNotification::Empty
@@ -1521,6 +1535,8 @@ impl TermWindow {
             }
             | MuxNotification::AssignClipboard { .. }
             | MuxNotification::SaveToDownloads { .. }
+            | MuxNotification::SshAgentConfirmation { .. }
+            | MuxNotification::ScreenshotRequested { .. }
             | MuxNotification::WindowCreated(_)
             | MuxNotification::ActiveWorkspaceChanged(_)
             | MuxNotification::WorkspaceRenamed { .. }
@@ -2367,6 +2383,47 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    fn show_sftp_browser(&mut self) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+
+        let sftp = mux::sftp::sftp_for_pane(&pane);
+        let cwd = mux::sftp::remote_cwd(&pane);
+
+        let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
+            crate::overlay::sftp::sftp_browser(pane_id, term, sftp, cwd)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    /// Asks the user whether the built-in ssh agent may use a key
+    /// on behalf of a client
+    pub fn show_ssh_agent_confirmation(
+        &mut self,
+        id: mux::ssh_agent::ConfirmationId,
+        message: String,
+    ) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => {
+                mux::ssh_agent::resolve_confirmation(id, false);
+                return;
+            }
+        };
+
+        let (overlay, future) = start_overlay_pane(self, &pane, move |_pane_id, mut term| {
+            let allowed =
+                crate::overlay::confirm::run_confirmation(&message, &mut term).unwrap_or(false);
+            mux::ssh_agent::resolve_confirmation(id, allowed);
+            Ok(())
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
     fn show_tab_navigator(&mut self) {
         let mux = Mux::get();
         let active_tab_idx = match mux.get_window(self.mux_window_id) {
@@ -2497,6 +2554,49 @@ impl TermWindow {
         &cache.zones
     }
 
+    /// Returns the set of markers to draw on the scrollbar track
+    /// for the specified pane
+    fn get_scrollbar_markers(&mut self, pane: &Arc<dyn Pane>) -> Vec<ScrollbarMarker> {
+        let mut markers = vec![];
+
+        if let Some(copy) = pane.downcast_ref::<CopyOverlay>() {
+            markers.extend(
+                copy.search_result_rows()
+                    .into_iter()
+                    .map(|row| ScrollbarMarker {
+                        row,
+                        kind: ScrollbarMarkerKind::SearchMatch,
+                    }),
+            );
+        }
+
+        markers.extend(
+            self.get_semantic_prompt_zones(pane)
+                .iter()
+                .map(|&row| ScrollbarMarker {
+                    row,
+                    kind: ScrollbarMarkerKind::Prompt,
+                }),
+        );
+
+        let patterns = self.config.scroll_bar_error_patterns.clone();
+        let cache = self
+            .scrollbar_markers
+            .entry(pane.pane_id())
+            .or_insert_with(ScrollbarMarkerCache::default);
+        cache.update(&**pane, &patterns);
+        markers.extend(cache.marks().map(|row| ScrollbarMarker {
+            row,
+            kind: ScrollbarMarkerKind::Mark,
+        }));
+        markers.extend(cache.errors().map(|row| ScrollbarMarker {
+            row,
+            kind: ScrollbarMarkerKind::Error,
+        }));
+
+        markers
+    }
+
     fn scroll_to_prompt(&mut self, amount: isize, pane: &Arc<dyn Pane>) -> anyhow::Result<()> {
         let dims = pane.get_dimensions();
         let position = self
@@ -2777,6 +2877,7 @@ impl TermWindow {
             ScrollToBottom => self.scroll_to_bottom(pane),
             ShowTabNavigator => self.show_tab_navigator(),
             ShowDebugOverlay => self.show_debug_overlay(),
+            ShowSftpBrowser => self.show_sftp_browser(),
             ShowLauncher => self.show_launcher(),
             ShowLauncherArgs(args) => {
                 let title = args.title.clone().unwrap_or("Launcher".to_string());
@@ -43,6 +43,7 @@ impl super::TermWindow {
             | UIItemType::AboveScrollThumb
             | UIItemType::BelowScrollThumb
             | UIItemType::ScrollThumb
+            | UIItemType::ScrollbarMarker(_)
             | UIItemType::Split(_) => {}
         }
     }
@@ -54,6 +55,7 @@ impl super::TermWindow {
             | UIItemType::AboveScrollThumb
             | UIItemType::BelowScrollThumb
             | UIItemType::ScrollThumb
+            | UIItemType::ScrollbarMarker(_)
             | UIItemType::Split(_) => {}
         }
     }
@@ -376,6 +378,9 @@ impl super::TermWindow {
             UIItemType::BelowScrollThumb => {
                 self.mouse_event_below_scroll_thumb(item, pane, event, context);
             }
+            UIItemType::ScrollbarMarker(row) => {
+                self.mouse_event_scrollbar_marker(row, pane, event, context);
+            }
             UIItemType::Split(split) => {
                 self.mouse_event_split(item, split, event, context);
             }
@@ -613,6 +618,26 @@ impl super::TermWindow {
         context.set_cursor(Some(MouseCursor::Arrow));
     }
 
+    pub fn mouse_event_scrollbar_marker(
+        &mut self,
+        row: StableRowIndex,
+        pane: Arc<dyn Pane>,
+        event: MouseEvent,
+        context: &dyn WindowOps,
+    ) {
+        if let WMEK::Press(MousePress::Left) = event.kind {
+            let dims = pane.get_dimensions();
+            // Center the marked row in the viewport
+            self.set_viewport(
+                pane.pane_id(),
+                Some(row.saturating_sub(dims.viewport_rows as StableRowIndex / 2)),
+                dims,
+            );
+            context.invalidate();
+        }
+        context.set_cursor(Some(MouseCursor::Arrow));
+    }
+
     pub fn mouse_event_scroll_thumb(
         &mut self,
         item: UIItem,
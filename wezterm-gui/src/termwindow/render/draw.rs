@@ -8,6 +8,7 @@ use ::window::glium::uniforms::{
 };
 use ::window::glium::{BlendingFunction, LinearBlendingFactor, Surface};
 use config::FreeTypeLoadTarget;
+use std::rc::Rc;
 
 impl crate::TermWindow {
     pub fn call_draw(&mut self, frame: &mut RenderFrame) -> anyhow::Result<()> {
@@ -18,15 +19,32 @@ impl crate::TermWindow {
     }
 
     fn call_draw_webgpu(&mut self) -> anyhow::Result<()> {
-        use crate::termwindow::webgpu::WebGpuTexture;
-
-        let webgpu = self.webgpu.as_mut().unwrap();
-        let render_state = self.render_state.as_ref().unwrap();
-
+        let webgpu = Rc::clone(self.webgpu.as_ref().unwrap());
         let output = webgpu.surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let commands = self.encode_draw_webgpu(&view)?;
+
+        // submit will accept anything that implements IntoIter
+        webgpu.queue.submit(std::iter::once(commands));
+        output.present();
+
+        Ok(())
+    }
+
+    /// Encodes the commands that render the current quads into `view`,
+    /// which must have the same format as the surface
+    pub fn encode_draw_webgpu(
+        &mut self,
+        view: &wgpu::TextureView,
+    ) -> anyhow::Result<wgpu::CommandBuffer> {
+        use crate::termwindow::webgpu::WebGpuTexture;
+
+        let webgpu = self.webgpu.as_ref().unwrap();
+        let render_state = self.render_state.as_ref().unwrap();
+
         let mut encoder = webgpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -76,6 +94,15 @@ impl crate::TermWindow {
             foreground_text_hsb.brightness,
         ];
 
+        let text_gamma = [
+            self.config.text_gamma.light_on_dark,
+            self.config.text_gamma.dark_on_light,
+        ];
+        let text_contrast = [
+            self.config.text_contrast.light_on_dark,
+            self.config.text_contrast.dark_on_light,
+        ];
+
         let milliseconds = self.created.elapsed().as_millis() as u32;
         let projection = euclid::Transform3D::<f32, f32, f32>::ortho(
             -(self.dimensions.pixel_width as f32) / 2.0,
@@ -98,7 +125,7 @@ impl crate::TermWindow {
                     let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: Some("Render Pass"),
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view,
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: if cleared {
@@ -124,6 +151,8 @@ impl crate::TermWindow {
                         foreground_text_hsb,
                         milliseconds,
                         projection,
+                        text_gamma,
+                        text_contrast,
                     });
 
                     render_pass.set_pipeline(&webgpu.render_pipeline);
@@ -142,14 +171,12 @@ impl crate::TermWindow {
             }
         }
 
-        // submit will accept anything that implements IntoIter
-        webgpu.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
+        Ok(encoder.finish())
     }
 
-    fn call_draw_glium(&mut self, frame: &mut glium::Frame) -> anyhow::Result<()> {
+    /// Renders the current quads into `frame`, which may be either the
+    /// window or an offscreen framebuffer
+    pub fn call_draw_glium<S: Surface>(&mut self, frame: &mut S) -> anyhow::Result<()> {
         use window::glium::texture::SrgbTexture2d;
 
         let gl_state = self.render_state.as_ref().unwrap();
@@ -228,6 +255,15 @@ impl crate::TermWindow {
             foreground_text_hsb.brightness,
         );
 
+        let text_gamma = (
+            self.config.text_gamma.light_on_dark,
+            self.config.text_gamma.dark_on_light,
+        );
+        let text_contrast = (
+            self.config.text_contrast.light_on_dark,
+            self.config.text_contrast.dark_on_light,
+        );
+
         let milliseconds = self.created.elapsed().as_millis() as u32;
 
         let cursor_blink: ColorEaseUniform = (*self.cursor_blink_state.borrow()).into();
@@ -250,6 +286,8 @@ impl crate::TermWindow {
                     uniforms.add("foreground_text_hsb", &foreground_text_hsb);
                     uniforms.add("subpixel_aa", &subpixel_aa);
                     uniforms.add("milliseconds", &milliseconds);
+                    uniforms.add("text_gamma", &text_gamma);
+                    uniforms.add("text_contrast", &text_contrast);
                     uniforms.add_struct("cursor_blink", &cursor_blink);
                     uniforms.add_struct("blink", &blink);
                     uniforms.add_struct("rapid_blink", &rapid_blink);
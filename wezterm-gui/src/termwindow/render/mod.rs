@@ -29,7 +29,7 @@ use termwiz::hyperlink::Hyperlink;
 use termwiz::surface::{CursorShape, CursorVisibility, SequenceNo};
 use wezterm_font::shaper::PresentationWidth;
 use wezterm_font::units::{IntPixelLength, PixelLength};
-use wezterm_font::{ClearShapeCache, GlyphInfo, LoadedFont};
+use wezterm_font::{ClearShapeCache, FallbackIdx, GlyphInfo, LoadedFont};
 use wezterm_term::color::{ColorAttribute, ColorPalette};
 use wezterm_term::{CellAttributes, Line, StableRowIndex};
 use window::color::LinearRgba;
@@ -41,6 +41,7 @@ pub mod fancy_tab_bar;
 pub mod paint;
 pub mod pane;
 pub mod screen_line;
+pub mod screenshot;
 pub mod split;
 pub mod tab_bar;
 pub mod window_buttons;
@@ -109,14 +110,93 @@ pub struct LineToElementShapeItem {
 }
 
 pub struct LineToElementShape {
+    /// The underline, along with any overline or strikethrough
+    /// that is drawn using the underline color
     pub underline_tex_rect: TextureRect,
+    /// The overline, when it has a color that differs from the underline
+    pub overline_tex_rect: Option<TextureRect>,
+    /// The strikethrough, when it has a color that differs from the underline
+    pub strikethrough_tex_rect: Option<TextureRect>,
     pub fg_color: LinearRgba,
     pub bg_color: LinearRgba,
-    pub underline_color: LinearRgba,
+    pub decoration_colors: DecorationColors,
     pub x_pos: f32,
     pub pixel_width: f32,
     pub glyph_info: Rc<Vec<ShapedInfo>>,
     pub cluster: CellCluster,
+    /// When the cluster was formed from several runs of text that
+    /// differ only in their foreground color, holds the colors of
+    /// each of those runs.  Empty otherwise.
+    pub color_runs: Vec<ColorRun>,
+}
+
+/// The colors used to draw the line decorations of a cell
+#[derive(Clone, Copy, Debug)]
+pub struct DecorationColors {
+    pub underline: LinearRgba,
+    pub overline: LinearRgba,
+    pub strikethrough: LinearRgba,
+}
+
+/// The colors of a range of cells within a LineToElementShape
+pub struct ColorRun {
+    pub cells: Range<usize>,
+    pub fg_color: LinearRgba,
+    pub decoration_colors: DecorationColors,
+}
+
+impl LineToElementShape {
+    /// Returns the decoration colors to use for the cell
+    pub fn decoration_colors_for_cell(&self, cell_idx: usize) -> DecorationColors {
+        self.color_runs
+            .iter()
+            .find(|run| run.cells.contains(&cell_idx))
+            .map(|run| run.decoration_colors)
+            .unwrap_or(self.decoration_colors)
+    }
+
+    /// Splits the pixel range `range` according to the foreground
+    /// colors of the cells that it covers, returning each of the
+    /// resulting ranges together with its foreground color.
+    /// The first and last color runs extend out to include any
+    /// portion of the range that overhangs the cluster.
+    pub fn fg_color_strips(
+        &self,
+        range: Range<f32>,
+        left_pixel_x: f32,
+        cell_width: f32,
+    ) -> Vec<(Range<f32>, LinearRgba)> {
+        if self.color_runs.is_empty() {
+            return vec![(range, self.fg_color)];
+        }
+
+        let last = self.color_runs.len() - 1;
+        self.color_runs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, run)| {
+                let start = if idx == 0 {
+                    range.start
+                } else {
+                    range
+                        .start
+                        .max(left_pixel_x + run.cells.start as f32 * cell_width)
+                };
+                let end = if idx == last {
+                    range.end
+                } else {
+                    range
+                        .end
+                        .min(left_pixel_x + run.cells.end as f32 * cell_width)
+                };
+                if end > start {
+                    Some((start..end, run.fg_color))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
 }
 
 pub struct RenderScreenLineResult {
@@ -216,10 +296,20 @@ pub struct ComputeCellFgBgResult {
 pub struct ClusterStyleCache<'a> {
     attrs: &'a CellAttributes,
     style: &'a TextStyle,
-    underline_tex_rect: TextureRect,
+    decorations: Option<ClusterDecorations>,
     fg_color: LinearRgba,
     bg_color: LinearRgba,
-    underline_color: LinearRgba,
+    decoration_colors: DecorationColors,
+}
+
+/// The line sprites for the decorations of a cluster.  Their geometry
+/// comes from the face that the cluster was shaped with.
+#[derive(Clone, Debug)]
+pub struct ClusterDecorations {
+    font_idx: FallbackIdx,
+    underline_tex_rect: TextureRect,
+    overline_tex_rect: Option<TextureRect>,
+    strikethrough_tex_rect: Option<TextureRect>,
 }
 
 impl crate::TermWindow {
@@ -904,7 +994,7 @@ impl crate::TermWindow {
     }
 }
 
-fn resolve_fg_color_attr(
+pub(crate) fn resolve_fg_color_attr(
     attrs: &CellAttributes,
     fg: ColorAttribute,
     palette: &ColorPalette,
@@ -34,8 +34,55 @@ impl crate::TermWindow {
             }
         }
 
+        self.paint_with_retries(|tw| tw.paint_pass());
+        log::debug!("paint_impl before call_draw elapsed={:?}", start.elapsed());
+
+        self.call_draw(frame).ok();
+        self.last_frame_duration = start.elapsed();
+        log::debug!(
+            "paint_impl elapsed={:?}, fps={}",
+            self.last_frame_duration,
+            self.fps
+        );
+        metrics::histogram!("gui.paint.impl").record(self.last_frame_duration);
+        metrics::histogram!("gui.paint.impl.rate").record(1.);
+
+        // If self.has_animation is some, then the last render detected
+        // image attachments with multiple frames, so we also need to
+        // invalidate the viewport when the next frame is due
+        if self.focused.is_some() {
+            if let Some(next_due) = *self.has_animation.borrow() {
+                let prior = self.scheduled_animation.borrow_mut().take();
+                match prior {
+                    Some(prior) if prior <= next_due => {
+                        // Already due before that time
+                    }
+                    _ => {
+                        self.scheduled_animation.borrow_mut().replace(next_due);
+                        let window = self.window.clone().take().unwrap();
+                        promise::spawn::spawn(async move {
+                            Timer::at(next_due).await;
+                            let win = window.clone();
+                            window.notify(TermWindowNotif::Apply(Box::new(move |tw| {
+                                tw.scheduled_animation.borrow_mut().take();
+                                win.invalidate();
+                            })));
+                        })
+                        .detach();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls `paint` to populate the quads, retrying with a larger
+    /// texture atlas or a cleared shape cache as needed
+    pub fn paint_with_retries<F>(&mut self, paint: F)
+    where
+        F: Fn(&mut Self) -> anyhow::Result<()>,
+    {
         'pass: for pass in 0.. {
-            match self.paint_pass() {
+            match paint(self) {
                 Ok(_) => match self.render_state.as_mut().unwrap().allocated_more_quads() {
                     Ok(allocated) => {
                         if !allocated {
@@ -103,44 +150,6 @@ impl crate::TermWindow {
                 }
             }
         }
-        log::debug!("paint_impl before call_draw elapsed={:?}", start.elapsed());
-
-        self.call_draw(frame).ok();
-        self.last_frame_duration = start.elapsed();
-        log::debug!(
-            "paint_impl elapsed={:?}, fps={}",
-            self.last_frame_duration,
-            self.fps
-        );
-        metrics::histogram!("gui.paint.impl").record(self.last_frame_duration);
-        metrics::histogram!("gui.paint.impl.rate").record(1.);
-
-        // If self.has_animation is some, then the last render detected
-        // image attachments with multiple frames, so we also need to
-        // invalidate the viewport when the next frame is due
-        if self.focused.is_some() {
-            if let Some(next_due) = *self.has_animation.borrow() {
-                let prior = self.scheduled_animation.borrow_mut().take();
-                match prior {
-                    Some(prior) if prior <= next_due => {
-                        // Already due before that time
-                    }
-                    _ => {
-                        self.scheduled_animation.borrow_mut().replace(next_due);
-                        let window = self.window.clone().take().unwrap();
-                        promise::spawn::spawn(async move {
-                            Timer::at(next_due).await;
-                            let win = window.clone();
-                            window.notify(TermWindowNotif::Apply(Box::new(move |tw| {
-                                tw.scheduled_animation.borrow_mut().take();
-                                win.invalidate();
-                            })));
-                        })
-                        .detach();
-                    }
-                }
-            }
-        }
     }
 
     pub fn paint_modal(&mut self) -> anyhow::Result<()> {
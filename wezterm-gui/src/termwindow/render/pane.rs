@@ -5,15 +5,16 @@ use crate::termwindow::render::{
     same_hyperlink, CursorProperties, LineQuadCacheKey, LineQuadCacheValue, LineToEleShapeCacheKey,
     RenderScreenLineParams,
 };
-use crate::termwindow::{ScrollHit, UIItem, UIItemType};
+use crate::termwindow::{ScrollHit, ScrollbarMarker, ScrollbarMarkerKind, UIItem, UIItemType};
 use ::window::bitmaps::TextureRect;
 use ::window::DeadKeyStatus;
 use anyhow::Context;
-use config::VisualBellTarget;
+use config::{RgbaColor, VisualBellTarget};
 use mux::pane::{PaneId, WithPaneLines};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::PositionedPane;
 use ordered_float::NotNan;
+use std::collections::BTreeMap;
 use std::time::Instant;
 use wezterm_dynamic::Value;
 use wezterm_term::color::{ColorAttribute, ColorPalette};
@@ -29,6 +30,87 @@ impl crate::TermWindow {
         self.render_element(&computed, gl_state, None)
     }
 
+    fn paint_scrollbar_markers(
+        &mut self,
+        pos: &PositionedPane,
+        layers: &mut TripleLayerQuadAllocator,
+        thumb_x: usize,
+        thumb_y_offset: usize,
+        width: f32,
+        max_thumb_height: usize,
+    ) -> anyhow::Result<()> {
+        let markers = self.get_scrollbar_markers(&pos.pane);
+        if markers.is_empty() {
+            return Ok(());
+        }
+
+        let marker_height = (self.render_metrics.cell_size.height as usize / 4).max(2);
+
+        // Several markers may land on the same pixel row; keep only
+        // the most important one for each position.
+        let mut by_pixel: BTreeMap<usize, ScrollbarMarker> = BTreeMap::new();
+        for marker in markers {
+            let top =
+                ScrollHit::marker_top(&*pos.pane, marker.row, max_thumb_height, marker_height);
+            let replace = by_pixel
+                .get(&top)
+                .map(|existing| marker.kind > existing.kind)
+                .unwrap_or(true);
+            if replace {
+                by_pixel.insert(top, marker);
+            }
+        }
+
+        let palette = &self.config.resolved_palette;
+        let resolve = |color: &Option<RgbaColor>, default: LinearRgba| {
+            color.as_deref().map(|c| c.to_linear()).unwrap_or(default)
+        };
+        let search_color = resolve(
+            &palette.scrollbar_search_match,
+            LinearRgba::with_srgba(0xe5, 0xc0, 0x7b, 0xff),
+        );
+        let prompt_color = resolve(
+            &palette.scrollbar_prompt,
+            LinearRgba::with_srgba(0x61, 0xaf, 0xef, 0xff),
+        );
+        let mark_color = resolve(
+            &palette.scrollbar_mark,
+            LinearRgba::with_srgba(0xc6, 0x78, 0xdd, 0xff),
+        );
+        let error_color = resolve(
+            &palette.scrollbar_error,
+            LinearRgba::with_srgba(0xe0, 0x6c, 0x75, 0xff),
+        );
+
+        for (top, marker) in by_pixel {
+            let color = match marker.kind {
+                ScrollbarMarkerKind::SearchMatch => search_color,
+                ScrollbarMarkerKind::Prompt => prompt_color,
+                ScrollbarMarkerKind::Mark => mark_color,
+                ScrollbarMarkerKind::Error => error_color,
+            };
+            let y = thumb_y_offset + top;
+
+            self.ui_items.push(UIItem {
+                x: thumb_x,
+                width: width as usize,
+                y,
+                height: marker_height,
+                item_type: UIItemType::ScrollbarMarker(marker.row),
+            });
+
+            self.filled_rectangle(
+                layers,
+                2,
+                euclid::rect(thumb_x as f32, y as f32, width, marker_height as f32),
+                color,
+            )
+            .context("filled_rectangle")?;
+        }
+
+        Ok(())
+    }
+
     pub fn paint_pane(
         &mut self,
         pos: &PositionedPane,
@@ -257,13 +339,6 @@ impl crate::TermWindow {
                 height: info.top,
                 item_type: UIItemType::AboveScrollThumb,
             });
-            self.ui_items.push(UIItem {
-                x: thumb_x,
-                width: padding as usize,
-                y: abs_thumb_top,
-                height: thumb_size,
-                item_type: UIItemType::ScrollThumb,
-            });
             self.ui_items.push(UIItem {
                 x: thumb_x,
                 width: padding as usize,
@@ -287,6 +362,30 @@ impl crate::TermWindow {
                 color,
             )
             .context("filled_rectangle")?;
+
+            if self.config.show_scroll_bar_markers {
+                self.paint_scrollbar_markers(
+                    pos,
+                    layers,
+                    thumb_x,
+                    thumb_y_offset,
+                    padding,
+                    self.dimensions.pixel_height.saturating_sub(
+                        thumb_y_offset + border.bottom.get() + bottom_bar_height as usize,
+                    ),
+                )?;
+            }
+
+            // UI items are hit-tested in reverse order, so the thumb is
+            // registered after the markers in order to take precedence
+            // over any that overlap it when dragging
+            self.ui_items.push(UIItem {
+                x: thumb_x,
+                width: padding as usize,
+                y: abs_thumb_top,
+                height: thumb_size,
+                item_type: UIItemType::ScrollThumb,
+            });
         }
 
         let (selrange, rectangular) = {
@@ -1,8 +1,10 @@
+use crate::glyphcache::StrikeThrough;
 use crate::quad::{QuadTrait, TripleLayerQuadAllocator, TripleLayerQuadAllocatorTrait};
+use crate::renderstate::RenderState;
 use crate::termwindow::render::{
-    resolve_fg_color_attr, same_hyperlink, update_next_frame_time, ClusterStyleCache,
-    ComputeCellFgBgParams, ComputeCellFgBgResult, LineToElementParams, LineToElementShape,
-    RenderScreenLineParams, RenderScreenLineResult,
+    resolve_fg_color_attr, same_hyperlink, update_next_frame_time, ClusterDecorations,
+    ClusterStyleCache, ColorRun, ComputeCellFgBgParams, ComputeCellFgBgResult, DecorationColors,
+    LineToElementParams, LineToElementShape, RenderScreenLineParams, RenderScreenLineResult,
 };
 use crate::termwindow::LineToElementShapeItem;
 use ::window::DeadKeyStatus;
@@ -12,11 +14,13 @@ use std::ops::Range;
 use std::rc::Rc;
 use std::time::Instant;
 use termwiz::cell::{unicode_column_width, Blink};
+use termwiz::cellcluster::CellCluster;
 use termwiz::color::LinearRgba;
 use termwiz::surface::CursorShape;
 use wezterm_bidi::Direction;
+use wezterm_font::FallbackIdx;
 use wezterm_term::color::ColorAttribute;
-use wezterm_term::CellAttributes;
+use wezterm_term::{CellAttributes, Underline};
 
 impl crate::TermWindow {
     /// "Render" a line of the terminal screen into the vertex buffer.
@@ -257,12 +261,16 @@ impl crate::TermWindow {
                 }
             }
 
-            // Underlines
-            if item.underline_tex_rect != params.white_space {
+            // Underlines, overlines and strikethrough
+            let underline_tex_rect =
+                Some(item.underline_tex_rect).filter(|rect| *rect != params.white_space);
+            if underline_tex_rect.is_some()
+                || item.overline_tex_rect.is_some()
+                || item.strikethrough_tex_rect.is_some()
+            {
                 // Draw one per cell, otherwise curly underlines
                 // stretch across the whole span
                 for i in 0..cluster_width {
-                    let mut quad = layers.allocate(0).context("layers.allocate(0)")?;
                     let x = gl_x
                         + params.left_pixel_x
                         + if params.use_pixel_positioning {
@@ -271,12 +279,25 @@ impl crate::TermWindow {
                             phys(cluster.first_cell_idx + i, num_cols, direction) as f32
                                 * cell_width
                         };
-
-                    quad.set_position(x, pos_y, x + cell_width, pos_y + cell_height);
-                    quad.set_hsv(hsv);
-                    quad.set_has_color(false);
-                    quad.set_texture(item.underline_tex_rect);
-                    quad.set_fg_color(item.underline_color);
+                    let colors = item.decoration_colors_for_cell(cluster.first_cell_idx + i);
+
+                    let decorations = [
+                        (underline_tex_rect, colors.underline),
+                        (item.overline_tex_rect, colors.overline),
+                        (item.strikethrough_tex_rect, colors.strikethrough),
+                    ];
+                    for (tex_rect, color) in IntoIterator::into_iter(decorations) {
+                        let tex_rect = match tex_rect {
+                            Some(tex_rect) => tex_rect,
+                            None => continue,
+                        };
+                        let mut quad = layers.allocate(0).context("layers.allocate(0)")?;
+                        quad.set_position(x, pos_y, x + cell_width, pos_y + cell_height);
+                        quad.set_hsv(hsv);
+                        quad.set_has_color(false);
+                        quad.set_texture(tex_rect);
+                        quad.set_fg_color(color);
+                    }
                 }
             }
         }
@@ -592,12 +613,15 @@ impl crate::TermWindow {
                         let (la, lb, lc) = range3(&left, &selection_pixel_range);
                         let (ra, rb, rc) = range3(&right, &selection_pixel_range);
 
-                        // and render each of these strips
-                        for range in [la, lb, lc, mid, ra, rb, rc] {
-                            if range.is_empty() {
-                                continue;
-                            }
-
+                        // and render each of these strips, further divided
+                        // according to the foreground color of the cells
+                        for (range, fg_color) in
+                            IntoIterator::into_iter([la, lb, lc, mid, ra, rb, rc])
+                                .filter(|range| !range.is_empty())
+                                .flat_map(|range| {
+                                    item.fg_color_strips(range, params.left_pixel_x, cell_width)
+                                })
+                        {
                             let is_cursor = cursor_range_pixels.contains(&range.start);
                             let selected =
                                 !is_cursor && selection_pixel_range.contains(&range.start);
@@ -611,7 +635,7 @@ impl crate::TermWindow {
                             } = self.compute_cell_fg_bg(ComputeCellFgBgParams {
                                 cursor: if is_cursor { Some(params.cursor) } else { None },
                                 selected,
-                                fg_color: item.fg_color,
+                                fg_color,
                                 bg_color: item.bg_color,
                                 is_active_pane: params.is_active,
                                 config: params.config,
@@ -717,6 +741,94 @@ impl crate::TermWindow {
         })
     }
 
+    /// Computes the foreground, background and decoration colors
+    /// to use for text with the specified attributes
+    fn compute_cluster_colors(
+        &self,
+        params: &LineToElementParams,
+        attrs: &CellAttributes,
+        style: &TextStyle,
+        expires: &mut Option<Instant>,
+    ) -> (LinearRgba, LinearRgba, DecorationColors) {
+        let bg_is_default = attrs.background() == ColorAttribute::Default;
+        let bg_color = params.palette.resolve_bg(attrs.background()).to_linear();
+
+        let fg_color = resolve_fg_color_attr(
+            &attrs,
+            attrs.foreground(),
+            &params.palette,
+            &params.config,
+            style,
+        );
+        let (fg_color, bg_color, bg_is_default) = {
+            let mut fg = fg_color;
+            let mut bg = bg_color;
+            let mut bg_default = bg_is_default;
+
+            // Check the line reverse_video flag and flip.
+            if attrs.reverse() == !params.reverse_video {
+                std::mem::swap(&mut fg, &mut bg);
+                bg_default = false;
+            }
+
+            // Check for blink, and if this is the "not-visible"
+            // part of blinking then set fg = bg.  This is a cheap
+            // means of getting it done without impacting other
+            // features.
+            let blink_rate = match attrs.blink() {
+                Blink::None => None,
+                Blink::Slow => Some((params.config.text_blink_rate, self.blink_state.borrow_mut())),
+                Blink::Rapid => Some((
+                    params.config.text_blink_rate_rapid,
+                    self.rapid_blink_state.borrow_mut(),
+                )),
+            };
+            if let Some((blink_rate, mut colorease)) = blink_rate {
+                if blink_rate != 0 {
+                    let (intensity, next) = colorease.intensity_continuous();
+
+                    let (r1, g1, b1, a) = bg.tuple();
+                    let (r, g, b, _a) = fg.tuple();
+                    fg = LinearRgba::with_components(
+                        r1 + (r - r1) * intensity,
+                        g1 + (g - g1) * intensity,
+                        b1 + (b - b1) * intensity,
+                        a,
+                    );
+
+                    update_next_frame_time(expires, Some(next));
+                    self.update_next_frame_time(Some(next));
+                }
+            }
+
+            (fg, bg, bg_default)
+        };
+
+        let decoration_color = |color: ColorAttribute| match color {
+            ColorAttribute::Default => fg_color,
+            c => resolve_fg_color_attr(&attrs, c, &params.palette, &params.config, style),
+        };
+        let decoration_colors = DecorationColors {
+            underline: decoration_color(attrs.underline_color()),
+            overline: decoration_color(attrs.overline_color()),
+            strikethrough: decoration_color(attrs.strikethrough_color()),
+        };
+
+        let (bg_r, bg_g, bg_b, _) = bg_color.tuple();
+        let bg_color = LinearRgba::with_components(
+            bg_r,
+            bg_g,
+            bg_b,
+            if params.window_is_transparent && bg_is_default {
+                0.0
+            } else {
+                params.config.text_background_opacity
+            },
+        );
+
+        (fg_color, bg_color, decoration_colors)
+    }
+
     fn build_line_element_shape(
         &self,
         params: LineToElementParams,
@@ -739,6 +851,18 @@ impl crate::TermWindow {
             params.line.cluster(bidi_hint)
         };
 
+        // Clusters that differ only in their foreground color can be
+        // shaped together so that ligatures can form across the color
+        // change.  The glyphs are recolored per cell when rendering.
+        let cell_clusters = if params.config.shape_across_color_changes && !bidi_enabled {
+            merge_color_runs(cell_clusters, params.reverse_video)
+        } else {
+            cell_clusters
+                .into_iter()
+                .map(|cluster| (cluster, vec![]))
+                .collect()
+        };
+
         let gl_state = self.render_state.as_ref().unwrap();
         let mut shaped = vec![];
         let mut last_style = None;
@@ -746,114 +870,46 @@ impl crate::TermWindow {
         let mut expires = None;
         let mut invalidate_on_hover_change = false;
 
-        for cluster in &cell_clusters {
+        for (cluster, runs) in &cell_clusters {
             if !matches!(last_style.as_ref(), Some(ClusterStyleCache{attrs,..}) if *attrs == &cluster.attrs)
             {
                 let attrs = &cluster.attrs;
                 let style = self.fonts.match_style(params.config, attrs);
-                let hyperlink = attrs.hyperlink();
-                let is_highlited_hyperlink =
-                    same_hyperlink(hyperlink, self.current_highlight.as_ref());
-                if hyperlink.is_some() {
+                if attrs.hyperlink().is_some() {
                     invalidate_on_hover_change = true;
                 }
-                // underline and strikethrough
-                let underline_tex_rect = gl_state
-                    .glyph_cache
-                    .borrow_mut()
-                    .cached_line_sprite(
-                        is_highlited_hyperlink,
-                        attrs.strikethrough(),
-                        attrs.underline(),
-                        attrs.overline(),
-                        &self.render_metrics,
-                    )?
-                    .texture_coords();
-                let bg_is_default = attrs.background() == ColorAttribute::Default;
-                let bg_color = params.palette.resolve_bg(attrs.background()).to_linear();
-
-                let fg_color = resolve_fg_color_attr(
-                    &attrs,
-                    attrs.foreground(),
-                    &params.palette,
-                    &params.config,
-                    style,
-                );
-                let (fg_color, bg_color, bg_is_default) = {
-                    let mut fg = fg_color;
-                    let mut bg = bg_color;
-                    let mut bg_default = bg_is_default;
-
-                    // Check the line reverse_video flag and flip.
-                    if attrs.reverse() == !params.reverse_video {
-                        std::mem::swap(&mut fg, &mut bg);
-                        bg_default = false;
-                    }
 
-                    // Check for blink, and if this is the "not-visible"
-                    // part of blinking then set fg = bg.  This is a cheap
-                    // means of getting it done without impacting other
-                    // features.
-                    let blink_rate = match attrs.blink() {
-                        Blink::None => None,
-                        Blink::Slow => {
-                            Some((params.config.text_blink_rate, self.blink_state.borrow_mut()))
-                        }
-                        Blink::Rapid => Some((
-                            params.config.text_blink_rate_rapid,
-                            self.rapid_blink_state.borrow_mut(),
-                        )),
-                    };
-                    if let Some((blink_rate, mut colorease)) = blink_rate {
-                        if blink_rate != 0 {
-                            let (intensity, next) = colorease.intensity_continuous();
-
-                            let (r1, g1, b1, a) = bg.tuple();
-                            let (r, g, b, _a) = fg.tuple();
-                            fg = LinearRgba::with_components(
-                                r1 + (r - r1) * intensity,
-                                g1 + (g - g1) * intensity,
-                                b1 + (b - b1) * intensity,
-                                a,
-                            );
-
-                            update_next_frame_time(&mut expires, Some(next));
-                            self.update_next_frame_time(Some(next));
-                        }
-                    }
-
-                    (fg, bg, bg_default)
-                };
-
-                let glyph_color = fg_color;
-                let underline_color = match attrs.underline_color() {
-                    ColorAttribute::Default => fg_color,
-                    c => resolve_fg_color_attr(&attrs, c, &params.palette, &params.config, style),
-                };
-
-                let (bg_r, bg_g, bg_b, _) = bg_color.tuple();
-                let bg_color = LinearRgba::with_components(
-                    bg_r,
-                    bg_g,
-                    bg_b,
-                    if params.window_is_transparent && bg_is_default {
-                        0.0
-                    } else {
-                        params.config.text_background_opacity
-                    },
-                );
+                let (fg_color, bg_color, decoration_colors) =
+                    self.compute_cluster_colors(&params, attrs, style, &mut expires);
 
                 last_style.replace(ClusterStyleCache {
                     attrs,
                     style,
-                    underline_tex_rect: underline_tex_rect.clone(),
+                    decorations: None,
                     bg_color,
-                    fg_color: glyph_color,
-                    underline_color,
+                    fg_color,
+                    decoration_colors,
                 });
             }
 
-            let style_params = last_style.as_ref().expect("we just set it up").clone();
+            let style_params = last_style.as_mut().expect("we just set it up");
+
+            let color_runs = runs
+                .iter()
+                .map(|(cells, attrs)| {
+                    let (fg_color, _bg_color, decoration_colors) = self.compute_cluster_colors(
+                        &params,
+                        attrs,
+                        style_params.style,
+                        &mut expires,
+                    );
+                    ColorRun {
+                        cells: cells.clone(),
+                        fg_color,
+                        decoration_colors,
+                    }
+                })
+                .collect();
 
             let glyph_info = self.cached_cluster_shape(
                 style_params.style,
@@ -867,15 +923,35 @@ impl crate::TermWindow {
                 .map(|info| info.glyph.x_advance.get() as f32)
                 .sum();
 
+            // The decorations follow the metrics of the face that
+            // the cluster was shaped with
+            let font_idx = glyph_info.first().map(|info| info.font_idx).unwrap_or(0);
+            let decorations = match &style_params.decorations {
+                Some(decorations) if decorations.font_idx == font_idx => decorations.clone(),
+                _ => {
+                    let decorations = self.cluster_decorations(
+                        &cluster.attrs,
+                        style_params.style,
+                        font_idx,
+                        gl_state,
+                    )?;
+                    style_params.decorations.replace(decorations.clone());
+                    decorations
+                }
+            };
+
             shaped.push(LineToElementShape {
-                underline_tex_rect: style_params.underline_tex_rect,
+                underline_tex_rect: decorations.underline_tex_rect,
+                overline_tex_rect: decorations.overline_tex_rect,
+                strikethrough_tex_rect: decorations.strikethrough_tex_rect,
                 bg_color: style_params.bg_color,
                 fg_color: style_params.fg_color,
-                underline_color: style_params.underline_color,
+                decoration_colors: style_params.decoration_colors,
                 pixel_width,
                 cluster: cluster.clone(),
                 glyph_info,
                 x_pos,
+                color_runs,
             });
 
             x_pos += pixel_width;
@@ -901,4 +977,268 @@ impl crate::TermWindow {
 
         Ok((shaped, invalidate_on_hover_change))
     }
+
+    /// Returns the line sprites for the underline, overline and
+    /// strikethrough of a cluster, drawn with the decoration metrics
+    /// of the face identified by `font_idx`
+    fn cluster_decorations(
+        &self,
+        attrs: &CellAttributes,
+        style: &TextStyle,
+        font_idx: FallbackIdx,
+        gl_state: &RenderState,
+    ) -> anyhow::Result<ClusterDecorations> {
+        let font = self.fonts.resolve_font(style)?;
+        let metrics = self
+            .render_metrics
+            .with_face_decorations(&self.fonts, &font.metrics_for_idx(font_idx)?);
+
+        let is_highlited_hyperlink =
+            same_hyperlink(attrs.hyperlink(), self.current_highlight.as_ref());
+        // underline, overline and strikethrough.
+        // Overlines and strikethrough that use a different color
+        // from the underline need their own sprites so that they
+        // can be tinted separately.
+        let strike_through = match (attrs.strikethrough(), attrs.double_strikethrough()) {
+            (false, _) => StrikeThrough::None,
+            (true, false) => StrikeThrough::Single,
+            (true, true) => StrikeThrough::Double,
+        };
+        let separate_overline =
+            attrs.overline() && attrs.overline_color() != attrs.underline_color();
+        let separate_strike = strike_through != StrikeThrough::None
+            && attrs.strikethrough_color() != attrs.underline_color();
+
+        let mut glyph_cache = gl_state.glyph_cache.borrow_mut();
+        let underline_tex_rect = glyph_cache
+            .cached_line_sprite(
+                is_highlited_hyperlink,
+                if separate_strike {
+                    StrikeThrough::None
+                } else {
+                    strike_through
+                },
+                attrs.underline(),
+                attrs.overline() && !separate_overline,
+                &metrics,
+            )?
+            .texture_coords();
+        let overline_tex_rect = if separate_overline {
+            Some(
+                glyph_cache
+                    .cached_line_sprite(
+                        false,
+                        StrikeThrough::None,
+                        Underline::None,
+                        true,
+                        &metrics,
+                    )?
+                    .texture_coords(),
+            )
+        } else {
+            None
+        };
+        let strikethrough_tex_rect = if separate_strike {
+            Some(
+                glyph_cache
+                    .cached_line_sprite(false, strike_through, Underline::None, false, &metrics)?
+                    .texture_coords(),
+            )
+        } else {
+            None
+        };
+
+        Ok(ClusterDecorations {
+            font_idx,
+            underline_tex_rect,
+            overline_tex_rect,
+            strikethrough_tex_rect,
+        })
+    }
+}
+
+/// Merges adjacent clusters whose attributes differ only in their
+/// foreground color, so that they can be shaped as a single run.
+/// Each resulting cluster is returned together with the cell ranges
+/// and attributes of the clusters that it was formed from; that list
+/// is empty for clusters that were not merged.
+fn merge_color_runs(
+    clusters: Vec<CellCluster>,
+    reverse_video: bool,
+) -> Vec<(CellCluster, Vec<(Range<usize>, CellAttributes)>)> {
+    fn cells(cluster: &CellCluster) -> Range<usize> {
+        cluster.first_cell_idx..cluster.first_cell_idx + cluster.width
+    }
+
+    let mut merged: Vec<(CellCluster, Vec<(Range<usize>, CellAttributes)>)> = vec![];
+    for cluster in clusters {
+        if let Some((last, runs)) = merged.last_mut() {
+            let prior = runs.last().map(|(_, attrs)| attrs).unwrap_or(&last.attrs);
+            if can_merge_color_run(last, prior, &cluster, reverse_video) {
+                if runs.is_empty() {
+                    runs.push((cells(last), last.attrs.clone()));
+                }
+                runs.push((cells(&cluster), cluster.attrs.clone()));
+                last.append(&cluster);
+                continue;
+            }
+        }
+        merged.push((cluster, vec![]));
+    }
+    merged
+}
+
+/// Returns true if `next` can be appended to `last`, whose final
+/// run of text has the attributes `prior`
+fn can_merge_color_run(
+    last: &CellCluster,
+    prior: &CellAttributes,
+    next: &CellCluster,
+    reverse_video: bool,
+) -> bool {
+    if last.presentation != next.presentation
+        || last.direction != Direction::LeftToRight
+        || next.direction != Direction::LeftToRight
+        || next.first_cell_idx != last.first_cell_idx + last.width
+    {
+        return false;
+    }
+
+    // When the colors are swapped, the foreground color
+    // is used to fill the background of the cells
+    if next.attrs.reverse() != reverse_video {
+        return false;
+    }
+
+    if next.attrs.foreground() == prior.foreground() {
+        // Either the attributes differ in some other way, or
+        // the clusters were split for some other reason
+        return false;
+    }
+
+    let mut attrs = next.attrs.clone();
+    attrs.set_foreground(prior.foreground());
+    attrs == *prior
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::cell::Intensity;
+    use termwiz::surface::{Line, SEQ_ZERO};
+    use wezterm_bidi::ParagraphDirectionHint;
+
+    fn colored(idx: u8) -> CellAttributes {
+        let mut attrs = CellAttributes::default();
+        attrs.set_foreground(ColorAttribute::PaletteIndex(idx));
+        attrs
+    }
+
+    fn make_line(runs: &[(&str, CellAttributes)]) -> Line {
+        let mut line = Line::new(SEQ_ZERO);
+        for (text, attrs) in runs {
+            line.append_line(Line::from_text(text, attrs, SEQ_ZERO, None), SEQ_ZERO);
+        }
+        line
+    }
+
+    fn summarize(
+        merged: &[(CellCluster, Vec<(Range<usize>, CellAttributes)>)],
+    ) -> Vec<(String, Vec<Range<usize>>)> {
+        merged
+            .iter()
+            .map(|(cluster, runs)| {
+                (
+                    cluster.text.clone(),
+                    runs.iter().map(|(range, _)| range.clone()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_foreground_changes() {
+        let red = colored(1);
+        let blue = colored(4);
+        let line = make_line(&[("=>", red.clone()), ("=", blue.clone()), ("=", red.clone())]);
+
+        let merged = merge_color_runs(line.cluster(None), false);
+        assert_eq!(
+            summarize(&merged),
+            vec![("=>==".to_string(), vec![0..2, 2..3, 3..4])]
+        );
+        let (cluster, runs) = &merged[0];
+        // The merged cluster keeps the attributes of its first run
+        assert_eq!(cluster.attrs, red);
+        assert_eq!(runs[1].1, blue);
+        assert_eq!(runs[2].1, red);
+    }
+
+    #[test]
+    fn does_not_merge_other_attribute_changes() {
+        let red = colored(1);
+        let mut bold_blue = colored(4);
+        bold_blue.set_intensity(Intensity::Bold);
+        let mut italic_red = colored(1);
+        italic_red.set_italic(true);
+
+        let line = make_line(&[("=>", red), ("=", bold_blue)]);
+        let merged = merge_color_runs(line.cluster(None), false);
+        assert_eq!(
+            summarize(&merged),
+            vec![("=>".to_string(), vec![]), ("=".to_string(), vec![])]
+        );
+
+        // The final run of a merged cluster is what the next
+        // cluster is compared against
+        let line = make_line(&[("=>", colored(1)), ("=", colored(4)), ("=", italic_red)]);
+        let merged = merge_color_runs(line.cluster(None), false);
+        assert_eq!(
+            summarize(&merged),
+            vec![
+                ("=>=".to_string(), vec![0..2, 2..3]),
+                ("=".to_string(), vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse_video() {
+        let mut red = colored(1);
+        red.set_reverse(true);
+        let mut blue = colored(4);
+        blue.set_reverse(true);
+        let line = make_line(&[("=>", red), ("=", blue)]);
+
+        // The foreground color of reversed cells fills their
+        // background, so they must not be merged
+        let merged = merge_color_runs(line.cluster(None), false);
+        assert_eq!(merged.len(), 2);
+
+        // ... unless reverse video swaps them back again
+        let merged = merge_color_runs(line.cluster(None), true);
+        assert_eq!(
+            summarize(&merged),
+            vec![("=>=".to_string(), vec![0..2, 2..3])]
+        );
+
+        // Conversely, cells that are not reversed have their
+        // foreground color used as the background in reverse video
+        let line = make_line(&[("=>", colored(1)), ("=", colored(4))]);
+        let merged = merge_color_runs(line.cluster(None), true);
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn does_not_merge_right_to_left_runs() {
+        let line = make_line(&[("\u{5d0}\u{5d1}", colored(1)), ("\u{5d2}", colored(4))]);
+        let clusters = line.cluster(Some(ParagraphDirectionHint::RightToLeft));
+        assert!(clusters
+            .iter()
+            .all(|cluster| cluster.direction == Direction::RightToLeft));
+
+        let merged = merge_color_runs(clusters, false);
+        assert_eq!(merged.len(), 2);
+        assert!(merged.iter().all(|(_, runs)| runs.is_empty()));
+    }
 }
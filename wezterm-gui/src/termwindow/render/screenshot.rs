@@ -0,0 +1,272 @@
+use crate::renderstate::RenderContext;
+use crate::screenshot::ScreenshotImage;
+use crate::termwindow::render::paint::AllowImage;
+use anyhow::Context;
+use mux::pane::Pane;
+use mux::tab::PositionedPane;
+use mux::Mux;
+use std::rc::Rc;
+use std::sync::Arc;
+use window::WindowOps;
+
+impl crate::TermWindow {
+    /// Renders `pane` through the same quad pipeline that paints the
+    /// window, but into an offscreen target, and returns the pixels
+    /// that cover its cells as PNG encoded data.
+    pub fn screenshot_pane(&mut self, pane: &Arc<dyn Pane>) -> anyhow::Result<Vec<u8>> {
+        let pos = self.positioned_pane_for_screenshot(pane);
+
+        // Everything that rendering the screenshot changes is put
+        // back afterwards, whether or not it succeeded
+        let saved_dimensions = self.dimensions;
+        let saved_allow_images = self.allow_images;
+        let saved_ui_items = std::mem::take(&mut self.ui_items);
+
+        let result = self.render_pane_offscreen(&pos);
+
+        self.dimensions = saved_dimensions;
+        self.allow_images = saved_allow_images;
+        self.ui_items = saved_ui_items;
+        // The quads that were prepared for the window have been
+        // replaced by those for the screenshot, so repaint
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+
+        result?.to_png()
+    }
+
+    /// Returns the position of the pane within its tab, as it would be
+    /// laid out were its tab active in this window
+    fn positioned_pane_for_screenshot(&self, pane: &Arc<dyn Pane>) -> PositionedPane {
+        let pane_id = pane.pane_id();
+        let mux = Mux::get();
+        if let Some(tab) = mux
+            .resolve_pane_id(pane_id)
+            .and_then(|(_domain, _window, tab_id)| mux.get_tab(tab_id))
+        {
+            // Prefer the positions that we render, as they take
+            // overlays and zooming into account
+            if let Some(pos) = self
+                .get_pos_panes_for_tab(&tab)
+                .into_iter()
+                .chain(tab.iter_panes_ignoring_zoom())
+                .find(|pos| pos.pane.pane_id() == pane_id)
+            {
+                return pos;
+            }
+        }
+
+        let dims = pane.get_dimensions();
+        PositionedPane {
+            index: 0,
+            is_active: true,
+            is_zoomed: false,
+            left: 0,
+            top: 0,
+            width: dims.cols,
+            pixel_width: dims.pixel_width,
+            height: dims.viewport_rows,
+            pixel_height: dims.pixel_height,
+            pane: Arc::clone(pane),
+        }
+    }
+
+    fn render_pane_offscreen(&mut self, pos: &PositionedPane) -> anyhow::Result<ScreenshotImage> {
+        let (padding_left, padding_top) = self.padding_left_top();
+        let border = self.get_os_border();
+        let top_bar_height = if self.show_tab_bar && !self.config.tab_bar_at_bottom {
+            self.tab_bar_pixel_height()
+                .context("tab_bar_pixel_height")?
+        } else {
+            0.
+        };
+        let cell_width = self.render_metrics.cell_size.width as usize;
+        let cell_height = self.render_metrics.cell_size.height as usize;
+
+        let x = padding_left as usize + border.left.get() + pos.left * cell_width;
+        let y = (top_bar_height + padding_top) as usize + border.top.get() + pos.top * cell_height;
+        let width = pos.width * cell_width;
+        let height = pos.height * cell_height;
+
+        // A pane from a tab in another window may extend beyond the
+        // bounds of this one; grow the target so that it fits
+        self.dimensions.pixel_width = self.dimensions.pixel_width.max(x + width);
+        self.dimensions.pixel_height = self.dimensions.pixel_height.max(y + height);
+
+        let render_state = self
+            .render_state
+            .as_ref()
+            .context("the window has no render state")?;
+        let glium_context = match &render_state.context {
+            RenderContext::Glium(context) => Some(Rc::clone(context)),
+            RenderContext::WebGpu(_) => None,
+        };
+
+        self.allow_images = AllowImage::Yes;
+        self.paint_with_retries(|tw| tw.paint_screenshot_pass(pos));
+
+        let image = match glium_context {
+            Some(context) => self.draw_offscreen_glium(&context)?,
+            None => self.draw_offscreen_webgpu()?,
+        };
+
+        Ok(image.crop(x, y, width, height))
+    }
+
+    fn paint_screenshot_pass(&mut self, pos: &PositionedPane) -> anyhow::Result<()> {
+        {
+            let gl_state = self
+                .render_state
+                .as_ref()
+                .context("the window has no render state")?;
+            for layer in gl_state.layers.borrow().iter() {
+                layer.clear_quad_allocation();
+            }
+        }
+        self.ui_items.clear();
+
+        if !self.window_background.is_empty() && self.allow_images != AllowImage::No {
+            let bg_color = self.palette().background.to_linear();
+            let top = match self.get_viewport(pos.pane.pane_id()) {
+                Some(top) => top,
+                None => pos.pane.get_dimensions().physical_top,
+            };
+            self.render_backgrounds(bg_color, top)
+                .context("render_backgrounds")?;
+        }
+
+        let gl_state = self
+            .render_state
+            .as_ref()
+            .context("the window has no render state")?;
+        let layer = gl_state
+            .layer_for_zindex(0)
+            .context("layer_for_zindex(0)")?;
+        let mut layers = layer.quad_allocator();
+        self.paint_pane(pos, &mut layers).context("paint_pane")?;
+
+        Ok(())
+    }
+
+    fn draw_offscreen_glium(
+        &mut self,
+        context: &Rc<::window::glium::backend::Context>,
+    ) -> anyhow::Result<ScreenshotImage> {
+        use ::window::glium::framebuffer::SimpleFrameBuffer;
+        use ::window::glium::texture::{MipmapsOption, SrgbFormat, SrgbTexture2d};
+
+        let texture = SrgbTexture2d::empty_with_format(
+            context,
+            SrgbFormat::U8U8U8U8,
+            MipmapsOption::NoMipmap,
+            self.dimensions.pixel_width as u32,
+            self.dimensions.pixel_height as u32,
+        )?;
+        {
+            let mut target = SimpleFrameBuffer::new(context, &texture)?;
+            self.call_draw_glium(&mut target)?;
+        }
+
+        let rows: Vec<Vec<(u8, u8, u8, u8)>> = texture.read();
+        Ok(ScreenshotImage::from_bottom_up_rows(rows))
+    }
+
+    fn draw_offscreen_webgpu(&mut self) -> anyhow::Result<ScreenshotImage> {
+        let webgpu = Rc::clone(
+            self.webgpu
+                .as_ref()
+                .context("the window has no webgpu state")?,
+        );
+        let width = self.dimensions.pixel_width as u32;
+        let height = self.dimensions.pixel_height as u32;
+
+        // The render pipeline targets the surface format, so the
+        // offscreen texture has to use that same format
+        let format = webgpu.config.borrow().format;
+        let bgra = match format.remove_srgb_suffix() {
+            wgpu::TextureFormat::Rgba8Unorm => false,
+            wgpu::TextureFormat::Bgra8Unorm => true,
+            format => anyhow::bail!("cannot read back pixels in {format:?} format"),
+        };
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = webgpu.device.create_texture(&wgpu::TextureDescriptor {
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            label: Some("Screenshot"),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let draw = self.encode_draw_webgpu(&view)?;
+
+        // Rows in the copy destination must be suitably aligned
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let bytes_per_row = (width * 4).div_ceil(align) * align;
+        let buffer = webgpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot readback"),
+            size: bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = webgpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Screenshot Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+        webgpu.queue.submit([draw, encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            tx.send(result).ok();
+        });
+        webgpu
+            .device
+            .poll(wgpu::PollType::Wait)
+            .map_err(|err| anyhow::anyhow!("waiting for screenshot readback: {err:#}"))?;
+        rx.recv()
+            .context("waiting for screenshot readback")?
+            .context("mapping screenshot readback buffer")?;
+
+        let image = {
+            let data = slice.get_mapped_range();
+            ScreenshotImage::from_padded_rows(
+                &data,
+                width as usize,
+                height as usize,
+                bytes_per_row as usize,
+                bgra,
+            )
+        };
+        buffer.unmap();
+
+        Ok(image)
+    }
+}
@@ -17,6 +17,8 @@ pub struct ShaderUniform {
     pub foreground_text_hsb: [f32; 3],
     pub milliseconds: u32,
     pub projection: [[f32; 4]; 4],
+    pub text_gamma: [f32; 2],
+    pub text_contrast: [f32; 2],
     // sampler2D atlas_nearest_sampler;
     // sampler2D atlas_linear_sampler;
 }
@@ -15,7 +15,9 @@ pub struct RenderMetrics {
     pub descender_row: IntPixelLength,
     pub descender_plus_two: IntPixelLength,
     pub underline_height: IntPixelLength,
+    /// The top row of the strikethrough line
     pub strike_row: IntPixelLength,
+    pub strike_height: IntPixelLength,
     pub cell_size: Size,
 }
 
@@ -32,13 +34,18 @@ impl RenderMetrics {
             (cell_height as f64 + (metrics.descender - metrics.underline_position).get()) as isize;
         let descender_plus_two =
             (2 * underline_height + descender_row).min(cell_height as isize - underline_height);
-        let strike_row = descender_row / 2;
+        let baseline_row = cell_height as f64 + metrics.descender.get();
+        let (strike_row, strike_height) =
+            strike_metrics(metrics, baseline_row, descender_row / 2, underline_height);
+        let (strike_row, strike_height) =
+            clamp_line(strike_row, strike_height, cell_height as isize);
 
         Self {
             descender: metrics.descender,
             descender_row,
             descender_plus_two,
             strike_row,
+            strike_height,
             cell_size: Size::new(cell_width as isize, cell_height as isize),
             underline_height,
         }
@@ -52,12 +59,15 @@ impl RenderMetrics {
 
         let adjust = (((self.descender_row as f64 * line_height) - self.descender_row as f64) / 2.0)
             as isize;
+        let (strike_row, strike_height) =
+            clamp_line(self.strike_row, self.strike_height, size.height);
         Self {
             descender: self.descender - PixelLength::new(adjust as f64),
             descender_row: self.descender_row - adjust,
             descender_plus_two: self.descender_plus_two - adjust,
             underline_height: self.underline_height,
-            strike_row: self.strike_row,
+            strike_row,
+            strike_height,
             cell_size: size,
         }
     }
@@ -85,6 +95,67 @@ impl RenderMetrics {
         // such that we are horizontally centered.
         let line_height_y_adjust = (cell_height as f64 - metrics.cell_height.get().ceil()) / 2.;
 
+        let baseline_row = cell_height as f64 + metrics.descender.get() - line_height_y_adjust;
+        let decorations = Decorations::compute(fonts, &metrics, cell_height as isize, baseline_row);
+
+        Ok(Self {
+            descender: metrics.descender - PixelLength::new(line_height_y_adjust),
+            descender_row: decorations.descender_row,
+            descender_plus_two: decorations.descender_plus_two,
+            strike_row: decorations.strike_row,
+            strike_height: decorations.strike_height,
+            cell_size: Size::new(cell_width as isize, cell_height as isize),
+            underline_height: decorations.underline_height,
+        })
+    }
+
+    /// Returns a copy of these metrics with the underline and
+    /// strikethrough geometry taken from the metrics of another face,
+    /// such as a fallback font.  The glyphs of that face are placed on
+    /// the same baseline, so the decorations are positioned relative to it.
+    pub fn with_face_decorations(&self, fonts: &FontConfiguration, metrics: &FontMetrics) -> Self {
+        let baseline_row = self.cell_size.height as f64 + self.descender.get();
+        let decorations = Decorations::compute(fonts, metrics, self.cell_size.height, baseline_row);
+        Self {
+            descender_row: decorations.descender_row,
+            descender_plus_two: decorations.descender_plus_two,
+            underline_height: decorations.underline_height,
+            strike_row: decorations.strike_row,
+            strike_height: decorations.strike_height,
+            ..*self
+        }
+    }
+
+    /// Returns the top rows of the two lines of a double strikethrough.
+    /// They are placed either side of the single line, separated by a
+    /// gap of the same thickness, but are kept within the cell.
+    pub fn double_strike_rows(&self) -> (IntPixelLength, IntPixelLength) {
+        let max_row = (self.cell_size.height - self.strike_height).max(0);
+        (
+            (self.strike_row - self.strike_height).clamp(0, max_row),
+            (self.strike_row + self.strike_height).clamp(0, max_row),
+        )
+    }
+}
+
+/// The rows occupied by the underline and strikethrough in a cell
+struct Decorations {
+    descender_row: IntPixelLength,
+    descender_plus_two: IntPixelLength,
+    underline_height: IntPixelLength,
+    strike_row: IntPixelLength,
+    strike_height: IntPixelLength,
+}
+
+impl Decorations {
+    /// Computes the decorations for a face whose baseline is at
+    /// `baseline_row`, taking into account any overrides from the config
+    fn compute(
+        fonts: &FontConfiguration,
+        metrics: &FontMetrics,
+        cell_height: IntPixelLength,
+        baseline_row: f64,
+    ) -> Self {
         let config = fonts.config();
         let underline_height = match &config.underline_thickness {
             None => metrics.underline_thickness.get().round().max(1.) as isize,
@@ -106,15 +177,26 @@ impl RenderMetrics {
             }) as f64,
         };
 
-        let descender_row = (cell_height as f64 + (metrics.descender.get() - underline_position)
-            - line_height_y_adjust) as isize;
+        let descender_row = (baseline_row - underline_position) as isize;
         let descender_plus_two =
-            (2 * underline_height + descender_row).min(cell_height as isize - underline_height);
+            (2 * underline_height + descender_row).min(cell_height - underline_height);
+
+        // Without strikeout metrics, place the strikethrough midway
+        // between the top of the face and the underline
+        let top_row = baseline_row - (metrics.cell_height + metrics.descender).get();
+        let (font_strike_row, font_strike_height) = strike_metrics(
+            metrics,
+            baseline_row,
+            ((top_row + baseline_row - underline_position) / 2.) as isize,
+            underline_height,
+        );
+        let strike_height = if config.underline_thickness.is_some() {
+            underline_height
+        } else {
+            font_strike_height
+        };
         let strike_row = match &config.strikethrough_position {
-            None => {
-                ((cell_height as f64 + (metrics.descender.get() - underline_position)) / 2.)
-                    as isize
-            }
+            None => font_strike_row,
             Some(d) => d
                 .evaluate_as_pixels(DimensionContext {
                     dpi: fonts.get_dpi() as f32,
@@ -123,18 +205,53 @@ impl RenderMetrics {
                 })
                 .round() as isize,
         };
+        let (strike_row, strike_height) = clamp_line(strike_row, strike_height, cell_height);
 
-        Ok(Self {
-            descender: metrics.descender - PixelLength::new(line_height_y_adjust),
+        Self {
             descender_row,
             descender_plus_two,
-            strike_row,
-            cell_size: Size::new(cell_width as isize, cell_height as isize),
             underline_height,
-        })
+            strike_row,
+            strike_height,
+        }
+    }
+}
+
+/// Computes the top row and thickness of the strikethrough line.
+/// Uses the strikeout metrics from the font when it provides them,
+/// otherwise falls back to `fallback_row` and the underline thickness.
+fn strike_metrics(
+    metrics: &FontMetrics,
+    baseline_row: f64,
+    fallback_row: IntPixelLength,
+    underline_height: IntPixelLength,
+) -> (IntPixelLength, IntPixelLength) {
+    match (
+        metrics.strikethrough_position,
+        metrics.strikethrough_thickness,
+    ) {
+        (Some(position), Some(thickness)) => {
+            let height = thickness.get().round().max(1.) as isize;
+            let row = (baseline_row - position.get()).round().max(0.) as isize;
+            (row, height)
+        }
+        _ => (fallback_row, underline_height),
     }
 }
 
+/// Keeps a line that is `height` rows thick and whose top is at `row`
+/// within a cell that is `cell_height` rows tall, thinning the line if
+/// the cell is too short to hold it
+fn clamp_line(
+    row: IntPixelLength,
+    height: IntPixelLength,
+    cell_height: IntPixelLength,
+) -> (IntPixelLength, IntPixelLength) {
+    let height = height.min(cell_height).max(1);
+    let row = row.min(cell_height - height).max(0);
+    (row, height)
+}
+
 pub struct UtilSprites {
     pub white_space: Sprite,
     pub filled_box: Sprite,
@@ -167,3 +284,140 @@ impl UtilSprites {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::cell::Presentation;
+
+    fn font_metrics(cell_height: f64, strikethrough: Option<(f64, f64)>) -> FontMetrics {
+        FontMetrics {
+            cell_width: PixelLength::new(cell_height / 2.),
+            cell_height: PixelLength::new(cell_height),
+            descender: PixelLength::new(-cell_height / 5.),
+            underline_thickness: PixelLength::new(cell_height / 16.),
+            underline_position: PixelLength::new(-cell_height / 16.),
+            strikethrough_position: strikethrough.map(|(pos, _)| PixelLength::new(pos)),
+            strikethrough_thickness: strikethrough.map(|(_, thick)| PixelLength::new(thick)),
+            cap_height_ratio: None,
+            cap_height: None,
+            is_scaled: true,
+            presentation: Presentation::Text,
+            force_y_adjust: PixelLength::new(0.),
+        }
+    }
+
+    fn render_metrics(
+        cell_height: IntPixelLength,
+        strike_row: IntPixelLength,
+        strike_height: IntPixelLength,
+    ) -> RenderMetrics {
+        RenderMetrics {
+            descender: PixelLength::new(0.),
+            descender_row: cell_height - 1,
+            descender_plus_two: cell_height - 1,
+            underline_height: 1,
+            strike_row,
+            strike_height,
+            cell_size: Size::new(cell_height / 2, cell_height),
+        }
+    }
+
+    #[test]
+    fn clamp_lines_to_the_cell() {
+        for ((row, height, cell_height), expected) in [
+            // Fits already
+            ((8, 2, 20), (8, 2)),
+            // Would extend past the bottom of the cell
+            ((19, 2, 20), (18, 2)),
+            ((30, 2, 20), (18, 2)),
+            // Would start above the top of the cell
+            ((-3, 2, 20), (0, 2)),
+            // Thicker than the cell
+            ((0, 5, 3), (0, 3)),
+            ((2, 5, 3), (0, 3)),
+            // A line is always at least one row thick
+            ((0, 0, 3), (0, 1)),
+        ] {
+            assert_eq!(
+                clamp_line(row, height, cell_height),
+                expected,
+                "row={} height={} cell_height={}",
+                row,
+                height,
+                cell_height
+            );
+        }
+    }
+
+    #[test]
+    fn strike_from_font_metrics() {
+        for (cell_height, strikethrough, expected) in [
+            // Small cell with OS/2 metrics: the line is rounded to whole rows
+            (8., Some((2.4, 0.4)), (4, 1)),
+            // Large cell with OS/2 metrics
+            (64., Some((19.2, 3.2)), (32, 3)),
+            // Without OS/2 metrics, the fallback row and underline are used
+            (8., None, (2, 1)),
+            (64., None, (21, 4)),
+        ] {
+            let metrics = font_metrics(cell_height, strikethrough);
+            let baseline_row = cell_height + metrics.descender.get();
+            let underline_height = metrics.underline_thickness.get().round().max(1.) as isize;
+            let fallback_row = (cell_height / 3.) as isize;
+            assert_eq!(
+                strike_metrics(&metrics, baseline_row, fallback_row, underline_height),
+                expected,
+                "cell_height={} strikethrough={:?}",
+                cell_height,
+                strikethrough
+            );
+        }
+    }
+
+    #[test]
+    fn strike_stays_within_small_and_large_cells() {
+        for (cell_height, strikethrough) in [
+            (4., Some((10., 3.))),
+            (4., Some((-10., 8.))),
+            (8., Some((2.4, 0.4))),
+            (8., None),
+            (64., Some((80., 3.2))),
+            (64., None),
+        ] {
+            let metrics =
+                RenderMetrics::with_font_metrics(&font_metrics(cell_height, strikethrough));
+            for scaled in [
+                metrics,
+                metrics.scale_line_height(0.5),
+                metrics.scale_line_height(2.0),
+            ] {
+                assert!(scaled.strike_row >= 0, "{:?}", scaled);
+                assert!(
+                    scaled.strike_row + scaled.strike_height <= scaled.cell_size.height,
+                    "{:?}",
+                    scaled
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn double_strike_rows_stay_within_the_cell() {
+        for ((cell_height, strike_row, strike_height), expected) in [
+            // Large cell: either side of the single line
+            ((64, 30, 3), (27, 33)),
+            // The upper line would start above the cell
+            ((8, 1, 2), (0, 3)),
+            // The lower line would extend past the bottom of the cell
+            ((8, 5, 2), (3, 6)),
+            // The cell is barely tall enough for one line
+            ((2, 0, 2), (0, 0)),
+        ] {
+            let metrics = render_metrics(cell_height, strike_row, strike_height);
+            let (upper, lower) = metrics.double_strike_rows();
+            assert_eq!((upper, lower), expected, "{:?}", metrics);
+            assert!(lower + strike_height <= cell_height, "{:?}", metrics);
+        }
+    }
+}
@@ -204,6 +204,32 @@ where
             }
             Ok(Item::Notif(MuxNotification::ActiveWorkspaceChanged(_))) => {}
             Ok(Item::Notif(MuxNotification::Empty)) => {}
+            Ok(Item::Notif(MuxNotification::SshAgentConfirmation {
+                id,
+                message,
+                client_id,
+            })) => {
+                // Only the gui that the user is interacting with is asked
+                if client_id.is_some() && handler.client_id() == client_id.as_ref() {
+                    Pdu::ConfirmSshAgentRequest(codec::ConfirmSshAgentRequest { id, message })
+                        .encode_async(&mut stream, 0)
+                        .await?;
+                    stream.flush().await.context("flushing PDU to client")?;
+                }
+            }
+            Ok(Item::Notif(MuxNotification::ScreenshotRequested {
+                id,
+                pane_id,
+                client_id,
+            })) => {
+                // Only the gui that was chosen to render it is asked
+                if handler.client_id() == Some(&client_id) {
+                    Pdu::RenderScreenshot(codec::RenderScreenshot { id, pane_id })
+                        .encode_async(&mut stream, 0)
+                        .await?;
+                    stream.flush().await.context("flushing PDU to client")?;
+                }
+            }
             Err(err) => {
                 log::error!("process_async Err {}", err);
                 return Ok(());
@@ -6,6 +6,7 @@ use mux::client::ClientId;
 use mux::domain::SplitSource;
 use mux::pane::{CachePolicy, Pane, PaneId};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use mux::ssh::RemoteSshDomain;
 use mux::tab::TabId;
 use mux::{Mux, MuxNotification};
 use promise::spawn::spawn_into_main_thread;
@@ -14,6 +15,7 @@ use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use termwiz::surface::SequenceNo;
 use url::Url;
+use wezterm_ssh::{ForwardInfo, ForwardSpec};
 use wezterm_term::terminal::Alert;
 use wezterm_term::StableRowIndex;
 
@@ -208,6 +210,7 @@ impl Drop for SessionHandler {
         if let Some(client_id) = self.client_id.take() {
             let mux = Mux::get();
             mux.unregister_client(&client_id);
+            mux::sftp::remove_client_transfers(&client_id);
         }
     }
 }
@@ -222,6 +225,11 @@ impl SessionHandler {
         }
     }
 
+    /// The identity that the client declared via SetClientId
+    pub(crate) fn client_id(&self) -> Option<&Arc<ClientId>> {
+        self.client_id.as_ref()
+    }
+
     pub(crate) fn per_pane(&mut self, pane_id: PaneId) -> Arc<Mutex<PerPane>> {
         Arc::clone(
             self.per_pane
@@ -987,6 +995,38 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::ScreenshotPane(ScreenshotPane { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    schedule_screenshot_pane(pane_id, send_response);
+                })
+                .detach();
+            }
+
+            Pdu::ScreenshotRendered(ScreenshotRendered { id, result }) => {
+                mux::screenshot::resolve_screenshot(id, result);
+                send_response(Ok(Pdu::UnitResponse(UnitResponse {})));
+            }
+
+            Pdu::SshAgentRequestConfirmed(SshAgentRequestConfirmed { id, allowed }) => {
+                mux::ssh_agent::resolve_confirmation(id, allowed);
+                send_response(Ok(Pdu::UnitResponse(UnitResponse {})));
+            }
+
+            Pdu::SshForward(request) => {
+                spawn_into_main_thread(async move {
+                    schedule_ssh_forward(request, send_response);
+                })
+                .detach();
+            }
+
+            Pdu::SftpTransfer(request) => {
+                let client_id = self.client_id.clone();
+                spawn_into_main_thread(async move {
+                    schedule_sftp_transfer(request, send_response, client_id);
+                })
+                .detach();
+            }
+
             Pdu::Invalid { .. } => send_response(Err(anyhow!("invalid PDU {:?}", decoded.pdu))),
             Pdu::Pong { .. }
             | Pdu::ListPanesResponse { .. }
@@ -997,6 +1037,11 @@ impl SessionHandler {
             | Pdu::UnitResponse { .. }
             | Pdu::LivenessResponse { .. }
             | Pdu::GetPaneDirectionResponse { .. }
+            | Pdu::ScreenshotPaneResponse { .. }
+            | Pdu::RenderScreenshot { .. }
+            | Pdu::ConfirmSshAgentRequest { .. }
+            | Pdu::SshForwardResponse { .. }
+            | Pdu::SftpTransferResponse { .. }
             | Pdu::SearchScrollbackResponse { .. }
             | Pdu::GetLinesResponse { .. }
             | Pdu::GetCodecVersionResponse { .. }
@@ -1105,6 +1150,140 @@ fn schedule_move_pane<SND>(
         .detach();
 }
 
+fn schedule_screenshot_pane<SND>(pane_id: PaneId, send_response: SND)
+where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    promise::spawn::spawn(async move { send_response(screenshot_pane(pane_id).await) }).detach();
+}
+
+async fn screenshot_pane(pane_id: PaneId) -> anyhow::Result<Pdu> {
+    let mux = Mux::get();
+    let pane = mux
+        .get_pane(pane_id)
+        .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+    let data = mux::screenshot::screenshot_pane(pane).await?;
+    Ok(Pdu::ScreenshotPaneResponse(ScreenshotPaneResponse { data }))
+}
+
+fn schedule_ssh_forward<SND>(request: SshForward, send_response: SND)
+where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    promise::spawn::spawn(async move { send_response(ssh_forward(request).await) }).detach();
+}
+
+async fn ssh_forward(request: SshForward) -> anyhow::Result<Pdu> {
+    let mux = Mux::get();
+    let pane = mux
+        .get_pane(request.pane_id)
+        .ok_or_else(|| anyhow!("no such pane {}", request.pane_id))?;
+    let domain = mux
+        .get_domain(pane.domain_id())
+        .ok_or_else(|| anyhow!("no domain for pane {}", request.pane_id))?;
+    let domain = domain
+        .downcast_ref::<RemoteSshDomain>()
+        .ok_or_else(|| anyhow!("pane {} is not in an ssh domain", request.pane_id))?;
+
+    let to_entry = |info: ForwardInfo| SshForwardEntry {
+        id: info.id,
+        spec: info.spec.to_string(),
+        bound_port: info.bound_port,
+    };
+
+    let forwards = match request.action {
+        SshForwardAction::Add(spec) => {
+            let spec: ForwardSpec = spec.parse()?;
+            vec![to_entry(domain.add_forward(spec).await?)]
+        }
+        SshForwardAction::Cancel(id) => {
+            domain.cancel_forward(id).await?;
+            vec![]
+        }
+        SshForwardAction::List => domain
+            .list_forwards()
+            .await?
+            .into_iter()
+            .map(to_entry)
+            .collect(),
+    };
+
+    Ok(Pdu::SshForwardResponse(SshForwardResponse { forwards }))
+}
+
+fn schedule_sftp_transfer<SND>(
+    request: SftpTransfer,
+    send_response: SND,
+    client_id: Option<Arc<ClientId>>,
+) where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    promise::spawn::spawn(async move { send_response(sftp_transfer(request, client_id).await) })
+        .detach();
+}
+
+async fn sftp_transfer(
+    request: SftpTransfer,
+    client_id: Option<Arc<ClientId>>,
+) -> anyhow::Result<Pdu> {
+    // Transfers are scoped to the client and pane that opened them,
+    // and are abandoned when the client disconnects
+    let client_id =
+        client_id.ok_or_else(|| anyhow!("sftp transfers require the client to identify itself"))?;
+    let owner = mux::sftp::TransferOwner {
+        pane_id: request.pane_id,
+        client_id: (*client_id).clone(),
+    };
+    let mut response = SftpTransferResponse {
+        handle: 0,
+        path: String::new(),
+        size: None,
+        data: vec![],
+    };
+
+    // None to open for reading, otherwise the file name to use
+    // when opening a directory for writing
+    let open = match request.action {
+        SftpTransferAction::OpenRead { path } => Some((path, None)),
+        SftpTransferAction::OpenWrite { path, file_name } => Some((path, Some(file_name))),
+        SftpTransferAction::Read { handle, len } => {
+            response.handle = handle;
+            response.data = mux::sftp::read_chunk(handle, &owner, len).await?;
+            None
+        }
+        SftpTransferAction::Write { handle, data } => {
+            response.handle = handle;
+            mux::sftp::write_chunk(handle, &owner, &data).await?;
+            None
+        }
+        SftpTransferAction::Close { handle } => {
+            response.handle = handle;
+            mux::sftp::close(handle, &owner).await?;
+            None
+        }
+    };
+
+    if let Some((path, write_as)) = open {
+        let mux = Mux::get();
+        let pane = mux
+            .get_pane(request.pane_id)
+            .ok_or_else(|| anyhow!("no such pane {}", request.pane_id))?;
+        let sftp = mux::sftp::sftp_for_pane(&pane)?;
+        let path = mux::sftp::resolve_remote_path(&pane, &path);
+        let transfer = match write_as {
+            Some(file_name) => {
+                mux::sftp::open_write(&sftp, owner, path, file_name.as_deref()).await?
+            }
+            None => mux::sftp::open_read(&sftp, owner, path).await?,
+        };
+        response.handle = transfer.id;
+        response.path = transfer.path.into_string();
+        response.size = transfer.size;
+    }
+
+    Ok(Pdu::SftpTransferResponse(response))
+}
+
 async fn move_pane(
     request: MovePaneToNewTab,
     client_id: Option<Arc<ClientId>>,
@@ -13,6 +13,7 @@ use wezterm_gui_subcommands::*;
 use wezterm_mux_server_impl::update_mux_domains_for_server;
 
 mod daemonize;
+mod screenshot;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -228,6 +229,21 @@ fn run() -> anyhow::Result<()> {
     let mux = Arc::new(mux::Mux::new(Some(domain.clone())));
     Mux::set_mux(&mux);
 
+    // Render screenshots with the same configuration as ours
+    let mut config_args = vec![];
+    if opts.skip_config {
+        config_args.push("-n".into());
+    }
+    if let Some(f) = &opts.config_file {
+        config_args.push("--config-file".into());
+        config_args.push(f.clone());
+    }
+    for (name, value) in &opts.config_override {
+        config_args.push("--config".into());
+        config_args.push(format!("{name}={value}").into());
+    }
+    screenshot::register(config_args);
+
     let executor = promise::spawn::SimpleExecutor::new();
 
     spawn_listener().map_err(|e| {
@@ -0,0 +1,83 @@
+//! wezterm-mux-server has no renderer of its own.  When no gui is
+//! attached to render a screenshot on its behalf, the visible portion
+//! of the pane is passed to `wezterm-gui render-screenshot`, which
+//! renders it without a window.
+use anyhow::{anyhow, Context};
+use codec::PaneSnapshot;
+use mux::pane::Pane;
+use smol::io::AsyncWriteExt;
+use smol::process::{Command, Stdio};
+use std::ffi::OsString;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// How long to wait for wezterm-gui to render the screenshot
+const RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The configuration arguments that we were started with, so that
+/// wezterm-gui renders with the same configuration
+static CONFIG_ARGS: OnceLock<Vec<OsString>> = OnceLock::new();
+
+pub fn register(config_args: Vec<OsString>) {
+    CONFIG_ARGS.set(config_args).ok();
+    mux::screenshot::register_windowless_screenshot_renderer(render_with_gui);
+}
+
+fn render_with_gui(pane: Arc<dyn Pane>) -> Pin<Box<dyn Future<Output = anyhow::Result<Vec<u8>>>>> {
+    Box::pin(async move {
+        let mut snapshot = vec![];
+        PaneSnapshot::capture(&*pane).write_to(&mut snapshot)?;
+
+        smol::future::or(run_gui(snapshot), async {
+            smol::Timer::after(RENDER_TIMEOUT).await;
+            Err(anyhow!(
+                "timed out waiting for wezterm-gui to render the screenshot"
+            ))
+        })
+        .await
+    })
+}
+
+async fn run_gui(snapshot: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let exe_name = if cfg!(windows) {
+        "wezterm-gui.exe"
+    } else {
+        "wezterm-gui"
+    };
+    let exe = std::env::current_exe()?
+        .parent()
+        .ok_or_else(|| anyhow!("exe has no parent dir!?"))?
+        .join(exe_name);
+
+    let mut child = Command::new(&exe)
+        .args(CONFIG_ARGS.get().into_iter().flatten())
+        .arg("render-screenshot")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // If we time out, don't leave it running
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("running {}", exe.display()))?;
+
+    let mut stdin = child.stdin.take().expect("stdin to be piped");
+    let write_snapshot = async move {
+        // Dropping stdin once it has been written marks the
+        // end of the snapshot
+        stdin.write_all(&snapshot).await
+    };
+    let (written, output) = smol::future::zip(write_snapshot, child.output()).await;
+    let output = output.with_context(|| format!("waiting for {}", exe.display()))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed to render the screenshot: {}",
+            exe.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    written.context("sending the pane to wezterm-gui")?;
+    Ok(output.stdout)
+}
@@ -1,6 +1,9 @@
 use crate::session::SessionEvent;
 use anyhow::Context;
 use smol::channel::{bounded, Sender};
+use std::path::Path;
+#[cfg(feature = "ssh2")]
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct AuthenticationPrompt {
@@ -26,6 +29,26 @@ impl AuthenticationEvent {
     }
 }
 
+/// Returns true if the identity is held by a FIDO security key,
+/// which is the case for the `sk-ecdsa-sha2-nistp256@openssh.com`
+/// and `sk-ssh-ed25519@openssh.com` key types.
+/// Neither backend can ask the authenticator to sign a challenge, so
+/// these identities can only be used when they are loaded into ssh-agent.
+pub(crate) fn is_security_key(identity: &Path) -> bool {
+    match std::fs::read_to_string(format!("{}.pub", identity.display())) {
+        Ok(pubkey) => pubkey
+            .split_whitespace()
+            .next()
+            .map(|key_type| key_type.starts_with("sk-"))
+            .unwrap_or(false),
+        Err(_) => identity
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.ends_with("_sk"))
+            .unwrap_or(false),
+    }
+}
+
 impl crate::sessioninner::SessionInner {
     #[cfg(feature = "ssh2")]
     fn agent_auth(&mut self, sess: &ssh2::Session, user: &str) -> anyhow::Result<bool> {
@@ -53,6 +76,27 @@ impl crate::sessioninner::SessionInner {
         Ok(false)
     }
 
+    /// Returns the certificates to present alongside `identity`: the
+    /// `<identity>-cert.pub` file that OpenSSH looks for next to each
+    /// identity, followed by any that are listed by `CertificateFile`
+    #[cfg(feature = "ssh2")]
+    fn certificate_files(&self, identity: &Path) -> Vec<PathBuf> {
+        let mut certs = vec![];
+        let implicit = PathBuf::from(format!("{}-cert.pub", identity.display()));
+        if implicit.exists() {
+            certs.push(implicit);
+        }
+        if let Some(files) = self.config.get("certificatefile") {
+            for file in files.split_whitespace() {
+                let file = PathBuf::from(file);
+                if file.exists() && !certs.contains(&file) {
+                    certs.push(file);
+                }
+            }
+        }
+        certs
+    }
+
     #[cfg(feature = "ssh2")]
     fn pubkey_auth(
         &mut self,
@@ -60,8 +104,6 @@ impl crate::sessioninner::SessionInner {
         user: &str,
         host: &str,
     ) -> anyhow::Result<bool> {
-        use std::path::{Path, PathBuf};
-
         if let Some(files) = self.config.get("identityfile") {
             for file in files.split_whitespace() {
                 let pubkey: PathBuf = format!("{}.pub", file).into();
@@ -71,58 +113,81 @@ impl crate::sessioninner::SessionInner {
                     continue;
                 }
 
-                let pubkey = if pubkey.exists() {
-                    Some(pubkey.as_ref())
-                } else {
-                    None
-                };
-
-                // We try with no passphrase first, in case the key is unencrypted
-                match sess.userauth_pubkey_file(user, pubkey, &file, None) {
-                    Ok(_) => {
-                        log::info!("pubkey_file immediately ok for {}", file.display());
-                        return Ok(true);
-                    }
-                    Err(_) => {
-                        // Most likely cause of error is that we need a passphrase
-                        // to decrypt the key, so let's prompt the user for one.
-                        let (reply, answers) = bounded(1);
-                        self.tx_event
-                            .try_send(SessionEvent::Authenticate(AuthenticationEvent {
-                                username: "".to_string(),
-                                instructions: "".to_string(),
-                                prompts: vec![AuthenticationPrompt {
-                                    prompt: format!(
-                                        "Passphrase to decrypt {} for {}@{}:\n> ",
-                                        file.display(),
-                                        user,
-                                        host
-                                    ),
-                                    echo: false,
-                                }],
-                                reply,
-                            }))
-                            .context("sending Authenticate request to user")?;
-
-                        let answers = smol::block_on(answers.recv())
-                            .context("waiting for authentication answers from user")?;
-
-                        if answers.is_empty() {
-                            anyhow::bail!("user cancelled authentication");
-                        }
+                if is_security_key(file) {
+                    // libssh2 can only authenticate with a security key if
+                    // the caller signs the challenge with the authenticator
+                    // itself, and we have no means of talking to one
+                    log::warn!(
+                        "Skipping {}: security key identities must be \
+                         loaded into ssh-agent to be used with the ssh2 backend",
+                        file.display()
+                    );
+                    continue;
+                }
 
-                        let passphrase = &answers[0];
+                // Certificates are presented ahead of the plain public key
+                let mut pubkeys: Vec<Option<PathBuf>> =
+                    self.certificate_files(file).into_iter().map(Some).collect();
+                pubkeys.push(if pubkey.exists() { Some(pubkey) } else { None });
 
-                        match sess.userauth_pubkey_file(user, pubkey, &file, Some(passphrase)) {
+                let try_pubkeys = |passphrase: Option<&str>| {
+                    for pubkey in &pubkeys {
+                        match sess.userauth_pubkey_file(user, pubkey.as_deref(), file, passphrase) {
                             Ok(_) => {
-                                return Ok(true);
+                                log::info!(
+                                    "pubkey_file ok for {} with {}",
+                                    file.display(),
+                                    pubkey
+                                        .as_deref()
+                                        .map(|p| p.display().to_string())
+                                        .unwrap_or_else(|| "derived public key".to_string())
+                                );
+                                return true;
                             }
                             Err(err) => {
-                                log::warn!("pubkey auth: {:#}", err);
+                                log::debug!("pubkey auth for {}: {:#}", file.display(), err);
                             }
                         }
                     }
+                    false
+                };
+
+                // We try with no passphrase first, in case the key is unencrypted
+                if try_pubkeys(None) {
+                    return Ok(true);
+                }
+
+                // Most likely cause of error is that we need a passphrase
+                // to decrypt the key, so let's prompt the user for one.
+                let (reply, answers) = bounded(1);
+                self.tx_event
+                    .try_send(SessionEvent::Authenticate(AuthenticationEvent {
+                        username: "".to_string(),
+                        instructions: "".to_string(),
+                        prompts: vec![AuthenticationPrompt {
+                            prompt: format!(
+                                "Passphrase to decrypt {} for {}@{}:\n> ",
+                                file.display(),
+                                user,
+                                host
+                            ),
+                            echo: false,
+                        }],
+                        reply,
+                    }))
+                    .context("sending Authenticate request to user")?;
+
+                let answers = smol::block_on(answers.recv())
+                    .context("waiting for authentication answers from user")?;
+
+                if answers.is_empty() {
+                    anyhow::bail!("user cancelled authentication");
                 }
+
+                if try_pubkeys(Some(&answers[0])) {
+                    return Ok(true);
+                }
+                log::warn!("pubkey auth failed for {}", file.display());
             }
         }
         Ok(false)
@@ -363,3 +428,52 @@ impl crate::sessioninner::SessionInner {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+
+    #[test]
+    fn security_key_from_public_key_type() {
+        let dir = TempDir::new().unwrap();
+        for (name, pubkey, expected) in [
+            (
+                "ecdsa",
+                "sk-ecdsa-sha2-nistp256@openssh.com AAAAInNr me@host",
+                true,
+            ),
+            (
+                "ed25519",
+                "sk-ssh-ed25519@openssh.com AAAAGnNr me@host",
+                true,
+            ),
+            ("plain", "ssh-ed25519 AAAAC3Nz me@host", false),
+            // The public key takes precedence over the file name
+            ("plain_sk", "ssh-ed25519 AAAAC3Nz me@host", false),
+        ] {
+            dir.child(name).touch().unwrap();
+            dir.child(format!("{name}.pub")).write_str(pubkey).unwrap();
+            assert_eq!(
+                is_security_key(dir.child(name).path()),
+                expected,
+                "{name}: {pubkey}"
+            );
+        }
+    }
+
+    #[test]
+    fn security_key_from_file_name() {
+        let dir = TempDir::new().unwrap();
+        for (name, expected) in [
+            ("id_ecdsa_sk", true),
+            ("id_ed25519_sk", true),
+            ("id_ed25519", false),
+            ("id_sk_rsa", false),
+        ] {
+            dir.child(name).touch().unwrap();
+            assert_eq!(is_security_key(dir.child(name).path()), expected, "{name}");
+        }
+    }
+}
@@ -79,6 +79,19 @@ impl ChannelWrap {
         }
     }
 
+    /// Signals to the remote end that no more data will be sent
+    pub fn send_eof(&mut self) -> std::io::Result<()> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(chan) => chan.send_eof().map_err(std::io::Error::from),
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(chan) => chan
+                .send_eof()
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err)),
+        }
+    }
+
     pub fn close(&mut self) {
         match self {
             #[cfg(feature = "ssh2")]
@@ -161,6 +174,33 @@ impl ChannelWrap {
         }
     }
 
+    pub fn request_x11(&mut self, protocol: &str, cookie: &str, screen: u32) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(_chan) => Err(anyhow::anyhow!("ssh2 does not support X11 forwarding")),
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(chan) => {
+                Ok(chan.request_x11(false, Some(protocol), Some(cookie), screen as i32)?)
+            }
+        }
+    }
+
+    /// Returns an X11 channel that the remote host has opened, if any.
+    /// libssh accepts these on behalf of the whole session, so any
+    /// channel may be used to do so.
+    pub fn accept_x11(&mut self) -> Option<ChannelWrap> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(_chan) => None,
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(chan) => chan
+                .accept_x11(std::time::Duration::ZERO)
+                .map(ChannelWrap::LibSsh),
+        }
+    }
+
     pub fn resize_pty(&mut self, resize: &ResizePty) -> anyhow::Result<()> {
         match self {
             #[cfg(feature = "ssh2")]
@@ -344,14 +344,20 @@ impl ParsedConfigFile {
                 }
 
                 fn add_option(options: &mut ConfigMap, k: String, v: &str) {
-                    // first option wins in ssh_config, except for identityfile
-                    // which explicitly allows multiple entries to combine together
-                    let is_identity_file = k == "identityfile";
+                    // first option wins in ssh_config, except for identityfile,
+                    // certificatefile and the forwarding options, which explicitly
+                    // allow multiple entries to combine together
+                    let separator = match k.as_str() {
+                        "identityfile" | "certificatefile" => Some(' '),
+                        // Forwards contain spaces, so use a comma instead
+                        "localforward" | "remoteforward" | "dynamicforward" => Some(','),
+                        _ => None,
+                    };
                     options
                         .entry(k)
                         .and_modify(|e| {
-                            if is_identity_file {
-                                e.push(' ');
+                            if let Some(separator) = separator {
+                                e.push(separator);
                                 e.push_str(v);
                             }
                         })
@@ -591,8 +597,9 @@ impl Config {
                 result.insert(
                     "identityfile".to_string(),
                     format!(
-                        "{}/.ssh/id_dsa {}/.ssh/id_ecdsa {}/.ssh/id_ed25519 {}/.ssh/id_rsa",
-                        home, home, home, home
+                        "{}/.ssh/id_dsa {}/.ssh/id_ecdsa {}/.ssh/id_ecdsa_sk \
+                         {}/.ssh/id_ed25519 {}/.ssh/id_ed25519_sk {}/.ssh/id_rsa",
+                        home, home, home, home, home, home
                     ),
                 );
             }
@@ -621,7 +628,7 @@ impl Config {
     fn should_expand_tokens(&self, key: &str) -> Option<&[&str]> {
         match key {
             "certificatefile" | "controlpath" | "identityagent" | "identityfile"
-            | "localforward" | "remotecommand" | "remoteforward" | "userknownkostsfile" => {
+            | "localforward" | "remotecommand" | "remoteforward" | "userknownhostsfile" => {
                 Some(&["%C", "%d", "%h", "%i", "%L", "%l", "%n", "%p", "%r", "%u"])
             }
             "hostname" => Some(&["%h"]),
@@ -704,7 +711,7 @@ impl Config {
                 }
             } else if t == "%j" {
                 // %j: The contents of the ProxyJump option, or the empty string if this option is unset
-                // We don't directly support ProxyJump, and this %j token referencing
+                // ProxyJump is handled when connecting, but this %j token referencing
                 // may technically put this into two-phase evaluation territory which
                 // we don't support.
                 // Let's silently gloss over this and treat this token as the empty
@@ -823,7 +830,7 @@ mod test {
             r#"
 {
     "hostname": "foo",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "serveraliveinterval": "60",
     "user": "me",
@@ -854,7 +861,7 @@ mod test {
             r#"
 {
     "hostname": "foo",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "2222",
     "proxycommand": "/usr/bin/corp-ssh-helper -dst_username=me foo 2222",
     "user": "me",
@@ -935,7 +942,7 @@ Config {
             r#"
 {
     "hostname": "target-host",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "localcommand": "C=8de28522efb92214d9c442ea0402863e34d095a4006467ad9136a48e930870ea d=/home/me h=target-host i=1000 L=localhost l=localhost n=target-host p=22 r=me T=NONE u=me",
     "port": "22",
     "user": "me",
@@ -945,6 +952,43 @@ Config {
         );
     }
 
+    #[test]
+    fn multiple_forwards() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host foo
+            LocalForward 8080 localhost:80
+            LocalForward 127.0.0.1:9090 localhost:90
+            RemoteForward 2222 localhost:22
+            DynamicForward 1080
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "dynamicforward": "1080",
+    "hostname": "foo",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
+    "localforward": "8080 localhost:80,127.0.0.1:9090 localhost:90",
+    "port": "22",
+    "remoteforward": "2222 localhost:22",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
     #[test]
     fn parse_user() {
         let mut config = Config::new();
@@ -1044,7 +1088,7 @@ Config {
             r#"
 {
     "hostname": "server-foo0",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "user": "me",
     "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
@@ -1058,7 +1102,7 @@ Config {
             r#"
 {
     "hostname": "server-foo1",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "user": "me",
     "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
@@ -1072,7 +1116,7 @@ Config {
             r#"
 {
     "hostname": "server-foo2",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "user": "me",
     "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
@@ -1104,7 +1148,7 @@ Config {
             r#"
 {
     "hostname": "server-foo",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "proxycommand": "nc -x localhost:1080 server-foo 22",
     "user": "me",
@@ -1148,6 +1192,42 @@ Config {
         );
     }
 
+    #[test]
+    fn multiple_certificatefile() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host foo
+            HostName 10.0.0.1
+            User foo
+            IdentityFile "~/.ssh/id_ed25519_sk"
+            CertificateFile "~/.ssh/id_ed25519_sk-cert.pub"
+            CertificateFile "%d/.ssh/%r-cert.pub"
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "certificatefile": "/home/me/.ssh/id_ed25519_sk-cert.pub /home/me/.ssh/foo-cert.pub",
+    "hostname": "10.0.0.1",
+    "identityfile": "/home/me/.ssh/id_ed25519_sk",
+    "port": "22",
+    "user": "foo",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
     #[test]
     fn sub_tilde() {
         let mut config = Config::new();
@@ -1368,7 +1448,7 @@ Config {
             r#"
 {
     "hostname": "random",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "something": "first",
     "user": "me",
@@ -1399,7 +1479,7 @@ Config {
             r#"
 {
     "hostname": "a.b",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "something": "first",
     "user": "me",
@@ -1416,7 +1496,7 @@ Config {
     "forwardagent": "no",
     "hostname": "b.b",
     "identityagent": "/home/me/.ssh/agent-me",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "something": "first",
     "user": "me",
@@ -1438,7 +1518,7 @@ Config {
     "forwardagent": "no",
     "hostname": "b.b",
     "identityagent": "/home/fred/.ssh/agent",
-    "identityfile": "/home/fred/.ssh/id_dsa /home/fred/.ssh/id_ecdsa /home/fred/.ssh/id_ed25519 /home/fred/.ssh/id_rsa",
+    "identityfile": "/home/fred/.ssh/id_dsa /home/fred/.ssh/id_ecdsa /home/fred/.ssh/id_ecdsa_sk /home/fred/.ssh/id_ed25519 /home/fred/.ssh/id_ed25519_sk /home/fred/.ssh/id_rsa",
     "port": "22",
     "something": "first",
     "user": "fred",
@@ -1576,7 +1656,7 @@ Config {
             r#"
 {
     "hostname": "random",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "something": "first",
     "user": "me",
@@ -1607,7 +1687,7 @@ Config {
             r#"
 {
     "hostname": "a.b",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "something": "first",
     "user": "me",
@@ -1624,7 +1704,7 @@ Config {
     "forwardagent": "no",
     "hostname": "b.b",
     "identityagent": "/home/me/.ssh/agent",
-    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ecdsa_sk /home/me/.ssh/id_ed25519 /home/me/.ssh/id_ed25519_sk /home/me/.ssh/id_rsa",
     "port": "22",
     "something": "first",
     "user": "me",
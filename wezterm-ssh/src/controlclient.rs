@@ -0,0 +1,684 @@
+//! Runs a Session over the control socket of another process that is
+//! acting as the ControlMaster for a host, as `ssh` does when its
+//! `ControlPath` names a socket that is already being served.
+//! The protocol is described in PROTOCOL.mux in the OpenSSH sources.
+//!
+//! The master owns the connection, so the things that we can do are
+//! limited to what the protocol offers: sessions, with or without a
+//! pty, tunnels and port forwards.  The sftp subsystem, X11 and agent
+//! forwarding and sending signals are not available.
+use crate::controlmaster::*;
+use crate::forward::{ForwardId, ForwardInfo, ForwardSpec};
+use crate::pty::{NewPty, ResizePty, SshChildProcess, SshPty};
+use crate::session::{Exec, ExecResult, SessionRequest};
+use crate::sessioninner::ChannelId;
+use anyhow::{anyhow, Context};
+use filedescriptor::{poll, pollfd, socketpair, FileDescriptor, POLLIN};
+use passfd::FdPassingExt;
+use portable_pty::{ExitStatus, PtySize};
+use smol::channel::{bounded, Receiver, Sender, TryRecvError};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often we check that the master is still there
+const ALIVE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long we wait for the master to respond to a request on the
+/// control connection, so that a wedged master can't hang us
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tells OpenSSH not to watch for an escape character
+const NO_ESCAPE_CHAR: u32 = 0xffff_ffff;
+
+/// A session or tunnel that was opened through the master.
+/// Each one has a connection to the control socket of its own.
+struct ClientChannel {
+    stream: UnixStream,
+    /// The master side of the local pty that was passed to the
+    /// master, if the session has one
+    pty: Option<FileDescriptor>,
+}
+
+pub(crate) struct ControlClient {
+    path: PathBuf,
+    rx_req: Receiver<SessionRequest>,
+    sender_read: FileDescriptor,
+    control: UnixStream,
+    master_pid: u32,
+    next_request_id: u32,
+    next_channel_id: ChannelId,
+    next_forward_id: ForwardId,
+    channels: Arc<Mutex<HashMap<ChannelId, ClientChannel>>>,
+    forwards: HashMap<ForwardId, ForwardInfo>,
+    shown_sftp_error: bool,
+}
+
+/// Connects to the control socket at `path` and exchanges hellos
+fn connect(path: &Path) -> anyhow::Result<UnixStream> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("connecting to control socket {}", path.display()))?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+
+    let hello = read_message(&mut stream)?
+        .ok_or_else(|| anyhow!("control master closed the connection"))?;
+    let mut p = Payload { buf: &hello };
+    match (p.u32()?, p.u32()?) {
+        (MUX_MSG_HELLO, MUX_PROTOCOL_VERSION) => {}
+        (MUX_MSG_HELLO, version) => {
+            anyhow::bail!("unsupported control protocol version {version}")
+        }
+        (msg_type, _) => anyhow::bail!("expected hello but got message type {msg_type:#x}"),
+    }
+
+    Message::new(MUX_MSG_HELLO)
+        .u32(MUX_PROTOCOL_VERSION)
+        .write_to(&mut stream)?;
+    Ok(stream)
+}
+
+/// Reads the reply to request `id`, returning its type
+/// and the fields that follow the request id
+fn read_reply(stream: &mut UnixStream, id: u32) -> anyhow::Result<(u32, Vec<u8>)> {
+    let payload =
+        read_message(stream)?.ok_or_else(|| anyhow!("control master closed the connection"))?;
+    let mut p = Payload { buf: &payload };
+    let msg_type = p.u32()?;
+    let reply_id = p.u32()?;
+    if reply_id != id {
+        anyhow::bail!("control master replied to request {reply_id} rather than {id}");
+    }
+    match msg_type {
+        MUX_S_PERMISSION_DENIED => anyhow::bail!("permission denied: {}", p.string()?),
+        MUX_S_FAILURE => anyhow::bail!("{}", p.string()?),
+        _ => Ok((msg_type, p.buf.to_vec())),
+    }
+}
+
+fn send_fd(stream: &UnixStream, fd: RawFd) -> anyhow::Result<()> {
+    stream
+        .as_raw_fd()
+        .send_fd(fd)
+        .context("passing file descriptor to control master")
+}
+
+fn set_cloexec(fd: RawFd) -> anyhow::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("setting FD_CLOEXEC");
+    }
+    Ok(())
+}
+
+fn winsize(size: PtySize) -> libc::winsize {
+    libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: size.pixel_width,
+        ws_ypixel: size.pixel_height,
+    }
+}
+
+/// Opens a local pty whose slave is handed to the master to use as
+/// the terminal for the session, returning its master and slave.
+/// The slave is put into raw mode, as the remote pty takes care of
+/// the line discipline.
+fn open_local_pty(size: PtySize) -> anyhow::Result<(FileDescriptor, FileDescriptor)> {
+    let mut master: RawFd = -1;
+    let mut slave: RawFd = -1;
+    let mut size = winsize(size);
+    let result = unsafe {
+        // BSDish systems may require mut pointers to some args
+        #[allow(clippy::unnecessary_mut_passed)]
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut size,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error()).context("openpty");
+    }
+    let master = unsafe { FileDescriptor::from_raw_fd(master) };
+    let slave = unsafe { FileDescriptor::from_raw_fd(slave) };
+    set_cloexec(master.as_raw_fd())?;
+    set_cloexec(slave.as_raw_fd())?;
+
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(slave.as_raw_fd(), &mut termios) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("tcgetattr");
+    }
+    unsafe { libc::cfmakeraw(&mut termios) };
+    if unsafe { libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("tcsetattr");
+    }
+
+    Ok((master, slave))
+}
+
+fn forward_message(msg_type: u32, id: u32, spec: &ForwardSpec) -> Message {
+    let (fwd_type, bind, host, port) = match spec {
+        ForwardSpec::Local { bind, host, port } => (MUX_FWD_LOCAL, bind, host.as_str(), *port),
+        ForwardSpec::Remote { bind, host, port } => (MUX_FWD_REMOTE, bind, host.as_str(), *port),
+        ForwardSpec::Dynamic { bind } => (MUX_FWD_DYNAMIC, bind, "", 0),
+    };
+    Message::new(msg_type)
+        .u32(id)
+        .u32(fwd_type)
+        .string(bind.address.as_deref().unwrap_or(""))
+        .u32(bind.port.into())
+        .string(host)
+        .u32(port.into())
+}
+
+impl ControlClient {
+    pub fn connect(
+        path: &Path,
+        rx_req: Receiver<SessionRequest>,
+        sender_read: FileDescriptor,
+    ) -> anyhow::Result<Self> {
+        let control = connect(path)?;
+        let mut client = Self {
+            path: path.to_path_buf(),
+            rx_req,
+            sender_read,
+            control,
+            master_pid: 0,
+            next_request_id: 0,
+            next_channel_id: 1,
+            next_forward_id: 1,
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            forwards: HashMap::new(),
+            shown_sftp_error: false,
+        };
+        client.alive_check()?;
+        log::debug!(
+            "using ssh control socket {}, served by pid {}",
+            path.display(),
+            client.master_pid
+        );
+        Ok(client)
+    }
+
+    pub fn run(mut self) {
+        if let Err(err) = self.run_impl() {
+            log::error!("ssh control socket {}: {:#}", self.path.display(), err);
+        }
+
+        // Let the master know that we no longer need these
+        let forwards = self
+            .forwards
+            .drain()
+            .map(|(_, info)| info)
+            .collect::<Vec<_>>();
+        for info in forwards {
+            let _ = self.close_forward(&info.spec);
+        }
+        for (_, channel) in self.channels.lock().unwrap().drain() {
+            let _ = channel.stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    fn run_impl(&mut self) -> anyhow::Result<()> {
+        loop {
+            let mut pfd = [pollfd {
+                fd: self.sender_read.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            if poll(&mut pfd, Some(ALIVE_CHECK_INTERVAL))? == 0 {
+                self.alive_check()
+                    .context("control master is no longer responding")?;
+                continue;
+            }
+
+            // Drain the wakeup bytes; the requests are in the channel
+            let mut buf = [0u8; 64];
+            while let Ok(n) = self.sender_read.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+
+            loop {
+                match self.rx_req.try_recv() {
+                    Ok(SessionRequest::SessionDropped) => return Ok(()),
+                    Ok(req) => self.dispatch(req)?,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+    }
+
+    fn next_request_id(&mut self) -> u32 {
+        let id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        id
+    }
+
+    /// Handles a request.  Errors that are specific to the request are
+    /// sent back to the requester, while errors returned from here mean
+    /// that we can no longer talk to the master.
+    fn dispatch(&mut self, req: SessionRequest) -> anyhow::Result<()> {
+        match req {
+            SessionRequest::NewPty(newpty, reply) => {
+                reply.try_send(self.new_pty(newpty)).ok();
+            }
+            SessionRequest::ResizePty(resize, reply) => {
+                let result = self.resize_pty(resize);
+                if let Some(reply) = reply {
+                    reply.try_send(result).ok();
+                } else if let Err(err) = result {
+                    log::error!("resizing pty: {:#}", err);
+                }
+            }
+            SessionRequest::Exec(exec, reply) => {
+                reply.try_send(self.exec(exec)).ok();
+            }
+            SessionRequest::OpenTunnel(host, port, reply) => {
+                reply.try_send(self.open_tunnel(&host, port)).ok();
+            }
+            SessionRequest::SignalChannel(signal) => {
+                // There's no way to deliver a signal through the master,
+                // so hang up the session instead
+                if let Some(channel) = self.channels.lock().unwrap().remove(&signal.channel) {
+                    log::debug!(
+                        "cannot send SIG{} via the control master; hanging up instead",
+                        signal.signame
+                    );
+                    let _ = channel.stream.shutdown(std::net::Shutdown::Both);
+                }
+            }
+            SessionRequest::Forward(spec, reply) => {
+                let result = self.forward(spec);
+                self.check_control_result(&result)?;
+                reply.try_send(result).ok();
+            }
+            SessionRequest::CancelForward(id, reply) => {
+                let result = match self.forwards.get(&id).map(|info| info.spec.clone()) {
+                    Some(spec) => self.close_forward(&spec).map(|()| {
+                        self.forwards.remove(&id);
+                    }),
+                    None => Err(anyhow!("no forward with id {id}")),
+                };
+                self.check_control_result(&result)?;
+                reply.try_send(result).ok();
+            }
+            SessionRequest::ListForwards(reply) => {
+                let mut forwards = self.forwards.values().cloned().collect::<Vec<_>>();
+                forwards.sort_by_key(|info| info.id);
+                reply.try_send(forwards).ok();
+            }
+            SessionRequest::Sftp(_) => {
+                // Dropping the reply fails the request
+                if !self.shown_sftp_error {
+                    log::error!(
+                        "sftp is not available through the ssh control socket {}",
+                        self.path.display()
+                    );
+                    self.shown_sftp_error = true;
+                }
+            }
+            SessionRequest::SessionDropped => {}
+        }
+        Ok(())
+    }
+
+    /// A failed forward request is reported to the requester, but if
+    /// the failure was that the master went away, we're done too
+    fn check_control_result<T>(&self, result: &anyhow::Result<T>) -> anyhow::Result<()> {
+        if let Err(err) = result {
+            if err.downcast_ref::<std::io::Error>().is_some() {
+                anyhow::bail!("lost the connection to the control master: {:#}", err);
+            }
+        }
+        Ok(())
+    }
+
+    fn alive_check(&mut self) -> anyhow::Result<()> {
+        let id = self.next_request_id();
+        Message::new(MUX_C_ALIVE_CHECK)
+            .u32(id)
+            .write_to(&mut self.control)?;
+        match read_reply(&mut self.control, id)? {
+            (MUX_S_ALIVE, fields) => {
+                self.master_pid = Payload { buf: &fields }.u32()?;
+                Ok(())
+            }
+            (msg_type, _) => anyhow::bail!("unexpected reply {msg_type:#x} to alive check"),
+        }
+    }
+
+    fn forward(&mut self, spec: ForwardSpec) -> anyhow::Result<ForwardInfo> {
+        let id = self.next_request_id();
+        forward_message(MUX_C_OPEN_FWD, id, &spec).write_to(&mut self.control)?;
+        let bound_port = match read_reply(&mut self.control, id)? {
+            (MUX_S_OK, _) => match &spec {
+                ForwardSpec::Local { bind, .. }
+                | ForwardSpec::Remote { bind, .. }
+                | ForwardSpec::Dynamic { bind } => bind.port,
+            },
+            (MUX_S_REMOTE_PORT, fields) => {
+                let port = Payload { buf: &fields }.u32()?;
+                u16::try_from(port).map_err(|_| anyhow!("invalid remote port {port}"))?
+            }
+            (msg_type, _) => anyhow::bail!("unexpected reply {msg_type:#x} to forward request"),
+        };
+
+        let info = ForwardInfo {
+            id: self.next_forward_id,
+            spec,
+            bound_port,
+        };
+        self.next_forward_id += 1;
+        self.forwards.insert(info.id, info.clone());
+        Ok(info)
+    }
+
+    fn close_forward(&mut self, spec: &ForwardSpec) -> anyhow::Result<()> {
+        let id = self.next_request_id();
+        forward_message(MUX_C_CLOSE_FWD, id, spec).write_to(&mut self.control)?;
+        match read_reply(&mut self.control, id)? {
+            (MUX_S_OK, _) => Ok(()),
+            (msg_type, _) => anyhow::bail!("unexpected reply {msg_type:#x} to cancel forward"),
+        }
+    }
+
+    /// Asks the master to start a session whose stdio is `fds`,
+    /// returning the connection that is dedicated to it
+    fn new_session(
+        &mut self,
+        want_tty: bool,
+        term: &str,
+        command: &str,
+        env: Option<&HashMap<String, String>>,
+        fds: [&FileDescriptor; 3],
+    ) -> anyhow::Result<UnixStream> {
+        let mut stream = connect(&self.path)?;
+        let id = self.next_request_id();
+        let mut msg = Message::new(MUX_C_NEW_SESSION)
+            .u32(id)
+            .string("")
+            .u32(want_tty.into())
+            // X11 and agent forwarding, and subsystem
+            .u32(0)
+            .u32(0)
+            .u32(0)
+            .u32(NO_ESCAPE_CHAR)
+            .string(term)
+            .string(command);
+        for (name, value) in env.into_iter().flatten() {
+            msg = msg.string(&format!("{name}={value}"));
+        }
+        msg.write_to(&mut stream)?;
+        for fd in fds {
+            send_fd(&stream, fd.as_raw_fd())?;
+        }
+
+        match read_reply(&mut stream, id)? {
+            (MUX_S_SESSION_OPENED, _) => Ok(stream),
+            (msg_type, _) => anyhow::bail!("unexpected reply {msg_type:#x} to new session"),
+        }
+    }
+
+    /// Registers a session that was opened through the master,
+    /// and waits in the background for the master to report its
+    /// exit status
+    fn add_channel(
+        &mut self,
+        stream: UnixStream,
+        pty: Option<FileDescriptor>,
+    ) -> anyhow::Result<SshChildProcess> {
+        let channel_id = self.next_channel_id;
+        self.next_channel_id += 1;
+
+        // Sessions last as long as they like
+        stream.set_read_timeout(None)?;
+        let (exit_tx, exit_rx) = bounded(1);
+        let mut reader = stream.try_clone()?;
+        self.channels
+            .lock()
+            .unwrap()
+            .insert(channel_id, ClientChannel { stream, pty });
+
+        let channels = Arc::clone(&self.channels);
+        std::thread::spawn(move || {
+            wait_for_exit(&mut reader, exit_tx);
+            channels.lock().unwrap().remove(&channel_id);
+        });
+
+        Ok(SshChildProcess {
+            channel: channel_id,
+            tx: None,
+            exit: exit_rx,
+            exited: None,
+        })
+    }
+
+    fn new_pty(&mut self, newpty: NewPty) -> anyhow::Result<(SshPty, SshChildProcess)> {
+        let (master, slave) = open_local_pty(newpty.size)?;
+        let stream = self.new_session(
+            true,
+            &newpty.term,
+            newpty.command_line.as_deref().unwrap_or(""),
+            newpty.env.as_ref(),
+            [&slave, &slave, &slave],
+        )?;
+        // The master has its own copies of the slave now
+        drop(slave);
+
+        let reader = master.try_clone()?;
+        let writer = master.try_clone()?;
+        let child = self.add_channel(stream, Some(master))?;
+        let ssh_pty = SshPty {
+            channel: child.channel,
+            tx: None,
+            reader,
+            writer,
+            size: Mutex::new(newpty.size),
+        };
+        Ok((ssh_pty, child))
+    }
+
+    fn resize_pty(&mut self, resize: ResizePty) -> anyhow::Result<()> {
+        let channels = self.channels.lock().unwrap();
+        let pty = channels
+            .get(&resize.channel)
+            .and_then(|channel| channel.pty.as_ref())
+            .ok_or_else(|| anyhow!("invalid channel id {}", resize.channel))?;
+        let size = winsize(resize.size);
+        if unsafe { libc::ioctl(pty.as_raw_fd(), libc::TIOCSWINSZ as _, &size) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("TIOCSWINSZ");
+        }
+        // Like the `ssh` mux client, prompt the master to look at the
+        // size of the terminal that we gave it
+        if self.master_pid != 0 {
+            unsafe { libc::kill(self.master_pid as libc::pid_t, libc::SIGWINCH) };
+        }
+        Ok(())
+    }
+
+    fn exec(&mut self, exec: Exec) -> anyhow::Result<ExecResult> {
+        let (stdin, remote_stdin) = socketpair()?;
+        let (stdout, remote_stdout) = socketpair()?;
+        let (stderr, remote_stderr) = socketpair()?;
+        let stream = self.new_session(
+            false,
+            "",
+            &exec.command_line,
+            exec.env.as_ref(),
+            [&remote_stdin, &remote_stdout, &remote_stderr],
+        )?;
+        let child = self.add_channel(stream, None)?;
+        Ok(ExecResult {
+            stdin,
+            stdout,
+            stderr,
+            child,
+        })
+    }
+
+    fn open_tunnel(&mut self, host: &str, port: u16) -> anyhow::Result<FileDescriptor> {
+        let (local, remote) = socketpair()?;
+        let mut stream = connect(&self.path)?;
+        let id = self.next_request_id();
+        Message::new(MUX_C_NEW_STDIO_FWD)
+            .u32(id)
+            .string("")
+            .string(host)
+            .u32(port.into())
+            .write_to(&mut stream)?;
+        send_fd(&stream, remote.as_raw_fd())?;
+        send_fd(&stream, remote.as_raw_fd())?;
+
+        match read_reply(&mut stream, id)? {
+            (MUX_S_SESSION_OPENED, _) => {}
+            (msg_type, _) => anyhow::bail!("unexpected reply {msg_type:#x} to open tunnel"),
+        }
+
+        // The master tears down the tunnel when the connection that
+        // requested it is closed, so hold it open until the master
+        // closes it at the end of the tunnel
+        stream.set_read_timeout(None)?;
+        std::thread::spawn(move || while let Ok(Some(_)) = read_message(&mut stream) {});
+        Ok(local)
+    }
+}
+
+/// Reads the messages that the master sends about a session until
+/// it reports the exit status of the session or hangs up
+fn wait_for_exit(stream: &mut UnixStream, exit_tx: Sender<ExitStatus>) {
+    while let Ok(Some(payload)) = read_message(stream) {
+        let mut p = Payload { buf: &payload };
+        if let (Ok(MUX_S_EXIT_MESSAGE), Ok(_session_id), Ok(exit_code)) =
+            (p.u32(), p.u32(), p.u32())
+        {
+            exit_tx.try_send(ExitStatus::with_exit_code(exit_code)).ok();
+            return;
+        }
+    }
+    // Dropping exit_tx without a status reports a failure
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::forward::BindAddress;
+    use crate::session::Session;
+    use portable_pty::MasterPty;
+    use std::io::Write;
+    use std::os::unix::net::UnixListener;
+
+    /// Plays the part of the master well enough to serve the
+    /// requests that the test makes of it
+    fn fake_master(listener: UnixListener) {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            std::thread::spawn(move || {
+                Message::new(MUX_MSG_HELLO)
+                    .u32(MUX_PROTOCOL_VERSION)
+                    .write_to(&mut stream)
+                    .unwrap();
+                while let Ok(Some(payload)) = read_message(&mut stream) {
+                    let mut p = Payload { buf: &payload };
+                    let msg_type = p.u32().unwrap();
+                    if msg_type == MUX_MSG_HELLO {
+                        continue;
+                    }
+                    let id = p.u32().unwrap();
+                    match msg_type {
+                        MUX_C_ALIVE_CHECK => Message::new(MUX_S_ALIVE).u32(id).u32(0),
+                        MUX_C_OPEN_FWD => Message::new(MUX_S_REMOTE_PORT).u32(id).u32(4242),
+                        MUX_C_CLOSE_FWD => Message::new(MUX_S_OK).u32(id),
+                        MUX_C_NEW_SESSION => {
+                            let mut fds = vec![];
+                            for _ in 0..3 {
+                                let fd = stream.as_raw_fd().recv_fd().unwrap();
+                                fds.push(unsafe { FileDescriptor::from_raw_fd(fd) });
+                            }
+                            Message::new(MUX_S_SESSION_OPENED)
+                                .u32(id)
+                                .u32(1)
+                                .write_to(&mut stream)
+                                .unwrap();
+                            fds[1].write_all(b"hello\n").unwrap();
+                            drop(fds);
+                            Message::new(MUX_S_EXIT_MESSAGE).u32(1).u32(3)
+                        }
+                        _ => Message::new(MUX_S_FAILURE).u32(id).string("unsupported"),
+                    }
+                    .write_to(&mut stream)
+                    .unwrap();
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn session_via_control_socket() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("control");
+        let listener = UnixListener::bind(&path).unwrap();
+        std::thread::spawn(move || fake_master(listener));
+
+        let session = Session::connect_control_socket(&path).unwrap();
+
+        let mut exec = smol::block_on(session.exec("greet", None)).unwrap();
+        let mut output = String::new();
+        exec.stdout.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "hello\n");
+        let status = smol::block_on(exec.child.async_wait()).unwrap();
+        assert_eq!(status.exit_code(), 3);
+
+        // The local pty is in raw mode, so the output is untranslated
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            ..PtySize::default()
+        };
+        let (pty, mut child) =
+            smol::block_on(session.request_pty("xterm", size, None, None)).unwrap();
+        let mut output = [0u8; 6];
+        pty.reader
+            .try_clone()
+            .unwrap()
+            .read_exact(&mut output)
+            .unwrap();
+        assert_eq!(&output, b"hello\n");
+        let size = PtySize { rows: 50, ..size };
+        pty.resize(size).unwrap();
+        assert_eq!(pty.get_size().unwrap(), size);
+        assert_eq!(smol::block_on(child.async_wait()).unwrap().exit_code(), 3);
+
+        let spec = ForwardSpec::Remote {
+            bind: BindAddress {
+                address: None,
+                port: 0,
+            },
+            host: "localhost".to_string(),
+            port: 8080,
+        };
+        let info = smol::block_on(session.forward(spec.clone())).unwrap();
+        assert_eq!(info.bound_port, 4242);
+        assert_eq!(
+            smol::block_on(session.list_forwards()).unwrap(),
+            vec![info.clone()]
+        );
+        smol::block_on(session.cancel_forward(info.id)).unwrap();
+        assert!(smol::block_on(session.list_forwards()).unwrap().is_empty());
+
+        assert!(smol::block_on(session.sftp().metadata("/")).is_err());
+        assert!(!session.is_closed());
+    }
+}
@@ -0,0 +1,894 @@
+//! Serves an OpenSSH compatible control socket for a Session, so that
+//! `ssh` and `scp` configured with a matching `ControlPath` can run
+//! their sessions over our connection rather than making their own.
+//! The protocol is described in PROTOCOL.mux in the OpenSSH sources.
+use crate::config::ConfigMap;
+use crate::forward::{BindAddress, ForwardSpec};
+use crate::session::{Session, WeakSession};
+use anyhow::{anyhow, Context};
+use filedescriptor::{poll, pollfd, AsRawSocketDescriptor, FileDescriptor, POLLIN};
+use passfd::FdPassingExt;
+use portable_pty::{Child, MasterPty, PtySize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) const MUX_MSG_HELLO: u32 = 0x0000_0001;
+pub(crate) const MUX_C_NEW_SESSION: u32 = 0x1000_0002;
+pub(crate) const MUX_C_ALIVE_CHECK: u32 = 0x1000_0004;
+const MUX_C_TERMINATE: u32 = 0x1000_0005;
+pub(crate) const MUX_C_OPEN_FWD: u32 = 0x1000_0006;
+pub(crate) const MUX_C_CLOSE_FWD: u32 = 0x1000_0007;
+pub(crate) const MUX_C_NEW_STDIO_FWD: u32 = 0x1000_0008;
+const MUX_C_STOP_LISTENING: u32 = 0x1000_0009;
+pub(crate) const MUX_S_OK: u32 = 0x8000_0001;
+pub(crate) const MUX_S_PERMISSION_DENIED: u32 = 0x8000_0002;
+pub(crate) const MUX_S_FAILURE: u32 = 0x8000_0003;
+pub(crate) const MUX_S_EXIT_MESSAGE: u32 = 0x8000_0004;
+pub(crate) const MUX_S_ALIVE: u32 = 0x8000_0005;
+pub(crate) const MUX_S_SESSION_OPENED: u32 = 0x8000_0006;
+pub(crate) const MUX_S_REMOTE_PORT: u32 = 0x8000_0007;
+
+pub(crate) const MUX_FWD_LOCAL: u32 = 1;
+pub(crate) const MUX_FWD_REMOTE: u32 = 2;
+pub(crate) const MUX_FWD_DYNAMIC: u32 = 3;
+
+pub(crate) const MUX_PROTOCOL_VERSION: u32 = 4;
+
+/// Guards against a misbehaving client making us allocate
+/// an unreasonable amount of memory
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+/// A message being built up for sending to the other end of the socket
+pub(crate) struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    pub fn new(msg_type: u32) -> Self {
+        Self { buf: vec![] }.u32(msg_type)
+    }
+
+    pub fn u32(mut self, value: u32) -> Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn string(mut self, value: &str) -> Self {
+        self = self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = (self.buf.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&self.buf);
+        data
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&self.encode())?;
+        w.flush()
+    }
+}
+
+/// Decodes the fields of a message received from the other end of the socket
+pub(crate) struct Payload<'a> {
+    pub buf: &'a [u8],
+}
+
+impl<'a> Payload<'a> {
+    pub fn u32(&mut self) -> anyhow::Result<u32> {
+        if self.buf.len() < 4 {
+            anyhow::bail!("truncated control message");
+        }
+        let (value, rest) = self.buf.split_at(4);
+        self.buf = rest;
+        Ok(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+    }
+
+    pub fn string(&mut self) -> anyhow::Result<String> {
+        let len = self.u32()? as usize;
+        if self.buf.len() < len {
+            anyhow::bail!("truncated control message");
+        }
+        let (value, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(String::from_utf8_lossy(value).into_owned())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+/// Reads the payload of the next message.
+/// Returns None if the other end closed the connection.
+pub(crate) fn read_message<R: Read>(r: &mut R) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        anyhow::bail!("control message length {len} exceeds {MAX_MESSAGE_LEN}");
+    }
+    let mut payload = vec![0u8; len];
+    r.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+#[derive(Debug, PartialEq)]
+enum Request {
+    Hello {
+        version: u32,
+    },
+    NewSession {
+        id: u32,
+        want_tty: bool,
+        subsystem: bool,
+        term: String,
+        command: String,
+        env: Vec<String>,
+    },
+    AliveCheck {
+        id: u32,
+    },
+    Terminate {
+        id: u32,
+    },
+    OpenForward {
+        id: u32,
+        spec: ForwardSpec,
+    },
+    CloseForward {
+        id: u32,
+        spec: ForwardSpec,
+    },
+    NewStdioForward {
+        id: u32,
+        host: String,
+        port: u16,
+    },
+    StopListening {
+        id: u32,
+    },
+    Unknown {
+        msg_type: u32,
+        id: Option<u32>,
+    },
+}
+
+fn port_number(port: u32) -> anyhow::Result<u16> {
+    // Unix domain socket forwards use a port of -2
+    u16::try_from(port).map_err(|_| anyhow!("forwarding unix domain sockets is not supported"))
+}
+
+fn parse_forward_spec(p: &mut Payload) -> anyhow::Result<ForwardSpec> {
+    let fwd_type = p.u32()?;
+    let listen_host = p.string()?;
+    let listen_port = p.u32()?;
+    let connect_host = p.string()?;
+    let connect_port = p.u32()?;
+
+    let bind = BindAddress {
+        address: if listen_host.is_empty() {
+            None
+        } else {
+            Some(listen_host)
+        },
+        port: port_number(listen_port)?,
+    };
+
+    match fwd_type {
+        MUX_FWD_LOCAL => Ok(ForwardSpec::Local {
+            bind,
+            host: connect_host,
+            port: port_number(connect_port)?,
+        }),
+        MUX_FWD_REMOTE => Ok(ForwardSpec::Remote {
+            bind,
+            host: connect_host,
+            port: port_number(connect_port)?,
+        }),
+        MUX_FWD_DYNAMIC => Ok(ForwardSpec::Dynamic { bind }),
+        _ => anyhow::bail!("unknown forwarding type {fwd_type}"),
+    }
+}
+
+impl Request {
+    fn parse(payload: &[u8]) -> anyhow::Result<Self> {
+        let mut p = Payload { buf: payload };
+        let msg_type = p.u32()?;
+        match msg_type {
+            MUX_MSG_HELLO => {
+                // Any extensions that follow are ignored
+                let version = p.u32()?;
+                Ok(Self::Hello { version })
+            }
+            MUX_C_NEW_SESSION => {
+                let id = p.u32()?;
+                let _reserved = p.string()?;
+                let want_tty = p.u32()? != 0;
+                let _want_x11 = p.u32()?;
+                let _want_agent = p.u32()?;
+                let subsystem = p.u32()? != 0;
+                let _escape_char = p.u32()?;
+                let term = p.string()?;
+                let command = p.string()?;
+                let mut env = vec![];
+                while !p.is_empty() {
+                    env.push(p.string()?);
+                }
+                Ok(Self::NewSession {
+                    id,
+                    want_tty,
+                    subsystem,
+                    term,
+                    command,
+                    env,
+                })
+            }
+            MUX_C_ALIVE_CHECK => Ok(Self::AliveCheck { id: p.u32()? }),
+            MUX_C_TERMINATE => Ok(Self::Terminate { id: p.u32()? }),
+            MUX_C_OPEN_FWD => {
+                let id = p.u32()?;
+                let spec = parse_forward_spec(&mut p)?;
+                Ok(Self::OpenForward { id, spec })
+            }
+            MUX_C_CLOSE_FWD => {
+                let id = p.u32()?;
+                let spec = parse_forward_spec(&mut p)?;
+                Ok(Self::CloseForward { id, spec })
+            }
+            MUX_C_NEW_STDIO_FWD => {
+                let id = p.u32()?;
+                let _reserved = p.string()?;
+                let host = p.string()?;
+                let port = port_number(p.u32()?)?;
+                Ok(Self::NewStdioForward { id, host, port })
+            }
+            MUX_C_STOP_LISTENING => Ok(Self::StopListening { id: p.u32()? }),
+            msg_type => Ok(Self::Unknown {
+                msg_type,
+                id: p.u32().ok(),
+            }),
+        }
+    }
+}
+
+struct Shared {
+    path: PathBuf,
+    shutdown: AtomicBool,
+    next_session_id: AtomicU32,
+}
+
+/// Serves a control socket for a Session until it is dropped,
+/// or until the Session is closed
+pub struct ControlMaster {
+    shared: Arc<Shared>,
+}
+
+impl Drop for ControlMaster {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ControlMaster {
+    /// Serves a control socket for `session` if the `ControlMaster`
+    /// and `ControlPath` options in `config` call for one.
+    /// Returns None if they don't, or if another process is already
+    /// serving the control socket.
+    pub fn from_config(session: &Session, config: &ConfigMap) -> anyhow::Result<Option<Self>> {
+        let mode = config
+            .get("controlmaster")
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        match mode.as_str() {
+            "yes" | "auto" => {}
+            "ask" | "autoask" => {
+                log::warn!(
+                    "ControlMaster {mode} requires confirming each use of the \
+                     control socket, which is not supported; not serving it"
+                );
+                return Ok(None);
+            }
+            _ => return Ok(None),
+        }
+
+        let path = match config.get("controlpath") {
+            Some(path) if path != "none" => Path::new(path),
+            _ => return Ok(None),
+        };
+
+        if UnixStream::connect(path).is_ok() {
+            log::debug!("control socket {} is already being served", path.display());
+            return Ok(None);
+        }
+
+        Self::start(session, path).map(Some)
+    }
+
+    /// Serves a control socket for `session` at `path`
+    pub fn start(session: &Session, path: &Path) -> anyhow::Result<Self> {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("control socket {} is already in use", path.display());
+        }
+        if let Ok(meta) = std::fs::symlink_metadata(path) {
+            if !meta.file_type().is_socket() {
+                anyhow::bail!(
+                    "cannot serve a control socket at {} because \
+                     a file that isn't a socket already exists there",
+                    path.display()
+                );
+            }
+            // Left behind by a process that has since gone away
+            std::fs::remove_file(path)
+                .with_context(|| format!("removing stale control socket {}", path.display()))?;
+        }
+
+        let listener = bind_private_socket(path)?;
+        listener.set_nonblocking(true)?;
+
+        let shared = Arc::new(Shared {
+            path: path.to_path_buf(),
+            shutdown: AtomicBool::new(false),
+            next_session_id: AtomicU32::new(0),
+        });
+
+        let weak = session.downgrade();
+        let thread_shared = Arc::clone(&shared);
+        std::thread::Builder::new()
+            .name(format!("ssh control socket {}", path.display()))
+            .spawn(move || accept_loop(listener, weak, thread_shared))?;
+
+        log::debug!("serving ssh control socket {}", path.display());
+        Ok(Self { shared })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.shared.path
+    }
+}
+
+/// Binds a unix socket at `path` that only we can connect to.
+/// The socket is bound inside a directory that only we can access,
+/// and is moved into place once its permissions are restricted,
+/// so that no one else can connect to it in the meantime.
+pub fn bind_private_socket(path: &Path) -> anyhow::Result<UnixListener> {
+    let temp_dir = PathBuf::from(format!("{}.{}.d", path.display(), std::process::id()));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&temp_dir)
+        .with_context(|| format!("creating private directory {}", temp_dir.display()))?;
+    let temp_path = temp_dir.join("sock");
+
+    let result = UnixListener::bind(&temp_path)
+        .with_context(|| format!("binding socket {}", temp_path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600))
+                .and_then(|_| std::fs::rename(&temp_path, path))
+                .with_context(|| format!("creating socket {}", path.display()))?;
+            Ok(listener)
+        });
+
+    let _ = std::fs::remove_file(&temp_path);
+    let _ = std::fs::remove_dir(&temp_dir);
+    result
+}
+
+/// Returns the effective uid of the process at the other end of `stream`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if res != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// Returns the effective uid of the process at the other end of `stream`
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<libc::uid_t> {
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// Like OpenSSH, only allow our own user and root to use the socket
+fn check_peer(stream: &UnixStream) -> anyhow::Result<()> {
+    let uid = peer_uid(stream).context("getting the uid of the client")?;
+    let our_uid = unsafe { libc::geteuid() };
+    if uid != 0 && uid != our_uid {
+        anyhow::bail!("rejecting client with uid {uid}, which is not ours ({our_uid})");
+    }
+    Ok(())
+}
+
+fn accept_loop(listener: UnixListener, weak: WeakSession, shared: Arc<Shared>) {
+    // Wake up periodically so that we notice when we have been
+    // dropped or the session has gone away
+    let interval = Duration::from_secs(1);
+    loop {
+        if shared.shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        let session = match weak.upgrade() {
+            Some(session) if !session.is_closed() => session,
+            _ => break,
+        };
+
+        let mut pfd = [pollfd {
+            fd: listener.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        }];
+        if let Err(err) = poll(&mut pfd, Some(interval)) {
+            log::error!(
+                "polling control socket {}: {:#}",
+                shared.path.display(),
+                err
+            );
+            break;
+        }
+
+        match listener.accept() {
+            Ok((stream, _addr)) => {
+                let shared = Arc::clone(&shared);
+                std::thread::spawn(move || {
+                    if let Err(err) = serve_client(stream, session, &shared) {
+                        log::error!("ssh control socket client: {:#}", err);
+                    }
+                });
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(err) => {
+                log::error!(
+                    "accepting on control socket {}: {:#}",
+                    shared.path.display(),
+                    err
+                );
+                break;
+            }
+        }
+    }
+
+    log::debug!(
+        "no longer serving ssh control socket {}",
+        shared.path.display()
+    );
+    let _ = std::fs::remove_file(&shared.path);
+}
+
+fn send_failure(stream: &mut UnixStream, id: u32, reason: &str) -> anyhow::Result<()> {
+    Message::new(MUX_S_FAILURE)
+        .u32(id)
+        .string(reason)
+        .write_to(stream)?;
+    Ok(())
+}
+
+fn serve_client(mut stream: UnixStream, session: Session, shared: &Shared) -> anyhow::Result<()> {
+    check_peer(&stream)?;
+    stream.set_nonblocking(false)?;
+
+    Message::new(MUX_MSG_HELLO)
+        .u32(MUX_PROTOCOL_VERSION)
+        .write_to(&mut stream)?;
+    let hello = read_message(&mut stream)?.ok_or_else(|| anyhow!("client went away"))?;
+    match Request::parse(&hello)? {
+        Request::Hello { version } if version == MUX_PROTOCOL_VERSION => {}
+        Request::Hello { version } => {
+            anyhow::bail!("unsupported control protocol version {version}")
+        }
+        request => anyhow::bail!("expected hello but got {request:?}"),
+    }
+
+    while let Some(payload) = read_message(&mut stream)? {
+        match Request::parse(&payload)? {
+            Request::Hello { .. } => anyhow::bail!("unexpected hello"),
+            Request::AliveCheck { id } => {
+                Message::new(MUX_S_ALIVE)
+                    .u32(id)
+                    .u32(std::process::id())
+                    .write_to(&mut stream)?;
+            }
+            Request::Terminate { id } => {
+                Message::new(MUX_S_PERMISSION_DENIED)
+                    .u32(id)
+                    .string("this connection is shared with wezterm and cannot be terminated")
+                    .write_to(&mut stream)?;
+            }
+            Request::OpenForward { id, spec } => {
+                let assigns_remote_port =
+                    matches!(&spec, ForwardSpec::Remote { bind, .. } if bind.port == 0);
+                match smol::block_on(session.forward(spec)) {
+                    Ok(info) if assigns_remote_port => {
+                        Message::new(MUX_S_REMOTE_PORT)
+                            .u32(id)
+                            .u32(info.bound_port.into())
+                            .write_to(&mut stream)?;
+                    }
+                    Ok(_) => Message::new(MUX_S_OK).u32(id).write_to(&mut stream)?,
+                    Err(err) => send_failure(&mut stream, id, &format!("{:#}", err))?,
+                }
+            }
+            Request::CloseForward { id, spec } => {
+                let result = smol::block_on(async {
+                    let forwards = session.list_forwards().await?;
+                    let info = forwards
+                        .into_iter()
+                        .find(|info| info.spec == spec)
+                        .ok_or_else(|| anyhow!("no forward matches {spec}"))?;
+                    session.cancel_forward(info.id).await
+                });
+                match result {
+                    Ok(()) => Message::new(MUX_S_OK).u32(id).write_to(&mut stream)?,
+                    Err(err) => send_failure(&mut stream, id, &format!("{:#}", err))?,
+                }
+            }
+            Request::StopListening { id } => {
+                shared.shutdown.store(true, Ordering::SeqCst);
+                Message::new(MUX_S_OK).u32(id).write_to(&mut stream)?;
+            }
+            Request::NewSession {
+                id,
+                want_tty,
+                subsystem,
+                term,
+                command,
+                env,
+            } => {
+                // The connection is dedicated to the session from here on
+                let stdin = receive_fd(&stream)?;
+                let stdout = receive_fd(&stream)?;
+                let stderr = receive_fd(&stream)?;
+                let env = env
+                    .iter()
+                    .filter_map(|var| var.split_once('='))
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect::<HashMap<_, _>>();
+                let env = if env.is_empty() { None } else { Some(env) };
+
+                if subsystem {
+                    return send_failure(&mut stream, id, "subsystems are not supported");
+                }
+
+                let session_id = shared.next_session_id.fetch_add(1, Ordering::SeqCst);
+                let exit_code = if want_tty {
+                    let command = if command.is_empty() {
+                        None
+                    } else {
+                        Some(command.as_str())
+                    };
+                    let size = terminal_size(stdin.as_raw_fd());
+                    let (pty, mut child) =
+                        match smol::block_on(session.request_pty(&term, size, command, env)) {
+                            Ok(pair) => pair,
+                            Err(err) => {
+                                return send_failure(&mut stream, id, &format!("{:#}", err))
+                            }
+                        };
+                    Message::new(MUX_S_SESSION_OPENED)
+                        .u32(id)
+                        .u32(session_id)
+                        .write_to(&mut stream)?;
+
+                    let done = Arc::new(AtomicBool::new(false));
+                    let output = relay_output(pty.try_clone_reader()?, stdout);
+                    let writer = pty.take_writer()?;
+                    watch_terminal_size(stdin.as_raw_fd(), pty, Arc::clone(&done));
+                    relay_input(stdin, writer, Arc::clone(&done));
+
+                    let status = child.wait()?;
+                    let _ = output.join();
+                    done.store(true, Ordering::SeqCst);
+                    status.exit_code()
+                } else {
+                    if command.is_empty() {
+                        return send_failure(
+                            &mut stream,
+                            id,
+                            "a command is required when no tty is requested",
+                        );
+                    }
+                    let mut exec = match smol::block_on(session.exec(&command, env)) {
+                        Ok(exec) => exec,
+                        Err(err) => return send_failure(&mut stream, id, &format!("{:#}", err)),
+                    };
+                    Message::new(MUX_S_SESSION_OPENED)
+                        .u32(id)
+                        .u32(session_id)
+                        .write_to(&mut stream)?;
+
+                    let done = Arc::new(AtomicBool::new(false));
+                    let output = relay_output(exec.stdout, stdout);
+                    let errors = relay_output(exec.stderr, stderr);
+                    relay_input(stdin, exec.stdin, Arc::clone(&done));
+
+                    let status = exec.child.wait()?;
+                    let _ = output.join();
+                    let _ = errors.join();
+                    done.store(true, Ordering::SeqCst);
+                    status.exit_code()
+                };
+
+                Message::new(MUX_S_EXIT_MESSAGE)
+                    .u32(session_id)
+                    .u32(exit_code)
+                    .write_to(&mut stream)?;
+                return Ok(());
+            }
+            Request::NewStdioForward { id, host, port } => {
+                // The connection is dedicated to the tunnel from here on
+                let stdin = receive_fd(&stream)?;
+                let stdout = receive_fd(&stream)?;
+
+                let tunnel = match smol::block_on(session.open_tunnel(&host, port)) {
+                    Ok(tunnel) => tunnel,
+                    Err(err) => return send_failure(&mut stream, id, &format!("{:#}", err)),
+                };
+                let session_id = shared.next_session_id.fetch_add(1, Ordering::SeqCst);
+                Message::new(MUX_S_SESSION_OPENED)
+                    .u32(id)
+                    .u32(session_id)
+                    .write_to(&mut stream)?;
+
+                let done = Arc::new(AtomicBool::new(false));
+                relay_input(stdin, tunnel.try_clone()?, Arc::clone(&done));
+                let _ = relay_output(tunnel, stdout).join();
+                done.store(true, Ordering::SeqCst);
+                return Ok(());
+            }
+            Request::Unknown { msg_type, id } => {
+                log::debug!("unsupported control message type {msg_type:#x}");
+                match id {
+                    Some(id) => send_failure(&mut stream, id, "unsupported request")?,
+                    None => anyhow::bail!("malformed control message type {msg_type:#x}"),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn receive_fd(stream: &UnixStream) -> anyhow::Result<FileDescriptor> {
+    let fd = stream
+        .as_raw_fd()
+        .recv_fd()
+        .context("receiving file descriptor from control client")?;
+    Ok(unsafe { FileDescriptor::from_raw_fd(fd) })
+}
+
+/// Copies from `src` to `dest` until `src` reaches EOF
+fn relay_output<R: Read + Send + 'static>(
+    mut src: R,
+    mut dest: FileDescriptor,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut src, &mut dest);
+    })
+}
+
+/// Copies from `src` to `dest` until `done` is set.
+/// The client's stdin is frequently its controlling terminal,
+/// which remains open after the client has gone away, so
+/// we mustn't keep blocking in read once the session is over.
+fn relay_input<W: Write + Send + 'static>(
+    mut src: FileDescriptor,
+    mut dest: W,
+    done: Arc<AtomicBool>,
+) {
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        while !done.load(Ordering::SeqCst) {
+            let mut pfd = [pollfd {
+                fd: src.as_socket_descriptor(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            match poll(&mut pfd, Some(Duration::from_millis(100))) {
+                Ok(0) => continue,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+            match src.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if dest
+                        .write_all(&buf[..n])
+                        .and_then(|_| dest.flush())
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn terminal_size(fd: RawFd) -> PtySize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ as _, &mut size) } != 0 || size.ws_row == 0 {
+        return PtySize::default();
+    }
+    PtySize {
+        rows: size.ws_row,
+        cols: size.ws_col,
+        pixel_width: size.ws_xpixel,
+        pixel_height: size.ws_ypixel,
+    }
+}
+
+/// OpenSSH clients don't tell the master about changes to their
+/// window size; it is expected to track the size of the terminal
+/// that was passed to it, so we poll it for changes
+fn watch_terminal_size<P: MasterPty + Send + 'static>(tty: RawFd, pty: P, done: Arc<AtomicBool>) {
+    // Keep our own reference to the terminal, as the
+    // client may close its copy before we're done
+    let tty = match unsafe { libc::dup(tty) } {
+        -1 => return,
+        fd => unsafe { FileDescriptor::from_raw_fd(fd) },
+    };
+    std::thread::spawn(move || {
+        let mut size = terminal_size(tty.as_raw_fd());
+        while !done.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(250));
+            let new_size = terminal_size(tty.as_raw_fd());
+            if new_size != size {
+                size = new_size;
+                if pty.resize(size).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_message() {
+        let msg = Message::new(MUX_S_FAILURE).u32(3).string("nope");
+        assert_eq!(
+            msg.encode(),
+            vec![0, 0, 0, 16, 0x80, 0, 0, 3, 0, 0, 0, 3, 0, 0, 0, 4, b'n', b'o', b'p', b'e']
+        );
+
+        let payload = read_message(&mut msg.encode().as_slice()).unwrap().unwrap();
+        let mut p = Payload { buf: &payload };
+        assert_eq!(p.u32().unwrap(), MUX_S_FAILURE);
+        assert_eq!(p.u32().unwrap(), 3);
+        assert_eq!(p.string().unwrap(), "nope");
+        assert!(p.is_empty());
+        assert!(p.u32().is_err());
+
+        assert_eq!(read_message(&mut std::io::empty()).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_requests() {
+        let new_session = Message::new(MUX_C_NEW_SESSION)
+            .u32(1)
+            .string("")
+            .u32(1)
+            .u32(0)
+            .u32(0)
+            .u32(0)
+            .u32(0xffff_ffff)
+            .string("xterm-256color")
+            .string("uname -a")
+            .string("LANG=C");
+        assert_eq!(
+            Request::parse(&new_session.buf).unwrap(),
+            Request::NewSession {
+                id: 1,
+                want_tty: true,
+                subsystem: false,
+                term: "xterm-256color".to_string(),
+                command: "uname -a".to_string(),
+                env: vec!["LANG=C".to_string()],
+            }
+        );
+
+        let open_fwd = Message::new(MUX_C_OPEN_FWD)
+            .u32(2)
+            .u32(MUX_FWD_REMOTE)
+            .string("")
+            .u32(0)
+            .string("localhost")
+            .u32(8080);
+        assert_eq!(
+            Request::parse(&open_fwd.buf).unwrap(),
+            Request::OpenForward {
+                id: 2,
+                spec: ForwardSpec::Remote {
+                    bind: BindAddress {
+                        address: None,
+                        port: 0
+                    },
+                    host: "localhost".to_string(),
+                    port: 8080,
+                },
+            }
+        );
+
+        let unix_fwd = Message::new(MUX_C_OPEN_FWD)
+            .u32(3)
+            .u32(MUX_FWD_LOCAL)
+            .string("/tmp/sock")
+            .u32(-2i32 as u32)
+            .string("localhost")
+            .u32(22);
+        assert!(Request::parse(&unix_fwd.buf).is_err());
+
+        let stdio_fwd = Message::new(MUX_C_NEW_STDIO_FWD)
+            .u32(4)
+            .string("")
+            .string("inner")
+            .u32(22);
+        assert_eq!(
+            Request::parse(&stdio_fwd.buf).unwrap(),
+            Request::NewStdioForward {
+                id: 4,
+                host: "inner".to_string(),
+                port: 22,
+            }
+        );
+
+        let unknown = Message::new(0x1000_0042).u32(5);
+        assert_eq!(
+            Request::parse(&unknown.buf).unwrap(),
+            Request::Unknown {
+                msg_type: 0x1000_0042,
+                id: Some(5),
+            }
+        );
+    }
+
+    #[test]
+    fn private_socket() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("control");
+        let listener = bind_private_socket(&path).unwrap();
+
+        let meta = std::fs::symlink_metadata(&path).unwrap();
+        assert!(meta.file_type().is_socket());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        // The private directory that it was bound in is gone
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let client = UnixStream::connect(&path).unwrap();
+        let (server, _addr) = listener.accept().unwrap();
+        assert_eq!(peer_uid(&server).unwrap(), unsafe { libc::geteuid() });
+        check_peer(&server).unwrap();
+        drop(client);
+    }
+}
@@ -0,0 +1,959 @@
+use crate::channelwrap::ChannelWrap;
+use crate::sessioninner::SessionInner;
+use crate::sessionwrap::{DirectTcpipOpen, SessionWrap};
+use anyhow::{anyhow, Context};
+use filedescriptor::{socketpair, AsRawSocketDescriptor, FileDescriptor, SocketDescriptor};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+pub type ForwardId = usize;
+
+/// The address on which a forward listens for connections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindAddress {
+    /// The address to bind; `None` means the loopback interface
+    /// and `Some("*")` means all interfaces.
+    pub address: Option<String>,
+    /// The port to bind; 0 means that a port will be assigned
+    pub port: u16,
+}
+
+impl std::fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.address {
+            Some(addr) if addr.contains(':') => write!(f, "[{}]:{}", addr, self.port),
+            Some(addr) => write!(f, "{}:{}", addr, self.port),
+            None => write!(f, "{}", self.port),
+        }
+    }
+}
+
+/// Describes a port forward that is carried over an ssh session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForwardSpec {
+    /// Listen on a local address and connect to `host:port` from
+    /// the remote host, like `ssh -L`
+    Local {
+        bind: BindAddress,
+        host: String,
+        port: u16,
+    },
+    /// Ask the remote host to listen on an address and connect to
+    /// `host:port` from the local host, like `ssh -R`
+    Remote {
+        bind: BindAddress,
+        host: String,
+        port: u16,
+    },
+    /// Listen on a local address and act as a SOCKS4/SOCKS5 proxy
+    /// whose connections are made from the remote host, like `ssh -D`
+    Dynamic { bind: BindAddress },
+}
+
+fn format_host(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+impl std::fmt::Display for ForwardSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Local { bind, host, port } => {
+                write!(f, "L {}:{}:{}", bind, format_host(host), port)
+            }
+            Self::Remote { bind, host, port } => {
+                write!(f, "R {}:{}:{}", bind, format_host(host), port)
+            }
+            Self::Dynamic { bind } => write!(f, "D {}", bind),
+        }
+    }
+}
+
+/// Parses a spec of the form `KIND ARGS`, where KIND is one of
+/// `L`, `R` or `D` and ARGS are the same as the corresponding
+/// `ssh -L`, `ssh -R` or `ssh -D` command line arguments.
+/// This is the same format produced by the `Display` impl.
+impl std::str::FromStr for ForwardSpec {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let (kind, args) = s
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("expected `L`, `R` or `D` followed by the forward in {s:?}"))?;
+        match kind {
+            "L" | "l" | "-L" => Self::parse_local(args),
+            "R" | "r" | "-R" => Self::parse_remote(args),
+            "D" | "d" | "-D" => Self::parse_dynamic(args),
+            _ => anyhow::bail!("invalid forward kind {kind:?}; expected `L`, `R` or `D`"),
+        }
+    }
+}
+
+/// Splits `args` into its colon separated fields, respecting
+/// square bracketed IPv6 addresses.  The whitespace separated
+/// form used in ssh_config is accepted too.
+fn split_fields(args: &str) -> anyhow::Result<Vec<String>> {
+    let args = args.split_whitespace().collect::<Vec<_>>().join(":");
+    if args.contains('/') {
+        anyhow::bail!("forwarding unix domain sockets is not supported: {args}");
+    }
+    let mut fields = vec![];
+    let mut remain = args.as_str();
+    loop {
+        if let Some(bracketed) = remain.strip_prefix('[') {
+            let (addr, rest) = bracketed
+                .split_once(']')
+                .ok_or_else(|| anyhow!("unterminated `[` in {args:?}"))?;
+            fields.push(addr.to_string());
+            match rest.strip_prefix(':') {
+                Some(rest) => remain = rest,
+                None if rest.is_empty() => return Ok(fields),
+                None => anyhow::bail!("expected `:` after `]` in {args:?}"),
+            }
+        } else {
+            match remain.split_once(':') {
+                Some((field, rest)) => {
+                    fields.push(field.to_string());
+                    remain = rest;
+                }
+                None => {
+                    fields.push(remain.to_string());
+                    return Ok(fields);
+                }
+            }
+        }
+    }
+}
+
+fn parse_port(port: &str) -> anyhow::Result<u16> {
+    port.parse()
+        .with_context(|| format!("invalid port number {port:?}"))
+}
+
+fn parse_bind(address: Option<&str>, port: &str) -> anyhow::Result<BindAddress> {
+    Ok(BindAddress {
+        address: match address {
+            None => None,
+            Some("") => Some("*".to_string()),
+            Some(addr) => Some(addr.to_string()),
+        },
+        port: parse_port(port)?,
+    })
+}
+
+impl ForwardSpec {
+    fn parse_bind_host_port(args: &str) -> anyhow::Result<(BindAddress, String, u16)> {
+        let fields = split_fields(args)?;
+        let (bind, host, port) = match fields.as_slice() {
+            [bind_port, host, port] => (parse_bind(None, bind_port)?, host, port),
+            [addr, bind_port, host, port] => (parse_bind(Some(addr), bind_port)?, host, port),
+            _ => anyhow::bail!("expected `[bind_address:]port:host:hostport`, got {args:?}"),
+        };
+        Ok((bind, host.to_string(), parse_port(port)?))
+    }
+
+    /// Parses a `LocalForward` value from ssh_config, or an `ssh -L` argument
+    pub fn parse_local(args: &str) -> anyhow::Result<Self> {
+        let (bind, host, port) = Self::parse_bind_host_port(args)?;
+        Ok(Self::Local { bind, host, port })
+    }
+
+    /// Parses a `RemoteForward` value from ssh_config, or an `ssh -R` argument
+    pub fn parse_remote(args: &str) -> anyhow::Result<Self> {
+        let (bind, host, port) = Self::parse_bind_host_port(args)?;
+        Ok(Self::Remote { bind, host, port })
+    }
+
+    /// Parses a `DynamicForward` value from ssh_config, or an `ssh -D` argument
+    pub fn parse_dynamic(args: &str) -> anyhow::Result<Self> {
+        let fields = split_fields(args)?;
+        let bind = match fields.as_slice() {
+            [port] => parse_bind(None, port)?,
+            [addr, port] => parse_bind(Some(addr), port)?,
+            _ => anyhow::bail!("expected `[bind_address:]port`, got {args:?}"),
+        };
+        Ok(Self::Dynamic { bind })
+    }
+
+    /// Returns the forwards that are configured in the ssh config.
+    /// Multiple forwards of the same kind are separated by commas.
+    pub fn from_config(config: &crate::ConfigMap) -> Vec<anyhow::Result<Self>> {
+        let mut specs = vec![];
+        for (key, parse) in [
+            (
+                "localforward",
+                Self::parse_local as fn(&str) -> anyhow::Result<Self>,
+            ),
+            ("remoteforward", Self::parse_remote),
+            ("dynamicforward", Self::parse_dynamic),
+        ] {
+            if let Some(value) = config.get(key) {
+                for args in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                    specs.push(parse(args).with_context(|| format!("parsing {key} {args}")));
+                }
+            }
+        }
+        specs
+    }
+}
+
+/// Describes a forward that has been established
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardInfo {
+    pub id: ForwardId,
+    pub spec: ForwardSpec,
+    /// The port that is actually listening for connections,
+    /// which is useful when the spec requested port 0.
+    /// For remote forwards, this is the port on the remote host.
+    pub bound_port: u16,
+}
+
+pub(crate) enum ForwardListener {
+    /// Local and dynamic forwards listen on a local socket
+    Tcp(TcpListener),
+
+    #[cfg(feature = "ssh2")]
+    Ssh2(ssh2::Listener),
+
+    /// libssh accepts remote forwarded connections via the session,
+    /// identifying them by their bound port
+    #[cfg(feature = "libssh-rs")]
+    LibSsh,
+}
+
+pub(crate) struct ForwardState {
+    pub info: ForwardInfo,
+    pub listener: ForwardListener,
+}
+
+/// A connection to a dynamic forward that has not yet completed
+/// its SOCKS handshake
+pub(crate) struct PendingSocks {
+    pub forward: ForwardId,
+    pub stream: TcpStream,
+    pub peer: SocketAddr,
+    pub buf: Vec<u8>,
+    pub greeted: bool,
+}
+
+/// A connection from a remote forward whose local destination is
+/// being connected on a helper thread, so that resolving and
+/// connecting to it doesn't stall the session
+pub(crate) struct PendingConnect {
+    pub forward: ForwardId,
+    pub channel: ChannelWrap,
+    /// Becomes readable once the connection attempt has completed
+    pub notify: FileDescriptor,
+    pub result: Receiver<anyhow::Result<TcpStream>>,
+}
+
+/// A local or SOCKS forwarded connection whose direct-tcpip channel
+/// is being opened, without blocking the session while the server
+/// connects to the destination
+pub(crate) struct PendingOpen {
+    pub forward: ForwardId,
+    pub stream: TcpStream,
+    pub peer: SocketAddr,
+    pub host: String,
+    pub port: u16,
+    /// The SOCKS version to reply with once the outcome is known
+    pub socks_version: Option<u8>,
+    pub state: DirectTcpipOpen,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SocksStep {
+    /// More data is needed
+    Incomplete,
+    /// A SOCKS5 greeting which allows unauthenticated access
+    Greeting { consumed: usize },
+    /// A request to connect to `host:port`
+    Connect {
+        version: u8,
+        host: String,
+        port: u16,
+    },
+}
+
+/// Parses the SOCKS4, SOCKS4a or SOCKS5 request at the start of `buf`.
+/// `greeted` indicates whether a SOCKS5 greeting has already been
+/// received and answered.
+pub(crate) fn parse_socks(buf: &[u8], greeted: bool) -> anyhow::Result<SocksStep> {
+    fn nul_terminated(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+        let pos = buf.iter().position(|&b| b == 0)?;
+        Some((&buf[..pos], &buf[pos + 1..]))
+    }
+
+    match buf.first() {
+        None => Ok(SocksStep::Incomplete),
+        Some(4) if !greeted => {
+            if buf.len() < 8 {
+                return Ok(SocksStep::Incomplete);
+            }
+            if buf[1] != 1 {
+                anyhow::bail!("unsupported SOCKS4 command {}", buf[1]);
+            }
+            let port = u16::from_be_bytes([buf[2], buf[3]]);
+            let ip = [buf[4], buf[5], buf[6], buf[7]];
+            let (_user_id, remain) = match nul_terminated(&buf[8..]) {
+                Some(split) => split,
+                None => return Ok(SocksStep::Incomplete),
+            };
+            // SOCKS4a signals that a hostname follows with 0.0.0.x
+            let host = if ip[..3] == [0, 0, 0] && ip[3] != 0 {
+                match nul_terminated(remain) {
+                    Some((host, _)) => {
+                        String::from_utf8(host.to_vec()).context("SOCKS4a hostname is not UTF-8")?
+                    }
+                    None => return Ok(SocksStep::Incomplete),
+                }
+            } else {
+                std::net::Ipv4Addr::from(ip).to_string()
+            };
+            Ok(SocksStep::Connect {
+                version: 4,
+                host,
+                port,
+            })
+        }
+        Some(5) if !greeted => {
+            if buf.len() < 2 {
+                return Ok(SocksStep::Incomplete);
+            }
+            let consumed = 2 + buf[1] as usize;
+            if buf.len() < consumed {
+                return Ok(SocksStep::Incomplete);
+            }
+            if !buf[2..consumed].contains(&0) {
+                anyhow::bail!("SOCKS5 client does not support unauthenticated access");
+            }
+            Ok(SocksStep::Greeting { consumed })
+        }
+        Some(5) => {
+            if buf.len() < 5 {
+                return Ok(SocksStep::Incomplete);
+            }
+            if buf[1] != 1 {
+                anyhow::bail!("unsupported SOCKS5 command {}", buf[1]);
+            }
+            let (host, remain) = match buf[3] {
+                1 if buf.len() >= 8 => (
+                    std::net::Ipv4Addr::from([buf[4], buf[5], buf[6], buf[7]]).to_string(),
+                    &buf[8..],
+                ),
+                3 if buf.len() >= 5 + buf[4] as usize => {
+                    let end = 5 + buf[4] as usize;
+                    (
+                        String::from_utf8(buf[5..end].to_vec())
+                            .context("SOCKS5 hostname is not UTF-8")?,
+                        &buf[end..],
+                    )
+                }
+                4 if buf.len() >= 20 => {
+                    let mut addr = [0u8; 16];
+                    addr.copy_from_slice(&buf[4..20]);
+                    (std::net::Ipv6Addr::from(addr).to_string(), &buf[20..])
+                }
+                1 | 3 | 4 => return Ok(SocksStep::Incomplete),
+                atyp => anyhow::bail!("unsupported SOCKS5 address type {atyp}"),
+            };
+            if remain.len() < 2 {
+                return Ok(SocksStep::Incomplete);
+            }
+            Ok(SocksStep::Connect {
+                version: 5,
+                host,
+                port: u16::from_be_bytes([remain[0], remain[1]]),
+            })
+        }
+        Some(version) => anyhow::bail!("unsupported SOCKS version {version}"),
+    }
+}
+
+/// Returns the reply to send to a SOCKS client that made a
+/// connect request using the specified protocol version
+fn socks_reply(version: u8, success: bool) -> &'static [u8] {
+    match (version, success) {
+        (4, true) => &[0, 0x5a, 0, 0, 0, 0, 0, 0],
+        (4, false) => &[0, 0x5b, 0, 0, 0, 0, 0, 0],
+        (_, true) => &[5, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        (_, false) => &[5, 1, 0, 1, 0, 0, 0, 0, 0, 0],
+    }
+}
+
+/// Resolves the address to use for a locally bound listener
+fn local_bind_address(bind: &BindAddress) -> anyhow::Result<SocketAddr> {
+    let host = match bind.address.as_deref() {
+        None | Some("localhost") => "127.0.0.1",
+        Some("*") => "0.0.0.0",
+        Some(addr) => addr,
+    };
+    (host, bind.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("unable to resolve bind address {bind}"))
+}
+
+/// Returns the address to ask the remote host to bind for a remote forward
+fn remote_bind_address(bind: &BindAddress) -> &str {
+    match bind.address.as_deref() {
+        None => "localhost",
+        Some("*") => "0.0.0.0",
+        Some(addr) => addr,
+    }
+}
+
+impl SessionInner {
+    /// Establishes a new forward
+    pub fn forward(
+        &mut self,
+        sess: &mut SessionWrap,
+        spec: ForwardSpec,
+    ) -> anyhow::Result<ForwardInfo> {
+        let (listener, bound_port) = match &spec {
+            ForwardSpec::Local { bind, .. } | ForwardSpec::Dynamic { bind } => {
+                let addr = local_bind_address(bind)?;
+                let listener =
+                    TcpListener::bind(addr).with_context(|| format!("binding to {addr}"))?;
+                listener.set_nonblocking(true)?;
+                let bound_port = listener.local_addr()?.port();
+                (ForwardListener::Tcp(listener), bound_port)
+            }
+            ForwardSpec::Remote { bind, .. } => sess
+                .listen_forward(remote_bind_address(bind), bind.port)
+                .with_context(|| format!("requesting remote listener on {bind}"))?,
+        };
+
+        let id = self.next_forward_id;
+        self.next_forward_id += 1;
+
+        let info = ForwardInfo {
+            id,
+            spec,
+            bound_port,
+        };
+        log::debug!("established forward {info:?}");
+        self.forwards.insert(
+            id,
+            ForwardState {
+                info: info.clone(),
+                listener,
+            },
+        );
+        Ok(info)
+    }
+
+    /// Stops accepting new connections for a forward.
+    /// Connections that were already established are unaffected.
+    pub fn cancel_forward(&mut self, id: ForwardId) -> anyhow::Result<()> {
+        let state = self
+            .forwards
+            .remove(&id)
+            .ok_or_else(|| anyhow!("invalid forward id {id}"))?;
+        self.pending_socks.retain(|pending| pending.forward != id);
+        #[cfg(feature = "libssh-rs")]
+        if let ForwardListener::LibSsh = state.listener {
+            // libssh doesn't provide a way to cancel the tcpip-forward
+            // request, so the remote host will continue to listen until
+            // the session ends; we'll reject its connections.
+            log::debug!(
+                "forward {} cancelled; remote listener remains until the session ends",
+                state.info.spec
+            );
+        }
+        drop(state);
+        Ok(())
+    }
+
+    pub fn list_forwards(&self) -> Vec<ForwardInfo> {
+        let mut forwards: Vec<ForwardInfo> =
+            self.forwards.values().map(|f| f.info.clone()).collect();
+        forwards.sort_by_key(|f| f.id);
+        forwards
+    }
+
+    /// Opens a direct-tcpip channel to `host:port` and returns a socket
+    /// whose data is relayed to and from it
+    pub fn open_tunnel(
+        &mut self,
+        sess: &mut SessionWrap,
+        host: &str,
+        port: u16,
+    ) -> anyhow::Result<FileDescriptor> {
+        let (mut ours, theirs) = socketpair()?;
+        ours.set_non_blocking(true)?;
+        let originator = SocketAddr::from(([127, 0, 0, 1], 0));
+        let channel = sess
+            .open_direct_tcpip(host, port, &originator)
+            .with_context(|| format!("opening channel to {host}:{port}"))?;
+        self.add_stream_channel(channel, ours)?;
+        Ok(theirs)
+    }
+
+    /// Establishes the forwards specified by the ssh config.
+    /// Failures are logged, unless ExitOnForwardFailure is set,
+    /// in which case they are fatal to the session.
+    pub fn start_configured_forwards(&mut self, sess: &mut SessionWrap) -> anyhow::Result<()> {
+        let exit_on_failure = matches!(
+            self.config.get("exitonforwardfailure").map(|s| s.as_str()),
+            Some("yes")
+        );
+        sess.set_blocking(true);
+        let result = (|| {
+            for spec in ForwardSpec::from_config(&self.config) {
+                let result = spec.and_then(|spec| {
+                    let desc = spec.to_string();
+                    self.forward(sess, spec)
+                        .with_context(|| format!("establishing forward {desc}"))
+                });
+                if let Err(err) = result {
+                    if exit_on_failure {
+                        return Err(err);
+                    }
+                    log::error!("{:#}", err);
+                }
+            }
+            Ok(())
+        })();
+        sess.set_blocking(false);
+        result
+    }
+
+    /// Returns the descriptors that should be polled for readability
+    /// in order to notice new forwarded connections
+    pub fn forward_poll_descriptors(&self) -> Vec<SocketDescriptor> {
+        self.forwards
+            .values()
+            .filter_map(|state| match &state.listener {
+                ForwardListener::Tcp(listener) => Some(listener.as_socket_descriptor()),
+                #[cfg(feature = "ssh2")]
+                ForwardListener::Ssh2(_) => None,
+                #[cfg(feature = "libssh-rs")]
+                ForwardListener::LibSsh => None,
+            })
+            .chain(
+                self.pending_socks
+                    .iter()
+                    .map(|pending| pending.stream.as_socket_descriptor()),
+            )
+            .chain(
+                self.pending_connects
+                    .iter()
+                    .map(|pending| pending.notify.as_socket_descriptor()),
+            )
+            .collect()
+    }
+
+    /// Accepts any pending connections on our forwards, and progresses
+    /// any pending SOCKS handshakes and outgoing connections
+    pub fn accept_forwarded_connections(&mut self, sess: &mut SessionWrap) {
+        let mut local = vec![];
+        let mut remote = vec![];
+
+        for (&id, state) in self.forwards.iter_mut() {
+            match &mut state.listener {
+                ForwardListener::Tcp(listener) => loop {
+                    match listener.accept() {
+                        Ok((stream, peer)) => local.push((id, stream, peer)),
+                        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                        Err(err) => {
+                            log::error!("accepting connection for {}: {:#}", state.info.spec, err);
+                            break;
+                        }
+                    }
+                },
+                #[cfg(feature = "ssh2")]
+                ForwardListener::Ssh2(listener) => {
+                    while let Ok(channel) = listener.accept() {
+                        remote.push((id, ChannelWrap::Ssh2(channel)));
+                    }
+                }
+                #[cfg(feature = "libssh-rs")]
+                ForwardListener::LibSsh => {}
+            }
+        }
+
+        while let Some((port, channel)) = sess.accept_remote_forward() {
+            match self.forwards.values().find(|state| {
+                matches!(state.info.spec, ForwardSpec::Remote { .. })
+                    && state.info.bound_port == port
+            }) {
+                Some(state) => remote.push((state.info.id, channel)),
+                None => log::debug!("rejecting remote forwarded connection for port {port}"),
+            }
+        }
+
+        for (id, stream, peer) in local {
+            if let Err(err) = self.accept_local_connection(id, stream, peer) {
+                log::error!("forward {id}: {:#}", err);
+            }
+        }
+
+        for (id, channel) in remote {
+            if let Err(err) = self.accept_remote_connection(id, channel) {
+                log::error!("forward {id}: {:#}", err);
+            }
+        }
+
+        self.progress_pending_connects();
+        self.progress_socks_handshakes();
+        self.progress_pending_opens(sess);
+    }
+
+    fn accept_local_connection(
+        &mut self,
+        id: ForwardId,
+        stream: TcpStream,
+        peer: SocketAddr,
+    ) -> anyhow::Result<()> {
+        let spec = self.forwards[&id].info.spec.clone();
+        stream.set_nonblocking(true)?;
+        match spec {
+            ForwardSpec::Local { host, port, .. } => {
+                log::debug!("forwarding connection from {peer} to {host}:{port}");
+                self.pending_opens.push(PendingOpen {
+                    forward: id,
+                    stream,
+                    peer,
+                    host,
+                    port,
+                    socks_version: None,
+                    state: DirectTcpipOpen::default(),
+                });
+                Ok(())
+            }
+            ForwardSpec::Dynamic { .. } => {
+                self.pending_socks.push(PendingSocks {
+                    forward: id,
+                    stream,
+                    peer,
+                    buf: vec![],
+                    greeted: false,
+                });
+                Ok(())
+            }
+            ForwardSpec::Remote { .. } => unreachable!(),
+        }
+    }
+
+    fn accept_remote_connection(
+        &mut self,
+        id: ForwardId,
+        mut channel: ChannelWrap,
+    ) -> anyhow::Result<()> {
+        let (host, port) = match &self.forwards[&id].info.spec {
+            ForwardSpec::Remote { host, port, .. } => (host.clone(), *port),
+            _ => unreachable!(),
+        };
+        log::debug!("forwarding remote connection to {host}:{port}");
+        let (notify, notify_tx) = match socketpair() {
+            Ok(pair) => pair,
+            Err(err) => {
+                channel.close();
+                return Err(err.into());
+            }
+        };
+        let (tx, result) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let stream = TcpStream::connect((host.as_str(), port))
+                .with_context(|| format!("connecting to {host}:{port}"));
+            tx.send(stream).ok();
+            // Closing our end wakes up the session
+            drop(notify_tx);
+        });
+        self.pending_connects.push(PendingConnect {
+            forward: id,
+            channel,
+            notify,
+            result,
+        });
+        Ok(())
+    }
+
+    /// Starts relaying data for the remote forwarded connections
+    /// whose local connection attempts have completed
+    fn progress_pending_connects(&mut self) {
+        let mut idx = 0;
+        while idx < self.pending_connects.len() {
+            let stream = match self.pending_connects[idx].result.try_recv() {
+                Ok(stream) => stream,
+                Err(TryRecvError::Empty) => {
+                    idx += 1;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => Err(anyhow!("connection thread terminated")),
+            };
+            let mut pending = self.pending_connects.remove(idx);
+            let result = stream.and_then(|stream| {
+                stream.set_nonblocking(true)?;
+                stream_to_fd(stream)
+            });
+            let result = match result {
+                Ok(fd) => self.add_stream_channel(pending.channel, fd),
+                Err(err) => {
+                    pending.channel.close();
+                    Err(err)
+                }
+            };
+            if let Err(err) = result {
+                log::error!("forward {}: {:#}", pending.forward, err);
+            }
+        }
+    }
+
+    fn progress_socks_handshakes(&mut self) {
+        let mut idx = 0;
+        while idx < self.pending_socks.len() {
+            match self.progress_socks_handshake(idx) {
+                Ok(true) => idx += 1,
+                Ok(false) => {}
+                Err(err) => {
+                    let pending = self.pending_socks.remove(idx);
+                    log::error!("SOCKS connection from {}: {:#}", pending.peer, err);
+                }
+            }
+        }
+    }
+
+    /// Starts relaying data for the local and SOCKS forwarded connections
+    /// whose channels have opened.  They are opened in the order in which
+    /// they arrived, as libssh2 can only open one channel at a time.
+    fn progress_pending_opens(&mut self, sess: &mut SessionWrap) {
+        while let Some(pending) = self.pending_opens.first_mut() {
+            let channel = match sess
+                .poll_direct_tcpip(
+                    &pending.host,
+                    pending.port,
+                    &pending.peer,
+                    &mut pending.state,
+                )
+                .with_context(|| format!("opening channel to {}:{}", pending.host, pending.port))
+                .transpose()
+            {
+                Some(channel) => channel,
+                None => return,
+            };
+
+            let PendingOpen {
+                forward,
+                mut stream,
+                peer,
+                socks_version,
+                ..
+            } = self.pending_opens.remove(0);
+            // The stream is non-blocking, but the SOCKS replies are
+            // small enough that they will fit in the socket buffer
+            if let Some(version) = socks_version {
+                stream.write_all(socks_reply(version, channel.is_ok())).ok();
+            }
+            let result =
+                channel.and_then(|channel| self.add_stream_channel(channel, stream_to_fd(stream)?));
+            if let Err(err) = result {
+                log::error!("forward {forward}: connection from {peer}: {:#}", err);
+            }
+        }
+    }
+
+    /// Returns Ok(true) if the handshake is still pending,
+    /// Ok(false) if it has completed and been removed from
+    /// the pending list
+    fn progress_socks_handshake(&mut self, idx: usize) -> anyhow::Result<bool> {
+        let pending = &mut self.pending_socks[idx];
+        let mut buf = [0u8; 512];
+        match pending.stream.read(&mut buf) {
+            Ok(0) => anyhow::bail!("connection closed during handshake"),
+            Ok(n) => pending.buf.extend_from_slice(&buf[..n]),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        loop {
+            let pending = &mut self.pending_socks[idx];
+            let step = match parse_socks(&pending.buf, pending.greeted) {
+                Ok(step) => step,
+                Err(err) => {
+                    let reply: &[u8] = if pending.greeted || pending.buf[0] == 4 {
+                        socks_reply(pending.buf[0], false)
+                    } else {
+                        &[5, 0xff]
+                    };
+                    pending.stream.write_all(reply).ok();
+                    return Err(err);
+                }
+            };
+            match step {
+                SocksStep::Incomplete => return Ok(true),
+                SocksStep::Greeting { consumed } => {
+                    pending.stream.write_all(&[5, 0])?;
+                    pending.buf.drain(..consumed);
+                    pending.greeted = true;
+                }
+                SocksStep::Connect {
+                    version,
+                    host,
+                    port,
+                } => {
+                    let pending = self.pending_socks.remove(idx);
+                    log::debug!(
+                        "forwarding SOCKS{version} connection from {} to {host}:{port}",
+                        pending.peer
+                    );
+                    self.pending_opens.push(PendingOpen {
+                        forward: pending.forward,
+                        stream: pending.stream,
+                        peer: pending.peer,
+                        host,
+                        port,
+                        socks_version: Some(version),
+                        state: DirectTcpipOpen::default(),
+                    });
+                    return Ok(false);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn stream_to_fd(stream: TcpStream) -> anyhow::Result<FileDescriptor> {
+    #[cfg(unix)]
+    {
+        Ok(FileDescriptor::new(stream))
+    }
+    #[cfg(windows)]
+    unsafe {
+        use std::os::windows::io::{FromRawSocket, IntoRawSocket};
+        Ok(FileDescriptor::from_raw_socket(stream.into_raw_socket()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_forward_specs() {
+        assert_eq!(
+            ForwardSpec::parse_local("8080 localhost:80").unwrap(),
+            ForwardSpec::Local {
+                bind: BindAddress {
+                    address: None,
+                    port: 8080
+                },
+                host: "localhost".to_string(),
+                port: 80,
+            }
+        );
+        assert_eq!(
+            ForwardSpec::parse_remote("*:2222:[::1]:22").unwrap(),
+            ForwardSpec::Remote {
+                bind: BindAddress {
+                    address: Some("*".to_string()),
+                    port: 2222
+                },
+                host: "::1".to_string(),
+                port: 22,
+            }
+        );
+        assert_eq!(
+            ForwardSpec::parse_dynamic("[::1]:1080").unwrap(),
+            ForwardSpec::Dynamic {
+                bind: BindAddress {
+                    address: Some("::1".to_string()),
+                    port: 1080
+                },
+            }
+        );
+        assert!(ForwardSpec::parse_local("8080").is_err());
+        assert!(ForwardSpec::parse_local("/tmp/sock /tmp/other").is_err());
+
+        for spec in ["L 127.0.0.1:0:example.com:80", "R 22:[::1]:2222", "D 1080"] {
+            let parsed: ForwardSpec = spec.parse().unwrap();
+            assert_eq!(parsed.to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn forwards_from_config() {
+        let mut config = crate::ConfigMap::new();
+        config.insert(
+            "localforward".to_string(),
+            "8080 localhost:80,9090 localhost:90".to_string(),
+        );
+        config.insert("dynamicforward".to_string(), "1080".to_string());
+        let specs: Vec<String> = ForwardSpec::from_config(&config)
+            .into_iter()
+            .map(|spec| spec.unwrap().to_string())
+            .collect();
+        assert_eq!(
+            specs,
+            vec!["L 8080:localhost:80", "L 9090:localhost:90", "D 1080"]
+        );
+    }
+
+    #[test]
+    fn parse_socks4() {
+        let req = [4, 1, 0, 80, 10, 0, 0, 1, b'm', b'e', 0];
+        assert_eq!(
+            parse_socks(&req[..6], false).unwrap(),
+            SocksStep::Incomplete
+        );
+        assert_eq!(
+            parse_socks(&req, false).unwrap(),
+            SocksStep::Connect {
+                version: 4,
+                host: "10.0.0.1".to_string(),
+                port: 80
+            }
+        );
+
+        let req = b"\x04\x01\x00\x50\x00\x00\x00\x01\x00example.com\x00";
+        assert_eq!(
+            parse_socks(req, false).unwrap(),
+            SocksStep::Connect {
+                version: 4,
+                host: "example.com".to_string(),
+                port: 80
+            }
+        );
+    }
+
+    #[test]
+    fn parse_socks5() {
+        assert_eq!(
+            parse_socks(&[5, 2, 0], false).unwrap(),
+            SocksStep::Incomplete
+        );
+        assert_eq!(
+            parse_socks(&[5, 2, 2, 0], false).unwrap(),
+            SocksStep::Greeting { consumed: 4 }
+        );
+        assert!(parse_socks(&[5, 1, 2], false).is_err());
+
+        let req = b"\x05\x01\x00\x03\x0bexample.com\x01\xbb";
+        assert_eq!(
+            parse_socks(&req[..10], true).unwrap(),
+            SocksStep::Incomplete
+        );
+        assert_eq!(
+            parse_socks(req, true).unwrap(),
+            SocksStep::Connect {
+                version: 5,
+                host: "example.com".to_string(),
+                port: 443
+            }
+        );
+        assert_eq!(
+            parse_socks(&[5, 1, 0, 1, 127, 0, 0, 1, 0, 22], true).unwrap(),
+            SocksStep::Connect {
+                version: 5,
+                host: "127.0.0.1".to_string(),
+                port: 22
+            }
+        );
+        assert!(parse_socks(&[5, 2, 0, 1, 127, 0, 0, 1, 0, 22], true).is_err());
+    }
+}
@@ -1,6 +1,11 @@
+use crate::knownhosts::{HostKeyStatus, KnownHosts};
 use crate::session::SessionEvent;
 use anyhow::Context;
 use smol::channel::{bounded, Sender};
+use std::path::PathBuf;
+
+/// Used when GlobalKnownHostsFile is not set, as for OpenSSH
+const DEFAULT_GLOBAL_KNOWN_HOSTS: &str = "/etc/ssh/ssh_known_hosts /etc/ssh/ssh_known_hosts2";
 
 #[derive(Debug, thiserror::Error)]
 #[error("host key mismatch for ssh server {remote_address}. Got fingerprint {key} instead of the expected value from your known hosts file {file:?}.")]
@@ -8,6 +13,35 @@ pub struct HostVerificationFailed {
     pub remote_address: String,
     pub key: String,
     pub file: Option<std::path::PathBuf>,
+    /// The visual host key for the key presented by the server
+    pub randomart: String,
+    pub(crate) replace: Option<Sender<bool>>,
+}
+
+impl HostVerificationFailed {
+    /// Returns true if the entry in `file` can be replaced by the
+    /// key that the server presented, by answering `true`
+    pub fn can_replace(&self) -> bool {
+        self.replace.is_some()
+    }
+
+    /// If `replace` is true, the known hosts entry is replaced and
+    /// the connection proceeds, otherwise the connection fails.
+    /// Dropping the event without answering is the same as
+    /// answering `false`.
+    pub async fn answer(self, replace: bool) -> anyhow::Result<()> {
+        match self.replace {
+            Some(reply) => Ok(reply.send(replace).await?),
+            None => Ok(()),
+        }
+    }
+
+    pub fn try_answer(self, replace: bool) -> anyhow::Result<()> {
+        match self.replace {
+            Some(reply) => Ok(reply.try_send(replace)?),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -25,7 +59,116 @@ impl HostVerificationEvent {
     }
 }
 
+/// Describes the key presented by a server to the user
+struct PresentedKey {
+    key_type: Option<String>,
+    fingerprint: String,
+    randomart: String,
+}
+
 impl crate::sessioninner::SessionInner {
+    fn user_known_hosts_files(&self) -> Vec<PathBuf> {
+        self.config
+            .get("userknownhostsfile")
+            .map(|files| files.split_whitespace().map(PathBuf::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns both the user and global known hosts files
+    fn load_known_hosts(&self) -> KnownHosts {
+        let global = self
+            .config
+            .get("globalknownhostsfile")
+            .map(|s| s.as_str())
+            .unwrap_or(DEFAULT_GLOBAL_KNOWN_HOSTS);
+        KnownHosts::load(
+            self.user_known_hosts_files()
+                .into_iter()
+                .chain(global.split_whitespace().map(PathBuf::from)),
+        )
+    }
+
+    fn hash_known_hosts(&self) -> bool {
+        self.config.get("hashknownhosts").map(|s| s.as_str()) == Some("yes")
+    }
+
+    /// Asks the user whether to trust a host that has no known key of
+    /// the type that it presented, returning an error if they decline
+    fn confirm_new_host(
+        &self,
+        remote_address: &str,
+        key: &PresentedKey,
+        other_types: Option<&[String]>,
+    ) -> anyhow::Result<()> {
+        let mut message = format!("SSH host {} is not yet trusted.\n", remote_address);
+        if let Some(other_types) = other_types {
+            if other_types.is_empty() {
+                message.push_str("Keys of a different type are already known for this host.\n");
+            } else {
+                message.push_str(&format!(
+                    "Keys of a different type ({}) are already known for this host.\n",
+                    other_types.join(", ")
+                ));
+            }
+        }
+        match &key.key_type {
+            Some(key_type) => {
+                message.push_str(&format!("{} Fingerprint: {}.\n", key_type, key.fingerprint))
+            }
+            None => message.push_str(&format!("Fingerprint: {}.\n", key.fingerprint)),
+        }
+        message.push_str(&key.randomart);
+        message.push_str("\nTrust and continue connecting?");
+
+        let (reply, confirm) = bounded(1);
+        self.tx_event
+            .try_send(SessionEvent::HostVerify(HostVerificationEvent {
+                message,
+                reply,
+            }))
+            .context("sending HostVerify request to user")?;
+
+        let trusted = smol::block_on(confirm.recv())
+            .context("waiting for host verification confirmation from user")?;
+
+        if !trusted {
+            anyhow::bail!("user declined to trust host");
+        }
+        Ok(())
+    }
+
+    /// Tells the user that the key for the host has changed, offering to
+    /// replace the entry in `file` if it is one of the user's files.
+    /// Returns Ok if the user chose to replace it.
+    fn confirm_replace_host_key(
+        &self,
+        remote_address: &str,
+        key: &PresentedKey,
+        file: PathBuf,
+    ) -> anyhow::Result<()> {
+        let replaceable = self.user_known_hosts_files().contains(&file);
+        let (reply, confirm) = bounded(1);
+
+        let failed = HostVerificationFailed {
+            remote_address: remote_address.to_string(),
+            key: key.fingerprint.clone(),
+            file: Some(file),
+            randomart: key.randomart.clone(),
+            replace: if replaceable { Some(reply) } else { None },
+        };
+        self.tx_event
+            .try_send(SessionEvent::HostVerificationFailed(failed))
+            .context("sending HostVerificationFailed event to user")?;
+
+        // If the user doesn't answer, the sender is dropped and
+        // we treat that as declining to replace the key
+        let replace = smol::block_on(confirm.recv()).unwrap_or(false);
+        if !replace {
+            anyhow::bail!("Host key verification failed");
+        }
+        Ok(())
+    }
+
     #[cfg(feature = "libssh-rs")]
     pub fn host_verification_libssh(
         &mut self,
@@ -33,61 +176,54 @@ impl crate::sessioninner::SessionInner {
         hostname: &str,
         port: u16,
     ) -> anyhow::Result<()> {
-        let key = sess
+        // libssh doesn't expose the key itself, so we identify it
+        // by its digest, which is sufficient to match it against
+        // the known hosts
+        let digest = sess
             .get_server_public_key()?
-            .get_public_key_hash_hexa(libssh_rs::PublicKeyHashType::Sha256)?;
-
-        match sess.is_known_server()? {
-            libssh_rs::KnownHosts::Ok => Ok(()),
-            libssh_rs::KnownHosts::NotFound | libssh_rs::KnownHosts::Unknown => {
-                let (reply, confirm) = bounded(1);
-                self.tx_event
-                    .try_send(SessionEvent::HostVerify(HostVerificationEvent {
-                        message: format!(
-                            "SSH host {}:{} is not yet trusted.\n\
-                                    Fingerprint: {}.\n\
-                                    Trust and continue connecting?",
-                            hostname, port, key
-                        ),
-                        reply,
-                    }))
-                    .context("sending HostVerify request to user")?;
-
-                let trusted = smol::block_on(confirm.recv())
-                    .context("waiting for host verification confirmation from user")?;
-
-                if !trusted {
-                    anyhow::bail!("user declined to trust host");
-                }
+            .get_public_key_hash(libssh_rs::PublicKeyHashType::Sha256)?;
+        let key = PresentedKey {
+            key_type: None,
+            fingerprint: crate::knownhosts::fingerprint_sha256(&digest),
+            randomart: crate::knownhosts::randomart(&digest, None),
+        };
+        let remote_address = format!("{hostname}:{port}");
 
+        let status = match (
+            self.load_known_hosts()
+                .check_sha256(hostname, port, &digest),
+            sess.is_known_server()?,
+        ) {
+            (status @ HostKeyStatus::Revoked { .. }, _) => status,
+            (_, libssh_rs::KnownHosts::Ok) => HostKeyStatus::Trusted,
+            // We can't tell the type of the key, but libssh can
+            (HostKeyStatus::Changed { .. }, libssh_rs::KnownHosts::Other) => {
+                HostKeyStatus::OtherKeyTypes(vec![])
+            }
+            (status, _) => status,
+        };
+
+        match status {
+            HostKeyStatus::Trusted => Ok(()),
+            HostKeyStatus::Revoked { file, line } => anyhow::bail!(
+                "The host key {} for {} has been revoked by {}:{}",
+                key.fingerprint,
+                remote_address,
+                file.display(),
+                line
+            ),
+            HostKeyStatus::NotFound => {
+                self.confirm_new_host(&remote_address, &key, None)?;
                 Ok(sess.update_known_hosts_file()?)
             }
-            libssh_rs::KnownHosts::Changed => {
-                let mut file = None;
-                if let Some(kh) = self.config.get("userknownhostsfile") {
-                    for candidate in kh.split_whitespace() {
-                        file.replace(candidate.into());
-                        break;
-                    }
-                }
-
-                let failed = HostVerificationFailed {
-                    remote_address: format!("{hostname}:{port}"),
-                    key,
-                    file,
-                };
-                self.tx_event
-                    .try_send(SessionEvent::HostVerificationFailed(failed))
-                    .context("sending HostVerificationFailed event to user")?;
-                anyhow::bail!("Host key verification failed");
+            HostKeyStatus::OtherKeyTypes(types) => {
+                self.confirm_new_host(&remote_address, &key, Some(&types))?;
+                Ok(sess.update_known_hosts_file()?)
             }
-            libssh_rs::KnownHosts::Other => {
-                anyhow::bail!(
-                    "The host key for this server was not found, but another\n\
-            type of key exists. An attacker might change the default\n\
-            server key to confuse your client into thinking the key\n\
-            does not exist"
-                );
+            HostKeyStatus::Changed { file, .. } => {
+                self.confirm_replace_host_key(&remote_address, &key, file.clone())?;
+                crate::knownhosts::remove_host_keys(&file, hostname, port, None)?;
+                Ok(sess.update_known_hosts_file()?)
             }
         }
     }
@@ -100,112 +236,61 @@ impl crate::sessioninner::SessionInner {
         port: u16,
         remote_address: &str,
     ) -> anyhow::Result<()> {
-        use anyhow::anyhow;
-        use std::io::Write;
-        use std::path::Path;
+        use crate::knownhosts::{add_host_key, replace_host_key, HostKey};
 
-        let mut known_hosts = sess.known_hosts().context("preparing known hosts")?;
+        let (blob, _key_type) = sess
+            .host_key()
+            .ok_or_else(|| anyhow::anyhow!("failed to get ssh host key"))?;
+        let host_key = HostKey::new(blob.to_vec())?;
+        let key = PresentedKey {
+            key_type: Some(host_key.key_type().to_string()),
+            fingerprint: host_key.fingerprint(),
+            randomart: host_key.randomart(),
+        };
 
-        let known_hosts_files = self
-            .config
-            .get("userknownhostsfile")
-            .unwrap()
-            .split_whitespace()
-            .map(|s| s.to_string());
-
-        for file in known_hosts_files {
-            let file = Path::new(&file);
-
-            if !file.exists() {
-                continue;
+        match self
+            .load_known_hosts()
+            .check(remote_host_name, port, &host_key)
+        {
+            HostKeyStatus::Trusted => Ok(()),
+            HostKeyStatus::Revoked { file, line } => anyhow::bail!(
+                "The host key {} for {} has been revoked by {}:{}",
+                key.fingerprint,
+                remote_address,
+                file.display(),
+                line
+            ),
+            status @ (HostKeyStatus::NotFound | HostKeyStatus::OtherKeyTypes(_)) => {
+                let other_types = match &status {
+                    HostKeyStatus::OtherKeyTypes(types) => Some(types.as_slice()),
+                    _ => None,
+                };
+                self.confirm_new_host(remote_address, &key, other_types)?;
+                let file = self
+                    .user_known_hosts_files()
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("UserKnownHostsFile is not set"))?;
+                add_host_key(
+                    &file,
+                    remote_host_name,
+                    port,
+                    &host_key,
+                    self.hash_known_hosts(),
+                )
+                .with_context(|| format!("adding host key to {}", file.display()))
             }
-
-            known_hosts
-                .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
-                .with_context(|| format!("reading known_hosts file {}", file.display()))?;
-
-            let (key, key_type) = sess
-                .host_key()
-                .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
-
-            let fingerprint = sess
-                .host_key_hash(ssh2::HashType::Sha256)
-                .map(|fingerprint| {
-                    use base64::Engine;
-                    let engine = base64::engine::general_purpose::GeneralPurpose::new(
-                        &base64::alphabet::STANDARD,
-                        base64::engine::general_purpose::NO_PAD,
-                    );
-                    format!("SHA256:{}", engine.encode(fingerprint))
-                })
-                .or_else(|| {
-                    // Querying for the Sha256 can fail if for example we were linked
-                    // against libssh < 1.9, so let's fall back to Sha1 in that case.
-                    sess.host_key_hash(ssh2::HashType::Sha1).map(|fingerprint| {
-                        let mut res = vec![];
-                        write!(&mut res, "SHA1").ok();
-                        for b in fingerprint {
-                            write!(&mut res, ":{:02x}", *b).ok();
-                        }
-                        String::from_utf8(res).unwrap()
-                    })
-                })
-                .ok_or_else(|| anyhow!("failed to get host fingerprint"))?;
-
-            match known_hosts.check_port(&remote_host_name, port, key) {
-                ssh2::CheckResult::Match => {}
-                ssh2::CheckResult::NotFound => {
-                    let (reply, confirm) = bounded(1);
-                    self.tx_event
-                        .try_send(SessionEvent::HostVerify(HostVerificationEvent {
-                            message: format!(
-                                "SSH host {} is not yet trusted.\n\
-                                {:?} Fingerprint: {}.\n\
-                                Trust and continue connecting?",
-                                remote_address, key_type, fingerprint
-                            ),
-                            reply,
-                        }))
-                        .context("sending HostVerify request to user")?;
-
-                    let trusted = smol::block_on(confirm.recv())
-                        .context("waiting for host verification confirmation from user")?;
-
-                    if !trusted {
-                        anyhow::bail!("user declined to trust host");
-                    }
-
-                    let host_and_port = if port != 22 {
-                        format!("[{}]:{}", remote_host_name, port)
-                    } else {
-                        remote_host_name.to_string()
-                    };
-
-                    known_hosts
-                        .add(&host_and_port, key, &remote_address, key_type.into())
-                        .context("adding known_hosts entry in memory")?;
-
-                    known_hosts
-                        .write_file(&file, ssh2::KnownHostFileKind::OpenSSH)
-                        .with_context(|| format!("writing known_hosts file {}", file.display()))?;
-                }
-                ssh2::CheckResult::Mismatch => {
-                    let failed = HostVerificationFailed {
-                        remote_address: remote_address.to_string(),
-                        key: fingerprint,
-                        file: Some(file.to_path_buf()),
-                    };
-                    self.tx_event
-                        .try_send(SessionEvent::HostVerificationFailed(failed))
-                        .context("sending HostVerificationFailed event to user")?;
-                    anyhow::bail!("Host key verification failed");
-                }
-                ssh2::CheckResult::Failure => {
-                    anyhow::bail!("failed to check the known hosts");
-                }
+            HostKeyStatus::Changed { file, .. } => {
+                self.confirm_replace_host_key(remote_address, &key, file.clone())?;
+                replace_host_key(
+                    &file,
+                    remote_host_name,
+                    port,
+                    &host_key,
+                    self.hash_known_hosts(),
+                )
+                .with_context(|| format!("replacing host key in {}", file.display()))
             }
         }
-
-        Ok(())
     }
 }
@@ -0,0 +1,228 @@
+use crate::config::{Config, ConfigMap};
+use crate::session::{Session, SessionEvent};
+use crate::sessioninner::SessionInner;
+use anyhow::{anyhow, Context};
+use socket2::Socket;
+
+/// One of the hosts listed in a `ProxyJump` option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct JumpHost {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl std::fmt::Display for JumpHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            write!(f, "{}", self.host)?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+impl JumpHost {
+    /// Parses `[user@]host[:port]` or `ssh://[user@]host[:port]`
+    fn parse(spec: &str) -> anyhow::Result<Self> {
+        let spec = spec.trim();
+        let spec = spec.strip_prefix("ssh://").unwrap_or(spec);
+        let (user, host_port) = match spec.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user.to_string()), host_port),
+            None => (None, spec),
+        };
+
+        let (host, port) = if let Some(bracketed) = host_port.strip_prefix('[') {
+            let (host, rest) = bracketed
+                .split_once(']')
+                .ok_or_else(|| anyhow!("unterminated `[` in ProxyJump host {spec:?}"))?;
+            match rest.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None if rest.is_empty() => (host, None),
+                None => anyhow::bail!("expected `:` after `]` in ProxyJump host {spec:?}"),
+            }
+        } else {
+            match host_port.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            }
+        };
+
+        if host.is_empty() {
+            anyhow::bail!("empty hostname in ProxyJump host {spec:?}");
+        }
+
+        let port = match port {
+            Some(port) => Some(
+                port.parse()
+                    .with_context(|| format!("invalid port in ProxyJump host {spec:?}"))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            user,
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Parses the comma separated list of hosts from a `ProxyJump` option.
+    /// Returns an empty list for `none`.
+    pub fn parse_list(value: &str) -> anyhow::Result<Vec<Self>> {
+        if value.trim() == "none" {
+            return Ok(vec![]);
+        }
+        value.split(',').map(Self::parse).collect()
+    }
+}
+
+fn join_hosts(hosts: &[JumpHost]) -> String {
+    hosts
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl SessionInner {
+    /// Resolves the configuration for a jump host.  Hosts that precede it
+    /// in the ProxyJump list are used as its own ProxyJump, which takes
+    /// precedence over anything configured for that host.
+    fn jump_host_config(&self, jump: &JumpHost, preceding: &[JumpHost]) -> ConfigMap {
+        let config = self.jump_config.clone().unwrap_or_else(|| {
+            let mut config = Config::new();
+            config.add_default_config_files();
+            config
+        });
+        let mut hop = config.for_host(&jump.host);
+        if let Some(user) = &jump.user {
+            hop.insert("user".to_string(), user.to_string());
+        }
+        if let Some(port) = jump.port {
+            hop.insert("port".to_string(), port.to_string());
+        }
+
+        if !preceding.is_empty() {
+            hop.insert("proxyjump".to_string(), join_hosts(preceding));
+        } else if let Some(own) = hop.get("proxyjump") {
+            // A catch-all `Host *` ProxyJump would otherwise make
+            // the jump host try to connect via itself, forever
+            let loops = JumpHost::parse_list(own)
+                .map(|hosts| hosts.iter().any(|h| h.host == jump.host))
+                .unwrap_or(true);
+            if loops {
+                hop.remove("proxyjump");
+            }
+        }
+
+        for key in ["wezterm_ssh_backend", "wezterm_ssh_verbose"] {
+            if let Some(value) = self.config.get(key) {
+                hop.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        hop
+    }
+
+    /// Connects to `hostname:port` via the hosts listed in `proxy_jump`.
+    /// A Session is established with the last of those hosts, itself
+    /// connecting via the others, and a direct-tcpip channel opened
+    /// through it to the target host.
+    /// Events from the jump hosts, such as host verification and
+    /// authentication prompts, are relayed via our own event channel.
+    pub(crate) fn connect_via_jump_hosts(
+        &self,
+        proxy_jump: &str,
+        hostname: &str,
+        port: u16,
+    ) -> anyhow::Result<(Socket, Session)> {
+        let hosts = JumpHost::parse_list(proxy_jump)?;
+        let (jump, preceding) = hosts
+            .split_last()
+            .ok_or_else(|| anyhow!("ProxyJump {proxy_jump:?} lists no hosts"))?;
+
+        let config = self.jump_host_config(jump, preceding);
+        log::debug!("connecting to {hostname}:{port} via jump host {jump}");
+
+        let (session, events) = Session::connect(config)?;
+
+        smol::block_on(async {
+            loop {
+                let event = events
+                    .recv()
+                    .await
+                    .with_context(|| format!("jump host {jump} closed the connection"))?;
+                match event {
+                    SessionEvent::Authenticated => return Ok(()),
+                    SessionEvent::Error(err) => {
+                        anyhow::bail!("connecting to jump host {jump}: {err}");
+                    }
+                    event => self
+                        .tx_event
+                        .send(event)
+                        .await
+                        .context("relaying jump host event to user")?,
+                }
+            }
+        })?;
+
+        let fd = smol::block_on(session.open_tunnel(hostname, port))
+            .with_context(|| format!("connecting to {hostname}:{port} via jump host {jump}"))?;
+
+        #[cfg(unix)]
+        let sock = unsafe {
+            use std::os::unix::io::{FromRawFd, IntoRawFd};
+            Socket::from_raw_fd(fd.into_raw_fd())
+        };
+        #[cfg(windows)]
+        let sock = unsafe {
+            use std::os::windows::io::{FromRawSocket, IntoRawSocket};
+            Socket::from_raw_socket(fd.into_raw_socket())
+        };
+
+        Ok((sock, session))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_jump_hosts() {
+        assert_eq!(
+            JumpHost::parse_list("bastion,me@inner:2222,ssh://[::1]:22").unwrap(),
+            vec![
+                JumpHost {
+                    user: None,
+                    host: "bastion".to_string(),
+                    port: None,
+                },
+                JumpHost {
+                    user: Some("me".to_string()),
+                    host: "inner".to_string(),
+                    port: Some(2222),
+                },
+                JumpHost {
+                    user: None,
+                    host: "::1".to_string(),
+                    port: Some(22),
+                },
+            ]
+        );
+        assert_eq!(JumpHost::parse_list("none").unwrap(), vec![]);
+        assert!(JumpHost::parse_list("bastion:nope").is_err());
+        assert!(JumpHost::parse_list("a,,b").is_err());
+
+        let hosts = JumpHost::parse_list("a@b:1,[::1]").unwrap();
+        assert_eq!(join_hosts(&hosts), "a@b:1,[::1]");
+    }
+}
@@ -0,0 +1,829 @@
+//! Checking and maintaining OpenSSH known_hosts files.
+//!
+//! This understands hashed host names and `@revoked` markers, and is able
+//! to replace a key that has changed, none of which are consistently
+//! available from the ssh backends.
+//! Neither backend negotiates certificate host keys, so `@cert-authority`
+//! entries are ignored.
+//! Verification is entirely offline: host keys are never looked up in DNS.
+use anyhow::{anyhow, bail, Context};
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.data.len() < len {
+            bail!("truncated key data");
+        }
+        let (value, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+
+    fn read_u32(&mut self) -> anyhow::Result<u32> {
+        let mut value = [0u8; 4];
+        value.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(value))
+    }
+
+    fn read_string(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    fn read_str(&mut self) -> anyhow::Result<&'a str> {
+        std::str::from_utf8(self.read_string()?).context("invalid utf8 in key data")
+    }
+}
+
+/// A public host key, as presented by a server or recorded
+/// in a known_hosts file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostKey {
+    key_type: String,
+    blob: Vec<u8>,
+}
+
+impl HostKey {
+    /// Constructs a key from its wire encoding
+    pub fn new(blob: Vec<u8>) -> anyhow::Result<Self> {
+        let key_type = Reader::new(&blob).read_str()?.to_string();
+        Ok(Self { key_type, blob })
+    }
+
+    /// Parses the `key-type base64-data` form that is used in
+    /// known_hosts and `.pub` files
+    pub fn from_openssh(s: &str) -> anyhow::Result<Self> {
+        let mut fields = s.split_whitespace();
+        let key_type = fields.next().ok_or_else(|| anyhow!("missing key type"))?;
+        let data = fields.next().ok_or_else(|| anyhow!("missing key data"))?;
+        let key = Self::new(STANDARD.decode(data).context("decoding key data")?)?;
+        if key.key_type != key_type {
+            bail!(
+                "key type {} doesn't match the encoded key type {}",
+                key_type,
+                key.key_type
+            );
+        }
+        Ok(key)
+    }
+
+    pub fn key_type(&self) -> &str {
+        &self.key_type
+    }
+
+    pub fn blob(&self) -> &[u8] {
+        &self.blob
+    }
+
+    /// Returns the `key-type base64-data` form of the key
+    pub fn to_openssh(&self) -> String {
+        format!("{} {}", self.key_type, STANDARD.encode(&self.blob))
+    }
+
+    /// Returns the SHA256 fingerprint in the same form that
+    /// `ssh-keygen -l` shows it
+    pub fn fingerprint(&self) -> String {
+        fingerprint_sha256(&sha256(&self.blob))
+    }
+
+    /// Returns the visual host key that `ssh-keygen -lv` and
+    /// `VisualHostKey` show for the key
+    pub fn randomart(&self) -> String {
+        let name = match self.key_type.as_str() {
+            "ssh-ed25519" => "ED25519",
+            "ssh-rsa" => "RSA",
+            "ssh-dss" => "DSA",
+            "sk-ssh-ed25519@openssh.com" => "ED25519-SK",
+            "sk-ecdsa-sha2-nistp256@openssh.com" => "ECDSA-SK",
+            t if t.starts_with("ecdsa-sha2-") => "ECDSA",
+            _ => "UNKNOWN",
+        };
+        let title = match self.bits() {
+            Some(bits) => format!("[{} {}]", name, bits),
+            None => format!("[{}]", name),
+        };
+        // Fall back to omitting the size if it won't fit,
+        // as ssh-keygen does
+        let title = if title.len() > FIELD_WIDTH {
+            format!("[{}]", name)
+        } else {
+            title
+        };
+        randomart(&sha256(&self.blob), Some(&title))
+    }
+
+    /// Returns the size of the key in bits
+    fn bits(&self) -> Option<usize> {
+        let mut reader = Reader::new(&self.blob);
+        let key_type = reader.read_str().ok()?;
+        match key_type {
+            "ssh-ed25519" | "sk-ssh-ed25519@openssh.com" => Some(256),
+            "ssh-rsa" => {
+                let _e = reader.read_string().ok()?;
+                mpint_bits(reader.read_string().ok()?)
+            }
+            "ssh-dss" => mpint_bits(reader.read_string().ok()?),
+            _ => match reader.read_str().ok()? {
+                "nistp256" => Some(256),
+                "nistp384" => Some(384),
+                "nistp521" => Some(521),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn mpint_bits(mpint: &[u8]) -> Option<usize> {
+    let pos = mpint.iter().position(|&b| b != 0)?;
+    let magnitude = &mpint[pos..];
+    Some((magnitude.len() - 1) * 8 + (8 - magnitude[0].leading_zeros() as usize))
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    use sha2::Digest;
+    sha2::Sha256::digest(data).to_vec()
+}
+
+/// Formats a SHA256 digest of a key as `SHA256:<base64>`
+pub fn fingerprint_sha256(digest: &[u8]) -> String {
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(digest))
+}
+
+const FIELD_WIDTH: usize = 17;
+const FIELD_HEIGHT: usize = 9;
+
+/// Renders the "drunken bishop" visual representation of a SHA256 key
+/// digest, optionally with a title such as `[ED25519 256]` in the border.
+pub fn randomart(digest: &[u8], title: Option<&str>) -> String {
+    const SYMBOLS: &[u8] = b" .o+=*BOX@%&#/^SE";
+    let start = SYMBOLS.len() - 2;
+    let end = SYMBOLS.len() - 1;
+
+    let mut field = [[0usize; FIELD_HEIGHT]; FIELD_WIDTH];
+    let mut x = FIELD_WIDTH / 2;
+    let mut y = FIELD_HEIGHT / 2;
+
+    for &byte in digest {
+        let mut input = byte;
+        for _ in 0..4 {
+            x = if input & 0x1 != 0 {
+                (x + 1).min(FIELD_WIDTH - 1)
+            } else {
+                x.saturating_sub(1)
+            };
+            y = if input & 0x2 != 0 {
+                (y + 1).min(FIELD_HEIGHT - 1)
+            } else {
+                y.saturating_sub(1)
+            };
+            if field[x][y] < start - 1 {
+                field[x][y] += 1;
+            }
+            input >>= 2;
+        }
+    }
+    field[FIELD_WIDTH / 2][FIELD_HEIGHT / 2] = start;
+    field[x][y] = end;
+
+    fn border(label: &str) -> String {
+        let label: String = label.chars().take(FIELD_WIDTH - 1).collect();
+        let left = (FIELD_WIDTH - label.len()) / 2;
+        let right = FIELD_WIDTH - left - label.len();
+        format!("+{}{}{}+", "-".repeat(left), label, "-".repeat(right))
+    }
+
+    let mut lines = vec![border(title.unwrap_or(""))];
+    for y in 0..FIELD_HEIGHT {
+        let row: String = (0..FIELD_WIDTH)
+            .map(|x| SYMBOLS[field[x][y].min(end)] as char)
+            .collect();
+        lines.push(format!("|{}|", row));
+    }
+    lines.push(border("[SHA256]"));
+    lines.join("\n")
+}
+
+/// Returns the name under which a host is recorded in known_hosts
+fn lookup_name(host: &str, port: u16) -> String {
+    let host = host.to_lowercase();
+    if port == 22 {
+        host
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn hmac_sha1(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Returns a `|1|salt|hash` hashed form of a host name,
+/// as produced by `HashKnownHosts yes` and `ssh-keygen -H`
+fn hash_host_name(name: &str) -> anyhow::Result<String> {
+    let mut salt = [0u8; 20];
+    getrandom::fill(&mut salt).map_err(|err| anyhow!("generating salt: {err:#}"))?;
+    let hashed = hmac_sha1(&salt, name.as_bytes());
+    Ok(format!(
+        "|1|{}|{}",
+        STANDARD.encode(salt),
+        STANDARD.encode(hashed)
+    ))
+}
+
+/// Matches `name` against a pattern that may use `*` and `?` wildcards
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            wildcard_match(&pattern[1..], name)
+                || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+enum HostPatterns {
+    Hashed { salt: Vec<u8>, hash: Vec<u8> },
+    List(String),
+}
+
+impl HostPatterns {
+    fn parse(hosts: &str) -> anyhow::Result<Self> {
+        match hosts.strip_prefix("|1|") {
+            Some(hashed) => {
+                let (salt, hash) = hashed
+                    .split_once('|')
+                    .ok_or_else(|| anyhow!("invalid hashed host"))?;
+                Ok(Self::Hashed {
+                    salt: STANDARD.decode(salt)?,
+                    hash: STANDARD.decode(hash)?,
+                })
+            }
+            None => Ok(Self::List(hosts.to_lowercase())),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Hashed { salt, hash } => hmac_sha1(salt, name.as_bytes()) == *hash,
+            Self::List(patterns) => {
+                let mut matched = false;
+                for pattern in patterns.split(',') {
+                    match pattern.strip_prefix('!') {
+                        Some(negated) => {
+                            if wildcard_match(negated.as_bytes(), name.as_bytes()) {
+                                return false;
+                            }
+                        }
+                        None => {
+                            if wildcard_match(pattern.as_bytes(), name.as_bytes()) {
+                                matched = true;
+                            }
+                        }
+                    }
+                }
+                matched
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    Revoked,
+}
+
+#[derive(Debug)]
+struct Entry {
+    marker: Option<Marker>,
+    hosts: HostPatterns,
+    key: HostKey,
+    /// The 1-based line number
+    line: usize,
+}
+
+impl Entry {
+    /// Parses a line from a known_hosts file, returning None for
+    /// comments, blank lines, unknown markers and malformed entries
+    fn parse(line: &str, line_number: usize) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut fields = line.split_whitespace();
+        let mut hosts = fields.next()?;
+        let marker = if hosts.starts_with('@') {
+            let marker = match hosts {
+                "@revoked" => Marker::Revoked,
+                _ => return None,
+            };
+            hosts = fields.next()?;
+            Some(marker)
+        } else {
+            None
+        };
+
+        let key_type = fields.next()?;
+        let data = fields.next()?;
+        let parsed = HostPatterns::parse(hosts).and_then(|hosts| {
+            Ok((
+                hosts,
+                HostKey::from_openssh(&format!("{} {}", key_type, data))?,
+            ))
+        });
+        match parsed {
+            Ok((hosts, key)) => Some(Self {
+                marker,
+                hosts,
+                key,
+                line: line_number,
+            }),
+            Err(err) => {
+                log::warn!("ignoring known_hosts line {}: {:#}", line_number, err);
+                None
+            }
+        }
+    }
+}
+
+/// The result of checking a host key against the known hosts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostKeyStatus {
+    /// The key is trusted for the host
+    Trusted,
+    /// The key has been revoked
+    Revoked { file: PathBuf, line: usize },
+    /// No keys are recorded for the host
+    NotFound,
+    /// Keys of other types are recorded for the host, but there
+    /// is no key of this type
+    OtherKeyTypes(Vec<String>),
+    /// A different key of the same type is recorded for the host
+    Changed { file: PathBuf, line: usize },
+}
+
+/// The entries of a set of known_hosts files
+#[derive(Debug, Default)]
+pub struct KnownHosts {
+    files: Vec<(PathBuf, Vec<Entry>)>,
+}
+
+impl KnownHosts {
+    /// Loads the entries from `files`; files that don't exist are skipped
+    pub fn load<P: AsRef<Path>>(files: impl IntoIterator<Item = P>) -> Self {
+        let mut known = Self::default();
+        for file in files {
+            let file = file.as_ref();
+            match std::fs::read_to_string(file) {
+                Ok(contents) => known.add_file(file, &contents),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => log::error!("reading known_hosts file {}: {:#}", file.display(), err),
+            }
+        }
+        known
+    }
+
+    fn add_file(&mut self, file: &Path, contents: &str) {
+        let entries = contents
+            .lines()
+            .enumerate()
+            .filter_map(|(idx, line)| Entry::parse(line, idx + 1))
+            .collect();
+        self.files.push((file.to_path_buf(), entries));
+    }
+
+    fn matching<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a Path, &'a Entry)> + 'a {
+        self.files.iter().flat_map(move |(file, entries)| {
+            entries
+                .iter()
+                .filter(move |entry| entry.hosts.matches(name))
+                .map(move |entry| (file.as_path(), entry))
+        })
+    }
+
+    /// Checks the key presented by `host` on `port`
+    pub fn check(&self, host: &str, port: u16, key: &HostKey) -> HostKeyStatus {
+        let name = lookup_name(host, port);
+        for (file, entry) in self.matching(&name) {
+            if entry.marker == Some(Marker::Revoked) && entry.key == *key {
+                return HostKeyStatus::Revoked {
+                    file: file.to_path_buf(),
+                    line: entry.line,
+                };
+            }
+        }
+
+        let mut other_types = vec![];
+        let mut changed = None;
+        for (file, entry) in self.matching(&name) {
+            if entry.marker.is_some() {
+                continue;
+            }
+            if entry.key == *key {
+                return HostKeyStatus::Trusted;
+            }
+            if entry.key.key_type == key.key_type {
+                if changed.is_none() {
+                    changed = Some(HostKeyStatus::Changed {
+                        file: file.to_path_buf(),
+                        line: entry.line,
+                    });
+                }
+            } else if !other_types.contains(&entry.key.key_type) {
+                other_types.push(entry.key.key_type.clone());
+            }
+        }
+
+        match changed {
+            Some(changed) => changed,
+            None if other_types.is_empty() => HostKeyStatus::NotFound,
+            None => HostKeyStatus::OtherKeyTypes(other_types),
+        }
+    }
+
+    /// Checks a key that is only known by its SHA256 digest.
+    /// Since the type of the key is not known, a host that has
+    /// keys recorded but none that match is reported as Changed.
+    pub fn check_sha256(&self, host: &str, port: u16, digest: &[u8]) -> HostKeyStatus {
+        let name = lookup_name(host, port);
+        let matching = |entry: &Entry| sha256(&entry.key.blob) == digest;
+
+        for (file, entry) in self.matching(&name) {
+            if entry.marker == Some(Marker::Revoked) && matching(entry) {
+                return HostKeyStatus::Revoked {
+                    file: file.to_path_buf(),
+                    line: entry.line,
+                };
+            }
+        }
+
+        let mut changed = None;
+        for (file, entry) in self.matching(&name) {
+            if entry.marker.is_some() {
+                continue;
+            }
+            if matching(entry) {
+                return HostKeyStatus::Trusted;
+            }
+            if changed.is_none() {
+                changed = Some(HostKeyStatus::Changed {
+                    file: file.to_path_buf(),
+                    line: entry.line,
+                });
+            }
+        }
+        changed.unwrap_or(HostKeyStatus::NotFound)
+    }
+}
+
+/// Appends an entry for `host` on `port` to `file`, creating it if needed.
+/// The host name is hashed if `hash` is true.
+pub fn add_host_key(
+    file: &Path,
+    host: &str,
+    port: u16,
+    key: &HostKey,
+    hash: bool,
+) -> anyhow::Result<()> {
+    let name = lookup_name(host, port);
+    let name = if hash { hash_host_name(&name)? } else { name };
+
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating directory {}", parent.display()))?;
+    }
+    let needs_newline = match std::fs::read(file) {
+        Ok(contents) => !contents.is_empty() && !contents.ends_with(b"\n"),
+        Err(_) => false,
+    };
+
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .with_context(|| format!("opening known_hosts file {}", file.display()))?;
+    if needs_newline {
+        writeln!(f)?;
+    }
+    writeln!(f, "{} {}", name, key.to_openssh())
+        .with_context(|| format!("writing known_hosts file {}", file.display()))?;
+    Ok(())
+}
+
+/// Removes the entries for `host` on `port` from `file`.
+/// If `key_type` is specified, only entries of that type are removed.
+/// Markers are left untouched.
+/// Returns the number of entries that were removed.
+pub fn remove_host_keys(
+    file: &Path,
+    host: &str,
+    port: u16,
+    key_type: Option<&str>,
+) -> anyhow::Result<usize> {
+    let name = lookup_name(host, port);
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("reading known_hosts file {}", file.display()))?;
+
+    let mut removed = 0;
+    let mut result = String::with_capacity(contents.len());
+    for (idx, line) in contents.lines().enumerate() {
+        if let Some(entry) = Entry::parse(line, idx + 1) {
+            if entry.marker.is_none()
+                && entry.hosts.matches(&name)
+                && key_type.map(|t| t == entry.key.key_type).unwrap_or(true)
+            {
+                removed += 1;
+                continue;
+            }
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+
+    if removed > 0 {
+        std::fs::write(file, result)
+            .with_context(|| format!("writing known_hosts file {}", file.display()))?;
+    }
+    Ok(removed)
+}
+
+/// Replaces any entries of the same type as `key` for `host` on
+/// `port` in `file` with `key`
+pub fn replace_host_key(
+    file: &Path,
+    host: &str,
+    port: u16,
+    key: &HostKey,
+    hash: bool,
+) -> anyhow::Result<()> {
+    remove_host_keys(file, host, port, Some(key.key_type()))?;
+    add_host_key(file, host, port, key, hash)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use predicates::prelude::*;
+
+    const KNOWN_HOSTS: &str = include_str!("../data/known_hosts/known_hosts");
+
+    fn known_hosts() -> KnownHosts {
+        let mut known = KnownHosts::default();
+        known.add_file(Path::new("known_hosts"), KNOWN_HOSTS);
+        known
+    }
+
+    fn key(s: &str) -> HostKey {
+        HostKey::from_openssh(s).unwrap()
+    }
+
+    fn ed25519() -> HostKey {
+        key(include_str!("../data/known_hosts/host_ed25519.pub"))
+    }
+
+    fn rsa() -> HostKey {
+        key(include_str!("../data/known_hosts/host_rsa.pub"))
+    }
+
+    fn ecdsa() -> HostKey {
+        key(include_str!("../data/known_hosts/host_ecdsa.pub"))
+    }
+
+    fn other_ed25519() -> HostKey {
+        key(include_str!("../data/known_hosts/other_ed25519.pub"))
+    }
+
+    fn changed(line: usize) -> HostKeyStatus {
+        HostKeyStatus::Changed {
+            file: "known_hosts".into(),
+            line,
+        }
+    }
+
+    #[test]
+    fn plain_entries() {
+        let known = known_hosts();
+        assert_eq!(
+            known.check("example.com", 22, &ed25519()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("EXAMPLE.com", 22, &ecdsa()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("192.0.2.1", 22, &ed25519()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("example.com", 22, &rsa()),
+            HostKeyStatus::OtherKeyTypes(vec![
+                "ssh-ed25519".to_string(),
+                "ecdsa-sha2-nistp256".to_string()
+            ])
+        );
+        assert_eq!(known.check("example.com", 2222, &ed25519()), changed(4));
+        assert_eq!(
+            known.check("example.org", 22, &ed25519()),
+            HostKeyStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn wildcards() {
+        let known = known_hosts();
+        assert_eq!(
+            known.check("build.internal", 22, &rsa()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("secret.internal", 22, &rsa()),
+            HostKeyStatus::NotFound
+        );
+        assert_eq!(
+            known.check("db1.example.org", 22, &ecdsa()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("db1.example.org", 22, &rsa()),
+            HostKeyStatus::OtherKeyTypes(vec!["ecdsa-sha2-nistp256".to_string()])
+        );
+        assert_eq!(
+            known.check("db12.example.org", 22, &ecdsa()),
+            HostKeyStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn hashed() {
+        let known = known_hosts();
+        assert_eq!(
+            known.check("hashed.example.com", 22, &rsa()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("hashed.example.com", 2222, &ecdsa()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("hashed.example.com", 22, &ecdsa()),
+            HostKeyStatus::OtherKeyTypes(vec!["ssh-rsa".to_string()])
+        );
+        assert_eq!(
+            known.check("hashed.example.org", 22, &rsa()),
+            HostKeyStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn revoked() {
+        let known = known_hosts();
+        let revoked = HostKeyStatus::Revoked {
+            file: "known_hosts".into(),
+            line: 18,
+        };
+        // Revocation takes precedence over the entry that
+        // trusts this key for this host
+        assert_eq!(known.check("example.com", 2222, &other_ed25519()), revoked);
+        assert_eq!(known.check("anywhere", 22, &other_ed25519()), revoked);
+        assert_eq!(
+            known.check_sha256("example.com", 2222, &sha256(other_ed25519().blob())),
+            revoked
+        );
+    }
+
+    #[test]
+    fn cert_authorities_are_ignored() {
+        let known = known_hosts();
+        let line = KNOWN_HOSTS.lines().nth(14).unwrap();
+        assert!(line.starts_with("@cert-authority *.example.net "));
+        let ca = key(line.splitn(3, ' ').nth(2).unwrap());
+        assert_eq!(
+            known.check("host.example.net", 22, &ca),
+            HostKeyStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn fingerprint_and_randomart() {
+        assert_eq!(
+            ed25519().fingerprint(),
+            "SHA256:7wzM4PHSW1Fysq3MTtDIwlJSdhm5e2ujYK/kOkjmX/U"
+        );
+        assert_eq!(
+            rsa().fingerprint(),
+            "SHA256:ZSrZVGv179yP4wxE31Yfh21RXH6hjUxG43hf1w8+wKw"
+        );
+        assert_eq!(
+            ed25519().randomart(),
+            include_str!("../data/known_hosts/host_ed25519.randomart").trim_end()
+        );
+        assert_eq!(
+            rsa().randomart(),
+            include_str!("../data/known_hosts/host_rsa.randomart").trim_end()
+        );
+        assert_eq!(
+            ecdsa().randomart(),
+            include_str!("../data/known_hosts/host_ecdsa.randomart").trim_end()
+        );
+    }
+
+    #[test]
+    fn check_sha256() {
+        let known = known_hosts();
+        assert_eq!(
+            known.check_sha256("example.com", 22, &sha256(ed25519().blob())),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check_sha256("example.com", 2222, &sha256(ed25519().blob())),
+            changed(4)
+        );
+        assert_eq!(
+            known.check_sha256("example.org", 22, &sha256(ed25519().blob())),
+            HostKeyStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn add_and_replace() {
+        let tmp = TempDir::new().unwrap();
+        let file = tmp.child("ssh/known_hosts");
+
+        add_host_key(file.path(), "new.example.com", 22, &ed25519(), false).unwrap();
+        add_host_key(file.path(), "hashed.example.com", 2222, &rsa(), true).unwrap();
+        file.assert(predicate::str::contains(format!(
+            "new.example.com {}\n",
+            ed25519().to_openssh()
+        )));
+        file.assert(predicate::str::contains("hashed.example.com").not());
+
+        let known = KnownHosts::load([file.path()]);
+        assert_eq!(
+            known.check("new.example.com", 22, &ed25519()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("hashed.example.com", 2222, &rsa()),
+            HostKeyStatus::Trusted
+        );
+
+        // Replacing the key leaves the other entries alone
+        file.write_str(KNOWN_HOSTS).unwrap();
+        replace_host_key(file.path(), "example.com", 2222, &ed25519(), false).unwrap();
+        let known = KnownHosts::load([file.path()]);
+        assert_eq!(
+            known.check("example.com", 2222, &ed25519()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("example.com", 22, &ed25519()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            known.check("example.com", 22, &ecdsa()),
+            HostKeyStatus::Trusted
+        );
+        assert_eq!(
+            std::fs::read_to_string(file.path())
+                .unwrap()
+                .lines()
+                .count(),
+            KNOWN_HOSTS.lines().count()
+        );
+
+        assert_eq!(
+            remove_host_keys(file.path(), "example.com", 22, None).unwrap(),
+            2
+        );
+        let known = KnownHosts::load([file.path()]);
+        assert_eq!(
+            known.check("example.com", 22, &ed25519()),
+            HostKeyStatus::NotFound
+        );
+        assert_eq!(
+            known.check("192.0.2.1", 22, &ed25519()),
+            HostKeyStatus::NotFound
+        );
+    }
+}
@@ -4,19 +4,31 @@ compile_error!("Either libssh-rs or ssh2 must be enabled!");
 mod auth;
 mod channelwrap;
 mod config;
+#[cfg(unix)]
+mod controlclient;
+#[cfg(unix)]
+mod controlmaster;
 mod dirwrap;
 mod filewrap;
+mod forward;
 mod host;
+mod jump;
+mod knownhosts;
 mod pty;
 mod session;
 mod sessioninner;
 mod sessionwrap;
 mod sftp;
 mod sftpwrap;
+mod x11;
 
 pub use auth::*;
 pub use config::*;
+#[cfg(unix)]
+pub use controlmaster::*;
+pub use forward::*;
 pub use host::*;
+pub use knownhosts::*;
 pub use pty::*;
 pub use session::*;
 pub use sftp::error::*;
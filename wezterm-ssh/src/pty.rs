@@ -98,6 +98,12 @@ pub struct SshChildProcess {
 }
 
 impl SshChildProcess {
+    /// Returns true if the session that hosts this process has ended,
+    /// which is the case when the connection to the server was lost.
+    pub fn is_session_closed(&self) -> bool {
+        self.tx.as_ref().map(|tx| tx.tx.is_closed()).unwrap_or(true)
+    }
+
     pub async fn async_wait(&mut self) -> std::io::Result<ExitStatus> {
         if let Some(status) = self.exited.as_ref() {
             return Ok(status.clone());
@@ -225,6 +231,7 @@ impl crate::sessioninner::SessionInner {
                 }
             }
         }
+        self.request_x11_forwarding(&mut channel);
 
         channel.request_pty(&newpty)?;
 
@@ -296,6 +303,8 @@ impl crate::sessioninner::SessionInner {
             channel,
             exit: Some(exit_tx),
             exited: false,
+            send_eof: false,
+            eof_pending: false,
             descriptors: [
                 DescriptorState {
                     fd: Some(read_from_stdin),
@@ -1,15 +1,17 @@
 use crate::auth::*;
-use crate::config::ConfigMap;
+use crate::config::{Config, ConfigMap};
+use crate::forward::{ForwardId, ForwardInfo, ForwardSpec};
 use crate::host::*;
 use crate::pty::*;
 use crate::sessioninner::*;
 use crate::sftp::{Sftp, SftpRequest};
+use crate::x11::X11State;
 use filedescriptor::{socketpair, FileDescriptor};
 use portable_pty::PtySize;
 use smol::channel::{bounded, Receiver, Sender};
 use std::collections::HashMap;
 use std::io::Write;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 
 #[derive(Debug)]
@@ -58,6 +60,10 @@ pub(crate) enum SessionRequest {
     Exec(Exec, Sender<anyhow::Result<ExecResult>>),
     Sftp(SftpRequest),
     SignalChannel(SignalChannel),
+    Forward(ForwardSpec, Sender<anyhow::Result<ForwardInfo>>),
+    CancelForward(ForwardId, Sender<anyhow::Result<()>>),
+    ListForwards(Sender<Vec<ForwardInfo>>),
+    OpenTunnel(String, u16, Sender<anyhow::Result<FileDescriptor>>),
     SessionDropped,
 }
 
@@ -73,20 +79,70 @@ pub(crate) struct Exec {
     pub env: Option<HashMap<String, String>>,
 }
 
-#[derive(Clone)]
-pub struct Session {
+/// Lets the session know when the last reference to it has been dropped
+struct DropNotifier {
     tx: SessionSender,
 }
 
-impl Drop for Session {
+impl Drop for DropNotifier {
     fn drop(&mut self) {
         self.tx.try_send(SessionRequest::SessionDropped).ok();
         log::trace!("Drop Session");
     }
 }
 
+/// A handle to an ssh session.  Clones of a Session share the same
+/// underlying connection, which is closed once all of them, and all
+/// of the channels opened through them, have been dropped.
+#[derive(Clone)]
+pub struct Session {
+    tx: SessionSender,
+    notifier: Arc<DropNotifier>,
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("Session")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// A reference to a Session that doesn't keep it alive
+#[derive(Clone)]
+pub struct WeakSession {
+    tx: SessionSender,
+    notifier: Weak<DropNotifier>,
+}
+
+impl WeakSession {
+    /// Returns the Session if it is still referenced elsewhere
+    pub fn upgrade(&self) -> Option<Session> {
+        Some(Session {
+            tx: self.tx.clone(),
+            notifier: self.notifier.upgrade()?,
+        })
+    }
+}
+
 impl Session {
     pub fn connect(config: ConfigMap) -> anyhow::Result<(Self, Receiver<SessionEvent>)> {
+        Self::connect_impl(config, None)
+    }
+
+    /// Like `connect`, but resolves the configuration of any ProxyJump
+    /// hosts using `jump_config` rather than the default ssh config files
+    pub fn connect_with_jump_config(
+        config: ConfigMap,
+        jump_config: Config,
+    ) -> anyhow::Result<(Self, Receiver<SessionEvent>)> {
+        Self::connect_impl(config, Some(jump_config))
+    }
+
+    fn connect_impl(
+        config: ConfigMap,
+        jump_config: Option<Config>,
+    ) -> anyhow::Result<(Self, Receiver<SessionEvent>)> {
         let (tx_event, rx_event) = bounded(8);
         let (tx_req, rx_req) = bounded(8);
         let (mut sender_write, mut sender_read) = socketpair()?;
@@ -107,10 +163,17 @@ impl Session {
             }
         });
 
+        let keep_alive_count_max = config
+            .get("serveralivecountmax")
+            .and_then(|value| value.parse().ok())
+            .filter(|&count: &u32| count > 0)
+            .unwrap_or(3);
+
         let now = Instant::now();
 
         let mut inner = SessionInner {
             config,
+            jump_config,
             tx_event,
             rx_req,
             channels: HashMap::new(),
@@ -123,9 +186,64 @@ impl Session {
             shown_accept_env_error: false,
             last_keep_alive: now,
             keep_alive,
+            keep_alive_count_max,
+            keep_alive_failures: 0,
+            keep_alive_unanswered: 0,
+            forwards: HashMap::new(),
+            pending_socks: vec![],
+            pending_connects: vec![],
+            pending_opens: vec![],
+            next_forward_id: 1,
+            x11: X11State::Unset,
         };
         std::thread::spawn(move || inner.run());
-        Ok((Self { tx: session_sender }, rx_event))
+        let session = Self {
+            notifier: Arc::new(DropNotifier {
+                tx: session_sender.clone(),
+            }),
+            tx: session_sender,
+        };
+        Ok((session, rx_event))
+    }
+
+    /// Runs a Session through the control socket at `path`, which is
+    /// served by another process that is the ControlMaster for the host,
+    /// much like `ssh` does when it finds a socket at its ControlPath.
+    /// No authentication takes place, as the master is already connected.
+    /// The sftp subsystem is not available through the control socket.
+    #[cfg(unix)]
+    pub fn connect_control_socket(path: &std::path::Path) -> anyhow::Result<Self> {
+        let (tx_req, rx_req) = bounded(8);
+        let (mut sender_write, mut sender_read) = socketpair()?;
+        sender_write.set_non_blocking(true)?;
+        sender_read.set_non_blocking(true)?;
+
+        let client = crate::controlclient::ControlClient::connect(path, rx_req, sender_read)?;
+        std::thread::spawn(move || client.run());
+
+        let session_sender = SessionSender {
+            tx: tx_req,
+            pipe: Arc::new(Mutex::new(sender_write)),
+        };
+        Ok(Self {
+            notifier: Arc::new(DropNotifier {
+                tx: session_sender.clone(),
+            }),
+            tx: session_sender,
+        })
+    }
+
+    pub fn downgrade(&self) -> WeakSession {
+        WeakSession {
+            tx: self.tx.clone(),
+            notifier: Arc::downgrade(&self.notifier),
+        }
+    }
+
+    /// Returns true if the connection has ended, either because it
+    /// failed or because the remote host closed it
+    pub fn is_closed(&self) -> bool {
+        self.tx.tx.is_closed()
     }
 
     pub async fn request_pty(
@@ -175,6 +293,51 @@ impl Session {
         Ok(exec)
     }
 
+    /// Establishes a port forward that will be carried over this session.
+    /// Forwards remain active until they are cancelled or the session ends.
+    pub async fn forward(&self, spec: ForwardSpec) -> anyhow::Result<ForwardInfo> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .send(SessionRequest::Forward(spec, reply))
+            .await
+            .map_err(|_| DeadSession)?;
+        rx.recv().await?
+    }
+
+    /// Stops accepting connections for the forward with the specified id.
+    /// Connections that were already made through it remain open.
+    pub async fn cancel_forward(&self, id: ForwardId) -> anyhow::Result<()> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .send(SessionRequest::CancelForward(id, reply))
+            .await
+            .map_err(|_| DeadSession)?;
+        rx.recv().await?
+    }
+
+    /// Returns the forwards that are active on this session,
+    /// including those that were configured via the ssh config
+    pub async fn list_forwards(&self) -> anyhow::Result<Vec<ForwardInfo>> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .send(SessionRequest::ListForwards(reply))
+            .await
+            .map_err(|_| DeadSession)?;
+        Ok(rx.recv().await?)
+    }
+
+    /// Opens a direct-tcpip channel to `host:port`, as seen from the
+    /// remote host, and returns a socket connected to it, much like
+    /// `ssh -W host:port`.
+    pub async fn open_tunnel(&self, host: &str, port: u16) -> anyhow::Result<FileDescriptor> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .send(SessionRequest::OpenTunnel(host.to_string(), port, reply))
+            .await
+            .map_err(|_| DeadSession)?;
+        rx.recv().await?
+    }
+
     /// Creates a new reference to the sftp channel for filesystem operations
     ///
     /// ### Note
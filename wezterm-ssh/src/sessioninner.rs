@@ -1,14 +1,16 @@
 use crate::channelwrap::ChannelWrap;
-use crate::config::ConfigMap;
+use crate::config::{Config, ConfigMap};
 use crate::dirwrap::DirWrap;
 use crate::filewrap::FileWrap;
+use crate::forward::{ForwardId, ForwardState, PendingConnect, PendingOpen, PendingSocks};
 use crate::pty::*;
-use crate::session::{Exec, ExecResult, SessionEvent, SessionRequest, SignalChannel};
+use crate::session::{Exec, ExecResult, Session, SessionEvent, SessionRequest, SignalChannel};
 use crate::sessionwrap::SessionWrap;
 use crate::sftp::dir::{Dir, DirId, DirRequest};
 use crate::sftp::file::{File, FileId, FileRequest};
 use crate::sftp::{OpenWithMode, SftpChannelResult, SftpRequest};
 use crate::sftpwrap::SftpWrap;
+use crate::x11::X11State;
 use anyhow::{anyhow, Context};
 use camino::Utf8PathBuf;
 use filedescriptor::{
@@ -33,6 +35,12 @@ pub(crate) struct ChannelInfo {
     pub channel: ChannelWrap,
     pub exit: Option<Sender<ExitStatus>>,
     pub exited: bool,
+    /// When set, reaching EOF on stdin sends EOF to the remote
+    /// command, rather than closing the channel
+    pub send_eof: bool,
+    /// Set once stdin has reached EOF; the EOF is sent after the
+    /// remaining buffered stdin data has been written
+    pub eof_pending: bool,
     pub descriptors: [DescriptorState; 3],
 }
 
@@ -40,6 +48,9 @@ pub(crate) type ChannelId = usize;
 
 pub(crate) struct SessionInner {
     pub config: ConfigMap,
+    /// Used to resolve the configuration of ProxyJump hosts;
+    /// if None, the default ssh config files are used
+    pub jump_config: Option<Config>,
     pub tx_event: Sender<SessionEvent>,
     pub rx_req: Receiver<SessionRequest>,
     pub channels: HashMap<ChannelId, ChannelInfo>,
@@ -52,6 +63,17 @@ pub(crate) struct SessionInner {
     pub shown_accept_env_error: bool,
     pub last_keep_alive: Instant,
     pub keep_alive: Option<Duration>,
+    pub keep_alive_count_max: u32,
+    pub keep_alive_failures: u32,
+    /// The number of keepalive requests sent since we last heard
+    /// from the server
+    pub keep_alive_unanswered: u32,
+    pub forwards: HashMap<ForwardId, ForwardState>,
+    pub pending_socks: Vec<PendingSocks>,
+    pub pending_connects: Vec<PendingConnect>,
+    pub pending_opens: Vec<PendingOpen>,
+    pub next_forward_id: ForwardId,
+    pub x11: X11State,
 }
 
 impl Drop for SessionInner {
@@ -186,9 +208,45 @@ impl SessionInner {
         }
         if let Some(files) = self.config.get("identityfile") {
             for file in files.split_whitespace() {
+                let path = std::path::Path::new(file);
+                if path.exists() && crate::auth::is_security_key(path) {
+                    // The bundled libssh has no FIDO support, so it cannot
+                    // sign with a security key, only with the agent
+                    log::warn!(
+                        "Skipping {}: security key identities must be \
+                         loaded into ssh-agent to be used with the libssh backend",
+                        file
+                    );
+                    continue;
+                }
                 sess.set_option(libssh_rs::SshOption::AddIdentity(file.to_string()))?;
             }
         }
+        if let Some(files) = self.config.get("certificatefile") {
+            // libssh presents `<identity>-cert.pub` alongside each
+            // identity, so make sure that the identity that goes with
+            // each certificate is being offered
+            let identities = self.config.get("identityfile").cloned().unwrap_or_default();
+            for file in files.split_whitespace() {
+                match file.strip_suffix("-cert.pub") {
+                    Some(identity) if std::path::Path::new(identity).exists() => {
+                        if !identities.split_whitespace().any(|i| i == identity) {
+                            sess.set_option(libssh_rs::SshOption::AddIdentity(
+                                identity.to_string(),
+                            ))?;
+                        }
+                    }
+                    _ => {
+                        log::warn!(
+                            "Ignoring CertificateFile {}: the libssh backend requires \
+                             certificates to be named <identity>-cert.pub and to be \
+                             next to their identity file",
+                            file
+                        );
+                    }
+                }
+            }
+        }
         if let Some(kh) = self.config.get("userknownhostsfile") {
             for file in kh.split_whitespace() {
                 sess.set_option(libssh_rs::SshOption::KnownHosts(Some(file.to_string())))?;
@@ -207,7 +265,7 @@ impl SessionInner {
             sess.set_option(libssh_rs::SshOption::HostKeys(host_key.to_string()))?;
         }
 
-        let (sock, _child) = self.connect_to_host(&hostname, port, verbose)?;
+        let (sock, _proxy) = self.connect_to_host(&hostname, port, verbose)?;
         let raw = {
             #[cfg(unix)]
             {
@@ -289,7 +347,7 @@ impl SessionInner {
             ))))
             .context("notifying user of banner")?;
 
-        let (sock, _child) = self.connect_to_host(&hostname, port, verbose)?;
+        let (sock, _proxy) = self.connect_to_host(&hostname, port, verbose)?;
 
         let mut sess = ssh2::Session::new()?;
         if verbose {
@@ -299,6 +357,11 @@ impl SessionInner {
         sess.set_tcp_stream(sock);
         sess.handshake()
             .with_context(|| format!("ssh handshake with {}", remote_address))?;
+        if let Some(duration) = self.keep_alive {
+            // Ask the server to reply to each keepalive, so that
+            // do_keepalive can tell when it stopped responding
+            sess.set_keepalive(true, duration.as_secs() as u32);
+        }
 
         self.tx_event
             .try_send(SessionEvent::Banner(sess.banner().map(|s| s.to_string())))
@@ -326,14 +389,24 @@ impl SessionInner {
     /// If proxy_command is set, then we execute that process for ourselves
     /// too, as proxy commands are not supported by libssh2 and are not supported
     /// on Windows in libssh.
+    /// Likewise, ProxyJump is implemented here by tunneling through
+    /// a Session with the jump host.
     fn connect_to_host(
         &self,
         hostname: &str,
         port: u16,
         verbose: bool,
-    ) -> anyhow::Result<(Socket, Option<KillOnDropChild>)> {
+    ) -> anyhow::Result<(Socket, Option<Proxy>)> {
         match self.config.get("proxycommand").map(|s| s.as_str()) {
-            Some("none") | None => {}
+            Some("none") | None => {
+                if let Some(proxy_jump) = self.config.get("proxyjump") {
+                    if proxy_jump != "none" {
+                        let (sock, session) =
+                            self.connect_via_jump_hosts(proxy_jump, hostname, port)?;
+                        return Ok((sock, Some(Proxy::Jump(session))));
+                    }
+                }
+            }
             Some(proxy_command) => {
                 let mut cmd;
                 if cfg!(windows) {
@@ -365,14 +438,17 @@ impl SessionInner {
                         _ => raw,
                     };
 
-                    return Ok((Socket::from_raw_fd(dest), Some(KillOnDropChild(child))));
+                    return Ok((
+                        Socket::from_raw_fd(dest),
+                        Some(Proxy::Command(KillOnDropChild(child))),
+                    ));
                 }
                 #[cfg(windows)]
                 unsafe {
                     use std::os::windows::io::{FromRawSocket, IntoRawSocket};
                     return Ok((
                         Socket::from_raw_socket(a.into_raw_socket()),
-                        Some(KillOnDropChild(child)),
+                        Some(Proxy::Command(KillOnDropChild(child))),
                     ));
                 }
             }
@@ -416,26 +492,75 @@ impl SessionInner {
     fn do_keepalive(&mut self, sess: &mut SessionWrap) -> anyhow::Result<()> {
         match sess {
             #[cfg(feature = "ssh2")]
-            SessionWrap::Ssh2(_sess) => Ok(()),
+            SessionWrap::Ssh2(sess) => {
+                // Every ServerAliveInterval seconds (if non-zero), we send
+                // a global request that the server has to answer.  Any
+                // traffic from the server resets the count of unanswered
+                // keepalives (see request_loop); like the openssh client,
+                // we give up once ServerAliveCountMax of them are
+                // outstanding.
+                if let Some(duration) = self.keep_alive {
+                    if self.last_keep_alive.elapsed() >= duration {
+                        if self.keep_alive_unanswered >= self.keep_alive_count_max {
+                            anyhow::bail!(
+                                "Timeout, server not responding after {} keepalives",
+                                self.keep_alive_unanswered
+                            );
+                        }
+                        log::trace!("sending keep alive");
+                        match sess.sess.keepalive_send() {
+                            Ok(_) => {
+                                self.last_keep_alive = Instant::now();
+                                // libssh2 only reads from the connection on
+                                // behalf of a channel.  Without channels the
+                                // answer stays unread, and would be mistaken
+                                // for the answer to every later keepalive,
+                                // so there is nothing we can count.
+                                if !self.channels.is_empty() {
+                                    self.keep_alive_unanswered += 1;
+                                }
+                            }
+                            Err(err) => {
+                                let err = std::io::Error::from(err);
+                                if err.kind() != std::io::ErrorKind::WouldBlock {
+                                    return Err(err).context("sending keepalive");
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
             #[cfg(feature = "libssh-rs")]
             SessionWrap::LibSsh(sess) => {
                 // We implement a very basic keep alive mechanism here;
                 // every ServerAliveInterval seconds (if non-zero), we will
                 // send an ignore packet.
-                // Unlike the openssh client, we do not have a ServerAliveCountMax
-                // limit (because it is not clear how we could correctly implement
-                // that based on what we can see here in this crate), nor do we
-                // explicitly trigger a disconnect if there is an error with
-                // the ignore packet.
+                // libssh-rs doesn't provide a way to send a request that the
+                // server must answer, and the server doesn't reply to ignore
+                // packets, so we cannot count unanswered keepalives.  Instead,
+                // ServerAliveCountMax consecutive failures to send the
+                // ignore packet are treated as the connection having been
+                // lost, which terminates the session.
                 if let Some(duration) = self.keep_alive {
                     if self.last_keep_alive.elapsed() >= duration {
                         log::trace!("sending keep alive");
                         self.last_keep_alive = Instant::now();
                         let ignore_me = [0x42; 128];
-                        if let Err(err) = sess.sess.send_ignore(&ignore_me) {
-                            log::warn!(
-                                "Error sending IGNORE packet: {err:#}. Is peer disconnected?"
-                            );
+                        match sess.sess.send_ignore(&ignore_me) {
+                            Ok(()) => self.keep_alive_failures = 0,
+                            Err(err) => {
+                                self.keep_alive_failures += 1;
+                                log::warn!(
+                                    "Error sending IGNORE packet: {err:#}. Is peer disconnected?"
+                                );
+                                if self.keep_alive_failures >= self.keep_alive_count_max {
+                                    anyhow::bail!(
+                                        "Timeout, server not responding after {} keepalives",
+                                        self.keep_alive_failures
+                                    );
+                                }
+                            }
                         }
                     }
                 }
@@ -447,12 +572,16 @@ impl SessionInner {
     fn request_loop(&mut self, sess: &mut SessionWrap) -> anyhow::Result<()> {
         let mut sleep_delay = Duration::from_millis(100);
 
+        self.start_configured_forwards(sess)?;
+
         loop {
             self.do_keepalive(sess)?;
             self.tick_io()?;
             self.drain_request_pipe();
             self.dispatch_pending_requests(sess)?;
             self.connect_pending_agent_forward_channels(sess);
+            self.accept_forwarded_connections(sess);
+            self.accept_x11_channels();
 
             if self.channels.is_empty() && self.session_was_dropped {
                 log::trace!(
@@ -469,14 +598,34 @@ impl SessionInner {
                 },
                 pollfd {
                     fd: sess.as_socket_descriptor(),
-                    events: sess.get_poll_flags(),
+                    events: if self.keep_alive_unanswered > 0 || !self.pending_opens.is_empty() {
+                        // Wait for the server to answer the keepalive,
+                        // or the requests to open forwarded channels
+                        sess.get_poll_flags() | POLLIN
+                    } else {
+                        sess.get_poll_flags()
+                    },
                     revents: 0,
                 },
             ];
+            for fd in self.forward_poll_descriptors() {
+                poll_array.push(pollfd {
+                    fd,
+                    events: POLLIN,
+                    revents: 0,
+                });
+            }
+            let num_fixed = poll_array.len();
             let mut mapping = vec![];
 
             for info in self.channels.values() {
                 for (fd_num, state) in info.descriptors.iter().enumerate() {
+                    if fd_num == 0 && state.buf.len() == state.buf.capacity() {
+                        // Wait for the channel to consume what we've
+                        // buffered; reading into a full buffer would be
+                        // mistaken for EOF
+                        continue;
+                    }
                     if let Some(fd) = state.fd.as_ref() {
                         poll_array.push(pollfd {
                             fd: fd.as_socket_descriptor(),
@@ -494,17 +643,29 @@ impl SessionInner {
                 }
             }
 
-            poll(&mut poll_array, Some(sleep_delay)).context("poll")?;
+            // Don't sleep past the next keepalive
+            let timeout = match self.keep_alive {
+                Some(duration) => {
+                    sleep_delay.min(duration.saturating_sub(self.last_keep_alive.elapsed()))
+                }
+                None => sleep_delay,
+            };
+            poll(&mut poll_array, Some(timeout)).context("poll")?;
             sleep_delay += sleep_delay;
 
+            if poll_array[1].revents & POLLIN != 0 {
+                // The server is still there
+                self.keep_alive_unanswered = 0;
+            }
+
             for (idx, poll) in poll_array.iter().enumerate() {
                 if poll.revents != 0 {
                     sleep_delay = Duration::from_millis(100);
                 }
-                if idx == 0 || idx == 1 {
+                if idx < num_fixed {
                     // Dealt with at the top of the loop
                 } else if poll.revents != 0 {
-                    let (channel_id, fd_num) = mapping[idx - 2];
+                    let (channel_id, fd_num) = mapping[idx - num_fixed];
                     let info = self.channels.get_mut(&channel_id).unwrap();
                     let state = &mut info.descriptors[fd_num];
                     let fd = state.fd.as_mut().unwrap();
@@ -518,7 +679,12 @@ impl SessionInner {
                                     "error reading from channel {channel_id} stdin pipe: {:#}",
                                     err
                                 );
-                                info.channel.close();
+                                if info.send_eof && err.kind() == std::io::ErrorKind::UnexpectedEof
+                                {
+                                    info.eof_pending = true;
+                                } else {
+                                    info.channel.close();
+                                }
                                 state.fd.take();
                             }
                         }
@@ -564,7 +730,7 @@ impl SessionInner {
             }
 
             let stdin = &mut chan.descriptors[0];
-            if stdin.fd.is_some() && !stdin.buf.is_empty() {
+            if (stdin.fd.is_some() || chan.eof_pending) && !stdin.buf.is_empty() {
                 if let Err(err) = write_from_buf(&mut chan.channel.writer(), &mut stdin.buf)
                     .context("writing to channel")
                 {
@@ -574,6 +740,17 @@ impl SessionInner {
                         err
                     );
                     stdin.fd.take();
+                    chan.eof_pending = false;
+                }
+            }
+            if chan.eof_pending && stdin.buf.is_empty() {
+                match chan.channel.send_eof() {
+                    Ok(()) => chan.eof_pending = false,
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(err) => {
+                        log::trace!("Failed to send EOF to channel {}: {:#}", id, err);
+                        chan.eof_pending = false;
+                    }
                 }
             }
 
@@ -616,6 +793,12 @@ impl SessionInner {
                 }
             }
 
+            if chan.exit.is_none() && !chan.exited && chan.descriptors[1].fd.is_none() {
+                // A stream channel, such as a forwarded connection, has
+                // reached EOF: close our end so that the peer sees it too
+                chan.descriptors[0].fd.take();
+            }
+
             if chan
                 .descriptors
                 .iter()
@@ -854,6 +1037,18 @@ impl SessionInner {
                     SessionRequest::Sftp(SftpRequest::RemoveFile(path, reply)) => {
                         dispatch(reply, || self.init_sftp(sess)?.unlink(&path), "remove_file")
                     }
+                    SessionRequest::Forward(spec, reply) => {
+                        dispatch(reply, || self.forward(sess, spec), "forward")
+                    }
+                    SessionRequest::CancelForward(id, reply) => {
+                        dispatch(reply, || self.cancel_forward(id), "cancel_forward")
+                    }
+                    SessionRequest::ListForwards(reply) => {
+                        dispatch(reply, || self.list_forwards(), "list_forwards")
+                    }
+                    SessionRequest::OpenTunnel(host, port, reply) => {
+                        dispatch(reply, || self.open_tunnel(sess, &host, port), "open_tunnel")
+                    }
                 };
                 sess.set_blocking(false);
                 res
@@ -881,33 +1076,7 @@ impl SessionInner {
                 }
             };
             fd.set_non_blocking(true)?;
-
-            let read_from_agent = fd;
-            let write_to_agent = read_from_agent.try_clone()?;
-            let channel_id = sess.next_channel_id;
-            sess.next_channel_id += 1;
-            let info = ChannelInfo {
-                channel_id,
-                channel,
-                exit: None,
-                exited: false,
-                descriptors: [
-                    DescriptorState {
-                        fd: Some(read_from_agent),
-                        buf: VecDeque::with_capacity(8192),
-                    },
-                    DescriptorState {
-                        fd: Some(write_to_agent),
-                        buf: VecDeque::with_capacity(8192),
-                    },
-                    DescriptorState {
-                        fd: None,
-                        buf: VecDeque::with_capacity(8192),
-                    },
-                ],
-            };
-            sess.channels.insert(channel_id, info);
-            Ok(())
+            sess.add_stream_channel(channel, fd)
         }
         while let Some(channel) = sess.accept_agent_forward() {
             if let Err(err) = process_one(self, channel) {
@@ -916,6 +1085,42 @@ impl SessionInner {
         }
     }
 
+    /// Registers a channel whose data is relayed to and from `fd`,
+    /// which is typically a socket.  `fd` must be non-blocking.
+    pub fn add_stream_channel(
+        &mut self,
+        channel: ChannelWrap,
+        fd: FileDescriptor,
+    ) -> anyhow::Result<()> {
+        let write_to_fd = fd.try_clone()?;
+        let channel_id = self.next_channel_id;
+        self.next_channel_id += 1;
+        let info = ChannelInfo {
+            channel_id,
+            channel,
+            exit: None,
+            exited: false,
+            send_eof: false,
+            eof_pending: false,
+            descriptors: [
+                DescriptorState {
+                    fd: Some(fd),
+                    buf: VecDeque::with_capacity(8192),
+                },
+                DescriptorState {
+                    fd: Some(write_to_fd),
+                    buf: VecDeque::with_capacity(8192),
+                },
+                DescriptorState {
+                    fd: None,
+                    buf: VecDeque::with_capacity(8192),
+                },
+            ],
+        };
+        self.channels.insert(channel_id, info);
+        Ok(())
+    }
+
     pub fn signal_channel(&mut self, info: &SignalChannel) -> anyhow::Result<()> {
         let chan_info = self
             .channels
@@ -936,6 +1141,7 @@ impl SessionInner {
                 }
             }
         }
+        self.request_x11_forwarding(&mut channel);
 
         if let Some(env) = &exec.env {
             for (key, val) in env {
@@ -988,6 +1194,8 @@ impl SessionInner {
             channel,
             exit: Some(exit_tx),
             exited: false,
+            send_eof: true,
+            eof_pending: false,
             descriptors: [
                 DescriptorState {
                     fd: Some(read_from_stdin),
@@ -1127,6 +1335,14 @@ where
     Ok(true)
 }
 
+/// Keeps alive whatever is carrying the connection to the host,
+/// when it is not connected to directly.
+#[allow(dead_code)]
+enum Proxy {
+    Command(KillOnDropChild),
+    Jump(Session),
+}
+
 /// A little helper to ensure the Child process is killed on Drop.
 struct KillOnDropChild(std::process::Child);
 
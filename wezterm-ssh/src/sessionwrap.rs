@@ -1,4 +1,5 @@
 use crate::channelwrap::ChannelWrap;
+use crate::forward::ForwardListener;
 use crate::sftpwrap::SftpWrap;
 use filedescriptor::{AsRawSocketDescriptor, SocketDescriptor, POLLIN, POLLOUT};
 
@@ -14,6 +15,15 @@ pub(crate) struct LibSshSession {
     pub sftp: Option<SftpWrap>,
 }
 
+/// The progress of a direct-tcpip channel that is being opened
+/// without blocking; see `SessionWrap::poll_direct_tcpip`
+#[derive(Default)]
+pub(crate) struct DirectTcpipOpen {
+    /// libssh opens the channel object that it was given
+    #[cfg(feature = "libssh-rs")]
+    channel: Option<libssh_rs::Channel>,
+}
+
 pub(crate) enum SessionWrap {
     #[cfg(feature = "ssh2")]
     Ssh2(Ssh2Session),
@@ -104,4 +114,128 @@ impl SessionWrap {
             Self::LibSsh(sess) => sess.sess.accept_agent_forward().map(ChannelWrap::LibSsh),
         }
     }
+
+    /// Opens a channel to `host:port`, as seen from the remote host,
+    /// on behalf of a connection that originated from `originator`
+    pub fn open_direct_tcpip(
+        &self,
+        host: &str,
+        port: u16,
+        originator: &std::net::SocketAddr,
+    ) -> anyhow::Result<ChannelWrap> {
+        let src_host = originator.ip().to_string();
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                let channel = sess.sess.channel_direct_tcpip(
+                    host,
+                    port,
+                    Some((&src_host, originator.port())),
+                )?;
+                Ok(ChannelWrap::Ssh2(channel))
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => {
+                let channel = sess.sess.new_channel()?;
+                channel.open_forward(host, port, &src_host, originator.port())?;
+                Ok(ChannelWrap::LibSsh(channel))
+            }
+        }
+    }
+
+    /// Makes progress on opening a direct-tcpip channel while the session
+    /// is non-blocking.  Returns Ok(None) if the server has yet to answer,
+    /// in which case this must be called again with the same parameters
+    /// and `state` once the session is ready.  libssh2 tracks the progress
+    /// in the session, so only one channel may be opened at a time.
+    pub fn poll_direct_tcpip(
+        &self,
+        host: &str,
+        port: u16,
+        originator: &std::net::SocketAddr,
+        state: &mut DirectTcpipOpen,
+    ) -> anyhow::Result<Option<ChannelWrap>> {
+        let src_host = originator.ip().to_string();
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                let _ = state;
+                match sess.sess.channel_direct_tcpip(
+                    host,
+                    port,
+                    Some((&src_host, originator.port())),
+                ) {
+                    Ok(channel) => Ok(Some(ChannelWrap::Ssh2(channel))),
+                    Err(err) => {
+                        let err = std::io::Error::from(err);
+                        if err.kind() == std::io::ErrorKind::WouldBlock {
+                            Ok(None)
+                        } else {
+                            Err(err.into())
+                        }
+                    }
+                }
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => {
+                let channel = match state.channel.take() {
+                    Some(channel) => channel,
+                    None => sess.sess.new_channel()?,
+                };
+                match channel.open_forward(host, port, &src_host, originator.port()) {
+                    Ok(()) => Ok(Some(ChannelWrap::LibSsh(channel))),
+                    Err(libssh_rs::Error::TryAgain) => {
+                        state.channel.replace(channel);
+                        Ok(None)
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
+        }
+    }
+
+    /// Asks the remote host to listen on `address:port` and to forward
+    /// connections to us.  Returns the listener and the bound port.
+    pub fn listen_forward(
+        &self,
+        address: &str,
+        port: u16,
+    ) -> anyhow::Result<(ForwardListener, u16)> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                let (listener, bound_port) =
+                    sess.sess
+                        .channel_forward_listen(port, Some(address), None)?;
+                Ok((ForwardListener::Ssh2(listener), bound_port))
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => {
+                let bound_port = sess.sess.listen_forward(Some(address), port)?;
+                // libssh only reports the port when it was assigned by the server
+                let bound_port = if bound_port == 0 { port } else { bound_port };
+                Ok((ForwardListener::LibSsh, bound_port))
+            }
+        }
+    }
+
+    /// Returns a connection that the remote host accepted on one of
+    /// the ports requested via `listen_forward`, along with that port.
+    /// For ssh2, connections are accepted via the `Listener` instead.
+    pub fn accept_remote_forward(&mut self) -> Option<(u16, ChannelWrap)> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(_sess) => None,
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => sess
+                .sess
+                .accept_forward(std::time::Duration::ZERO)
+                .ok()
+                .map(|(port, channel)| (port, ChannelWrap::LibSsh(channel))),
+        }
+    }
 }
@@ -0,0 +1,661 @@
+use crate::channelwrap::ChannelWrap;
+use crate::config::ConfigMap;
+use crate::forward::stream_to_fd;
+use crate::sessioninner::SessionInner;
+use anyhow::{anyhow, Context};
+use filedescriptor::{socketpair, FileDescriptor};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The first X11 display port; display N listens on 6000 + N
+const X11_BASE_PORT: u16 = 6000;
+
+const FAMILY_INTERNET: u16 = 0;
+const FAMILY_INTERNET6: u16 = 6;
+const FAMILY_LOCAL: u16 = 256;
+const FAMILY_WILD: u16 = 65535;
+
+const MIT_MAGIC_COOKIE: &str = "MIT-MAGIC-COOKIE-1";
+
+/// Tracks whether X11 forwarding has been set up for the session
+pub(crate) enum X11State {
+    /// Not yet requested for any channel
+    Unset,
+    /// Setting it up failed; the error has already been logged
+    Unavailable,
+    Forwarding(Arc<X11Forwarding>),
+}
+
+/// Where the local X server can be reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum DisplaySocket {
+    Unix(PathBuf),
+    Tcp(String, u16),
+}
+
+/// A parsed `DISPLAY` value, such as `:0`, `localhost:10.0`
+/// or the socket path style used by XQuartz on macOS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Display {
+    pub socket: DisplaySocket,
+    pub number: u32,
+    pub screen: u32,
+}
+
+impl Display {
+    pub fn parse(display: &str) -> anyhow::Result<Self> {
+        let (host, number_screen) = display
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("DISPLAY {display:?} has no display number"))?;
+        let (number, screen) = match number_screen.split_once('.') {
+            Some((number, screen)) => (number, screen),
+            None => (number_screen, "0"),
+        };
+        let number: u32 = number
+            .parse()
+            .with_context(|| format!("invalid display number in DISPLAY {display:?}"))?;
+        let screen: u32 = screen
+            .parse()
+            .with_context(|| format!("invalid screen number in DISPLAY {display:?}"))?;
+
+        let socket = if host.starts_with('/') {
+            // XQuartz uses the path to its socket as the hostname, and
+            // the socket may or may not include the display number
+            if is_socket(Path::new(display)) {
+                DisplaySocket::Unix(PathBuf::from(display))
+            } else {
+                DisplaySocket::Unix(PathBuf::from(host))
+            }
+        } else if host.is_empty() || host == "unix" {
+            DisplaySocket::Unix(PathBuf::from(format!("/tmp/.X11-unix/X{number}")))
+        } else {
+            let port = u16::try_from(number)
+                .ok()
+                .and_then(|n| n.checked_add(X11_BASE_PORT))
+                .ok_or_else(|| anyhow!("display number in DISPLAY {display:?} is too large"))?;
+            let host = host
+                .strip_prefix('[')
+                .and_then(|h| h.strip_suffix(']'))
+                .unwrap_or(host);
+            DisplaySocket::Tcp(host.to_string(), port)
+        };
+
+        Ok(Self {
+            socket,
+            number,
+            screen,
+        })
+    }
+
+    fn connect(&self) -> anyhow::Result<FileDescriptor> {
+        match &self.socket {
+            DisplaySocket::Tcp(host, port) => {
+                let stream = TcpStream::connect((host.as_str(), *port))
+                    .with_context(|| format!("connecting to X11 display at {host}:{port}"))?;
+                stream_to_fd(stream)
+            }
+            #[cfg(unix)]
+            DisplaySocket::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)
+                    .with_context(|| format!("connecting to X11 display at {}", path.display()))?;
+                Ok(FileDescriptor::new(stream))
+            }
+            #[cfg(windows)]
+            DisplaySocket::Unix(path) => anyhow::bail!(
+                "cannot connect to X11 display at {}: unix domain socket \
+                 displays are not supported on Windows",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_socket(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path)
+        .map(|meta| meta.file_type().is_socket())
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn is_socket(_path: &Path) -> bool {
+    false
+}
+
+/// An entry from an Xauthority file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct XAuthEntry {
+    pub family: u16,
+    pub address: Vec<u8>,
+    pub number: String,
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl XAuthEntry {
+    /// Parses the contents of an Xauthority file
+    pub fn parse_file(data: &[u8]) -> anyhow::Result<Vec<Self>> {
+        fn u16_at(data: &[u8], pos: &mut usize) -> anyhow::Result<u16> {
+            let bytes = data
+                .get(*pos..*pos + 2)
+                .ok_or_else(|| anyhow!("truncated Xauthority entry"))?;
+            *pos += 2;
+            Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+        }
+        fn bytes_at(data: &[u8], pos: &mut usize) -> anyhow::Result<Vec<u8>> {
+            let len = u16_at(data, pos)? as usize;
+            let bytes = data
+                .get(*pos..*pos + len)
+                .ok_or_else(|| anyhow!("truncated Xauthority entry"))?;
+            *pos += len;
+            Ok(bytes.to_vec())
+        }
+
+        let mut entries = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            let family = u16_at(data, &mut pos)?;
+            let address = bytes_at(data, &mut pos)?;
+            let number = String::from_utf8_lossy(&bytes_at(data, &mut pos)?).into_owned();
+            let name = String::from_utf8_lossy(&bytes_at(data, &mut pos)?).into_owned();
+            let data = bytes_at(data, &mut pos)?;
+            entries.push(Self {
+                family,
+                address,
+                number,
+                name,
+                data,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Returns the entry that the X server for `display`
+    /// will expect clients to present, if any
+    pub fn find<'a>(entries: &'a [Self], display: &Display, local_host: &str) -> Option<&'a Self> {
+        let number = display.number.to_string();
+        entries.iter().find(|entry| {
+            entry.number == number
+                && match entry.family {
+                    FAMILY_WILD => true,
+                    FAMILY_LOCAL => entry.address == local_host.as_bytes(),
+                    FAMILY_INTERNET | FAMILY_INTERNET6 => {
+                        matches!(display.socket, DisplaySocket::Tcp(..))
+                    }
+                    _ => false,
+                }
+        })
+    }
+}
+
+/// The state shared by the X11 channels of a session
+pub(crate) struct X11Forwarding {
+    display: Display,
+    protocol: String,
+    /// The cookie that we give to the remote host
+    fake_cookie: Vec<u8>,
+    /// The cookie that the local X server expects, which is
+    /// substituted for the fake one as connections are made.
+    /// None if the local X server doesn't use authentication.
+    real_cookie: Option<Vec<u8>>,
+}
+
+impl X11Forwarding {
+    fn from_config(config: &ConfigMap) -> anyhow::Result<Self> {
+        let display_var = match config.get("wezterm_ssh_x11_display") {
+            Some(display) => display.clone(),
+            None => std::env::var("DISPLAY")
+                .map_err(|_| anyhow!("DISPLAY is not set in the environment"))?,
+        };
+        let display = Display::parse(&display_var)?;
+
+        let trusted = matches!(
+            config.get("forwardx11trusted").map(|s| s.as_str()),
+            Some("yes")
+        );
+        let entry = if trusted {
+            let path = xauthority_path(config)?;
+            match std::fs::read(&path) {
+                Ok(data) => {
+                    let entries = XAuthEntry::parse_file(&data)
+                        .with_context(|| format!("parsing {}", path.display()))?;
+                    XAuthEntry::find(&entries, &display, &local_host_name()).cloned()
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => {
+                    return Err(err).with_context(|| format!("reading {}", path.display()));
+                }
+            }
+        } else {
+            Some(generate_untrusted_cookie(config, &display_var, &display)?)
+        };
+
+        let (protocol, real_cookie) = match entry {
+            Some(entry) => (entry.name, Some(entry.data)),
+            None => {
+                log::warn!(
+                    "No xauth data for DISPLAY {display_var}; \
+                     using fake authentication data for X11 forwarding"
+                );
+                (MIT_MAGIC_COOKIE.to_string(), None)
+            }
+        };
+
+        let mut fake_cookie = vec![0u8; real_cookie.as_ref().map(|c| c.len()).unwrap_or(16)];
+        getrandom::fill(&mut fake_cookie)
+            .map_err(|err| anyhow!("generating X11 authentication cookie: {err}"))?;
+
+        Ok(Self {
+            display,
+            protocol,
+            fake_cookie,
+            real_cookie,
+        })
+    }
+
+    /// Rewrites the connection setup that an X11 client sends as its
+    /// first message, replacing our fake cookie with the real one.
+    /// Returns None if `buf` doesn't yet hold the complete setup,
+    /// otherwise the length of the setup and its replacement.
+    fn rewrite_setup(&self, buf: &[u8]) -> anyhow::Result<Option<(usize, Vec<u8>)>> {
+        if buf.len() < 12 {
+            return Ok(None);
+        }
+        let big_endian = match buf[0] {
+            b'B' => true,
+            b'l' => false,
+            order => anyhow::bail!("X11 connection has invalid byte order {order:#x}"),
+        };
+        let get_u16 = |pos: usize| {
+            let bytes = [buf[pos], buf[pos + 1]];
+            if big_endian {
+                u16::from_be_bytes(bytes)
+            } else {
+                u16::from_le_bytes(bytes)
+            }
+        };
+        let name_len = get_u16(6) as usize;
+        let data_len = get_u16(8) as usize;
+        let setup_len = 12 + pad4(name_len) + pad4(data_len);
+        if buf.len() < setup_len {
+            return Ok(None);
+        }
+
+        let name = &buf[12..12 + name_len];
+        let data_start = 12 + pad4(name_len);
+        let data = &buf[data_start..data_start + data_len];
+        if name != self.protocol.as_bytes() || data != self.fake_cookie.as_slice() {
+            anyhow::bail!("X11 connection rejected because of wrong authentication");
+        }
+
+        let real = match &self.real_cookie {
+            Some(real) => real,
+            None => return Ok(Some((setup_len, buf[..setup_len].to_vec()))),
+        };
+
+        let mut setup = buf[..12].to_vec();
+        let real_len = real.len() as u16;
+        let real_len = if big_endian {
+            real_len.to_be_bytes()
+        } else {
+            real_len.to_le_bytes()
+        };
+        setup[8..10].copy_from_slice(&real_len);
+        setup.extend_from_slice(&buf[12..data_start]);
+        setup.extend_from_slice(real);
+        setup.resize(setup.len() + pad4(real.len()) - real.len(), 0);
+        Ok(Some((setup_len, setup)))
+    }
+
+    /// Relays an X11 connection, whose channel is connected to `remote`,
+    /// to the local display once its authentication has been checked
+    fn relay(&self, mut remote: FileDescriptor) -> anyhow::Result<()> {
+        let mut buf = vec![];
+        let mut chunk = [0u8; 4096];
+        let (setup_len, setup) = loop {
+            if let Some(rewritten) = self.rewrite_setup(&buf)? {
+                break rewritten;
+            }
+            let n = remote.read(&mut chunk)?;
+            if n == 0 {
+                anyhow::bail!("X11 connection closed during setup");
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        };
+
+        let mut local = self.display.connect()?;
+        local.write_all(&setup)?;
+        local.write_all(&buf[setup_len..])?;
+
+        let mut local_read = local.try_clone()?;
+        let mut remote_write = remote.try_clone()?;
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut local_read, &mut remote_write);
+        });
+        let _ = std::io::copy(&mut remote, &mut local);
+        Ok(())
+    }
+}
+
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Returns the Xauthority file that holds the real cookie.
+/// `wezterm_ssh_xauthority` takes precedence over `$XAUTHORITY`,
+/// just as `wezterm_ssh_x11_display` does over `$DISPLAY`, so that
+/// the local display can be chosen without changing the environment.
+fn xauthority_path(config: &ConfigMap) -> anyhow::Result<PathBuf> {
+    if let Some(path) = config.get("wezterm_ssh_xauthority") {
+        return Ok(PathBuf::from(path));
+    }
+    if let Some(path) = std::env::var_os("XAUTHORITY") {
+        return Ok(PathBuf::from(path));
+    }
+    let home = dirs_next::home_dir().ok_or_else(|| anyhow!("unable to resolve home directory"))?;
+    Ok(home.join(".Xauthority"))
+}
+
+fn local_host_name() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+/// Parses a time value such as `1200`, `20m` or `1h30m` into seconds
+fn parse_timeout(value: &str) -> Option<u64> {
+    let mut total = 0;
+    let mut digits = String::new();
+    for c in value.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let multiplier = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        total += digits.parse::<u64>().ok()? * multiplier;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        total += digits.parse::<u64>().ok()?;
+    }
+    Some(total)
+}
+
+/// Asks xauth to generate an untrusted cookie for the display,
+/// which restricts what the remote X clients are permitted to do
+fn generate_untrusted_cookie(
+    config: &ConfigMap,
+    display_var: &str,
+    display: &Display,
+) -> anyhow::Result<XAuthEntry> {
+    let xauth = config
+        .get("xauthlocation")
+        .map(|s| s.as_str())
+        .unwrap_or("xauth");
+    let timeout = config
+        .get("forwardx11timeout")
+        .and_then(|t| parse_timeout(t))
+        .unwrap_or(1200);
+
+    let mut random = [0u8; 8];
+    getrandom::fill(&mut random).ok();
+    let path = std::env::temp_dir().join(format!("wezterm-xauth-{}", hex::encode(random)));
+
+    let result = (|| -> anyhow::Result<XAuthEntry> {
+        let output = std::process::Command::new(xauth)
+            .arg("-f")
+            .arg(&path)
+            .args(["generate", display_var, MIT_MAGIC_COOKIE, "untrusted"])
+            .args(["timeout", &timeout.to_string()])
+            .stdin(std::process::Stdio::null())
+            .output()
+            .with_context(|| format!("running {xauth}"))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{xauth} generate failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let entries = XAuthEntry::parse_file(&std::fs::read(&path)?)?;
+        XAuthEntry::find(&entries, display, &local_host_name())
+            .or_else(|| entries.first())
+            .cloned()
+            .ok_or_else(|| anyhow!("{xauth} generate produced no cookie"))
+    })();
+    let _ = std::fs::remove_file(&path);
+
+    result.context(
+        "untrusted X11 forwarding setup failed; \
+         set ForwardX11Trusted to yes to use your existing X11 credentials",
+    )
+}
+
+impl SessionInner {
+    /// Requests X11 forwarding for `channel` if `ForwardX11` is enabled
+    pub(crate) fn request_x11_forwarding(&mut self, channel: &mut ChannelWrap) {
+        if self.config.get("forwardx11").map(|s| s.as_str()) != Some("yes") {
+            return;
+        }
+
+        if let X11State::Unset = self.x11 {
+            self.x11 = match X11Forwarding::from_config(&self.config) {
+                Ok(forwarding) => X11State::Forwarding(Arc::new(forwarding)),
+                Err(err) => {
+                    log::error!("X11 forwarding is not available: {:#}", err);
+                    X11State::Unavailable
+                }
+            };
+        }
+
+        if let X11State::Forwarding(forwarding) = &self.x11 {
+            if let Err(err) = channel.request_x11(
+                &forwarding.protocol,
+                &hex::encode(&forwarding.fake_cookie),
+                forwarding.display.screen,
+            ) {
+                log::error!("Failed to request X11 forwarding: {:#}", err);
+            }
+        }
+    }
+
+    /// Relays X11 channels opened by the remote host to the local display
+    pub(crate) fn accept_x11_channels(&mut self) {
+        let forwarding = match &self.x11 {
+            X11State::Forwarding(forwarding) => Arc::clone(forwarding),
+            _ => return,
+        };
+
+        loop {
+            let channel = match self
+                .channels
+                .values_mut()
+                .find_map(|info| info.channel.accept_x11())
+            {
+                Some(channel) => channel,
+                None => return,
+            };
+
+            let result = (|| -> anyhow::Result<()> {
+                let (mut ours, theirs) = socketpair()?;
+                ours.set_non_blocking(true)?;
+                self.add_stream_channel(channel, ours)?;
+
+                let forwarding = Arc::clone(&forwarding);
+                std::thread::spawn(move || {
+                    if let Err(err) = forwarding.relay(theirs) {
+                        log::error!("X11 forwarding: {:#}", err);
+                    }
+                });
+                Ok(())
+            })();
+            if let Err(err) = result {
+                log::error!("error accepting X11 channel: {:#}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_display() {
+        assert_eq!(
+            Display::parse(":0").unwrap(),
+            Display {
+                socket: DisplaySocket::Unix(PathBuf::from("/tmp/.X11-unix/X0")),
+                number: 0,
+                screen: 0,
+            }
+        );
+        assert_eq!(
+            Display::parse("unix:1.2").unwrap(),
+            Display {
+                socket: DisplaySocket::Unix(PathBuf::from("/tmp/.X11-unix/X1")),
+                number: 1,
+                screen: 2,
+            }
+        );
+        assert_eq!(
+            Display::parse("localhost:10.0").unwrap(),
+            Display {
+                socket: DisplaySocket::Tcp("localhost".to_string(), 6010),
+                number: 10,
+                screen: 0,
+            }
+        );
+        assert_eq!(
+            Display::parse("[::1]:3").unwrap().socket,
+            DisplaySocket::Tcp("::1".to_string(), 6003)
+        );
+        assert_eq!(
+            Display::parse("/private/tmp/com.apple.launchd.abc/org.xquartz:0")
+                .unwrap()
+                .socket,
+            DisplaySocket::Unix(PathBuf::from(
+                "/private/tmp/com.apple.launchd.abc/org.xquartz"
+            ))
+        );
+        assert!(Display::parse("localhost").is_err());
+        assert!(Display::parse(":x").is_err());
+        assert!(Display::parse("host:70000").is_err());
+    }
+
+    fn encode_entry(family: u16, address: &[u8], number: &str, data: &[u8]) -> Vec<u8> {
+        let mut out = family.to_be_bytes().to_vec();
+        for field in [
+            address,
+            number.as_bytes(),
+            MIT_MAGIC_COOKIE.as_bytes(),
+            data,
+        ] {
+            out.extend_from_slice(&(field.len() as u16).to_be_bytes());
+            out.extend_from_slice(field);
+        }
+        out
+    }
+
+    #[test]
+    fn find_xauth_entry() {
+        let data = [
+            encode_entry(FAMILY_LOCAL, b"otherhost", "0", &[1; 16]),
+            encode_entry(FAMILY_LOCAL, b"myhost", "1", &[2; 16]),
+            encode_entry(FAMILY_LOCAL, b"myhost", "0", &[3; 16]),
+            encode_entry(FAMILY_WILD, b"", "2", &[4; 16]),
+            encode_entry(FAMILY_INTERNET, &[127, 0, 0, 1], "10", &[5; 16]),
+        ]
+        .concat();
+        let entries = XAuthEntry::parse_file(&data).unwrap();
+        assert_eq!(entries.len(), 5);
+
+        let find = |display: &str| {
+            XAuthEntry::find(&entries, &Display::parse(display).unwrap(), "myhost")
+                .map(|entry| entry.data[0])
+        };
+        assert_eq!(find(":0"), Some(3));
+        assert_eq!(find(":1"), Some(2));
+        assert_eq!(find(":2"), Some(4));
+        assert_eq!(find("localhost:10"), Some(5));
+        assert_eq!(find(":10"), None);
+        assert_eq!(find(":3"), None);
+
+        assert!(XAuthEntry::parse_file(&data[..data.len() - 1]).is_err());
+    }
+
+    fn setup(order: u8, name: &str, data: &[u8]) -> Vec<u8> {
+        let encode = |v: u16| {
+            if order == b'B' {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut out = vec![order, 0];
+        out.extend_from_slice(&encode(11));
+        out.extend_from_slice(&encode(0));
+        out.extend_from_slice(&encode(name.len() as u16));
+        out.extend_from_slice(&encode(data.len() as u16));
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(name.as_bytes());
+        out.resize(12 + pad4(name.len()), 0);
+        out.extend_from_slice(data);
+        out.resize(12 + pad4(name.len()) + pad4(data.len()), 0);
+        out
+    }
+
+    #[test]
+    fn rewrite_setup() {
+        let forwarding = X11Forwarding {
+            display: Display::parse(":0").unwrap(),
+            protocol: MIT_MAGIC_COOKIE.to_string(),
+            fake_cookie: vec![1; 16],
+            real_cookie: Some(vec![2; 16]),
+        };
+
+        for order in [b'B', b'l'] {
+            let fake = setup(order, MIT_MAGIC_COOKIE, &[1; 16]);
+            assert_eq!(forwarding.rewrite_setup(&fake[..20]).unwrap(), None);
+
+            let mut with_request = fake.clone();
+            with_request.extend_from_slice(b"more");
+            assert_eq!(
+                forwarding.rewrite_setup(&with_request).unwrap(),
+                Some((fake.len(), setup(order, MIT_MAGIC_COOKIE, &[2; 16])))
+            );
+
+            let wrong = setup(order, MIT_MAGIC_COOKIE, &[2; 16]);
+            assert!(forwarding.rewrite_setup(&wrong).is_err());
+            let no_auth = setup(order, "", &[]);
+            assert!(forwarding.rewrite_setup(&no_auth).is_err());
+        }
+        assert!(forwarding.rewrite_setup(&[b'x'; 12]).is_err());
+
+        let unauthenticated = X11Forwarding {
+            real_cookie: None,
+            ..forwarding
+        };
+        let fake = setup(b'l', MIT_MAGIC_COOKIE, &[1; 16]);
+        assert_eq!(
+            unauthenticated.rewrite_setup(&fake).unwrap(),
+            Some((fake.len(), fake.clone()))
+        );
+    }
+
+    #[test]
+    fn parse_timeouts() {
+        assert_eq!(parse_timeout("1200"), Some(1200));
+        assert_eq!(parse_timeout("20m"), Some(1200));
+        assert_eq!(parse_timeout("1h30m"), Some(5400));
+        assert_eq!(parse_timeout("soon"), None);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::sshd::*;
+use assert_fs::prelude::*;
+use assert_fs::TempDir;
+use portable_pty::{MasterPty, PtySize};
+use rstest::*;
+use std::io::Read;
+use wezterm_ssh::Config;
+
+/// Stand up an sshd that only accepts certificates signed by a user CA,
+/// then sign the test identity with that CA and connect.
+/// If `certificate_file` is true, the certificate is placed in a separate
+/// directory, along with a copy of the identity, and passed via
+/// CertificateFile, otherwise it is placed next to the identity where
+/// it is found automatically.
+/// The returned TempDir holds the CA and must outlive the session.
+async fn session_with_certificate(
+    backend: &str,
+    certificate_file: bool,
+) -> (SessionWithSshd, TempDir) {
+    let ca = TempDir::new().unwrap();
+    let ca_key = ca.child("user_ca");
+    assert!(
+        SshKeygen::generate_rsa(ca_key.path(), "").unwrap(),
+        "Failed to ssh-keygen user_ca"
+    );
+
+    let mut sshd_config = SshdConfig::default();
+    sshd_config.set_trusted_user_ca_keys(ca_key.path().with_extension("pub"));
+    // Don't accept the plain public key, so that authentication
+    // can only succeed by presenting the certificate
+    sshd_config.set_authorized_keys_file("none");
+    let sshd = Sshd::spawn(sshd_config).unwrap();
+
+    let identity = sshd.tmp.child("id_rsa");
+    let mut config = Config::new();
+    config.set_option("wezterm_ssh_backend", backend);
+
+    if certificate_file {
+        // The libssh backend only picks up certificates that are
+        // named after an identity next to them
+        std::fs::copy(identity.path(), ca.child("signed").path()).unwrap();
+        let pubkey = ca.child("signed.pub");
+        std::fs::copy(identity.path().with_extension("pub"), pubkey.path()).unwrap();
+        assert!(
+            SshKeygen::sign_user_key(ca_key.path(), pubkey.path(), whoami::username()).unwrap(),
+            "Failed to sign signed.pub"
+        );
+        config.set_option(
+            "certificatefile",
+            ca.child("signed-cert.pub").path().display().to_string(),
+        );
+    } else {
+        assert!(
+            SshKeygen::sign_user_key(
+                ca_key.path(),
+                identity.path().with_extension("pub"),
+                whoami::username()
+            )
+            .unwrap(),
+            "Failed to sign id_rsa.pub"
+        );
+    }
+
+    (session(config, sshd).await, ca)
+}
+
+async fn assert_echoes(session: &SessionWithSshd) {
+    let (pty, _child_process) = session
+        .request_pty("dumb", PtySize::default(), Some("echo hello"), None)
+        .await
+        .unwrap();
+    let mut reader = pty.try_clone_reader().unwrap();
+    let mut output: String = String::new();
+    reader.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "hello\r\n");
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+#[cfg_attr(not(feature = "libssh-rs"), ignore)]
+fn libssh_should_present_certificate_next_to_identity() {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let (session, _ca) = session_with_certificate("libssh", false).await;
+        assert_echoes(&session).await;
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+#[cfg_attr(not(feature = "libssh-rs"), ignore)]
+fn libssh_should_present_certificate_file() {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let (session, _ca) = session_with_certificate("libssh", true).await;
+        assert_echoes(&session).await;
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+#[cfg_attr(not(feature = "ssh2"), ignore)]
+fn ssh2_should_present_certificate_next_to_identity() {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let (session, _ca) = session_with_certificate("ssh2", false).await;
+        assert_echoes(&session).await;
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+#[cfg_attr(not(feature = "ssh2"), ignore)]
+fn ssh2_should_present_certificate_file() {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let (session, _ca) = session_with_certificate("ssh2", true).await;
+        assert_echoes(&session).await;
+    })
+}
@@ -0,0 +1,88 @@
+use crate::sshd::*;
+use assert_fs::TempDir;
+use portable_pty::{MasterPty, PtySize};
+use rstest::*;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use wezterm_ssh::ControlMaster;
+
+fn write_message(stream: &mut UnixStream, fields: &[u32]) {
+    let mut data = ((fields.len() * 4) as u32).to_be_bytes().to_vec();
+    for field in fields {
+        data.extend_from_slice(&field.to_be_bytes());
+    }
+    stream.write_all(&data).unwrap();
+}
+
+fn read_message(stream: &mut UnixStream) -> Vec<u32> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).unwrap();
+    let mut payload = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut payload).unwrap();
+    payload
+        .chunks_exact(4)
+        .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn should_remain_usable_after_dropping_a_clone(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+
+        let weak = session.downgrade();
+        drop(session.clone());
+        assert!(!session.is_closed());
+        assert!(weak.upgrade().is_some());
+
+        let (pty, _child_process) = session
+            .request_pty("dumb", PtySize::default(), Some("echo hello"), None)
+            .await
+            .unwrap();
+        let mut reader = pty.try_clone_reader().unwrap();
+        let mut output: String = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "hello\r\n");
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn control_socket_should_answer_alive_check(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("control");
+        let control_master = ControlMaster::start(&session, &path).unwrap();
+        assert!(ControlMaster::start(&session, &path).is_err());
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        assert_eq!(read_message(&mut stream), vec![0x1, 4]);
+        write_message(&mut stream, &[0x1, 4]);
+
+        // MUX_C_ALIVE_CHECK -> MUX_S_ALIVE with the pid of the master
+        write_message(&mut stream, &[0x1000_0004, 42]);
+        assert_eq!(
+            read_message(&mut stream),
+            vec![0x8000_0005, 42, std::process::id()]
+        );
+
+        // MUX_C_TERMINATE is refused, as the session is shared
+        write_message(&mut stream, &[0x1000_0005, 43]);
+        let denied = read_message(&mut stream);
+        assert_eq!(&denied[..2], &[0x8000_0002, 43]);
+
+        drop(stream);
+        drop(control_master);
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        assert!(!path.exists());
+    })
+}
@@ -0,0 +1,93 @@
+use crate::sshd::*;
+use portable_pty::ExitStatus;
+use rstest::*;
+use std::io::{Read, Write};
+use wezterm_ssh::{ExecResult, Session};
+
+/// Runs `command`, feeding it `input` on stdin, and returns its
+/// stdout, stderr and exit status
+async fn run(session: &Session, command: &str, input: Vec<u8>) -> (Vec<u8>, Vec<u8>, ExitStatus) {
+    let ExecResult {
+        mut stdin,
+        mut stdout,
+        mut stderr,
+        mut child,
+    } = session.exec(command, None).await.unwrap();
+
+    // Dropping stdin once it has been written sends EOF
+    let writer = std::thread::spawn(move || stdin.write_all(&input).unwrap());
+    let reader = std::thread::spawn(move || {
+        let mut output = vec![];
+        stdout.read_to_end(&mut output).unwrap();
+        output
+    });
+    let mut errors = vec![];
+    stderr.read_to_end(&mut errors).unwrap();
+
+    writer.join().unwrap();
+    let output = reader.join().unwrap();
+    let status = child.async_wait().await.unwrap();
+    (output, errors, status)
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn exec_should_pass_stdin_to_command(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+        let (output, errors, status) = run(&session, "cat", b"hello\nworld\n".to_vec()).await;
+        assert_eq!(output, b"hello\nworld\n");
+        assert_eq!(errors, b"");
+        assert!(status.success());
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn exec_should_pass_stdin_larger_than_channel_window(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+        // Larger than the channel window of either backend, so that
+        // writing stdin has to wait for the window to be adjusted
+        let input: Vec<u8> = (0..8 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let (output, _errors, status) = run(&session, "cat", input.clone()).await;
+        assert_eq!(output.len(), input.len());
+        assert!(output == input, "output differs from input");
+        assert!(status.success());
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn exec_should_capture_stderr(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+        let (output, errors, status) = run(&session, "echo out; echo err >&2", Vec::new()).await;
+        assert_eq!(output, b"out\n");
+        assert_eq!(errors, b"err\n");
+        assert!(status.success());
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn exec_should_report_exit_code(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+        let (_output, _errors, status) = run(&session, "exit 3", Vec::new()).await;
+        assert!(!status.success());
+        assert_eq!(status.exit_code(), 3);
+    })
+}
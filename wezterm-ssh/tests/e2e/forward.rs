@@ -0,0 +1,161 @@
+use crate::sshd::*;
+use rstest::*;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use wezterm_ssh::{BindAddress, Config, ForwardSpec};
+
+/// Accepts a single connection on `listener` and echoes back
+/// whatever is sent to it
+fn spawn_echo_server(listener: TcpListener) {
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 1024];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => stream.write_all(&buf[..n]).unwrap(),
+            }
+        }
+    });
+}
+
+fn assert_echoes(stream: &mut TcpStream) {
+    stream.write_all(b"hello").unwrap();
+    let mut buf = [0u8; 5];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+fn loopback(port: u16) -> BindAddress {
+    BindAddress {
+        address: Some("127.0.0.1".to_string()),
+        port,
+    }
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn local_forward_should_connect_via_remote_host(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target_port = listener.local_addr().unwrap().port();
+        spawn_echo_server(listener);
+
+        let info = session
+            .forward(ForwardSpec::Local {
+                bind: loopback(0),
+                host: "127.0.0.1".to_string(),
+                port: target_port,
+            })
+            .await
+            .unwrap();
+        assert_ne!(info.bound_port, 0);
+
+        let mut stream = TcpStream::connect(("127.0.0.1", info.bound_port)).unwrap();
+        assert_echoes(&mut stream);
+
+        assert_eq!(session.list_forwards().await.unwrap(), vec![info.clone()]);
+        session.cancel_forward(info.id).await.unwrap();
+        assert_eq!(session.list_forwards().await.unwrap(), vec![]);
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn remote_forward_should_connect_via_local_host(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target_port = listener.local_addr().unwrap().port();
+        spawn_echo_server(listener);
+
+        let info = session
+            .forward(ForwardSpec::Remote {
+                bind: loopback(0),
+                host: "127.0.0.1".to_string(),
+                port: target_port,
+            })
+            .await
+            .unwrap();
+        assert_ne!(info.bound_port, 0);
+
+        // The "remote" host is the local machine in the test environment
+        let mut stream = TcpStream::connect(("127.0.0.1", info.bound_port)).unwrap();
+        assert_echoes(&mut stream);
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn dynamic_forward_should_act_as_socks5_proxy(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target_port = listener.local_addr().unwrap().port();
+        spawn_echo_server(listener);
+
+        let info = session
+            .forward(ForwardSpec::Dynamic { bind: loopback(0) })
+            .await
+            .unwrap();
+
+        let mut stream = TcpStream::connect(("127.0.0.1", info.bound_port)).unwrap();
+
+        stream.write_all(&[5, 1, 0]).unwrap();
+        let mut greeting = [0u8; 2];
+        stream.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 0]);
+
+        let mut request = vec![5, 1, 0, 1, 127, 0, 0, 1];
+        request.extend_from_slice(&target_port.to_be_bytes());
+        stream.write_all(&request).unwrap();
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[..2], [5, 0]);
+
+        assert_echoes(&mut stream);
+    })
+}
+
+#[fixture]
+async fn session_with_configured_forward(
+    #[future]
+    #[with({
+        let mut config = Config::new();
+        config.set_option("dynamicforward", "127.0.0.1:0");
+        config
+    })]
+    session: SessionWithSshd,
+) -> SessionWithSshd {
+    session.await
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn configured_forwards_should_be_established(
+    #[future] session_with_configured_forward: SessionWithSshd,
+) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session_with_configured_forward.await;
+
+        let forwards = session.list_forwards().await.unwrap();
+        assert_eq!(forwards.len(), 1);
+        assert_eq!(forwards[0].spec, ForwardSpec::Dynamic { bind: loopback(0) });
+    })
+}
@@ -0,0 +1,61 @@
+use crate::sshd::*;
+use portable_pty::{MasterPty, PtySize};
+use rstest::*;
+use std::io::Read;
+use wezterm_ssh::{Config, Session, SessionEvent};
+
+#[fixture]
+async fn session_via_jump_host(
+    #[future]
+    #[with({ let mut config = Config::new(); config.set_option("proxyjump", "localhost"); config })]
+    session: SessionWithSshd,
+) -> SessionWithSshd {
+    session.await
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn should_connect_via_jump_host(#[future] session_via_jump_host: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session_via_jump_host.await;
+
+        let (pty, _child_process) = session
+            .request_pty("dumb", PtySize::default(), Some("echo hello"), None)
+            .await
+            .unwrap();
+        let mut reader = pty.try_clone_reader().unwrap();
+        let mut output: String = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "hello\r\n");
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn should_fail_when_jump_host_is_unreachable() {
+    smol::block_on(async {
+        let mut config = Config::new();
+        config.set_option("proxyjump", "localhost:1,localhost");
+        #[cfg(not(feature = "libssh-rs"))]
+        config.set_option("wezterm_ssh_backend", "ssh2");
+        let config = config.for_host("localhost");
+
+        // Use an empty config for the jump hosts so that the
+        // ssh config of the user running the tests is not consulted
+        let (_session, events) = Session::connect_with_jump_config(config, Config::new()).unwrap();
+
+        loop {
+            match events.recv().await.unwrap() {
+                SessionEvent::Error(err) => {
+                    assert!(err.contains("jump host localhost:1"), "{}", err);
+                    break;
+                }
+                SessionEvent::Authenticated => panic!("should not have connected"),
+                _ => {}
+            }
+        }
+    })
+}
@@ -1,2 +1,10 @@
 mod agent_forward;
+mod cert;
+#[cfg(unix)]
+mod controlmaster;
+mod exec;
+mod forward;
+mod jump;
+mod reconnect;
 mod sftp;
+mod x11;
@@ -0,0 +1,101 @@
+use crate::sshd::*;
+use portable_pty::PtySize;
+use rstest::*;
+use std::io::Read;
+use std::time::{Duration, Instant};
+use wezterm_ssh::{Config, Session};
+
+async fn echo(session: &Session) -> String {
+    let (pty, _child_process) = session
+        .request_pty("dumb", PtySize::default(), Some("echo hello"), None)
+        .await
+        .unwrap();
+    let mut reader = pty.try_clone_reader().unwrap();
+    let mut output = String::new();
+    reader.read_to_string(&mut output).unwrap();
+    output
+}
+
+fn wait_until_closed(session: &Session) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !session.is_closed() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    session.is_closed()
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn should_connect_again_after_sshd_was_killed(sshd: Sshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let mut sshd = sshd;
+        let session = connect(Config::new(), &sshd).await;
+
+        let (pty, _child_process) = session
+            .request_pty(
+                "dumb",
+                PtySize::default(),
+                Some("echo ready; sleep 600"),
+                None,
+            )
+            .await
+            .unwrap();
+        let mut reader = pty.try_clone_reader().unwrap();
+        let mut ready = [0u8; 5];
+        reader.read_exact(&mut ready).unwrap();
+        assert_eq!(&ready, b"ready");
+
+        sshd.kill();
+
+        // The remote shell is seen to go away, which is what prompts
+        // an ssh domain to reconnect its panes
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut rest = vec![];
+            tx.send(reader.read_to_end(&mut rest).map(|_| ())).ok();
+        });
+        rx.recv_timeout(Duration::from_secs(30))
+            .expect("pty to reach EOF after sshd was killed")
+            .ok();
+
+        sshd.restart().unwrap();
+        let session = connect(Config::new(), &sshd).await;
+        assert_eq!(echo(&session).await, "hello\r\n");
+    })
+}
+
+#[rstest]
+#[cfg(feature = "ssh2")]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn should_close_session_when_keepalives_go_unanswered(sshd: Sshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let mut config = Config::new();
+        config.set_option("wezterm_ssh_backend", "ssh2");
+        config.set_option("serveraliveinterval", "1");
+        config.set_option("serveralivecountmax", "2");
+        let session = connect(config, &sshd).await;
+
+        // Keepalives are only counted while there are channels
+        let (_pty, _child_process) = session
+            .request_pty("dumb", PtySize::default(), Some("sleep 600"), None)
+            .await
+            .unwrap();
+
+        // A server that answers keeps the session alive
+        std::thread::sleep(Duration::from_secs(5));
+        assert!(!session.is_closed());
+
+        // A server that doesn't answer, even though the connection
+        // itself stays up, does not
+        sshd.signal_all("STOP");
+        let closed = wait_until_closed(&session);
+        sshd.signal_all("CONT");
+        assert!(closed, "session to close after unanswered keepalives");
+    })
+}
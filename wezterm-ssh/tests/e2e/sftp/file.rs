@@ -215,3 +215,64 @@ fn should_support_async_close(#[future] session: SessionWithSshd) {
             .expect("Failed to close file second time");
     })
 }
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn should_get_and_put_files_in_chunks(#[future] session: SessionWithSshd) {
+    if !sshd_available() {
+        return;
+    }
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+
+        // Transfer in chunks the way that `wezterm cli sftp` does,
+        // using a file that spans several chunks and ends part way
+        // through the last one
+        const CHUNK_SIZE: usize = 128 * 1024;
+        let contents: Vec<u8> = (0..CHUNK_SIZE * 2 + 1234)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        let temp = TempDir::new().unwrap();
+        let source = temp.child("source");
+        source.write_binary(&contents).unwrap();
+
+        // get
+        let mut remote_file = session
+            .sftp()
+            .open(source.path().to_path_buf())
+            .await
+            .expect("Failed to open remote file");
+        let mut fetched = vec![];
+        loop {
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            let n = remote_file
+                .read(&mut buf)
+                .await
+                .expect("Failed to read chunk");
+            if n == 0 {
+                break;
+            }
+            fetched.extend_from_slice(&buf[..n]);
+        }
+        remote_file.close().await.expect("Failed to close file");
+        assert_eq!(fetched, contents);
+
+        // put
+        let dest = temp.child("dest");
+        let mut remote_file = session
+            .sftp()
+            .create(dest.path().to_path_buf())
+            .await
+            .expect("Failed to create remote file");
+        for chunk in contents.chunks(CHUNK_SIZE) {
+            remote_file
+                .write_all(chunk)
+                .await
+                .expect("Failed to write chunk");
+        }
+        remote_file.flush().await.expect("Failed to flush file");
+        remote_file.close().await.expect("Failed to close file");
+        assert_eq!(std::fs::read(dest.path()).unwrap(), contents);
+    })
+}
@@ -0,0 +1,170 @@
+use crate::sshd::*;
+use assert_fs::prelude::*;
+use assert_fs::TempDir;
+use rstest::*;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use wezterm_ssh::{Config, Session};
+
+const REAL_COOKIE: [u8; 16] = [0x42; 16];
+const UNTRUSTED_COOKIE: [u8; 16] = [0x17; 16];
+
+fn xauth_available() -> bool {
+    Command::new("xauth")
+        .args(["-f", "/dev/null", "list"])
+        .output()
+        .is_ok()
+}
+
+/// Listens on the first available X11 display port, returning
+/// the listener and the display number
+fn bind_display() -> (TcpListener, u16) {
+    for number in 50..200 {
+        if let Ok(listener) = TcpListener::bind(("127.0.0.1", 6000 + number)) {
+            return (listener, number);
+        }
+    }
+    panic!("no X11 display port available");
+}
+
+/// Stands in for an X server: accepts a single connection, checks that
+/// its connection setup carries `cookie` and replies with a greeting
+fn spawn_x_server(listener: TcpListener, cookie: [u8; 16]) {
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        // 12 byte header, "MIT-MAGIC-COOKIE-1" padded to 20, and the cookie
+        let mut setup = [0u8; 48];
+        stream.read_exact(&mut setup).unwrap();
+        if setup[32..] == cookie {
+            stream.write_all(b"hello").unwrap();
+        } else {
+            stream.write_all(b"wrong").unwrap();
+        }
+    });
+}
+
+/// Writes an Xauthority file holding REAL_COOKIE for the display
+fn write_xauthority(tmp: &TempDir, number: u16) -> std::path::PathBuf {
+    let number = number.to_string();
+    let mut data = 0u16.to_be_bytes().to_vec();
+    for field in [
+        &[127, 0, 0, 1][..],
+        number.as_bytes(),
+        b"MIT-MAGIC-COOKIE-1",
+        &REAL_COOKIE,
+    ] {
+        data.extend_from_slice(&(field.len() as u16).to_be_bytes());
+        data.extend_from_slice(field);
+    }
+    let file = tmp.child("Xauthority");
+    file.write_binary(&data).unwrap();
+    file.path().to_path_buf()
+}
+
+/// Writes a stand-in for xauth that records its arguments in `args`
+/// and, rather than asking the X server for an untrusted cookie,
+/// stores UNTRUSTED_COOKIE in the file that it was asked to generate
+fn write_fake_xauth(tmp: &TempDir, args: &std::path::Path) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    // Invoked as: xauth -f FILE generate DISPLAY PROTOCOL untrusted timeout SECONDS
+    let script = format!(
+        "#!/bin/sh\nprintf '%s\\n' \"$*\" > {}\nexec xauth -f \"$2\" add \"$4\" \"$5\" {}\n",
+        args.display(),
+        hex::encode(UNTRUSTED_COOKIE)
+    );
+    let file = tmp.child("xauth");
+    file.write_str(&script).unwrap();
+    std::fs::set_permissions(file.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    file.path().to_path_buf()
+}
+
+/// Acts as an X client on the remote host, presenting the cookie that
+/// sshd recorded for the forwarded display, and returns the greeting
+/// that the stand-in X server replied with
+async fn greet_display(session: &Session) -> String {
+    let script = r#"
+        d=${DISPLAY#*:}
+        d=${d%.*}
+        cookie=$(xauth list "$DISPLAY" | awk '{print $3}' | head -n 1)
+        exec 3<>/dev/tcp/localhost/$((6000 + d))
+        {
+            printf 'l\0\x0b\0\0\0\x12\0\x10\0\0\0MIT-MAGIC-COOKIE-1\0\0'
+            printf "$(echo "$cookie" | sed 's/../\\x&/g')"
+        } >&3
+        head -c 5 <&3
+    "#;
+    let mut exec = session
+        .exec(&format!("bash -c {}", shell_words::quote(script)), None)
+        .await
+        .unwrap();
+    let mut output = String::new();
+    exec.stdout.read_to_string(&mut output).unwrap();
+    output
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+#[cfg_attr(not(feature = "libssh-rs"), ignore)]
+fn x11_connections_should_reach_local_display_with_real_cookie(sshd: Sshd) {
+    if !sshd_available() || !xauth_available() {
+        return;
+    }
+
+    let tmp = TempDir::new().unwrap();
+    let (listener, number) = bind_display();
+    spawn_x_server(listener, REAL_COOKIE);
+
+    // The display is passed through the config rather than the
+    // environment, which is shared with the tests running alongside
+    let mut config = Config::new();
+    config.set_option("forwardx11", "yes");
+    config.set_option("forwardx11trusted", "yes");
+    config.set_option("wezterm_ssh_x11_display", format!("127.0.0.1:{number}"));
+    config.set_option(
+        "wezterm_ssh_xauthority",
+        write_xauthority(&tmp, number).display().to_string(),
+    );
+
+    smol::block_on(async {
+        let session = connect(config, &sshd).await;
+        assert_eq!(greet_display(&session).await, "hello");
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+#[cfg_attr(not(feature = "libssh-rs"), ignore)]
+fn x11_connections_should_reach_local_display_with_untrusted_cookie(sshd: Sshd) {
+    if !sshd_available() || !xauth_available() {
+        return;
+    }
+
+    let tmp = TempDir::new().unwrap();
+    let (listener, number) = bind_display();
+    spawn_x_server(listener, UNTRUSTED_COOKIE);
+
+    let args = tmp.child("xauth-args");
+    let mut config = Config::new();
+    config.set_option("forwardx11", "yes");
+    config.set_option("forwardx11timeout", "5m");
+    config.set_option(
+        "xauthlocation",
+        write_fake_xauth(&tmp, args.path()).display().to_string(),
+    );
+    config.set_option("wezterm_ssh_x11_display", format!("127.0.0.1:{number}"));
+
+    smol::block_on(async {
+        let session = connect(config, &sshd).await;
+        assert_eq!(greet_display(&session).await, "hello");
+    });
+
+    let args = std::fs::read_to_string(args.path()).unwrap();
+    assert!(
+        args.trim_end().ends_with(&format!(
+            "generate 127.0.0.1:{number} MIT-MAGIC-COOKIE-1 untrusted timeout 300"
+        )),
+        "{}",
+        args
+    );
+}
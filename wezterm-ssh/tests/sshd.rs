@@ -57,6 +57,27 @@ impl SshKeygen {
 
         Ok(res)
     }
+
+    // ssh-keygen -s $CA -I wezterm-ssh-test -n $PRINCIPAL -V +1h $PUBKEY
+    // This produces a certificate alongside $PUBKEY, named with a
+    // -cert.pub suffix in place of .pub
+    pub fn sign_user_key(
+        ca: impl AsRef<Path>,
+        pubkey: impl AsRef<Path>,
+        principal: impl AsRef<str>,
+    ) -> IoResult<bool> {
+        Command::new("ssh-keygen")
+            .arg("-s")
+            .arg(ca.as_ref())
+            .args(&["-I", "wezterm-ssh-test"])
+            .arg("-n")
+            .arg(principal.as_ref())
+            .args(&["-V", "+1h"])
+            .arg("-q")
+            .arg(pubkey.as_ref())
+            .status()
+            .map(|status| status.success())
+    }
 }
 
 pub struct SshAgent {
@@ -119,6 +140,13 @@ impl SshdConfig {
         );
     }
 
+    pub fn set_trusted_user_ca_keys(&mut self, path: impl AsRef<Path>) {
+        self.0.insert(
+            "TrustedUserCAKeys".to_string(),
+            vec![path.as_ref().to_string_lossy().to_string()],
+        );
+    }
+
     pub fn set_host_key(&mut self, path: impl AsRef<Path>) {
         self.0.insert(
             "HostKey".to_string(),
@@ -154,6 +182,10 @@ impl SshdConfig {
             .insert("X11Forwarding".to_string(), Self::yes_value(yes));
     }
 
+    pub fn set_env(&mut self, vars: Vec<String>) {
+        self.0.insert("SetEnv".to_string(), vars);
+    }
+
     pub fn set_use_privilege_separation(&mut self, yes: bool) {
         self.0
             .insert("UsePrivilegeSeparation".to_string(), Self::yes_value(yes));
@@ -289,9 +321,18 @@ impl Sshd {
             "Failed to ssh-keygen ssh_host_rsa_key"
         );
 
-        config.set_authorized_keys_file(id_rsa_file.path().with_extension("pub"));
+        if !config.0.contains_key("AuthorizedKeysFile") {
+            config.set_authorized_keys_file(id_rsa_file.path().with_extension("pub"));
+        }
         config.set_host_key(ssh_host_rsa_key_file.path());
 
+        // Keep the xauth data that sshd records for X11 forwarding
+        // away from that of the user running the tests
+        config.set_env(vec![format!(
+            "XAUTHORITY={}",
+            tmp.child("Xauthority").path().display()
+        )]);
+
         let sshd_pid_file = tmp.child("sshd.pid");
         config.set_pid_file(sshd_pid_file.path());
 
@@ -395,6 +436,51 @@ impl Sshd {
             "ran out of ports when spawning sshd",
         ))
     }
+
+    /// Sends `signal` to sshd and to the processes that it spawned to
+    /// serve connections, in order to simulate the server going away
+    pub fn signal_all(&self, signal: &str) {
+        fn children(pid: u32, pids: &mut Vec<u32>) {
+            let output = Command::new("pgrep")
+                .arg("-P")
+                .arg(pid.to_string())
+                .output()
+                .expect("running pgrep");
+            for child in String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .filter_map(|pid| pid.parse().ok())
+            {
+                pids.push(child);
+                children(child, pids);
+            }
+        }
+
+        let mut pids = vec![self.child.id()];
+        children(self.child.id(), &mut pids);
+        let status = Command::new("kill")
+            .arg(format!("-{signal}"))
+            .args(pids.iter().map(|pid| pid.to_string()))
+            .status()
+            .expect("running kill");
+        assert!(status.success(), "kill -{} {:?} failed", signal, pids);
+    }
+
+    /// Kills sshd along with all of its connections
+    pub fn kill(&mut self) {
+        self.signal_all("KILL");
+        let _ = self.child.wait();
+    }
+
+    /// Starts sshd again, with the same configuration and port,
+    /// after it was killed
+    pub fn restart(&mut self) -> IoResult<()> {
+        self.child = Self::try_spawn(
+            self.port,
+            self.tmp.child("sshd_config").path(),
+            self.tmp.child("sshd.log").path(),
+        )?;
+        Ok(())
+    }
 }
 
 impl Drop for Sshd {
@@ -435,6 +521,15 @@ impl std::ops::DerefMut for SessionWithSshd {
 #[fixture]
 /// Stand up an sshd instance and then connect to it and perform authentication
 pub async fn session(#[default(Config::new())] config: Config, sshd: Sshd) -> SessionWithSshd {
+    let session = connect(config, &sshd).await;
+    SessionWithSshd {
+        session,
+        _sshd: sshd,
+    }
+}
+
+/// Connect to the sshd instance and perform authentication
+pub async fn connect(config: Config, sshd: &Sshd) -> Session {
     let port = sshd.port;
 
     // Do not add the default config files; they take the config of the
@@ -443,52 +538,63 @@ pub async fn session(#[default(Config::new())] config: Config, sshd: Sshd) -> Se
     // NO: config.add_default_config_files();
 
     // Load our config to point to ourselves, using current sshd instance's port,
-    // generated identity file, and host file
-    let mut config = config.for_host("localhost");
-    config.insert("port".to_string(), port.to_string());
-    config.insert("wezterm_ssh_verbose".to_string(), "true".to_string());
+    // generated identity file, and host file.
+    // These are also used for any ProxyJump hosts, which are this same sshd.
+    let overrides = vec![
+        ("port", port.to_string()),
+        ("wezterm_ssh_verbose", "true".to_string()),
+        ("identityagent", format!("{}", sshd.agent_sock.display())),
+        ("user", USERNAME.to_string()),
+        ("identitiesonly", "yes".to_string()),
+        (
+            "pubkeyacceptedtypes",
+            // Ensure that we have ssh-rsa in the list, as debian9
+            // seems unhappy without it
+            "ssh-rsa,ssh-ed25519,\
+                  rsa-sha2-512,rsa-sha2-256,ecdsa-sha2-nistp521,\
+                  ecdsa-sha2-nistp384,ecdsa-sha2-nistp256,\
+                  ssh-rsa-cert-v01@openssh.com,\
+                  rsa-sha2-512-cert-v01@openssh.com,\
+                  rsa-sha2-256-cert-v01@openssh.com"
+                .to_string(),
+        ),
+        (
+            "identityfile",
+            sshd.tmp
+                .child("id_rsa")
+                .path()
+                .to_str()
+                .expect("Failed to get string path for id_rsa")
+                .to_string(),
+        ),
+        (
+            "userknownhostsfile",
+            sshd.tmp
+                .child("known_hosts")
+                .path()
+                .to_str()
+                .expect("Failed to get string path for known_hosts")
+                .to_string(),
+        ),
+    ];
 
     // If libssh-rs is not loaded (but ssh2 is), then we use ssh2 as the backend
     #[cfg(not(feature = "libssh-rs"))]
-    config.insert("wezterm_ssh_backend".to_string(), "ssh2".to_string());
-
-    config.insert(
-        "identityagent".to_string(),
-        format!("{}", sshd.agent_sock.display()),
-    );
-
-    config.insert("user".to_string(), USERNAME.to_string());
-    config.insert("identitiesonly".to_string(), "yes".to_string());
-    config.insert(
-        "pubkeyacceptedtypes".to_string(),
-        // Ensure that we have ssh-rsa in the list, as debian9
-        // seems unhappy without it
-        "ssh-rsa,ssh-ed25519,\
-                  rsa-sha2-512,rsa-sha2-256,ecdsa-sha2-nistp521,\
-                  ecdsa-sha2-nistp384,ecdsa-sha2-nistp256"
-            .to_string(),
-    );
-    config.insert(
-        "identityfile".to_string(),
-        sshd.tmp
-            .child("id_rsa")
-            .path()
-            .to_str()
-            .expect("Failed to get string path for id_rsa")
-            .to_string(),
-    );
-    config.insert(
-        "userknownhostsfile".to_string(),
-        sshd.tmp
-            .child("known_hosts")
-            .path()
-            .to_str()
-            .expect("Failed to get string path for known_hosts")
-            .to_string(),
-    );
+    let overrides = [overrides, vec![("wezterm_ssh_backend", "ssh2".to_string())]].concat();
+
+    let mut jump_config = config.clone();
+    for (key, value) in &overrides {
+        jump_config.set_option(key, value);
+    }
+
+    let mut config = config.for_host("localhost");
+    for (key, value) in overrides {
+        config.insert(key.to_string(), value);
+    }
 
     // Perform our actual connection
-    let (session, events) = Session::connect(config.clone()).expect("Failed to connect to sshd");
+    let (session, events) = Session::connect_with_jump_config(config.clone(), jump_config)
+        .expect("Failed to connect to sshd");
 
     // Perform automated authentication, assuming that we have a publickey with empty password
     while let Ok(event) = events.recv().await {
@@ -531,8 +637,5 @@ pub async fn session(#[default(Config::new())] config: Config, sshd: Sshd) -> Se
         }
     }
 
-    SessionWithSshd {
-        session,
-        _sshd: sshd,
-    }
+    session
 }
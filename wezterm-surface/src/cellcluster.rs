@@ -231,6 +231,29 @@ impl CellCluster {
         }
     }
 
+    /// Append the cells of `other` to this cluster.
+    /// `other` must immediately follow this cluster in the line.
+    /// The attributes of this cluster are retained; this is
+    /// intended to allow shaping runs of text that differ only
+    /// in ways that don't affect shaping.
+    pub fn append(&mut self, other: &CellCluster) {
+        debug_assert_eq!(other.first_cell_idx, self.first_cell_idx + self.width);
+        let mut start = 0;
+        while start < other.text.len() {
+            let cell_idx = other.byte_to_cell_idx(start);
+            let mut end = start + 1;
+            while end < other.text.len() && other.byte_to_cell_idx(end) == cell_idx {
+                end += 1;
+            }
+            self.add(
+                &other.text[start..end],
+                cell_idx,
+                other.byte_to_cell_width(start) as usize,
+            );
+            start = end;
+        }
+    }
+
     /// Start off a new cluster with some initial data
     fn new(
         hint: usize,
@@ -364,6 +364,20 @@ impl Line {
         self.update_last_change_seqno(seqno);
     }
 
+    /// Check whether the line has been marked via the iTerm2
+    /// `SetMark` escape sequence.
+    #[inline]
+    pub fn is_marked(&self) -> bool {
+        self.bits.contains(LineBits::MARKED)
+    }
+
+    /// Set or clear the mark on this line.
+    #[inline]
+    pub fn set_marked(&mut self, marked: bool, seqno: SequenceNo) {
+        self.bits.set(LineBits::MARKED, marked);
+        self.update_last_change_seqno(seqno);
+    }
+
     /// Set a flag the indicate whether the line should have the bidi
     /// algorithm applied during rendering
     pub fn set_bidi_enabled(&mut self, enabled: bool, seqno: SequenceNo) {
@@ -49,5 +49,9 @@ bitflags! {
         /// Otherwise, the auto-detect direction is used, falling back
         /// to the direction specified by the RTL bit.
         const AUTO_DETECT_DIRECTION = 1<<8;
+
+        /// true if the line was marked via the iTerm2 `SetMark`
+        /// escape sequence.
+        const MARKED = 1<<9;
     }
 }
@@ -501,6 +501,31 @@ ClusteredLine {
     );
 }
 
+#[test]
+fn cell_cluster_append() {
+    let mut red = CellAttributes::default();
+    red.set_foreground(wezterm_cell::color::ColorAttribute::PaletteIndex(1));
+
+    let mut line = Line::from_text("a=", &CellAttributes::default(), SEQ_ZERO, None);
+    line.append_line(Line::from_text("中>b", &red, SEQ_ZERO, None), SEQ_ZERO);
+
+    let mut clusters = line.cluster(None);
+    assert_eq!(clusters.len(), 2);
+    let second = clusters.pop().unwrap();
+    let first = &mut clusters[0];
+    first.append(&second);
+
+    assert_eq!(first.text, "a=中>b");
+    assert_eq!(first.width, 6);
+    assert_eq!(first.attrs, CellAttributes::default());
+    let cells = first
+        .text
+        .char_indices()
+        .map(|(idx, _)| (first.byte_to_cell_idx(idx), first.byte_to_cell_width(idx)))
+        .collect::<Vec<_>>();
+    assert_eq!(cells, vec![(0, 1), (1, 1), (2, 2), (4, 1), (5, 1)]);
+}
+
 #[test]
 fn cluster_line_new() {
     let mut line = Line::new(1);
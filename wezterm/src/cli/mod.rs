@@ -15,11 +15,14 @@ mod list_clients;
 mod move_pane_to_new_tab;
 mod proxy;
 mod rename_workspace;
+mod screenshot;
 mod send_text;
 mod set_tab_title;
 mod set_window_title;
+mod sftp;
 mod spawn_command;
 mod split_pane;
+mod ssh_forward;
 mod tls_creds;
 mod zoom_pane;
 
@@ -121,6 +124,10 @@ Outputs the pane-id for the newly created pane on success"
     #[command(name = "get-text", rename_all = "kebab")]
     GetText(get_text::GetText),
 
+    /// Render the visible portion of a pane to a PNG image file
+    #[command(name = "screenshot", rename_all = "kebab")]
+    Screenshot(screenshot::Screenshot),
+
     /// Activate an adjacent pane in the specified direction.
     #[command(name = "activate-pane-direction", rename_all = "kebab")]
     ActivatePaneDirection(activate_pane_direction::ActivatePaneDirection),
@@ -163,6 +170,14 @@ Outputs the pane-id for the newly created pane on success"
     /// Zoom, unzoom, or toggle zoom state
     #[command(name = "zoom-pane", rename_all = "kebab")]
     ZoomPane(zoom_pane::ZoomPane),
+
+    /// Manage the port forwards of an ssh domain
+    #[command(name = "ssh-forward", rename_all = "kebab")]
+    SshForward(ssh_forward::SshForward),
+
+    /// Transfer files over the sftp subsystem of an ssh domain
+    #[command(name = "sftp", rename_all = "kebab")]
+    Sftp(sftp::Sftp),
 }
 
 async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()> {
@@ -186,6 +201,7 @@ async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()>
         CliSubCommand::SplitPane(cmd) => cmd.run(client).await,
         CliSubCommand::SendText(cmd) => cmd.run(client).await,
         CliSubCommand::GetText(cmd) => cmd.run(client).await,
+        CliSubCommand::Screenshot(cmd) => cmd.run(client).await,
         CliSubCommand::SpawnCommand(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
         CliSubCommand::Proxy(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
         CliSubCommand::TlsCreds(cmd) => cmd.run(client).await,
@@ -199,6 +215,8 @@ async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()>
         CliSubCommand::SetWindowTitle(cmd) => cmd.run(client).await,
         CliSubCommand::RenameWorkspace(cmd) => cmd.run(client).await,
         CliSubCommand::ZoomPane(cmd) => cmd.run(client).await,
+        CliSubCommand::SshForward(cmd) => cmd.run(client).await,
+        CliSubCommand::Sftp(cmd) => cmd.run(client).await,
     }
 }
 
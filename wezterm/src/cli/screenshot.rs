@@ -0,0 +1,32 @@
+use anyhow::Context;
+use clap::{Parser, ValueHint};
+use mux::pane::PaneId;
+use std::path::PathBuf;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct Screenshot {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// Where to save the image.  The image is written in PNG format.
+    #[arg(value_parser, value_hint=ValueHint::FilePath)]
+    output: PathBuf,
+}
+
+impl Screenshot {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+
+        let response = client
+            .screenshot_pane(codec::ScreenshotPane { pane_id })
+            .await?;
+
+        std::fs::write(&self.output, &response.data)
+            .with_context(|| format!("writing screenshot to {}", self.output.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use codec::{SftpTransfer, SftpTransferAction};
+use mux::pane::PaneId;
+use mux::sftp::CHUNK_SIZE;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct Sftp {
+    /// Specify a pane in the target ssh domain.
+    /// Relative remote paths are resolved against the
+    /// current working directory of that pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    #[command(subcommand)]
+    action: SftpCommand,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+enum SftpCommand {
+    /// Download a remote file.
+    /// If LOCAL is omitted, the file is saved with the same
+    /// name in the current directory.
+    Get {
+        remote: String,
+        #[arg(value_hint=clap::ValueHint::AnyPath)]
+        local: Option<PathBuf>,
+    },
+    /// Upload a local file.
+    /// If REMOTE is omitted, the file is saved with the same
+    /// name in the working directory of the pane.
+    /// If REMOTE is a directory, the file is saved into it.
+    Put {
+        #[arg(value_hint=clap::ValueHint::FilePath)]
+        local: PathBuf,
+        remote: Option<String>,
+    },
+}
+
+fn file_name(path: &str) -> anyhow::Result<&str> {
+    path.rsplit('/')
+        .find(|s| !s.is_empty())
+        .with_context(|| format!("{} has no file name", path))
+}
+
+impl Sftp {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+        match self.action {
+            SftpCommand::Get { remote, local } => {
+                let local = match local {
+                    Some(local) if local.is_dir() => local.join(file_name(&remote)?),
+                    Some(local) => local,
+                    None => PathBuf::from(file_name(&remote)?),
+                };
+                // Open the remote file first, so that a missing remote
+                // file doesn't leave behind an empty local file
+                let handle = client
+                    .sftp_transfer(SftpTransfer {
+                        pane_id,
+                        action: SftpTransferAction::OpenRead { path: remote },
+                    })
+                    .await?
+                    .handle;
+                let result = async {
+                    let mut file = std::fs::File::create(&local)
+                        .with_context(|| format!("creating {}", local.display()))?;
+                    loop {
+                        let data = client
+                            .sftp_transfer(SftpTransfer {
+                                pane_id,
+                                action: SftpTransferAction::Read {
+                                    handle,
+                                    len: CHUNK_SIZE,
+                                },
+                            })
+                            .await?
+                            .data;
+                        if data.is_empty() {
+                            break;
+                        }
+                        file.write_all(&data)
+                            .with_context(|| format!("writing {}", local.display()))?;
+                    }
+                    anyhow::Result::<()>::Ok(())
+                }
+                .await;
+                close(&client, pane_id, handle).await?;
+                result
+            }
+            SftpCommand::Put { local, remote } => {
+                let file_name = local
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| name.to_string())
+                    .with_context(|| format!("{} has no file name", local.display()))?;
+                // If remote is a directory, the server saves the
+                // file into it using file_name
+                let remote = remote.unwrap_or_else(|| file_name.clone());
+                let mut file = std::fs::File::open(&local)
+                    .with_context(|| format!("opening {}", local.display()))?;
+                let handle = client
+                    .sftp_transfer(SftpTransfer {
+                        pane_id,
+                        action: SftpTransferAction::OpenWrite {
+                            path: remote,
+                            file_name: Some(file_name),
+                        },
+                    })
+                    .await?
+                    .handle;
+                let result = async {
+                    let mut buf = vec![0u8; CHUNK_SIZE];
+                    loop {
+                        let n = file
+                            .read(&mut buf)
+                            .with_context(|| format!("reading {}", local.display()))?;
+                        if n == 0 {
+                            break;
+                        }
+                        client
+                            .sftp_transfer(SftpTransfer {
+                                pane_id,
+                                action: SftpTransferAction::Write {
+                                    handle,
+                                    data: buf[..n].to_vec(),
+                                },
+                            })
+                            .await?;
+                    }
+                    anyhow::Result::<()>::Ok(())
+                }
+                .await;
+                close(&client, pane_id, handle).await?;
+                result
+            }
+        }
+    }
+}
+
+async fn close(client: &Client, pane_id: PaneId, handle: usize) -> anyhow::Result<()> {
+    client
+        .sftp_transfer(SftpTransfer {
+            pane_id,
+            action: SftpTransferAction::Close { handle },
+        })
+        .await?;
+    Ok(())
+}
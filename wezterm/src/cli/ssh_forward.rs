@@ -0,0 +1,76 @@
+use clap::{Parser, Subcommand};
+use codec::SshForwardAction;
+use mux::pane::PaneId;
+use tabout::{tabulate_output, Alignment, Column};
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SshForward {
+    /// Specify a pane in the target ssh domain.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    #[command(subcommand)]
+    action: SshForwardCommand,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+enum SshForwardCommand {
+    /// Add a forward.  The spec is one of `L`, `R` or `D` followed by
+    /// the same arguments as `ssh -L`, `ssh -R` or `ssh -D`,
+    /// for example `L 8080:localhost:80`.
+    Add { spec: String },
+    /// Stop accepting connections for the forward with the specified id
+    Cancel { id: usize },
+    /// List the forwards
+    List,
+}
+
+impl SshForward {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+        let action = match self.action {
+            SshForwardCommand::Add { spec } => SshForwardAction::Add(spec),
+            SshForwardCommand::Cancel { id } => SshForwardAction::Cancel(id),
+            SshForwardCommand::List => SshForwardAction::List,
+        };
+        let is_cancel = matches!(action, SshForwardAction::Cancel(_));
+        let response = client
+            .ssh_forward(codec::SshForward { pane_id, action })
+            .await?;
+
+        if is_cancel {
+            return Ok(());
+        }
+
+        let cols = vec![
+            Column {
+                name: "ID".to_string(),
+                alignment: Alignment::Right,
+            },
+            Column {
+                name: "PORT".to_string(),
+                alignment: Alignment::Right,
+            },
+            Column {
+                name: "SPEC".to_string(),
+                alignment: Alignment::Left,
+            },
+        ];
+        let data: Vec<Vec<String>> = response
+            .forwards
+            .into_iter()
+            .map(|entry| {
+                vec![
+                    entry.id.to_string(),
+                    entry.bound_port.to_string(),
+                    entry.spec,
+                ]
+            })
+            .collect();
+        tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+        Ok(())
+    }
+}